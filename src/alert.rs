@@ -0,0 +1,291 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    currency::Currency,
+    dec,
+    exchange::{execute_if, Exchange, Exchanges, RealtimeData},
+    select_ex,
+    utils::{async_helpers, storage, Decimal},
+};
+
+const ALERTS_KEY: &str = "alerts";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Above,
+    Below,
+}
+
+/// Fires once when the price crosses `price` in `direction`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Alert {
+    pub id: u64,
+    pub exchange: String,
+    pub pair: (Currency, Currency),
+    pub direction: Direction,
+    pub price: Decimal,
+    /// False after firing, until the price moves back by the hysteresis.
+    pub armed: bool,
+}
+
+impl Alert {
+    pub fn new(
+        exchange: String,
+        pair: (Currency, Currency),
+        direction: Direction,
+        price: Decimal,
+    ) -> Self {
+        Self {
+            id: 0,
+            exchange,
+            pair,
+            direction,
+            price,
+            armed: true,
+        }
+    }
+
+    /// Returns true if the alert fires at `price`.
+    /// `hysteresis` is the ratio of the alert price the price has to move back by to re-arm.
+    pub fn check(&mut self, price: Decimal, hysteresis: Decimal) -> bool {
+        let band = self.price * hysteresis;
+        let (crossed, moved_back) = match self.direction {
+            Direction::Above => (price >= self.price, price < self.price - band),
+            Direction::Below => (price <= self.price, price > self.price + band),
+        };
+
+        if self.armed && crossed {
+            self.armed = false;
+            return true;
+        }
+
+        if !self.armed && moved_back {
+            self.armed = true;
+        }
+
+        false
+    }
+}
+
+/// Active alerts, persisted across restarts.
+pub struct Alerts {
+    alerts: Mutex<Vec<Alert>>,
+    /// Ids are never reused, so a watcher of a removed alert can not pick up a new one.
+    next_id: AtomicU64,
+}
+
+impl Alerts {
+    pub fn instance() -> &'static Alerts {
+        static ALERTS: Lazy<Alerts> = Lazy::new(|| {
+            let alerts: Vec<Alert> = storage::read(ALERTS_KEY)
+                .and_then(|text| match serde_json::from_str(&text) {
+                    Ok(alerts) => Some(alerts),
+                    Err(e) => {
+                        tracing::warn!("Discarding corrupted alerts: {}", e);
+                        None
+                    }
+                })
+                .unwrap_or_default();
+            let next_id = alerts
+                .iter()
+                .map(|alert| alert.id + 1)
+                .max()
+                .unwrap_or_default();
+
+            Alerts {
+                alerts: Mutex::new(alerts),
+                next_id: AtomicU64::new(next_id),
+            }
+        });
+
+        &ALERTS
+    }
+
+    pub fn list(&self) -> Vec<Alert> {
+        self.alerts.lock().unwrap().clone()
+    }
+
+    /// Adds the alert and starts watching its pair.
+    /// Nothing is saved if the exchange is unknown or does not stream prices.
+    pub fn add(&'static self, mut alert: Alert, exchanges: &Exchanges) -> Result<(), String> {
+        check_watchable(&alert, exchanges)?;
+
+        alert.id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        {
+            let mut alerts = self.alerts.lock().unwrap();
+            alerts.push(alert.clone());
+            save(&alerts);
+        }
+
+        request_permission();
+        self.watch(&alert, exchanges);
+        Ok(())
+    }
+
+    pub fn remove(&self, id: u64) {
+        let mut alerts = self.alerts.lock().unwrap();
+        alerts.retain(|alert| alert.id != id);
+        save(&alerts);
+    }
+
+    /// Starts watching the restored alerts, called once on startup.
+    /// Alerts that can not be watched anymore are skipped, but kept.
+    pub fn watch_all(&'static self, exchanges: &Exchanges) {
+        for alert in self.list() {
+            match check_watchable(&alert, exchanges) {
+                Ok(()) => self.watch(&alert, exchanges),
+                Err(e) => tracing::warn!("Alert {}: {}", alert.id, e),
+            }
+        }
+    }
+
+    fn watch(&'static self, alert: &Alert, exchanges: &Exchanges) {
+        let id = alert.id;
+        let pair = alert.pair;
+
+        select_ex!(exchanges, alert.exchange, |exchange| {
+            let subscription = exchange.subscribe(pair, None);
+            async_helpers::spawn(async move {
+                loop {
+                    let price = match subscription.recv().await {
+                        RealtimeData::Trade(trade) if trade.pair == pair => trade.price,
+                        RealtimeData::Orderbook(orderbook) if orderbook.pair == pair => {
+                            match (orderbook.bids.first(), orderbook.asks.first()) {
+                                (Some(bid), Some(ask)) => (bid.price + ask.price) / dec!(2),
+                                _ => continue,
+                            }
+                        }
+                        _ => continue,
+                    };
+
+                    // The alert was removed
+                    if !self.on_price(id, price) {
+                        break;
+                    }
+                }
+            })
+        });
+    }
+
+    /// Returns false if the alert does not exist anymore.
+    fn on_price(&self, id: u64, price: Decimal) -> bool {
+        let mut alerts = self.alerts.lock().unwrap();
+        let Some(alert) = alerts.iter_mut().find(|alert| alert.id == id) else {
+            return false;
+        };
+
+        let was_armed = alert.armed;
+        if alert.check(price, Config::get().alert.hysteresis) {
+            let message = format!(
+                "{} {}-{} is {} {} ({})",
+                alert.exchange,
+                alert.pair.0,
+                alert.pair.1,
+                match alert.direction {
+                    Direction::Above => "above",
+                    Direction::Below => "below",
+                },
+                alert.price,
+                price
+            );
+            notify(&message);
+        }
+
+        if was_armed != alert.armed {
+            save(&alerts);
+        }
+
+        true
+    }
+}
+
+/// Error if the exchange of the alert is unknown or has no realtime stream to watch.
+fn check_watchable(alert: &Alert, exchanges: &Exchanges) -> Result<(), String> {
+    let streams = select_ex!(exchanges, alert.exchange, |exchange| {
+        exchange.streams_realtime()
+    });
+    match streams {
+        Some(true) => Ok(()),
+        Some(false) => Err(format!(
+            "{} does not stream prices, alerts are not supported",
+            alert.exchange
+        )),
+        None => Err(format!("Unknown exchange {}", alert.exchange)),
+    }
+}
+
+fn save(alerts: &[Alert]) {
+    match serde_json::to_string(alerts) {
+        Ok(text) => storage::write(ALERTS_KEY, &text),
+        Err(e) => tracing::warn!("Failed to serialize alerts: {}", e),
+    }
+}
+
+#[cfg(any(target_arch = "wasm32"))]
+fn request_permission() {
+    let _ = web_sys::Notification::request_permission();
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn request_permission() {}
+
+#[cfg(any(target_arch = "wasm32"))]
+fn notify(message: &str) {
+    tracing::info!("Alert: {}", message);
+    if let Err(e) = web_sys::Notification::new(message) {
+        tracing::warn!("Failed to show notification: {:?}", e);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn notify(message: &str) {
+    tracing::info!("Alert: {}", message);
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Alert, Direction};
+    use crate::currency::Currency;
+    use crate::utils::Decimal;
+
+    fn alert(direction: Direction, price: i64) -> Alert {
+        Alert::new(
+            "Upbit".to_string(),
+            (Currency::BTC, Currency::KRW),
+            direction,
+            Decimal(price.into()),
+        )
+    }
+
+    #[test]
+    fn fires_once_per_crossing() {
+        let hysteresis = Decimal(1.into()) / Decimal(100.into());
+        let mut alert = alert(Direction::Above, 1000);
+
+        assert!(!alert.check(Decimal(999.into()), hysteresis));
+        assert!(alert.check(Decimal(1001.into()), hysteresis));
+        assert!(!alert.check(Decimal(1002.into()), hysteresis));
+
+        // Still inside the hysteresis band, not re-armed
+        assert!(!alert.check(Decimal(995.into()), hysteresis));
+        assert!(!alert.check(Decimal(1001.into()), hysteresis));
+
+        assert!(!alert.check(Decimal(980.into()), hysteresis));
+        assert!(alert.check(Decimal(1001.into()), hysteresis));
+    }
+
+    #[test]
+    fn below() {
+        let mut alert = alert(Direction::Below, 1000);
+
+        assert!(alert.check(Decimal(1000.into()), Decimal::ZERO));
+        assert!(!alert.check(Decimal(900.into()), Decimal::ZERO));
+        assert!(!alert.check(Decimal(1001.into()), Decimal::ZERO));
+        assert!(alert.check(Decimal(999.into()), Decimal::ZERO));
+    }
+}
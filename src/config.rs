@@ -1,24 +1,319 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
 
+use crate::currency::Currency;
+use crate::dec;
+use crate::ui::keybinds::KeybindsConfig;
+use crate::ui::theme::ThemeConfig;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::utils::async_helpers;
+use crate::utils::broadcaster::{Broadcaster, Subscription};
+use crate::utils::format::FormatConfig;
+use crate::utils::http::{BaseUrls, ClientConfig, RetryPolicy};
+use crate::utils::ledger::LedgerConfig;
+use crate::utils::rate_limiter::RateLimit;
+use crate::utils::throttle::ThrottleLimits;
+use crate::utils::Decimal;
+use crate::websocket::KeepaliveConfig;
+
+pub mod secrets;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Config {
     pub bithumb: Option<BithumbConfig>,
     pub upbit: Option<UpbitConfig>,
     pub binance: Option<BinanceConfig>,
+    pub okx: Option<OkxConfig>,
+
+    /// Overrides the default rate limit of an exchange, keyed by the exchange name.
+    #[serde(default)]
+    pub rate_limit: HashMap<String, RateLimit>,
+
+    /// Retry policy of transient http failures, shared by all exchanges.
+    #[serde(default)]
+    pub http_retry: RetryPolicy,
+
+    /// Timeout, user agent and proxy of the http client, applied on restart.
+    #[serde(default)]
+    pub http: ClientConfig,
+
+    /// Overrides the urls of an exchange, keyed by the exchange name.
+    #[serde(default)]
+    pub base_urls: HashMap<String, BaseUrls>,
+
+    /// Where the placed orders are recorded.
+    #[serde(default)]
+    pub ledger: LedgerConfig,
+
+    /// Ping interval and timeout of the websockets, applied on restart.
+    #[serde(default)]
+    pub websocket: KeepaliveConfig,
+
+    #[serde(default)]
+    pub orderbook: OrderbookConfig,
+
+    #[serde(default)]
+    pub alert: AlertConfig,
+
+    #[serde(default)]
+    pub convert: ConvertConfig,
+
+    /// Separators of the displayed prices and amounts.
+    #[serde(default)]
+    pub format: FormatConfig,
+
+    /// Colors of the ui, see [`crate::ui::theme`].
+    #[serde(default)]
+    pub theme: ThemeConfig,
+
+    /// Keyboard shortcuts of the main window, see [`crate::ui::keybinds`].
+    #[serde(default)]
+    pub keybinds: KeybindsConfig,
+
+    /// Source and lifetime of the reference exchange rates, see [`crate::exchange::fx`].
+    #[serde(default)]
+    pub fx: FxConfig,
+
+    /// Lifetimes of the cached market data and balances, see [`crate::exchange::cache`].
+    #[serde(default)]
+    pub cache: CacheConfig,
+
+    /// How many scripts and orders may run at once, see [`crate::utils::throttle`].
+    #[serde(default)]
+    pub actions: ActionConfig,
+
+    /// Scrollback of the console widgets.
+    #[serde(default)]
+    pub console: ConsoleConfig,
+
+    /// Fees and slippage of simulated orders, see [`crate::exchange::backtest`].
+    #[serde(default)]
+    pub backtest: BacktestConfig,
+
+    /// Encrypted exchange sections, see [`secrets`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secrets: Option<secrets::EncryptedSecrets>,
 }
 
+const CONFIG_PATH: &str = "config.toml";
+
+/// How often the config file is checked for changes.
+#[cfg(not(target_arch = "wasm32"))]
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Keys of the tests run without a config file, the ignored live tests need a real one.
+#[cfg(test)]
+const TEST_CONFIG: &str = "[upbit]\naccess_key = \"test-access-key\"\nsecret_key = \"test-secret-key\"\n\
+                           [binance]\napi_key = \"test-api-key\"\nsecret_key = \"test-secret-key\"\n";
+
+static CONFIG: Lazy<RwLock<Arc<Config>>> = Lazy::new(|| {
+    let config = match Config::load_at_startup() {
+        #[cfg(test)]
+        Err(ConfigError::Io(_)) => Config::parse_at_startup(TEST_CONFIG).unwrap(),
+        config => config.unwrap(),
+    };
+    RwLock::new(Arc::new(config))
+});
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("failed to read {CONFIG_PATH}: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("failed to parse {CONFIG_PATH}: {0}")]
+    Parse(#[from] toml::de::Error),
+
+    #[error("empty api key or passphrase in [{0}]")]
+    EmptyKey(&'static str),
+
+    #[error("failed to serialize the config: {0}")]
+    Serialize(#[from] toml::ser::Error),
+
+    #[error("invalid proxy in [http]: {0}")]
+    InvalidProxy(reqwest::Error),
+
+    #[error("{0} must be positive")]
+    NotPositive(&'static str),
+
+    #[error("invalid color in [theme]: {0}")]
+    InvalidColor(String),
+}
+
+/// Errors of reloads nobody is waiting on, shown in the open consoles.
+static RELOAD_ERRORS: Lazy<Broadcaster<String>> = Lazy::new(Broadcaster::new);
+
 impl Config {
-    pub fn get() -> &'static Config {
-        static CONFIG: once_cell::sync::Lazy<Config> = once_cell::sync::Lazy::new(|| {
-            let config = std::fs::read_to_string("config.toml").unwrap();
-            toml::from_str(&config).unwrap()
-        });
+    /// Returns the current config.
+    /// Read it again for each use instead of keeping it, so reloaded settings are picked up.
+    pub fn get() -> Arc<Config> {
+        CONFIG.read().clone()
+    }
+
+    fn load() -> Result<Config, ConfigError> {
+        let text = std::fs::read_to_string(CONFIG_PATH)?;
+        Self::parse(&text)
+    }
+
+    /// Like `load`, but an exchange with an empty api key is left out with an error
+    /// instead of failing, so a half filled section disables its exchange, not the app.
+    fn load_at_startup() -> Result<Config, ConfigError> {
+        let text = std::fs::read_to_string(CONFIG_PATH)?;
+        Self::parse_at_startup(&text)
+    }
+
+    fn parse_at_startup(text: &str) -> Result<Config, ConfigError> {
+        let mut config: Config = toml::from_str(text)?;
+        secrets::apply(&mut config);
+        while let Err(ConfigError::EmptyKey(name)) = config.validate() {
+            tracing::error!(
+                "{}, trading on {} is disabled",
+                ConfigError::EmptyKey(name),
+                name
+            );
+            match name {
+                "bithumb" => config.bithumb = None,
+                "upbit" => config.upbit = None,
+                "binance" => config.binance = None,
+                "okx" => config.okx = None,
+                _ => break,
+            }
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn parse(text: &str) -> Result<Config, ConfigError> {
+        let mut config: Config = toml::from_str(text)?;
+        secrets::apply(&mut config);
+        config.validate()?;
+        Ok(config)
+    }
 
-        &CONFIG
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let keys = [
+            (
+                "bithumb",
+                self.bithumb
+                    .as_ref()
+                    .map(|c| vec![&c.connect_key, &c.secret_key]),
+            ),
+            (
+                "upbit",
+                self.upbit
+                    .as_ref()
+                    .map(|c| vec![&c.access_key, &c.secret_key]),
+            ),
+            (
+                "binance",
+                self.binance
+                    .as_ref()
+                    .map(|c| vec![&c.api_key, &c.secret_key]),
+            ),
+            (
+                "okx",
+                self.okx
+                    .as_ref()
+                    .map(|c| vec![&c.api_key, &c.secret_key, &c.passphrase]),
+            ),
+        ];
+        for (name, keys) in keys {
+            if keys.is_some_and(|keys| keys.iter().any(|key| key.is_empty())) {
+                return Err(ConfigError::EmptyKey(name));
+            }
+        }
+        self.http.validate().map_err(ConfigError::InvalidProxy)?;
+        if self.actions.max_scripts == 0 {
+            return Err(ConfigError::NotPositive("[actions] max_scripts"));
+        }
+        if self.actions.max_orders == 0 {
+            return Err(ConfigError::NotPositive("[actions] max_orders"));
+        }
+        for limit in self.rate_limit.values() {
+            if limit.capacity == 0 {
+                return Err(ConfigError::NotPositive("[rate_limit] capacity"));
+            }
+            // Requests would wait forever for their tokens
+            if limit.refill_per_sec.is_nan() || limit.refill_per_sec <= 0.0 {
+                return Err(ConfigError::NotPositive("[rate_limit] refill_per_sec"));
+            }
+        }
+        self.theme.validate().map_err(ConfigError::InvalidColor)?;
+
+        Ok(())
+    }
+
+    /// Re-reads the config file and replaces the current config.
+    /// If the file is invalid, the current config stays active.
+    pub fn reload() -> Result<(), ConfigError> {
+        let config = Self::load()?;
+        *CONFIG.write() = Arc::new(config);
+
+        tracing::info!("Reloaded {}", CONFIG_PATH);
+        Ok(())
+    }
+
+    /// Reloads the config, a failure is logged and broadcast to [`Config::reload_errors`].
+    pub fn reload_or_report() {
+        if let Err(e) = Self::reload() {
+            let message = format!("{}, keeping the previous config", e);
+            tracing::error!("{}", message);
+            RELOAD_ERRORS.broadcast(message);
+        }
+    }
+
+    /// Reloads failing from now on, with the reason.
+    pub fn reload_errors() -> Subscription<String> {
+        RELOAD_ERRORS.subscribe()
+    }
+
+    /// Writes the config file and reloads it.
+    /// Encrypted api keys are kept as they are in the file, only their `[secrets]` table is written.
+    pub fn save(&self) -> Result<(), ConfigError> {
+        self.validate()?;
+
+        let mut config = self.clone();
+        if secrets::is_encrypted() {
+            config.bithumb = None;
+            config.upbit = None;
+            config.binance = None;
+            config.okx = None;
+        }
+        std::fs::write(CONFIG_PATH, toml::to_string(&config)?)?;
+
+        Self::reload()
+    }
+
+    /// Reloads the config whenever the file is modified.
+    /// Only available on desktop, the web build reloads with the `reload` command.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn watch() {
+        fn modified() -> Option<std::time::SystemTime> {
+            std::fs::metadata(CONFIG_PATH).ok()?.modified().ok()
+        }
+
+        async_helpers::spawn(async move {
+            let mut last = modified();
+            loop {
+                async_helpers::sleep(WATCH_INTERVAL).await;
+
+                let current = modified();
+                if current == last {
+                    continue;
+                }
+                last = current;
+
+                Config::reload_or_report();
+            }
+        });
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct BithumbConfig {
     pub connect_key: String,
     pub secret_key: String,
@@ -26,7 +321,7 @@ pub struct BithumbConfig {
     pub en_name: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct UpbitConfig {
     pub access_key: String,
     pub secret_key: String,
@@ -36,4 +331,266 @@ pub struct UpbitConfig {
 pub struct BinanceConfig {
     pub api_key: String,
     pub secret_key: String,
+    /// How long in milliseconds a signed request stays valid after its timestamp.
+    #[serde(default = "default_recv_window")]
+    pub recv_window: u64,
+}
+
+fn default_recv_window() -> u64 {
+    5000
+}
+
+impl Default for BinanceConfig {
+    fn default() -> Self {
+        Self {
+            api_key: String::new(),
+            secret_key: String::new(),
+            recv_window: default_recv_window(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct OkxConfig {
+    pub api_key: String,
+    pub secret_key: String,
+    pub passphrase: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct OrderbookConfig {
+    /// Levels with at least this amount are highlighted in the cumulative orderbook.
+    pub whale_threshold: Option<Decimal>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AlertConfig {
+    /// How far the price has to move back, as a ratio of the alert price,
+    /// before a fired alert can fire again.
+    pub hysteresis: Decimal,
+}
+
+impl Default for AlertConfig {
+    fn default() -> Self {
+        Self {
+            hysteresis: dec!(0.001),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ConvertConfig {
+    /// Currencies tried as an intermediate step when there is no direct market.
+    pub bridges: Vec<Currency>,
+}
+
+impl Default for ConvertConfig {
+    fn default() -> Self {
+        Self {
+            bridges: vec![Currency::USDT, Currency::KRW, Currency::BTC],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct FxConfig {
+    /// Rates older than this are fetched again, and flagged as stale if that fails.
+    pub ttl_secs: u64,
+    /// Exchange whose KRW markets price USDT and BTC.
+    pub krw_source: KrwSource,
+    /// Api answering the USD/KRW rate as `{"rates": {"KRW": 1350.5}}`.
+    /// The USDT/KRW price of `krw_source` is used as the USD rate if unset.
+    pub usd_krw_url: Option<String>,
+}
+
+impl Default for FxConfig {
+    fn default() -> Self {
+        Self {
+            ttl_secs: 5 * 60,
+            krw_source: KrwSource::Upbit,
+            usd_krw_url: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum KrwSource {
+    Upbit,
+    Bithumb,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub orderbook_ms: u64,
+    pub ticker_ms: u64,
+    /// Balances are also dropped from the cache by every order and withdrawal.
+    pub balance_ms: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            orderbook_ms: 300,
+            ticker_ms: 1000,
+            balance_ms: 2000,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct ActionConfig {
+    /// Console scripts running at once, across all exchanges. At least 1.
+    /// Edits of these limits apply from the next script or order queued.
+    pub max_scripts: usize,
+    /// Orders being placed at once on each exchange. At least 1.
+    pub max_orders: usize,
+    /// Least time between two orders on the same exchange.
+    pub order_interval_ms: u64,
+    /// Scripts or orders waiting beyond this are rejected.
+    pub max_queued: usize,
+    /// Market orders of scripts check the available balance first, which costs a balance request.
+    pub check_market_balance: bool,
+    /// Limit orders of scripts check the available balance first.
+    pub check_limit_balance: bool,
+}
+
+impl ActionConfig {
+    pub fn scripts(&self) -> ThrottleLimits {
+        ThrottleLimits {
+            max_running: self.max_scripts,
+            max_queued: self.max_queued,
+            min_interval: Duration::ZERO,
+        }
+    }
+
+    pub fn orders(&self) -> ThrottleLimits {
+        ThrottleLimits {
+            max_running: self.max_orders,
+            max_queued: self.max_queued,
+            min_interval: Duration::from_millis(self.order_interval_ms),
+        }
+    }
+}
+
+impl Default for ActionConfig {
+    fn default() -> Self {
+        Self {
+            max_scripts: 16,
+            max_orders: 4,
+            order_interval_ms: 100,
+            max_queued: 500,
+            check_market_balance: true,
+            check_limit_balance: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct ConsoleConfig {
+    /// Lines kept by each console, older lines are dropped.
+    pub scrollback_lines: usize,
+    /// Saves the scrollback of each console, restored when the layout is loaded again.
+    pub persist_scrollback: bool,
+    /// Newest lines rendered, older lines are rendered on request this many at a time.
+    /// Read through [`ConsoleConfig::rendered_lines`].
+    pub rendered_lines: usize,
+}
+
+impl ConsoleConfig {
+    /// At least one line, so a console configured with 0 still shows its output.
+    pub fn rendered_lines(&self) -> usize {
+        self.rendered_lines.max(1)
+    }
+}
+
+impl Default for ConsoleConfig {
+    fn default() -> Self {
+        Self {
+            scrollback_lines: 1000,
+            persist_scrollback: true,
+            rendered_lines: 200,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct BacktestConfig {
+    /// Fee of every fill, as a fraction of its value.
+    pub fee_rate: Decimal,
+    /// Market orders fill this fraction worse than the price of the candle.
+    pub slippage: Decimal,
+    /// Quote balance a backtest starts with.
+    pub initial_quote: Decimal,
+}
+
+impl Default for BacktestConfig {
+    fn default() -> Self {
+        Self {
+            fee_rate: dec!(0.0005),
+            slippage: dec!(0.001),
+            initial_quote: dec!(1000000),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Config, ConfigError, KrwSource};
+
+    #[test]
+    fn parse_and_validate() {
+        let config = Config::parse("[upbit]\naccess_key = \"a\"\nsecret_key = \"b\"\n").unwrap();
+        assert!(config.upbit.is_some());
+
+        let empty = Config::parse("[upbit]\naccess_key = \"\"\nsecret_key = \"b\"\n");
+        assert!(matches!(empty, Err(ConfigError::EmptyKey("upbit"))));
+
+        let invalid = Config::parse("[upbit\n");
+        assert!(matches!(invalid, Err(ConfigError::Parse(_))));
+
+        let no_scripts = Config::parse("[actions]\nmax_scripts = 0\n");
+        assert!(matches!(no_scripts, Err(ConfigError::NotPositive(_))));
+
+        let no_refill = Config::parse("[rate_limit.upbit]\ncapacity = 10\nrefill_per_sec = 0.0\n");
+        assert!(matches!(no_refill, Err(ConfigError::NotPositive(_))));
+
+        let no_passphrase =
+            Config::parse("[okx]\napi_key = \"a\"\nsecret_key = \"b\"\npassphrase = \"\"\n");
+        assert!(matches!(no_passphrase, Err(ConfigError::EmptyKey("okx"))));
+
+        let color = Config::parse("[theme]\nbid = \"green; }\"\n");
+        assert!(matches!(color, Err(ConfigError::InvalidColor(_))));
+
+        let bithumb = Config::parse("[fx]\nkrw_source = \"bithumb\"\n").unwrap();
+        assert_eq!(bithumb.fx.krw_source, KrwSource::Bithumb);
+        let unknown = Config::parse("[fx]\nkrw_source = \"binance\"\n");
+        assert!(matches!(unknown, Err(ConfigError::Parse(_))));
+
+        let no_lines = Config::parse("[console]\nrendered_lines = 0\n").unwrap();
+        assert_eq!(no_lines.console.rendered_lines(), 1);
+    }
+
+    #[test]
+    fn empty_keys_disable_their_exchange_at_startup() {
+        let text = "[upbit]\naccess_key = \"\"\nsecret_key = \"b\"\n\
+                    [binance]\napi_key = \"a\"\nsecret_key = \"b\"\n";
+        let config = Config::parse_at_startup(text).unwrap();
+        assert!(config.upbit.is_none());
+        assert!(config.binance.is_some());
+
+        let invalid = Config::parse_at_startup("[upbit\n");
+        assert!(matches!(invalid, Err(ConfigError::Parse(_))));
+
+        // Other errors are not skipped, a proxy typo must not connect directly
+        let proxy = Config::parse_at_startup("[http]\nproxy = \"http://[::1\"\n");
+        assert!(matches!(proxy, Err(ConfigError::InvalidProxy(_))));
+    }
 }
@@ -0,0 +1,288 @@
+//! At-rest encryption of the exchange api keys.
+//!
+//! The exchange sections are sealed with ChaCha20-Poly1305 under a key derived from a passphrase with Argon2.
+//! On desktop the sealed sections replace the plaintext ones in `config.toml` as a `[secrets]` table,
+//! on wasm they live in the local storage.
+//! The passphrase is asked for at startup, the decrypted keys are only kept in memory.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+
+use super::{BinanceConfig, BithumbConfig, Config, OkxConfig, UpbitConfig};
+
+const SALT_LEN: usize = 16;
+
+/// Key of the sealed sections in the local storage, wasm only.
+#[cfg(any(target_arch = "wasm32"))]
+const STORAGE_KEY: &str = "secrets";
+
+/// Key derived from the passphrase, with the salt it was derived with.
+static UNLOCKED: Lazy<RwLock<Option<(Vec<u8>, Key)>>> = Lazy::new(|| RwLock::new(None));
+
+#[derive(Debug, thiserror::Error)]
+pub enum SecretsError {
+    #[error("wrong passphrase")]
+    WrongPassphrase,
+
+    #[error("the api keys are not encrypted")]
+    NotEncrypted,
+
+    #[error("the api keys are already encrypted")]
+    AlreadyEncrypted,
+
+    #[error("there are no api keys to encrypt")]
+    NothingToEncrypt,
+
+    #[error("failed to derive the key: {0}")]
+    Kdf(argon2::Error),
+
+    #[error("invalid secrets: {0}")]
+    Invalid(String),
+
+    #[error("failed to write the config: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// The sealed exchange sections, as stored.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EncryptedSecrets {
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// The exchange sections of the config, the part that is encrypted.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct Secrets {
+    bithumb: Option<BithumbConfig>,
+    upbit: Option<UpbitConfig>,
+    binance: Option<BinanceConfig>,
+    okx: Option<OkxConfig>,
+}
+
+impl Secrets {
+    fn take(config: &mut Config) -> Self {
+        Self {
+            bithumb: config.bithumb.take(),
+            upbit: config.upbit.take(),
+            binance: config.binance.take(),
+            okx: config.okx.take(),
+        }
+    }
+
+    fn apply(self, config: &mut Config) {
+        config.bithumb = self.bithumb;
+        config.upbit = self.upbit;
+        config.binance = self.binance;
+        config.okx = self.okx;
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bithumb.is_none()
+            && self.upbit.is_none()
+            && self.binance.is_none()
+            && self.okx.is_none()
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key, SecretsError> {
+    let mut key = Key::default();
+    argon2::Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(SecretsError::Kdf)?;
+    Ok(key)
+}
+
+fn decode(text: &str) -> Result<Vec<u8>, SecretsError> {
+    STANDARD
+        .decode(text)
+        .map_err(|e| SecretsError::Invalid(e.to_string()))
+}
+
+impl EncryptedSecrets {
+    fn seal(secrets: &Secrets, passphrase: &str) -> Result<(Self, Key), SecretsError> {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let key = derive_key(passphrase, &salt)?;
+
+        let plaintext =
+            toml::to_string(secrets).map_err(|e| SecretsError::Invalid(e.to_string()))?;
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = ChaCha20Poly1305::new(&key)
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| SecretsError::Invalid("encryption failed".to_string()))?;
+
+        let sealed = Self {
+            salt: STANDARD.encode(salt),
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(ciphertext),
+        };
+        Ok((sealed, key))
+    }
+
+    fn open(&self, key: &Key) -> Result<Secrets, SecretsError> {
+        let nonce = decode(&self.nonce)?;
+        if nonce.len() != 12 {
+            return Err(SecretsError::Invalid("invalid nonce".to_string()));
+        }
+
+        // The authentication tag fails to verify with a key from another passphrase
+        let plaintext = ChaCha20Poly1305::new(key)
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                decode(&self.ciphertext)?.as_slice(),
+            )
+            .map_err(|_| SecretsError::WrongPassphrase)?;
+        let plaintext =
+            String::from_utf8(plaintext).map_err(|e| SecretsError::Invalid(e.to_string()))?;
+        toml::from_str(&plaintext).map_err(|e| SecretsError::Invalid(e.to_string()))
+    }
+
+    fn open_with(&self, passphrase: &str) -> Result<(Secrets, Key), SecretsError> {
+        let key = derive_key(passphrase, &decode(&self.salt)?)?;
+        let secrets = self.open(&key)?;
+        Ok((secrets, key))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn load_encrypted(config: &Config) -> Option<EncryptedSecrets> {
+    config.secrets.clone()
+}
+
+#[cfg(any(target_arch = "wasm32"))]
+fn load_encrypted(_config: &Config) -> Option<EncryptedSecrets> {
+    let text = crate::utils::storage::read(STORAGE_KEY)?;
+    serde_json::from_str(&text).ok()
+}
+
+/// Replaces the plaintext exchange sections of `config.toml` with the sealed ones.
+/// The file is edited as a table, so unknown keys are kept.
+#[cfg(not(target_arch = "wasm32"))]
+fn store_encrypted(sealed: &EncryptedSecrets) -> Result<(), SecretsError> {
+    let text = std::fs::read_to_string(super::CONFIG_PATH)?;
+    let mut table: toml::Table =
+        toml::from_str(&text).map_err(|e| SecretsError::Invalid(e.to_string()))?;
+    for section in ["bithumb", "upbit", "binance", "okx"] {
+        table.remove(section);
+    }
+    let sealed = toml::Value::try_from(sealed).map_err(|e| SecretsError::Invalid(e.to_string()))?;
+    table.insert("secrets".to_string(), sealed);
+
+    let text = toml::to_string(&table).map_err(|e| SecretsError::Invalid(e.to_string()))?;
+    std::fs::write(super::CONFIG_PATH, text)?;
+    Ok(())
+}
+
+#[cfg(any(target_arch = "wasm32"))]
+fn store_encrypted(sealed: &EncryptedSecrets) -> Result<(), SecretsError> {
+    let text = serde_json::to_string(sealed).map_err(|e| SecretsError::Invalid(e.to_string()))?;
+    crate::utils::storage::write(STORAGE_KEY, &text);
+    Ok(())
+}
+
+/// Fills the exchange sections of a freshly parsed config from the sealed ones, if unlocked.
+/// While locked or if decryption fails the sections stay empty, which disables trading.
+pub(super) fn apply(config: &mut Config) {
+    let Some(sealed) = load_encrypted(config) else {
+        return;
+    };
+
+    let unlocked = UNLOCKED.read();
+    let Some((salt, key)) = unlocked.as_ref() else {
+        return;
+    };
+    if decode(&sealed.salt).ok().as_ref() != Some(salt) {
+        tracing::error!("The api keys were encrypted with another passphrase, unlock them again");
+        return;
+    }
+
+    match sealed.open(key) {
+        Ok(secrets) => secrets.apply(config),
+        Err(e) => tracing::error!("Failed to decrypt the api keys: {}", e),
+    }
+}
+
+/// Returns true if the api keys are stored encrypted.
+pub fn is_encrypted() -> bool {
+    load_encrypted(&Config::get()).is_some()
+}
+
+/// Returns true if the api keys are encrypted and not unlocked yet.
+pub fn is_locked() -> bool {
+    is_encrypted() && UNLOCKED.read().is_none()
+}
+
+/// Decrypts the api keys with `passphrase` and reloads the config with them.
+pub fn unlock(passphrase: &str) -> Result<(), SecretsError> {
+    let sealed = load_encrypted(&Config::get()).ok_or(SecretsError::NotEncrypted)?;
+    let (_, key) = sealed.open_with(passphrase)?;
+    *UNLOCKED.write() = Some((decode(&sealed.salt)?, key));
+
+    Config::reload_or_report();
+    Ok(())
+}
+
+/// Encrypts the plaintext api keys of the config in place.
+pub fn encrypt(passphrase: &str) -> Result<(), SecretsError> {
+    let mut config = Config::clone(&Config::get());
+    if load_encrypted(&config).is_some() {
+        return Err(SecretsError::AlreadyEncrypted);
+    }
+
+    let secrets = Secrets::take(&mut config);
+    if secrets.is_empty() {
+        return Err(SecretsError::NothingToEncrypt);
+    }
+
+    let (sealed, key) = EncryptedSecrets::seal(&secrets, passphrase)?;
+    store_encrypted(&sealed)?;
+    *UNLOCKED.write() = Some((decode(&sealed.salt)?, key));
+
+    Config::reload_or_report();
+    Ok(())
+}
+
+/// Re-encrypts the api keys under a new passphrase.
+pub fn rotate(current: &str, new: &str) -> Result<(), SecretsError> {
+    let sealed = load_encrypted(&Config::get()).ok_or(SecretsError::NotEncrypted)?;
+    let (secrets, _) = sealed.open_with(current)?;
+
+    let (sealed, key) = EncryptedSecrets::seal(&secrets, new)?;
+    store_encrypted(&sealed)?;
+    *UNLOCKED.write() = Some((decode(&sealed.salt)?, key));
+
+    Config::reload_or_report();
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{EncryptedSecrets, Secrets, SecretsError};
+    use crate::config::UpbitConfig;
+
+    #[test]
+    fn seal_and_open() {
+        let secrets = Secrets {
+            upbit: Some(UpbitConfig {
+                access_key: "access".to_string(),
+                secret_key: "secret".to_string(),
+            }),
+            ..Default::default()
+        };
+
+        let (sealed, key) = EncryptedSecrets::seal(&secrets, "passphrase").unwrap();
+        assert!(!sealed.ciphertext.contains("secret"));
+
+        let opened = sealed.open(&key).unwrap();
+        assert_eq!(opened.upbit.unwrap().secret_key, "secret");
+
+        let wrong = sealed.open_with("wrong");
+        assert!(matches!(wrong, Err(SecretsError::WrongPassphrase)));
+    }
+}
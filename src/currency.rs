@@ -1,79 +1,202 @@
-use serde::{Deserialize, Serialize};
-
-#[derive(
-    Serialize,
-    Deserialize,
-    Debug,
-    Clone,
-    Copy,
-    PartialEq,
-    Eq,
-    Hash,
-    rune::Any,
-    strum::Display,
-    strum::EnumString,
-)]
-pub enum Currency {
-    #[rune(constructor)]
-    KRW,
-    #[rune(constructor)]
-    USDT,
-    #[rune(constructor)]
-    XRP,
-    #[rune(constructor)]
-    BTC,
-    #[rune(constructor)]
-    ARB,
-    #[rune(constructor)]
-    ETH,
-    #[rune(constructor)]
-    APT,
-    #[rune(constructor)]
-    SOL,
-    #[rune(constructor)]
-    SUI,
-    #[rune(constructor)]
-    AERGO,
-    #[rune(constructor)]
-    ATOM,
-    #[rune(constructor)]
-    IQ,
-    #[rune(constructor)]
-    XEM,
-    #[rune(constructor)]
-    QTUM,
-    #[rune(constructor)]
-    TRX,
-    #[rune(constructor)]
-    STRK,
-    #[rune(constructor)]
-    EOS,
-    #[rune(constructor)]
-    PEPE,
-    #[rune(constructor)]
-    DOGE,
-    #[rune(constructor)]
-    NEO,
-    #[rune(constructor)]
-    WLD,
-    #[rune(constructor)]
-    BIOT,
-    #[rune(constructor)]
-    POLA,
-    #[rune(constructor)]
-    BIGTIME,
-    #[rune(constructor)]
-    ONG,
-    #[rune(constructor)]
-    AGI,
-    #[rune(constructor)]
-    ACE,
-    #[rune(constructor)]
-    SHIB,
-    #[rune(constructor)]
-    HBAR,
-    #[rune(constructor)]
-    GLM,
+use std::collections::HashSet;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::str::FromStr;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Symbol of a currency without its own variant, interned so `Currency` stays `Copy`.
+/// Each distinct symbol is leaked once.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(&'static str);
+
+impl Symbol {
+    pub fn intern(symbol: &str) -> Self {
+        static SYMBOLS: Lazy<Mutex<HashSet<&'static str>>> =
+            Lazy::new(|| Mutex::new(HashSet::new()));
+
+        let mut symbols = SYMBOLS.lock();
+        match symbols.get(symbol) {
+            Some(symbol) => Symbol(symbol),
+            None => {
+                let symbol: &'static str = Box::leak(symbol.to_string().into_boxed_str());
+                symbols.insert(symbol);
+                Symbol(symbol)
+            }
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(self.0, f)
+    }
+}
+
+macro_rules! currencies {
+    ($($name:ident),* $(,)?) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, rune::Any)]
+        pub enum Currency {
+            $(
+                #[rune(constructor)]
+                $name,
+            )*
+            /// Any other ticker, e.g. a coin listed after this list was written.
+            Other(Symbol),
+        }
+
+        impl Currency {
+            /// Currencies with their own variant.
+            pub const KNOWN: &'static [Currency] = &[$(Currency::$name,)*];
+
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $(Currency::$name => stringify!($name),)*
+                    Currency::Other(symbol) => symbol.as_str(),
+                }
+            }
+
+            fn known(symbol: &str) -> Option<Self> {
+                match symbol {
+                    $(stringify!($name) => Some(Currency::$name),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+currencies!(
+    KRW, USDT, XRP, BTC, ARB, ETH, APT, SOL, SUI, AERGO, ATOM, IQ, XEM, QTUM, TRX, STRK, EOS, PEPE,
+    DOGE, NEO, WLD, BIOT, POLA, BIGTIME, ONG, AGI, ACE, SHIB, HBAR, GLM, USD,
+);
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+#[error("invalid currency symbol: {0:?}")]
+pub struct ParseCurrencyError(String);
+
+impl FromStr for Currency {
+    type Err = ParseCurrencyError;
+
+    /// Known tickers parse to their variant, any other uppercase alphanumeric ticker to `Other`.
+    fn from_str(symbol: &str) -> Result<Self, Self::Err> {
+        if let Some(currency) = Currency::known(symbol) {
+            return Ok(currency);
+        }
+
+        let valid = !symbol.is_empty()
+            && symbol
+                .chars()
+                .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit());
+        if !valid {
+            return Err(ParseCurrencyError(symbol.to_string()));
+        }
+        Ok(Currency::Other(Symbol::intern(symbol)))
+    }
+}
+
+impl Display for Currency {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for Currency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Currency {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let symbol = String::deserialize(deserializer)?;
+        symbol.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Quote currencies tried when a pair is written without a delimiter and its base is unknown.
+const QUOTES: [Currency; 4] = [Currency::KRW, Currency::USDT, Currency::BTC, Currency::ETH];
+
+/// A pair of `base` and `quote`, displayed as `BASE-QUOTE`.
+/// Exchanges that write the quote first, like upbit, swap it only when stringifying.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CurrencyPair(Currency, Currency);
+
+impl CurrencyPair {
+    pub fn new(base: Currency, quote: Currency) -> Self {
+        Self(base, quote)
+    }
+
+    pub fn base(&self) -> Currency {
+        self.0
+    }
+
+    pub fn quote(&self) -> Currency {
+        self.1
+    }
+
+    /// Splits a pair written without a delimiter, e.g. `BTCKRW`.
+    /// A split into two listed currencies is preferred, otherwise the pair has to end with a common quote.
+    fn split(pair: &str) -> Option<Self> {
+        let splits = (1..pair.len()).filter(|at| pair.is_char_boundary(*at));
+        for at in splits.clone() {
+            let (base, quote) = pair.split_at(at);
+            if let (Some(base), Some(quote)) = (Currency::known(base), Currency::known(quote)) {
+                return Some(Self(base, quote));
+            }
+        }
+
+        splits.rev().find_map(|at| {
+            let (base, quote) = pair.split_at(at);
+            let quote = Currency::known(quote).filter(|quote| QUOTES.contains(quote))?;
+            Some(Self(base.parse().ok()?, quote))
+        })
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+#[error("invalid currency pair: {0:?}")]
+pub struct ParseCurrencyPairError(String);
+
+impl FromStr for CurrencyPair {
+    type Err = ParseCurrencyPairError;
+
+    /// Parses `BTC-KRW`, `BTC/KRW` or `BTCKRW`, in any case.
+    fn from_str(pair: &str) -> Result<Self, Self::Err> {
+        let error = || ParseCurrencyPairError(pair.to_string());
+        let upper = pair.trim().to_uppercase();
+
+        match upper.split_once(['-', '/']) {
+            Some((base, quote)) => Ok(Self(
+                base.parse().map_err(|_| error())?,
+                quote.parse().map_err(|_| error())?,
+            )),
+            None => Self::split(&upper).ok_or_else(error),
+        }
+    }
+}
+
+impl Display for CurrencyPair {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.0, self.1)
+    }
+}
+
+impl From<(Currency, Currency)> for CurrencyPair {
+    fn from((base, quote): (Currency, Currency)) -> Self {
+        Self(base, quote)
+    }
+}
+
+impl From<CurrencyPair> for (Currency, Currency) {
+    fn from(pair: CurrencyPair) -> Self {
+        (pair.0, pair.1)
+    }
 }
 
 pub trait CurrencyPairStringifier {
@@ -83,7 +206,7 @@ pub trait CurrencyPairStringifier {
 pub struct NoDelimiterCurrencyPairStringifier;
 impl CurrencyPairStringifier for NoDelimiterCurrencyPairStringifier {
     fn stringify(c1: Currency, c2: Currency) -> Option<String> {
-        Some(format!("{:?}{:?}", c1, c2))
+        Some(format!("{}{}", c1, c2))
     }
 }
 
@@ -93,6 +216,121 @@ impl<const DELIMITER: char> CurrencyPairStringifier
     for CurrencyPairDelimiterStringifier<DELIMITER>
 {
     fn stringify(c1: Currency, c2: Currency) -> Option<String> {
-        Some(format!("{:?}{}{:?}", c1, DELIMITER, c2))
+        Some(format!("{}{}{}", c1, DELIMITER, c2))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{
+        Currency, CurrencyPair, CurrencyPairDelimiterStringifier, CurrencyPairStringifier,
+        NoDelimiterCurrencyPairStringifier,
+    };
+
+    #[test]
+    fn parse_unknown_symbols() {
+        assert_eq!("BTC".parse(), Ok(Currency::BTC));
+
+        let unknown: Currency = "1INCH".parse().unwrap();
+        assert!(matches!(unknown, Currency::Other(_)));
+        assert_eq!(unknown, "1INCH".parse().unwrap());
+        assert_eq!(unknown.to_string(), "1INCH");
+
+        assert!("".parse::<Currency>().is_err());
+        assert!("btc".parse::<Currency>().is_err());
+        assert!("BTC-KRW".parse::<Currency>().is_err());
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let pair: (Currency, Currency) = ("NEWCOIN".parse().unwrap(), Currency::KRW);
+
+        let json = serde_json::to_string(&pair).unwrap();
+        assert_eq!(json, r#"["NEWCOIN","KRW"]"#);
+        assert_eq!(
+            serde_json::from_str::<(Currency, Currency)>(&json).unwrap(),
+            pair
+        );
+        assert!(serde_json::from_str::<Currency>(r#""new coin""#).is_err());
+
+        let mut prices = HashMap::new();
+        prices.insert(pair, 1);
+        assert_eq!(
+            prices.get(&("NEWCOIN".parse().unwrap(), Currency::KRW)),
+            Some(&1)
+        );
+    }
+
+    #[test]
+    fn stringify_unknown_pairs() {
+        let coin = "NEWCOIN".parse().unwrap();
+
+        assert_eq!(
+            NoDelimiterCurrencyPairStringifier::stringify(coin, Currency::USDT).unwrap(),
+            "NEWCOINUSDT"
+        );
+        assert_eq!(
+            CurrencyPairDelimiterStringifier::<'-'>::stringify(Currency::KRW, coin).unwrap(),
+            "KRW-NEWCOIN"
+        );
+    }
+
+    #[test]
+    fn parse_pairs() {
+        let btc_krw = CurrencyPair::new(Currency::BTC, Currency::KRW);
+        for input in [
+            "BTC-KRW",
+            "btc-krw",
+            "BTC/KRW",
+            "btc/krw",
+            "BTCKRW",
+            "btckrw",
+            " btc-krw ",
+        ] {
+            assert_eq!(input.parse(), Ok(btc_krw), "{}", input);
+        }
+        assert_eq!(btc_krw.base(), Currency::BTC);
+        assert_eq!(btc_krw.quote(), Currency::KRW);
+        assert_eq!(btc_krw.to_string(), "BTC-KRW");
+
+        assert_eq!(
+            "ethbtc".parse(),
+            Ok(CurrencyPair::new(Currency::ETH, Currency::BTC))
+        );
+        assert_eq!(
+            "btcusdt".parse(),
+            Ok(CurrencyPair::new(Currency::BTC, Currency::USDT))
+        );
+        assert_eq!(
+            "1inchusdt".parse(),
+            Ok(CurrencyPair::new("1INCH".parse().unwrap(), Currency::USDT))
+        );
+        assert_eq!(
+            "newcoin-krw".parse(),
+            Ok(CurrencyPair::new("NEWCOIN".parse().unwrap(), Currency::KRW))
+        );
+
+        for input in [
+            "",
+            "btc",
+            "btc-",
+            "-krw",
+            "btc-krw-eth",
+            "btc krw",
+            "newcoinabc",
+        ] {
+            assert!(input.parse::<CurrencyPair>().is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn pair_tuple_conversions() {
+        let pair = CurrencyPair::from((Currency::ETH, Currency::KRW));
+        assert_eq!(pair.base(), Currency::ETH);
+
+        let (base, quote) = pair.into();
+        assert_eq!((base, quote), (Currency::ETH, Currency::KRW));
     }
 }
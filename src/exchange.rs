@@ -1,20 +1,45 @@
+use std::collections::HashMap;
 use std::error::Error as StdError;
+use std::ops::Range;
 use std::sync::Arc;
+use std::time::Duration;
 
+pub mod backtest;
 pub mod binance;
 pub mod bithumb;
+pub mod cache;
+pub mod candle;
+pub mod convert;
+pub mod fx;
+pub mod health;
+pub mod markets;
+pub mod okx;
+pub mod order_watch;
+pub mod replay;
 pub mod upbit;
+pub mod user_stream;
 
 use serde::{Deserialize, Serialize};
 
-use self::{binance::Binance, bithumb::Bithumb, upbit::Upbit};
+use self::{
+    binance::Binance, bithumb::Bithumb, cache::Cached, okx::Okx, upbit::Upbit,
+    user_stream::UserEvent,
+};
 use crate::utils::broadcaster::Subscription;
+use crate::utils::clock::Clock;
+use crate::utils::ledger::{self, LedgerEntry, LedgerEvent};
+use crate::websocket::StatusHandle;
 use crate::{
     currency::Currency,
+    utils::async_helpers,
     utils::maybe_trait::{MaybeSend, MaybeSync},
+    utils::server_time,
     utils::Decimal,
 };
 
+/// How often `wait_order_timeout` polls the order.
+const WAIT_ORDER_INTERVAL: Duration = Duration::from_millis(250);
+
 #[cfg_attr(not(target_arch = "wasm32"), trait_variant::make(Send))]
 pub trait Exchange: MaybeSync {
     const NAME: &'static str;
@@ -27,6 +52,47 @@ pub trait Exchange: MaybeSync {
         market: Option<Market>,
     ) -> Subscription<RealtimeData>;
 
+    /// Status of the connection streaming the realtime data, None if the exchange does not stream.
+    fn connection_status(&self) -> Option<StatusHandle>;
+
+    /// Returns false if `subscribe` never delivers anything, as the exchange has no realtime stream yet.
+    fn streams_realtime(&self) -> bool {
+        true
+    }
+
+    /// Used share of the rate limit, from 0.0 (idle) to 1.0 (exhausted).
+    /// None if the exchange is not rate limited.
+    fn rate_limit_utilization(&self) -> Option<f64> {
+        None
+    }
+
+    /// Server time minus local time in milliseconds, as last measured by `server_time`.
+    /// None until measured or if the exchange has no clock of its own.
+    fn clock_drift(&self) -> Option<i64> {
+        None
+    }
+
+    /// How far in milliseconds the timestamp of a signed request may be off the server clock
+    /// before the request is rejected. None if signed requests carry no timestamp.
+    fn recv_window(&self) -> Option<i64> {
+        None
+    }
+
+    /// Order updates and balance changes of the account, see [`user_stream`].
+    /// None if the exchange has no private stream or no api key is configured.
+    fn user_events(&self) -> Option<Subscription<UserEvent>> {
+        None
+    }
+
+    /// Time the orders of the exchange live in, virtual for a [`backtest`].
+    fn clock(&self) -> Clock {
+        Clock::Real
+    }
+
+    /// Returns every pair the exchange lists as `(base, quote, market)`.
+    /// Fetched at most once a day, see [`markets`].
+    async fn markets(&self) -> Result<Vec<(Currency, Currency, Market)>, Self::Error>;
+
     async fn orderbook(
         &self,
         pair: (Currency, Currency),
@@ -39,12 +105,39 @@ pub trait Exchange: MaybeSync {
         _market: Option<Market>,
     ) -> Result<CandleSticks, Self::Error>;
 
+    /// Returns the last traded price, cheaper than fetching the orderbook for a single price.
+    async fn ticker(
+        &self,
+        pair: (Currency, Currency),
+        market: Option<Market>,
+    ) -> Result<Decimal, Self::Error>;
+
+    /// Returns up to `limit` of the latest public trades, oldest first.
+    /// Used to backfill views before the trade stream delivers anything.
+    async fn recent_trades(
+        &self,
+        pair: (Currency, Currency),
+        market: Option<Market>,
+        limit: usize,
+    ) -> Result<Vec<Trade>, Self::Error>;
+
     async fn balance(
         &self,
         currency: Currency,
         market: Option<Market>,
     ) -> Result<Balance, Self::Error>;
 
+    /// Returns every non-zero balance of the account.
+    /// Assets that are not a known [`Currency`] are left out.
+    async fn balances(
+        &self,
+        market: Option<Market>,
+    ) -> Result<HashMap<Currency, Balance>, Self::Error>;
+
+    /// Returns the smallest order value in the quote currency the exchange accepts, None if unknown.
+    /// Orders below it are rejected with an error before being sent.
+    fn min_notional(&self, pair: (Currency, Currency), market: Option<Market>) -> Option<Decimal>;
+
     async fn bid_limit(
         &self,
         pair: (Currency, Currency),
@@ -75,8 +168,44 @@ pub trait Exchange: MaybeSync {
         market: Option<Market>,
     ) -> Result<OrderToken, Self::Error>;
 
+    /// Places a limit order at `limit_price` that is only submitted once the price reaches `stop_price`.
+    /// Exchanges without native stop orders return an unsupported error.
+    async fn stop_limit(
+        &self,
+        pair: (Currency, Currency),
+        stop_price: Decimal,
+        limit_price: Decimal,
+        amount: Decimal,
+        side: Side,
+        market: Option<Market>,
+    ) -> Result<OrderToken, Self::Error>;
+
     async fn view_order(&self, order_token: &OrderToken) -> Result<Order, Self::Error>;
     async fn wait_order(&self, order_token: &OrderToken) -> Result<Decimal, Self::Error>;
+
+    /// Waits until the order is closed like `wait_order`, but gives up after `timeout`.
+    /// Returns the executed volume, or None if the order is still open and should be cancelled.
+    async fn wait_order_timeout(
+        &self,
+        order_token: &OrderToken,
+        timeout: Duration,
+    ) -> Result<Option<Decimal>, Self::Error> {
+        let deadline = server_time::local_millis() + timeout.as_millis() as i64;
+        loop {
+            let order = self.view_order(order_token).await?;
+            if order.state == OrderState::Closed {
+                return Ok(Some(order.executed_volume));
+            }
+
+            let remaining = deadline - server_time::local_millis();
+            if remaining <= 0 {
+                return Ok(None);
+            }
+            async_helpers::sleep(WAIT_ORDER_INTERVAL.min(Duration::from_millis(remaining as u64)))
+                .await;
+        }
+    }
+
     async fn cancel_order(&self, order_token: &OrderToken) -> Result<Decimal, Self::Error>;
 
     async fn withdraw(
@@ -86,7 +215,22 @@ pub trait Exchange: MaybeSync {
         address1: &str,
         address2: Option<&str>,
         network: Option<&str>,
-    ) -> Result<(), Self::Error>;
+    ) -> Result<String, Self::Error>;
+
+    /// Returns the state of a withdrawal, `id` is the one returned by `withdraw`.
+    async fn withdraw_status(
+        &self,
+        currency: Currency,
+        id: &str,
+    ) -> Result<WithdrawState, Self::Error>;
+
+    /// Returns the fee deducted from a withdrawal of `currency` on `network`.
+    /// If network is None, the default network of the currency is used.
+    async fn withdraw_fee(
+        &self,
+        currency: Currency,
+        network: Option<&str>,
+    ) -> Result<Decimal, Self::Error>;
 
     /// Set leverage for a pair.
     /// If pair is None, set leverage for all pairs.
@@ -95,9 +239,39 @@ pub trait Exchange: MaybeSync {
         _pair: Option<(Currency, Currency)>,
         _value: u64,
     ) -> Result<(), Self::Error>;
+
+    /// Returns the server time in unix milliseconds.
+    /// This also re-measures the clock offset applied to signed requests.
+    async fn server_time(&self) -> Result<i64, Self::Error>;
 }
 
-pub type OrderToken = serde_json::Value;
+/// Identifies an order placed on an exchange.
+/// Each exchange only accepts its own variant and rejects the others.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(tag = "exchange", rename_all = "lowercase")]
+pub enum OrderToken {
+    Upbit {
+        uuid: String,
+    },
+    Binance {
+        id: u64,
+        market: Market,
+        symbol: String,
+    },
+    Bithumb {
+        id: String,
+        order_currency: String,
+        payment_currency: String,
+    },
+    Okx {
+        id: String,
+        inst_id: String,
+    },
+    /// Simulated order, only valid within its backtest.
+    Backtest {
+        id: u64,
+    },
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, Hash, rune::Any)]
 pub enum Market {
@@ -108,11 +282,21 @@ pub enum Market {
     Future,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, rune::Any)]
+pub enum Side {
+    #[rune(constructor)]
+    Bid,
+    #[rune(constructor)]
+    Ask,
+}
+
+/// The exchanges shared by the widgets and scripts, behind one cache each.
 #[derive(Clone)]
 pub struct Exchanges {
-    pub upbit: Arc<Upbit>,
-    pub binance: Arc<Binance>,
-    pub bithumb: Arc<Bithumb>,
+    pub upbit: Arc<Cached<Upbit>>,
+    pub binance: Arc<Cached<Binance>>,
+    pub bithumb: Arc<Cached<Bithumb>>,
+    pub okx: Arc<Cached<Okx>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, rune::Any)]
@@ -134,6 +318,15 @@ pub struct Orderbook {
 }
 
 impl Orderbook {
+    /// Sorts the book, bids descending and asks ascending,
+    /// so `bids[0]` and `asks[0]` are always the best prices.
+    pub fn sorted(mut self) -> Self {
+        self.bids.sort_by(|a, b| b.price.cmp(&a.price));
+        self.asks.sort_by(|a, b| a.price.cmp(&b.price));
+        self
+    }
+
+    /// Strips trailing zeros and sorts the book.
     pub fn normalize(self) -> Self {
         Orderbook {
             pair: self.pair,
@@ -154,6 +347,7 @@ impl Orderbook {
                 })
                 .collect(),
         }
+        .sorted()
     }
 
     pub fn max_amount(&self) -> Decimal {
@@ -173,6 +367,125 @@ impl Orderbook {
             .max()
             .unwrap_or_default()
     }
+
+    /// Returns the bids and asks with running sums of the amounts from the best price outward.
+    pub fn cumulative(&self) -> (Vec<Unit>, Vec<Unit>) {
+        fn running_sum(units: &[Unit]) -> Vec<Unit> {
+            let mut sum = Decimal::ZERO;
+            units
+                .iter()
+                .map(|unit| {
+                    sum += unit.amount;
+                    Unit {
+                        price: unit.price,
+                        amount: sum,
+                    }
+                })
+                .collect()
+        }
+
+        (running_sum(&self.bids), running_sum(&self.asks))
+    }
+
+    /// Quote amount received by selling `base_amount` into the bids.
+    /// Returns None if the bids are not deep enough.
+    pub fn fill_bid(&self, base_amount: Decimal) -> Option<Decimal> {
+        let mut remaining = base_amount;
+        let mut received = Decimal::ZERO;
+        for unit in &self.bids {
+            let taken = remaining.min(unit.amount);
+            received += taken * unit.price;
+            remaining -= taken;
+
+            if remaining == Decimal::ZERO {
+                return Some(received);
+            }
+        }
+
+        None
+    }
+
+    /// Base amount received by spending `quote_amount` on the asks.
+    /// Returns None if the asks are not deep enough.
+    pub fn fill_ask(&self, quote_amount: Decimal) -> Option<Decimal> {
+        let mut remaining = quote_amount;
+        let mut received = Decimal::ZERO;
+        for unit in &self.asks {
+            let cost = unit.amount * unit.price;
+            if remaining <= cost {
+                return Some(received + remaining / unit.price);
+            }
+
+            received += unit.amount;
+            remaining -= cost;
+        }
+
+        None
+    }
+
+    /// Sum of the bid amounts of the `levels`, 0 being the best.
+    /// Levels past the end of the book are left out.
+    pub fn bid_depth(&self, levels: Range<usize>) -> Decimal {
+        depth(&self.bids, levels)
+    }
+
+    /// Sum of the ask amounts of the `levels`, 0 being the best.
+    /// Levels past the end of the book are left out.
+    pub fn ask_depth(&self, levels: Range<usize>) -> Decimal {
+        depth(&self.asks, levels)
+    }
+
+    /// Lowest ask price of a sorted book, None if there are no asks.
+    /// Market bids are sized with it, a thin market can have none.
+    pub fn best_ask(&self) -> Option<Decimal> {
+        self.asks.first().map(|unit| unit.price)
+    }
+
+    /// Highest bid price of a sorted book, None if there are no bids.
+    pub fn best_bid(&self) -> Option<Decimal> {
+        self.bids.first().map(|unit| unit.price)
+    }
+
+    /// Best ask minus best bid, None if a side is empty.
+    pub fn spread(&self) -> Option<Decimal> {
+        Some(self.best_ask()? - self.best_bid()?)
+    }
+
+    /// Smallest price step between adjacent levels of either side of a sorted book,
+    /// None if neither side has two levels.
+    pub fn tick_size(&self) -> Option<Decimal> {
+        self.bids
+            .windows(2)
+            .chain(self.asks.windows(2))
+            .map(|pair| (pair[0].price - pair[1].price).abs())
+            .filter(|step| *step > Decimal::ZERO)
+            .min()
+    }
+
+    /// The spread in ticks, 1 for a book with no gap between the best prices.
+    pub fn spread_ticks(&self) -> Option<Decimal> {
+        Some(self.spread()? / self.tick_size()?)
+    }
+
+    /// Bid volume against ask volume over the best `levels` levels, in `[-1, 1]`.
+    /// Positive values mean the bids outweigh the asks, zero for an empty book.
+    pub fn imbalance(&self, levels: usize) -> Decimal {
+        let bid = self.bid_depth(0..levels);
+        let ask = self.ask_depth(0..levels);
+        if bid + ask == Decimal::ZERO {
+            return Decimal::ZERO;
+        }
+
+        (bid - ask) / (bid + ask)
+    }
+}
+
+fn depth(units: &[Unit], levels: Range<usize>) -> Decimal {
+    units
+        .iter()
+        .skip(levels.start)
+        .take(levels.end.saturating_sub(levels.start))
+        .fold(Decimal::ZERO, |sum, unit| sum + unit.amount)
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, rune::Any)]
@@ -183,6 +496,16 @@ pub struct Balance {
     pub locked: Decimal,
 }
 
+impl Balance {
+    pub fn total(&self) -> Decimal {
+        self.available + self.locked
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total() == Decimal::ZERO
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, rune::Any)]
 pub struct Ticker {
     pub timestamp: u64,
@@ -205,20 +528,45 @@ pub struct Trade {
     pub price: Decimal,
     pub amount: Decimal,
     pub is_bid: bool,
+    /// Id of the trade on the exchange, None if the exchange did not send one
+    /// or the trade was recorded in a replay before ids were kept.
+    #[serde(default)]
+    pub id: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, rune::Any)]
 pub struct Order {
+    #[rune(get)]
     pub state: OrderState,
+    #[rune(get)]
     pub executed_volume: Decimal,
+    /// Quantity not filled yet, as reported by the exchange.
+    #[rune(get)]
+    pub remaining: Decimal,
+    /// Average fill price, None if nothing is filled yet.
+    #[rune(get)]
+    pub avg_price: Option<Decimal>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, rune::Any)]
 pub enum OrderState {
+    #[rune(constructor)]
     Wait,
+    #[rune(constructor)]
     Closed,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, rune::Any)]
+pub enum WithdrawState {
+    /// Requested, but not sent to the network yet.
+    Pending,
+    /// Sent to the network, waiting for confirmations.
+    Processing,
+    Done,
+    /// Failed, cancelled or rejected.
+    Failed,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, rune::Any)]
 pub enum RealtimeData {
     Orderbook(#[rune(get)] Orderbook),
@@ -236,11 +584,226 @@ where
     None
 }
 
+/// Withdraws `amount` plus the network fee, so the recipient receives exactly `amount`.
+pub async fn withdraw_net<E>(
+    exchange: &E,
+    currency: Currency,
+    amount: Decimal,
+    address1: &str,
+    address2: Option<&str>,
+    network: Option<&str>,
+) -> Result<String, E::Error>
+where
+    E: Exchange,
+{
+    let fee = exchange.withdraw_fee(currency, network).await?;
+    let result = exchange
+        .withdraw(currency, amount + fee, address1, address2, network)
+        .await;
+
+    ledger::record(&LedgerEntry::now(
+        E::NAME,
+        LedgerEvent::Withdraw {
+            currency,
+            amount: amount + fee,
+            address: address1.to_string(),
+            network: network.map(str::to_string),
+            result: match &result {
+                Ok(id) => id.clone(),
+                Err(e) => e.to_string(),
+            },
+        },
+    ));
+    result
+}
+
+/// Polls the withdrawal until it is done or failed, None if it is neither within `timeout`.
+pub async fn wait_withdraw<E>(
+    exchange: &E,
+    currency: Currency,
+    id: &str,
+    timeout: Duration,
+) -> Result<Option<WithdrawState>, E::Error>
+where
+    E: Exchange,
+{
+    let deadline = server_time::local_millis()
+        .saturating_add(i64::try_from(timeout.as_millis()).unwrap_or(i64::MAX));
+    loop {
+        let state = exchange.withdraw_status(currency, id).await?;
+        if matches!(state, WithdrawState::Done | WithdrawState::Failed) {
+            return Ok(Some(state));
+        }
+
+        let remaining = deadline - server_time::local_millis();
+        if remaining <= 0 {
+            return Ok(None);
+        }
+        let poll = Duration::from_secs(10).min(Duration::from_millis(remaining as u64));
+        async_helpers::sleep(poll).await;
+    }
+}
+
 #[macro_export]
 macro_rules! select_ex {
     ($ex:expr, $name:expr, $f:expr) => {
         execute_if($name.as_str(), $ex.upbit.clone(), $f)
             .or_else(|| execute_if($name.as_str(), $ex.binance.clone(), $f))
             .or_else(|| execute_if($name.as_str(), $ex.bithumb.clone(), $f))
+            .or_else(|| execute_if($name.as_str(), $ex.okx.clone(), $f))
     };
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Orderbook, Unit};
+    use crate::currency::Currency;
+    use crate::utils::Decimal;
+
+    fn unit(price: i64, amount: i64) -> Unit {
+        Unit {
+            price: Decimal(price.into()),
+            amount: Decimal(amount.into()),
+        }
+    }
+
+    #[test]
+    fn sorted_shuffled_book() {
+        let orderbook = Orderbook {
+            pair: (Currency::BTC, Currency::KRW),
+            bids: vec![unit(98, 2), unit(100, 1), unit(96, 4), unit(99, 3)],
+            asks: vec![unit(103, 1), unit(101, 2), unit(104, 3), unit(102, 4)],
+        }
+        .sorted();
+
+        assert_eq!(orderbook.bids[0], unit(100, 1));
+        assert_eq!(orderbook.asks[0], unit(101, 2));
+        assert!(orderbook.bids.windows(2).all(|w| w[0].price > w[1].price));
+        assert!(orderbook.asks.windows(2).all(|w| w[0].price < w[1].price));
+    }
+
+    #[test]
+    fn normalize_sorts_best_first() {
+        let orderbook = Orderbook {
+            pair: (Currency::BTC, Currency::KRW),
+            bids: vec![unit(97, 3), unit(99, 1), unit(98, 2)],
+            asks: vec![unit(102, 5), unit(101, 4)],
+        }
+        .normalize();
+
+        assert_eq!(orderbook.bids, vec![unit(99, 1), unit(98, 2), unit(97, 3)]);
+        assert_eq!(orderbook.asks, vec![unit(101, 4), unit(102, 5)]);
+
+        let trailing = Orderbook {
+            pair: (Currency::BTC, Currency::KRW),
+            bids: vec![Unit {
+                price: Decimal::from_str("50000.00").unwrap(),
+                amount: Decimal::from_str("1.500").unwrap(),
+            }],
+            asks: vec![],
+        }
+        .normalize();
+        assert_eq!(trailing.bids[0].price.to_string(), "50000");
+        assert_eq!(trailing.bids[0].amount.to_string(), "1.5");
+    }
+
+    #[test]
+    fn cumulative() {
+        let orderbook = Orderbook {
+            pair: (Currency::BTC, Currency::KRW),
+            bids: vec![unit(99, 1), unit(98, 2), unit(97, 3)],
+            asks: vec![unit(101, 4), unit(102, 5)],
+        };
+
+        let (bids, asks) = orderbook.cumulative();
+        assert_eq!(bids, vec![unit(99, 1), unit(98, 3), unit(97, 6)]);
+        assert_eq!(asks, vec![unit(101, 4), unit(102, 9)]);
+    }
+
+    #[test]
+    fn fill() {
+        let orderbook = Orderbook {
+            pair: (Currency::BTC, Currency::KRW),
+            bids: vec![unit(100, 1), unit(90, 2)],
+            asks: vec![unit(110, 1), unit(120, 2)],
+        };
+
+        assert_eq!(
+            orderbook.fill_bid(Decimal(2.into())),
+            Some(Decimal(190.into()))
+        );
+        assert_eq!(orderbook.fill_bid(Decimal(4.into())), None);
+
+        assert_eq!(
+            orderbook.fill_ask(Decimal(350.into())),
+            Some(Decimal(3.into()))
+        );
+        assert_eq!(orderbook.fill_ask(Decimal(351.into())), None);
+    }
+
+    #[test]
+    fn imbalance_symmetric() {
+        let orderbook = Orderbook {
+            pair: (Currency::BTC, Currency::KRW),
+            bids: vec![unit(99, 2), unit(98, 3)],
+            asks: vec![unit(101, 2), unit(102, 3)],
+        };
+
+        assert_eq!(orderbook.imbalance(2), Decimal::ZERO);
+        assert_eq!(orderbook.imbalance(0), Decimal::ZERO);
+    }
+
+    #[test]
+    fn imbalance_skewed() {
+        let orderbook = Orderbook {
+            pair: (Currency::BTC, Currency::KRW),
+            bids: vec![unit(99, 3), unit(98, 100)],
+            asks: vec![unit(101, 1)],
+        };
+
+        // Only the best level of the bids is counted: (3 - 1) / (3 + 1)
+        assert_eq!(
+            orderbook.imbalance(1),
+            Decimal(1.into()) / Decimal(2.into())
+        );
+        assert_eq!(
+            orderbook.imbalance(10),
+            Decimal(102.into()) / Decimal(104.into())
+        );
+
+        let empty_asks = Orderbook {
+            asks: Vec::new(),
+            ..orderbook
+        };
+        assert_eq!(empty_asks.imbalance(1), Decimal::ONE);
+    }
+
+    #[test]
+    fn depth_and_ticks() {
+        let orderbook = Orderbook {
+            pair: (Currency::BTC, Currency::KRW),
+            bids: vec![unit(96, 1), unit(94, 2), unit(92, 3)],
+            asks: vec![unit(100, 4), unit(101, 5)],
+        };
+
+        assert_eq!(orderbook.bid_depth(1..3), Decimal(5.into()));
+        // Books shallower than the levels asked for are summed as far as they go
+        assert_eq!(orderbook.ask_depth(1..5), Decimal(5.into()));
+        assert_eq!(orderbook.ask_depth(4..5), Decimal::ZERO);
+        assert_eq!(orderbook.best_ask(), Some(Decimal(100.into())));
+        assert_eq!(orderbook.spread(), Some(Decimal(4.into())));
+        assert_eq!(orderbook.tick_size(), Some(Decimal::ONE));
+        assert_eq!(orderbook.spread_ticks(), Some(Decimal(4.into())));
+
+        let thin = Orderbook {
+            pair: (Currency::BTC, Currency::KRW),
+            bids: vec![unit(96, 1)],
+            asks: vec![],
+        };
+        assert_eq!(thin.best_ask(), None);
+        assert_eq!(thin.spread(), None);
+        assert_eq!(thin.tick_size(), None);
+        assert_eq!(thin.spread_ticks(), None);
+        assert_eq!(thin.bid_depth(0..5), Decimal::ONE);
+    }
+}
@@ -0,0 +1,957 @@
+//! An exchange simulated over historical candles, so strategy scripts can be tried on past prices.
+//! Its clock is virtual: time only passes when the script sleeps or waits for an order,
+//! and it stands at the close of a candle, so a script never sees a later price.
+//!
+//! A script reading prices without ever sleeping is moved to the next candle
+//! after [`READS_PER_CANDLE`] reads, so it can't spin on one candle forever.
+//!
+//! Market orders fill right away at the close of the current candle, worse by the slippage.
+//! Limit orders fill on a later candle trading through their price,
+//! at the open of that candle if it already opened past the price.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+
+use num_traits::ToPrimitive;
+use parking_lot::{Mutex, MutexGuard};
+
+use crate::config::BacktestConfig;
+use crate::currency::{Currency, CurrencyPair};
+use crate::utils::broadcaster::{Broadcaster, Subscription};
+use crate::utils::clock::Clock;
+use crate::utils::format::format_percent;
+use crate::utils::rounding::round_down_dp;
+use crate::utils::Decimal;
+use crate::websocket::StatusHandle;
+
+use super::{
+    Balance, CandleSticks, Exchange, Market, Order, OrderState, OrderToken, Orderbook,
+    RealtimeData, Side, Ticker, Trade, WithdrawState,
+};
+
+/// Decimal places of the quantities bought at market, so the cost never exceeds the funds.
+const QTY_DP: u32 = 8;
+
+/// Requests answered from one candle before the clock moves on by itself.
+pub const READS_PER_CANDLE: u32 = 1000;
+
+#[derive(thiserror::Error, Debug)]
+pub enum BacktestError {
+    #[error("no candles between the start and the end")]
+    NoCandles,
+
+    #[error("the backtest reached the end of its data")]
+    Ended,
+
+    #[error("only {0} is traded in this backtest")]
+    OtherPair(CurrencyPair),
+
+    #[error("insufficient {currency}: {needed} needed, {available} available")]
+    InsufficientBalance {
+        currency: Currency,
+        needed: Decimal,
+        available: Decimal,
+    },
+
+    #[error("invalid order token")]
+    InvalidOrderToken,
+
+    #[error("{0} is not supported")]
+    Unsupported(&'static str),
+}
+
+/// A simulated fill.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fill {
+    pub timestamp: u64,
+    pub side: Side,
+    pub price: Decimal,
+    pub qty: Decimal,
+    /// In the quote currency.
+    pub fee: Decimal,
+}
+
+struct SimOrder {
+    side: Side,
+    /// Limit price, None for market orders.
+    price: Option<Decimal>,
+    qty: Decimal,
+    /// Quote of a bid or base of an ask locked until the order closes.
+    reserved: Decimal,
+    fill: Option<Fill>,
+    closed: bool,
+}
+
+struct State {
+    /// Index of the candle the clock stands at.
+    cursor: usize,
+    /// Requests answered from the current candle.
+    reads: u32,
+    base: Balance,
+    quote: Balance,
+    orders: Vec<SimOrder>,
+    fills: Vec<Fill>,
+    /// Value of the account in the quote currency at the close of every candle passed.
+    equity: Vec<(u64, Decimal)>,
+}
+
+impl State {
+    fn equity(&self, price: Decimal) -> Decimal {
+        self.quote.available + self.quote.locked + (self.base.available + self.base.locked) * price
+    }
+
+    /// Locks the funds of a new order, fails if they are not available.
+    fn reserve(
+        &mut self,
+        pair: (Currency, Currency),
+        side: Side,
+        amount: Decimal,
+    ) -> Result<(), BacktestError> {
+        let (currency, balance) = match side {
+            Side::Bid => (pair.1, &mut self.quote),
+            Side::Ask => (pair.0, &mut self.base),
+        };
+        if amount > balance.available {
+            return Err(BacktestError::InsufficientBalance {
+                currency,
+                needed: amount,
+                available: balance.available,
+            });
+        }
+        balance.available -= amount;
+        balance.locked += amount;
+        Ok(())
+    }
+
+    /// Fills the whole order at `price`, the unused part of the reserved funds is returned.
+    fn fill(&mut self, id: usize, timestamp: u64, price: Decimal, fee_rate: Decimal) {
+        let order = &mut self.orders[id];
+        let value = price * order.qty;
+        let fee = value * fee_rate;
+        match order.side {
+            Side::Bid => {
+                self.quote.locked -= order.reserved;
+                self.quote.available += order.reserved - value - fee;
+                self.base.available += order.qty;
+            }
+            Side::Ask => {
+                self.base.locked -= order.reserved;
+                self.base.available += order.reserved - order.qty;
+                self.quote.available += value - fee;
+            }
+        }
+
+        let fill = Fill {
+            timestamp,
+            side: order.side,
+            price,
+            qty: order.qty,
+            fee,
+        };
+        order.fill = Some(fill.clone());
+        order.closed = true;
+        self.fills.push(fill);
+    }
+
+    /// Fills the open limit orders `candle` trades through.
+    fn fill_limits(&mut self, candle: &Ticker, fee_rate: Decimal) {
+        for id in 0..self.orders.len() {
+            let order = &self.orders[id];
+            let Some(limit) = order.price.filter(|_| !order.closed) else {
+                continue;
+            };
+            let price = match order.side {
+                Side::Bid if candle.low <= limit => candle.open.min(limit),
+                Side::Ask if candle.high >= limit => candle.open.max(limit),
+                _ => continue,
+            };
+            self.fill(id, candle.timestamp, price, fee_rate);
+        }
+    }
+}
+
+/// Trades one pair over its candles, with the configured fees, slippage and starting balance.
+pub struct Backtest {
+    pair: (Currency, Currency),
+    candles: Vec<Ticker>,
+    config: BacktestConfig,
+    clock: Clock,
+    state: Mutex<State>,
+    broadcaster: Broadcaster<RealtimeData>,
+}
+
+impl Backtest {
+    /// Runs from the first candle to the last, which are sorted by time.
+    pub fn new(
+        pair: (Currency, Currency),
+        mut candles: Vec<Ticker>,
+        config: BacktestConfig,
+    ) -> Result<Self, BacktestError> {
+        candles.sort_by_key(|candle| candle.timestamp);
+        let (Some(first), Some(last)) = (candles.first(), candles.last()) else {
+            return Err(BacktestError::NoCandles);
+        };
+        let (start, end) = (first.timestamp, last.timestamp);
+
+        let state = State {
+            cursor: 0,
+            reads: 0,
+            base: Balance {
+                available: Decimal::ZERO,
+                locked: Decimal::ZERO,
+            },
+            quote: Balance {
+                available: config.initial_quote,
+                locked: Decimal::ZERO,
+            },
+            orders: Vec::new(),
+            fills: Vec::new(),
+            equity: vec![(start, config.initial_quote)],
+        };
+        Ok(Self {
+            pair,
+            clock: Clock::virtual_between(start as i64, end as i64),
+            candles,
+            config,
+            state: Mutex::new(state),
+            broadcaster: Broadcaster::new(),
+        })
+    }
+
+    /// The fills and the equity curve so far.
+    pub fn report(&self) -> BacktestReport {
+        let state = self.advance();
+        BacktestReport {
+            pair: self.pair,
+            fills: state.fills.clone(),
+            equity: state.equity.clone(),
+        }
+    }
+
+    /// Moves to the candle of the current time, filling the limit orders of the candles passed.
+    fn advance(&self) -> MutexGuard<'_, State> {
+        let now = self.clock.now_millis();
+        let mut state = self.state.lock();
+        while let Some(candle) = self
+            .candles
+            .get(state.cursor + 1)
+            .filter(|candle| candle.timestamp as i64 <= now)
+        {
+            state.cursor += 1;
+            state.reads = 0;
+            state.fill_limits(candle, self.config.fee_rate);
+            let equity = state.equity(candle.close);
+            state.equity.push((candle.timestamp, equity));
+        }
+        state
+    }
+
+    /// Advances for a request of the script, moving the clock to the next candle
+    /// if the script keeps reading the current one without sleeping.
+    /// At the last candle the clock moves past the end of the data.
+    fn read(&self) -> MutexGuard<'_, State> {
+        let mut state = self.advance();
+        state.reads += 1;
+        if state.reads <= READS_PER_CANDLE {
+            return state;
+        }
+
+        let wait = self
+            .until_next_candle(state.cursor)
+            .unwrap_or(Duration::from_millis(1));
+        drop(state);
+        self.clock.skip(wait);
+        self.advance()
+    }
+
+    /// Fails once the data ended, or for a pair other than the traded one.
+    fn check(&self, pair: (Currency, Currency)) -> Result<(), BacktestError> {
+        if self.clock.ended() {
+            return Err(BacktestError::Ended);
+        }
+        if pair != self.pair {
+            return Err(BacktestError::OtherPair(pair.into()));
+        }
+        Ok(())
+    }
+
+    /// Time until the candle after the current one, None at the last candle.
+    fn until_next_candle(&self, cursor: usize) -> Option<Duration> {
+        let next = self.candles.get(cursor + 1)?.timestamp as i64;
+        Some(Duration::from_millis(
+            (next - self.clock.now_millis()).max(1) as u64,
+        ))
+    }
+
+    fn place(
+        &self,
+        pair: (Currency, Currency),
+        side: Side,
+        price: Option<Decimal>,
+        amount: Decimal,
+    ) -> Result<OrderToken, BacktestError> {
+        self.check(pair)?;
+        let mut state = self.advance();
+        let close = self.candles[state.cursor].close;
+        let fee_rate = self.config.fee_rate;
+
+        // Market bids spend `amount` of the quote, everything else trades `amount` of the base
+        let (qty, reserved, fill_price) = match (side, price) {
+            (Side::Bid, Some(price)) => (amount, price * amount * (Decimal::ONE + fee_rate), None),
+            (Side::Ask, Some(_)) => (amount, amount, None),
+            (Side::Bid, None) => {
+                let price = close * (Decimal::ONE + self.config.slippage);
+                let qty = round_down_dp(amount / (price * (Decimal::ONE + fee_rate)), QTY_DP);
+                (qty, amount, Some(price))
+            }
+            (Side::Ask, None) => (
+                amount,
+                amount,
+                Some(close * (Decimal::ONE - self.config.slippage)),
+            ),
+        };
+        state.reserve(pair, side, reserved)?;
+
+        let id = state.orders.len();
+        state.orders.push(SimOrder {
+            side,
+            price,
+            qty,
+            reserved,
+            fill: None,
+            closed: false,
+        });
+        if let Some(fill_price) = fill_price {
+            let timestamp = self.candles[state.cursor].timestamp;
+            state.fill(id, timestamp, fill_price, fee_rate);
+        }
+        Ok(OrderToken::Backtest { id: id as u64 })
+    }
+
+    fn order(&self, state: &State, order_token: &OrderToken) -> Result<Order, BacktestError> {
+        let OrderToken::Backtest { id } = order_token else {
+            return Err(BacktestError::InvalidOrderToken);
+        };
+        let order = state
+            .orders
+            .get(*id as usize)
+            .ok_or(BacktestError::InvalidOrderToken)?;
+
+        let executed_volume = order.fill.as_ref().map_or(Decimal::ZERO, |fill| fill.qty);
+        Ok(Order {
+            state: if order.closed {
+                OrderState::Closed
+            } else {
+                OrderState::Wait
+            },
+            executed_volume,
+            remaining: if order.closed {
+                Decimal::ZERO
+            } else {
+                order.qty
+            },
+            avg_price: order.fill.as_ref().map(|fill| fill.price),
+        })
+    }
+}
+
+impl Exchange for Backtest {
+    const NAME: &'static str = "backtest";
+
+    type Error = BacktestError;
+
+    /// Nothing is streamed, scripts poll the prices of the current candle.
+    fn subscribe(
+        &self,
+        _pair: (Currency, Currency),
+        _market: Option<Market>,
+    ) -> Subscription<RealtimeData> {
+        self.broadcaster.subscribe()
+    }
+
+    fn connection_status(&self) -> Option<StatusHandle> {
+        None
+    }
+
+    fn clock(&self) -> Clock {
+        self.clock.clone()
+    }
+
+    async fn markets(&self) -> Result<Vec<(Currency, Currency, Market)>, Self::Error> {
+        Ok(vec![(self.pair.0, self.pair.1, Market::Spot)])
+    }
+
+    async fn orderbook(
+        &self,
+        _pair: (Currency, Currency),
+        _market: Option<Market>,
+    ) -> Result<Orderbook, Self::Error> {
+        Err(BacktestError::Unsupported("orderbook"))
+    }
+
+    /// The candles up to the current one.
+    async fn candlesticks(
+        &self,
+        pair: (Currency, Currency),
+        _market: Option<Market>,
+    ) -> Result<CandleSticks, Self::Error> {
+        self.check(pair)?;
+        let state = self.read();
+        Ok(CandleSticks {
+            pair,
+            tickers: self.candles[..=state.cursor].to_vec(),
+        })
+    }
+
+    /// The close of the current candle.
+    async fn ticker(
+        &self,
+        pair: (Currency, Currency),
+        _market: Option<Market>,
+    ) -> Result<Decimal, Self::Error> {
+        self.check(pair)?;
+        let state = self.read();
+        Ok(self.candles[state.cursor].close)
+    }
+
+    async fn recent_trades(
+        &self,
+        _pair: (Currency, Currency),
+        _market: Option<Market>,
+        _limit: usize,
+    ) -> Result<Vec<Trade>, Self::Error> {
+        Err(BacktestError::Unsupported("trades"))
+    }
+
+    async fn balance(
+        &self,
+        currency: Currency,
+        _market: Option<Market>,
+    ) -> Result<Balance, Self::Error> {
+        let state = self.read();
+        Ok(if currency == self.pair.0 {
+            state.base.clone()
+        } else if currency == self.pair.1 {
+            state.quote.clone()
+        } else {
+            Balance {
+                available: Decimal::ZERO,
+                locked: Decimal::ZERO,
+            }
+        })
+    }
+
+    async fn balances(
+        &self,
+        _market: Option<Market>,
+    ) -> Result<HashMap<Currency, Balance>, Self::Error> {
+        let state = self.read();
+        Ok(HashMap::from([
+            (self.pair.0, state.base.clone()),
+            (self.pair.1, state.quote.clone()),
+        ]))
+    }
+
+    fn min_notional(
+        &self,
+        _pair: (Currency, Currency),
+        _market: Option<Market>,
+    ) -> Option<Decimal> {
+        None
+    }
+
+    async fn bid_limit(
+        &self,
+        pair: (Currency, Currency),
+        price: Decimal,
+        amount: Decimal,
+        _market: Option<Market>,
+    ) -> Result<OrderToken, Self::Error> {
+        self.place(pair, Side::Bid, Some(price), amount)
+    }
+
+    async fn bid_market(
+        &self,
+        pair: (Currency, Currency),
+        quote_qty: Decimal,
+        _market: Option<Market>,
+    ) -> Result<OrderToken, Self::Error> {
+        self.place(pair, Side::Bid, None, quote_qty)
+    }
+
+    async fn ask_limit(
+        &self,
+        pair: (Currency, Currency),
+        price: Decimal,
+        amount: Decimal,
+        _market: Option<Market>,
+    ) -> Result<OrderToken, Self::Error> {
+        self.place(pair, Side::Ask, Some(price), amount)
+    }
+
+    async fn ask_market(
+        &self,
+        pair: (Currency, Currency),
+        base_qty: Decimal,
+        _market: Option<Market>,
+    ) -> Result<OrderToken, Self::Error> {
+        self.place(pair, Side::Ask, None, base_qty)
+    }
+
+    async fn stop_limit(
+        &self,
+        _pair: (Currency, Currency),
+        _stop_price: Decimal,
+        _limit_price: Decimal,
+        _amount: Decimal,
+        _side: Side,
+        _market: Option<Market>,
+    ) -> Result<OrderToken, Self::Error> {
+        Err(BacktestError::Unsupported("stop order"))
+    }
+
+    async fn view_order(&self, order_token: &OrderToken) -> Result<Order, Self::Error> {
+        let state = self.read();
+        self.order(&state, order_token)
+    }
+
+    /// Moves the clock candle by candle until the order closes, fails once the data ends.
+    async fn wait_order(&self, order_token: &OrderToken) -> Result<Decimal, Self::Error> {
+        loop {
+            let next = {
+                let state = self.advance();
+                let order = self.order(&state, order_token)?;
+                if order.state == OrderState::Closed {
+                    return Ok(order.executed_volume);
+                }
+                self.until_next_candle(state.cursor)
+            };
+            match next {
+                Some(next) => self.clock.sleep(next).await,
+                None => return Err(BacktestError::Ended),
+            }
+        }
+    }
+
+    async fn wait_order_timeout(
+        &self,
+        order_token: &OrderToken,
+        timeout: Duration,
+    ) -> Result<Option<Decimal>, Self::Error> {
+        let deadline = self.clock.now_millis() + timeout.as_millis() as i64;
+        loop {
+            let next = {
+                let state = self.advance();
+                let order = self.order(&state, order_token)?;
+                if order.state == OrderState::Closed {
+                    return Ok(Some(order.executed_volume));
+                }
+                self.until_next_candle(state.cursor)
+            };
+
+            let remaining = deadline - self.clock.now_millis();
+            if remaining <= 0 {
+                return Ok(None);
+            }
+            let remaining = Duration::from_millis(remaining as u64);
+            self.clock
+                .sleep(next.map_or(remaining, |next| next.min(remaining)))
+                .await;
+        }
+    }
+
+    /// Returns the locked funds, the executed volume is zero as orders fill at once.
+    async fn cancel_order(&self, order_token: &OrderToken) -> Result<Decimal, Self::Error> {
+        let mut state = self.advance();
+        let executed_volume = self.order(&state, order_token)?.executed_volume;
+        let OrderToken::Backtest { id } = order_token else {
+            return Err(BacktestError::InvalidOrderToken);
+        };
+
+        let order = &mut state.orders[*id as usize];
+        if !order.closed {
+            order.closed = true;
+            let (side, reserved) = (order.side, order.reserved);
+            let balance = match side {
+                Side::Bid => &mut state.quote,
+                Side::Ask => &mut state.base,
+            };
+            balance.locked -= reserved;
+            balance.available += reserved;
+        }
+        Ok(executed_volume)
+    }
+
+    async fn withdraw(
+        &self,
+        _currency: Currency,
+        _amount: Decimal,
+        _address1: &str,
+        _address2: Option<&str>,
+        _network: Option<&str>,
+    ) -> Result<String, Self::Error> {
+        Err(BacktestError::Unsupported("withdraw"))
+    }
+
+    async fn withdraw_status(
+        &self,
+        _currency: Currency,
+        _id: &str,
+    ) -> Result<WithdrawState, Self::Error> {
+        Err(BacktestError::Unsupported("withdraw"))
+    }
+
+    async fn withdraw_fee(
+        &self,
+        _currency: Currency,
+        _network: Option<&str>,
+    ) -> Result<Decimal, Self::Error> {
+        Err(BacktestError::Unsupported("withdraw"))
+    }
+
+    async fn set_leverage(
+        &self,
+        _pair: Option<(Currency, Currency)>,
+        _value: u64,
+    ) -> Result<(), Self::Error> {
+        Err(BacktestError::Unsupported("leverage"))
+    }
+
+    async fn server_time(&self) -> Result<i64, Self::Error> {
+        Ok(self.clock.now_millis())
+    }
+}
+
+/// Fills and equity of a backtest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BacktestReport {
+    pub pair: (Currency, Currency),
+    pub fills: Vec<Fill>,
+    /// Value of the account in the quote currency at the close of every candle, oldest first.
+    pub equity: Vec<(u64, Decimal)>,
+}
+
+impl BacktestReport {
+    /// Final equity minus the starting equity.
+    pub fn pnl(&self) -> Decimal {
+        match (self.equity.first(), self.equity.last()) {
+            (Some((_, first)), Some((_, last))) => *last - *first,
+            _ => Decimal::ZERO,
+        }
+    }
+
+    /// Profit of each ask over the average cost of the position, fees included.
+    /// None for the bids.
+    pub fn realized(&self) -> Vec<Option<Decimal>> {
+        let mut position = Decimal::ZERO;
+        let mut cost = Decimal::ZERO;
+        self.fills
+            .iter()
+            .map(|fill| match fill.side {
+                Side::Bid => {
+                    position += fill.qty;
+                    cost += fill.price * fill.qty + fill.fee;
+                    None
+                }
+                Side::Ask => {
+                    let average = if position > Decimal::ZERO {
+                        cost / position
+                    } else {
+                        Decimal::ZERO
+                    };
+                    let sold_cost = average * fill.qty.min(position);
+                    cost -= sold_cost;
+                    position -= fill.qty.min(position);
+                    Some(fill.price * fill.qty - fill.fee - sold_cost)
+                }
+            })
+            .collect()
+    }
+
+    /// Share of the asks that made a profit, None without asks.
+    pub fn win_rate(&self) -> Option<Decimal> {
+        let realized = self.realized().into_iter().flatten().collect::<Vec<_>>();
+        if realized.is_empty() {
+            return None;
+        }
+        let wins = realized.iter().filter(|pnl| **pnl > Decimal::ZERO).count();
+        Some(Decimal(wins.into()) / Decimal(realized.len().into()))
+    }
+
+    /// The pnl as a fraction of the starting equity.
+    pub fn total_return(&self) -> Option<Decimal> {
+        let (_, start) = self.equity.first()?;
+        (*start > Decimal::ZERO).then(|| self.pnl() / *start)
+    }
+
+    /// Mean over the standard deviation of the returns from one candle to the next, not annualized.
+    /// None with fewer than two returns, or returns that never vary.
+    pub fn sharpe(&self) -> Option<f64> {
+        let returns = self
+            .equity
+            .windows(2)
+            .filter_map(|window| {
+                let previous = window[0].1 .0.to_f64()?;
+                let next = window[1].1 .0.to_f64()?;
+                (previous > 0.0).then(|| next / previous - 1.0)
+            })
+            .collect::<Vec<_>>();
+        if returns.len() < 2 {
+            return None;
+        }
+
+        let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+        let variance = returns
+            .iter()
+            .map(|value| (value - mean).powi(2))
+            .sum::<f64>()
+            / (returns.len() - 1) as f64;
+        let deviation = variance.sqrt();
+        (deviation > 0.0).then(|| mean / deviation)
+    }
+
+    /// Average fee of the fills in the quote currency, None without fills.
+    pub fn average_fee(&self) -> Option<Decimal> {
+        if self.fills.is_empty() {
+            return None;
+        }
+        let total = self
+            .fills
+            .iter()
+            .fold(Decimal::ZERO, |total, fill| total + fill.fee);
+        Some(total / Decimal(self.fills.len().into()))
+    }
+
+    /// Largest fall of the equity from an earlier peak, as a fraction of the peak.
+    pub fn max_drawdown(&self) -> Decimal {
+        let mut peak = Decimal::ZERO;
+        let mut drawdown = Decimal::ZERO;
+        for (_, equity) in &self.equity {
+            peak = peak.max(*equity);
+            if peak > Decimal::ZERO {
+                drawdown = drawdown.max((peak - *equity) / peak);
+            }
+        }
+        drawdown
+    }
+
+    /// The fills as csv with a header row, with the profit of each ask.
+    pub fn to_csv(&self) -> String {
+        let mut csv = "timestamp,side,price,qty,fee,pnl\n".to_string();
+        for (fill, realized) in self.fills.iter().zip(self.realized()) {
+            let side = match fill.side {
+                Side::Bid => "bid",
+                Side::Ask => "ask",
+            };
+            csv.push_str(&format!(
+                "{},{},{},{},{},{}\n",
+                fill.timestamp,
+                side,
+                fill.price.normalize(),
+                fill.qty.normalize(),
+                fill.fee.normalize(),
+                realized
+                    .map(|pnl| pnl.normalize().to_string())
+                    .unwrap_or_default()
+            ));
+        }
+        csv
+    }
+}
+
+impl Display for BacktestReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} fills, pnl {}",
+            CurrencyPair::from(self.pair),
+            self.fills.len(),
+            self.pnl().round_dp(8).normalize()
+        )?;
+        if let Some(total_return) = self.total_return() {
+            write!(f, " ({})", format_percent(total_return))?;
+        }
+        if let Some(win_rate) = self.win_rate() {
+            write!(f, ", win rate {}", format_percent(win_rate))?;
+        }
+        write!(f, ", max drawdown {}", format_percent(self.max_drawdown()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{Backtest, BacktestError, BacktestReport, Fill, READS_PER_CANDLE};
+    use crate::config::BacktestConfig;
+    use crate::currency::Currency;
+    use crate::dec;
+    use crate::exchange::{Exchange, OrderState, Side, Ticker};
+    use crate::utils::Decimal;
+
+    const PAIR: (Currency, Currency) = (Currency::BTC, Currency::KRW);
+    const MINUTE: u64 = 60_000;
+
+    fn candle(minute: u64, open: i64, high: i64, low: i64, close: i64) -> Ticker {
+        Ticker {
+            timestamp: minute * MINUTE,
+            open: Decimal(open.into()),
+            close: Decimal(close.into()),
+            low: Decimal(low.into()),
+            high: Decimal(high.into()),
+        }
+    }
+
+    fn backtest() -> Backtest {
+        let candles = vec![
+            candle(0, 100, 100, 100, 100),
+            candle(1, 100, 101, 95, 96),
+            candle(2, 96, 120, 96, 118),
+            candle(3, 118, 118, 90, 90),
+        ];
+        let config = BacktestConfig {
+            fee_rate: dec!(0.01),
+            slippage: Decimal::ZERO,
+            initial_quote: dec!(1000),
+        };
+        Backtest::new(PAIR, candles, config).unwrap()
+    }
+
+    #[tokio::test]
+    async fn polling_without_sleeping_moves_on() {
+        let backtest = backtest();
+        for _ in 0..READS_PER_CANDLE {
+            assert_eq!(backtest.ticker(PAIR, None).await.unwrap(), dec!(100));
+        }
+        assert_eq!(backtest.ticker(PAIR, None).await.unwrap(), dec!(96));
+        assert_eq!(backtest.clock().now_millis(), MINUTE as i64);
+
+        // Polling the last candle ends the data
+        let mut polls = 0;
+        while backtest.ticker(PAIR, None).await.is_ok() {
+            polls += 1;
+        }
+        assert!(polls <= 3 * (READS_PER_CANDLE + 1), "{}", polls);
+    }
+
+    #[tokio::test]
+    async fn limit_orders_fill_on_later_candles() {
+        let backtest = backtest();
+        let clock = backtest.clock();
+
+        // 5 at 96, with the fee 484.8 is locked
+        let bid = backtest
+            .bid_limit(PAIR, dec!(96), dec!(5), None)
+            .await
+            .unwrap();
+        let quote = backtest.balance(Currency::KRW, None).await.unwrap();
+        assert_eq!(quote.locked, dec!(484.8));
+        assert!(backtest
+            .bid_limit(PAIR, dec!(96), dec!(6), None)
+            .await
+            .is_err());
+
+        // The next candle trades through 96
+        assert_eq!(backtest.wait_order(&bid).await.unwrap(), dec!(5));
+        assert_eq!(clock.now_millis(), MINUTE as i64);
+
+        let ask = backtest
+            .ask_limit(PAIR, dec!(110), dec!(5), None)
+            .await
+            .unwrap();
+        assert_eq!(
+            backtest
+                .wait_order_timeout(&ask, Duration::from_secs(30))
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            backtest.view_order(&ask).await.unwrap().state,
+            OrderState::Wait
+        );
+        assert_eq!(backtest.wait_order(&ask).await.unwrap(), dec!(5));
+
+        let report = backtest.report();
+        // Bought for 484.8, sold for 550 - 5.5
+        assert_eq!(report.realized()[1], Some(dec!(59.7)));
+        assert_eq!(report.pnl(), dec!(59.7));
+        assert_eq!(report.win_rate(), Some(Decimal::ONE));
+        assert_eq!(
+            report.to_csv().lines().nth(2),
+            Some("120000,ask,110,5,5.5,59.7")
+        );
+
+        // Nothing left to wait for
+        let bid = backtest
+            .bid_limit(PAIR, dec!(10), dec!(1), None)
+            .await
+            .unwrap();
+        assert!(matches!(
+            backtest.wait_order(&bid).await,
+            Err(BacktestError::Ended)
+        ));
+    }
+
+    #[tokio::test]
+    async fn market_orders_and_drawdown() {
+        let backtest = backtest();
+        let clock = backtest.clock();
+
+        // Spends the whole 1000 at 100 with the fee
+        let bid = backtest.bid_market(PAIR, dec!(1000), None).await.unwrap();
+        let bought = backtest.view_order(&bid).await.unwrap().executed_volume;
+        assert_eq!(bought, dec!(9.90099009));
+
+        clock.sleep(Duration::from_millis(3 * MINUTE)).await;
+        assert_eq!(backtest.ticker(PAIR, None).await.unwrap(), dec!(90));
+        assert!(backtest
+            .ticker((Currency::ETH, Currency::KRW), None)
+            .await
+            .is_err());
+
+        // Peak at the close of 118, down to 90
+        let report = backtest.report();
+        assert_eq!(report.equity.len(), 4);
+        let drawdown = report.max_drawdown();
+        assert_eq!(drawdown.round_dp(4), dec!(0.2373));
+        assert_eq!(report.win_rate(), None);
+    }
+
+    #[test]
+    fn report_stats() {
+        let fill = |side: Side, fee: Decimal| Fill {
+            timestamp: 0,
+            side,
+            price: dec!(100),
+            qty: dec!(1),
+            fee,
+        };
+        let report = BacktestReport {
+            pair: PAIR,
+            fills: vec![fill(Side::Bid, dec!(1)), fill(Side::Ask, dec!(3))],
+            equity: vec![
+                (0, dec!(100)),
+                (MINUTE, dec!(110)),
+                (2 * MINUTE, dec!(99)),
+                (3 * MINUTE, dec!(108.9)),
+            ],
+        };
+
+        assert_eq!(report.total_return(), Some(dec!(0.089)));
+        assert_eq!(report.average_fee(), Some(dec!(2)));
+        // Returns of 10%, -10% and 10%
+        let sharpe = report.sharpe().unwrap();
+        assert!((sharpe - 0.2887).abs() < 0.0001, "{}", sharpe);
+
+        let flat = BacktestReport {
+            pair: PAIR,
+            fills: Vec::new(),
+            equity: vec![(0, dec!(100)), (MINUTE, dec!(100)), (2 * MINUTE, dec!(100))],
+        };
+        assert_eq!(flat.sharpe(), None);
+        assert_eq!(flat.average_fee(), None);
+        assert_eq!(flat.total_return(), Some(Decimal::ZERO));
+    }
+}
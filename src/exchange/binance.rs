@@ -1,24 +1,34 @@
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
+    str::FromStr,
     sync::{Arc, RwLock},
     time::Duration,
 };
 
-use num_traits::pow;
+use futures::future::{self, Either};
+use once_cell::sync::OnceCell;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use unwrap_let::unwrap_let;
 
+use crate::utils::broadcaster::Broadcaster;
+use crate::utils::rate_limiter::{RateLimit, RateLimiter};
+use crate::utils::rounding::round_down_dp;
+use crate::utils::server_time::{self, ServerTime};
+use crate::utils::signing::{BinanceSigner, SignRequest, Signer};
 use crate::utils::Decimal;
+use crate::websocket::{ConnState, StatusHandle, Websocket, WebsocketOptions};
 use crate::{
     config::Config,
     currency::{Currency, CurrencyPairStringifier, NoDelimiterCurrencyPairStringifier},
-    exchange::{Order, OrderState, Unit},
+    exchange::{Order, OrderState, Unit, WithdrawState},
     utils::async_helpers,
-    utils::http::{client, Client, Method},
+    utils::http::{client, send_with_retry, BaseUrls, Client, Method},
 };
 use crate::{dec, utils::broadcaster::Subscription};
 
-use super::{Balance, CandleSticks, Exchange, Market, OrderToken, Orderbook, RealtimeData};
+use super::{
+    markets, user_stream::UserEvent, Balance, CandleSticks, Exchange, Market, OrderToken,
+    Orderbook, RealtimeData, Side, Ticker, Trade,
+};
 
 #[derive(thiserror::Error, Debug)]
 pub enum BinanceError {
@@ -34,6 +44,12 @@ pub enum BinanceError {
     #[error("order failed")]
     OrderFailed,
 
+    #[error("order of {notional} is below the minimum of {min_notional}")]
+    OrderTooSmall {
+        notional: Decimal,
+        min_notional: Decimal,
+    },
+
     #[error("order cancel failed")]
     OrderCancelFailed,
 
@@ -43,46 +59,119 @@ pub enum BinanceError {
     #[error("withdraw failed")]
     WithdrawFailed,
 
+    #[error("withdraw fee not found")]
+    WithdrawFeeNotFound,
+
+    #[error("withdraw not found")]
+    WithdrawNotFound,
+
+    #[error("invalid order token")]
+    InvalidOrderToken,
+
     #[error("cofnig not found")]
     ConfigNotFound,
+
+    #[error("no asks in the orderbook to price the order")]
+    EmptyOrderbook,
 }
 
-fn api_key() -> Result<&'static str, BinanceError> {
+fn signer() -> Result<BinanceSigner, BinanceError> {
     Config::get()
         .binance
         .as_ref()
-        .map(|c| c.api_key.as_str())
+        .map(|c| BinanceSigner {
+            api_key: c.api_key.clone(),
+            secret_key: c.secret_key.clone(),
+        })
         .ok_or(BinanceError::ConfigNotFound)
 }
 
-fn secret_key() -> Result<&'static str, BinanceError> {
+fn recv_window() -> u64 {
     Config::get()
         .binance
         .as_ref()
-        .map(|c| c.secret_key.as_str())
-        .ok_or(BinanceError::ConfigNotFound)
+        .map(|c| c.recv_window)
+        .unwrap_or(5000)
 }
 
-fn hmac_signature(secret_key: &str, message: &str) -> String {
-    use hmac::{Hmac, Mac};
-    use sha2::Sha256;
+/// Binance limits requests by weight, 1200 per minute per IP.
+const RATE_LIMIT: RateLimit = RateLimit {
+    capacity: 1200,
+    refill_per_sec: 20.0,
+};
 
-    let mut mac = Hmac::<Sha256>::new_from_slice(secret_key.as_bytes()).unwrap();
-    mac.update(message.as_bytes());
-    hex::encode(mac.finalize().into_bytes())
+// Request weights of the endpoints we use
+const WEIGHT_DEPTH: u32 = 5;
+const WEIGHT_KLINES: u32 = 2;
+const WEIGHT_TICKER_PRICE: u32 = 2;
+const WEIGHT_TRADES: u32 = 25;
+const WEIGHT_ACCOUNT: u32 = 20;
+const WEIGHT_ORDER: u32 = 1;
+const WEIGHT_QUERY_ORDER: u32 = 4;
+const WEIGHT_WITHDRAW: u32 = 1;
+const WEIGHT_CAPITAL_CONFIG: u32 = 10;
+const WEIGHT_WITHDRAW_HISTORY: u32 = 10;
+const WEIGHT_LEVERAGE: u32 = 1;
+const WEIGHT_TIME: u32 = 1;
+const WEIGHT_EXCHANGE_INFO: u32 = 20;
+
+/// A listen key expires an hour after it is created or kept alive.
+const LISTEN_KEY_KEEPALIVE: Duration = Duration::from_secs(30 * 60);
+
+/// Wait after a failed listen key creation before trying again.
+const LISTEN_KEY_RETRY: Duration = Duration::from_secs(60);
+
+/// Production endpoints, overridden by the `[base_urls.binance]` config section.
+fn default_urls() -> BaseUrls {
+    BaseUrls::new(
+        "https://api.binance.com",
+        "https://fapi.binance.com",
+        "wss://stream.binance.com:9443",
+    )
 }
 
 pub struct Binance {
     subscriptions: Arc<RwLock<HashSet<(Currency, Currency)>>>,
     http_client: Client,
+    rate_limiter: RateLimiter,
+    clock: ServerTime,
+    urls: BaseUrls,
+    /// Started on the first subscription, see [`run_user_stream`].
+    user_events: OnceCell<Broadcaster<UserEvent>>,
+    /// Never broadcasts, the public streams are not implemented yet.
+    realtime: Broadcaster<RealtimeData>,
+    /// The notional filters of `exchangeInfo`, filled with the markets.
+    min_notionals: Arc<RwLock<HashMap<(Currency, Currency, Market), Decimal>>>,
 }
 
 impl Binance {
     pub fn new() -> Self {
+        Self::with_urls(BaseUrls::from_config(Self::NAME, default_urls()))
+    }
+
+    /// An exchange talking to `urls`, for testnets and mock servers.
+    pub fn with_urls(urls: BaseUrls) -> Self {
         Self {
             subscriptions: Arc::new(RwLock::new(HashSet::new())),
             http_client: client(),
+            rate_limiter: RateLimiter::from_config(Self::NAME, RATE_LIMIT),
+            clock: ServerTime::new(),
+            urls,
+            user_events: OnceCell::new(),
+            realtime: Broadcaster::new(),
+            min_notionals: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Timestamp of signed requests, adjusted to the server clock.
+    async fn timestamp(&self) -> i64 {
+        if self.clock.needs_sync() {
+            if let Err(e) = self.server_time().await {
+                tracing::warn!("Binance: failed to sync server time: {}", e);
+            }
         }
+
+        self.clock.now_millis()
     }
 
     pub async fn get_orderbook(
@@ -92,21 +181,24 @@ impl Binance {
     ) -> Result<String, BinanceError> {
         let pair = NoDelimiterCurrencyPairStringifier::stringify(pair.0, pair.1).unwrap();
 
+        self.rate_limiter.acquire(WEIGHT_DEPTH).await;
         Ok(match market {
             Market::Spot => {
-                self.http_client
-                    .get("https://api.binance.com/api/v3/depth")
-                    .query(&[("symbol", pair.as_str()), ("limit", "20")])
-                    .send()
+                let request = self
+                    .http_client
+                    .get(self.urls.rest_url("/api/v3/depth"))
+                    .query(&[("symbol", pair.as_str()), ("limit", "20")]);
+                send_with_retry(request, &Config::get().http_retry)
                     .await?
                     .text()
                     .await?
             }
             Market::Future => {
-                self.http_client
-                    .get("https://fapi.binance.com/fapi/v1/depth")
-                    .query(&[("symbol", pair.as_str()), ("limit", "20")])
-                    .send()
+                let request = self
+                    .http_client
+                    .get(self.urls.futures_url("/fapi/v1/depth"))
+                    .query(&[("symbol", pair.as_str()), ("limit", "20")]);
+                send_with_retry(request, &Config::get().http_retry)
                     .await?
                     .text()
                     .await?
@@ -114,12 +206,33 @@ impl Binance {
         })
     }
 
-    pub async fn get_balance(&self, currency: Currency, market: Market) -> Balance {
+    pub async fn get_balance(
+        &self,
+        currency: Currency,
+        market: Market,
+    ) -> Result<Balance, BinanceError> {
+        Ok(self
+            .get_balances(market)
+            .await?
+            .remove(&currency)
+            .unwrap_or(Balance {
+                available: Decimal::ZERO,
+                locked: Decimal::ZERO,
+            }))
+    }
+
+    /// Returns the non-zero balances of the account, assets unknown to [`Currency`] are skipped.
+    pub async fn get_balances(
+        &self,
+        market: Market,
+    ) -> Result<HashMap<Currency, Balance>, BinanceError> {
+        self.rate_limiter.acquire(WEIGHT_ACCOUNT).await;
         let message = serde_json::json!({
-            "timestamp": chrono::Utc::now().timestamp_millis(),
+            "timestamp": self.timestamp().await,
+            "recvWindow": recv_window(),
         });
 
-        match market {
+        let balances = match market {
             Market::Spot => {
                 #[derive(Deserialize)]
                 struct Response {
@@ -135,37 +248,32 @@ impl Binance {
 
                 let response: Response = request_userdata_trade_kind(
                     Method::GET,
-                    "https://api.binance.com/api/v3/account",
+                    &self.urls.rest_url("/api/v3/account"),
                     &self.http_client,
                     message,
                 )
-                .await
-                .unwrap();
+                .await?;
 
-                let balance = response
+                response
                     .balances
                     .into_iter()
-                    .find(|b| b.asset == currency.to_string())
-                    .unwrap_or(BinanceBalance {
-                        asset: currency.to_string(),
-                        free: Decimal::ZERO,
-                        locked: Decimal::ZERO,
-                    });
-
-                Balance {
-                    available: balance.free,
-                    locked: balance.locked,
-                }
+                    .map(|b| {
+                        let balance = Balance {
+                            available: b.free,
+                            locked: b.locked,
+                        };
+                        (b.asset, balance)
+                    })
+                    .collect::<Vec<_>>()
             }
             Market::Future => {
                 let response: Vec<FutureBalance> = request_userdata_trade_kind(
                     Method::GET,
-                    "https://fapi.binance.com/fapi/v2/balance",
+                    &self.urls.futures_url("/fapi/v2/balance"),
                     &self.http_client,
                     message,
                 )
-                .await
-                .unwrap();
+                .await?;
 
                 #[derive(Deserialize)]
                 #[serde(rename_all = "camelCase")]
@@ -175,21 +283,24 @@ impl Binance {
                     available_balance: Decimal,
                 }
 
-                let balance = response
+                response
                     .into_iter()
-                    .find(|b| b.asset == currency.to_string())
-                    .unwrap_or(FutureBalance {
-                        asset: currency.to_string(),
-                        balance: Decimal::ZERO,
-                        available_balance: Decimal::ZERO,
-                    });
-
-                Balance {
-                    available: balance.available_balance,
-                    locked: balance.balance - balance.available_balance,
-                }
+                    .map(|b| {
+                        let balance = Balance {
+                            available: b.available_balance,
+                            locked: b.balance - b.available_balance,
+                        };
+                        (b.asset, balance)
+                    })
+                    .collect::<Vec<_>>()
             }
-        }
+        };
+
+        Ok(balances
+            .into_iter()
+            .filter(|(_, balance)| !balance.is_empty())
+            .filter_map(|(asset, balance)| Some((Currency::from_str(&asset).ok()?, balance)))
+            .collect())
     }
 
     pub async fn make_spot_order(
@@ -198,31 +309,37 @@ impl Binance {
         side: &str,
         order_type: &str,
         price: Option<Decimal>,
+        stop_price: Option<Decimal>,
         amount: Decimal,
     ) -> Result<OrderToken, BinanceError> {
         let pair = NoDelimiterCurrencyPairStringifier::stringify(pair.0, pair.1).unwrap();
 
+        self.rate_limiter.acquire(WEIGHT_ORDER).await;
         let mut message = serde_json::json!({
             "symbol": pair,
             "side": side,
             "type": order_type,
-            "timestamp": chrono::Utc::now().timestamp_millis(),
+            "timestamp": self.timestamp().await,
+            "recvWindow": recv_window(),
             "quantity": amount,
         });
 
-        if order_type == "LIMIT" {
+        if matches!(order_type, "LIMIT" | "STOP_LOSS_LIMIT" | "STOP") {
             message["timeInForce"] = serde_json::json!("GTC");
             message["price"] = serde_json::json!(price);
         }
+        if let Some(stop_price) = stop_price {
+            message["stopPrice"] = serde_json::json!(stop_price);
+        }
 
         let query_string = serde_qs::to_string(&message).unwrap();
-        let signature = hmac_signature(secret_key()?, &query_string);
-        message["signature"] = serde_json::json!(signature);
+        let signed = signer()?.sign("", &query_string, 0);
+        message["signature"] = serde_json::json!(signed.signature);
 
         let response = self
             .http_client
-            .post("https://api.binance.com/api/v3/order")
-            .header("X-MBX-APIKEY", api_key()?)
+            .post(self.urls.rest_url("/api/v3/order"))
+            .signed(&signed)
             .body(serde_qs::to_string(&message).unwrap())
             .send()
             .await?;
@@ -242,11 +359,13 @@ impl Binance {
         }
 
         let response: serde_json::Value = serde_json::from_str(&result).unwrap();
-        Ok(OrderToken::Array(vec![
-            response["orderId"].clone(),
-            OrderToken::String("spot".into()),
-            pair.into(),
-        ]))
+        Ok(OrderToken::Binance {
+            id: response["orderId"]
+                .as_u64()
+                .ok_or(BinanceError::OrderFailed)?,
+            market: Market::Spot,
+            symbol: pair,
+        })
     }
 
     pub async fn make_future_order(
@@ -255,26 +374,32 @@ impl Binance {
         side: &str,
         order_type: &str,
         price: Option<Decimal>,
+        stop_price: Option<Decimal>,
         amount: Decimal,
     ) -> Result<OrderToken, BinanceError> {
         let pair = NoDelimiterCurrencyPairStringifier::stringify(pair.0, pair.1).unwrap();
 
+        self.rate_limiter.acquire(WEIGHT_ORDER).await;
         let mut message = serde_json::json!({
             "symbol": pair,
             "side": side,
             "type": order_type,
-            "timestamp": chrono::Utc::now().timestamp_millis(),
+            "timestamp": self.timestamp().await,
+            "recvWindow": recv_window(),
             "quantity": amount,
         });
 
-        if order_type == "LIMIT" {
+        if matches!(order_type, "LIMIT" | "STOP_LOSS_LIMIT" | "STOP") {
             message["timeInForce"] = serde_json::json!("GTC");
             message["price"] = serde_json::json!(price);
         }
+        if let Some(stop_price) = stop_price {
+            message["stopPrice"] = serde_json::json!(stop_price);
+        }
 
         let response: serde_json::Value = request_userdata_trade_kind(
             Method::POST,
-            "https://fapi.binance.com/fapi/v1/order",
+            &self.urls.futures_url("/fapi/v1/order"),
             &self.http_client,
             message,
         )
@@ -287,34 +412,33 @@ impl Binance {
             order_type,
             response
         );
-        Ok(OrderToken::Array(vec![
-            response["orderId"].clone(),
-            OrderToken::String("future".into()),
-            pair.into(),
-        ]))
+        Ok(OrderToken::Binance {
+            id: response["orderId"]
+                .as_u64()
+                .ok_or(BinanceError::OrderFailed)?,
+            market: Market::Future,
+            symbol: pair,
+        })
     }
 
-    async fn view_spot_order(&self, order_token: OrderToken) -> Result<Order, BinanceError> {
-        unwrap_let!(OrderToken::Array(order_token) = order_token);
-        unwrap_let!(
-            [OrderToken::Number(order_id), _, OrderToken::String(pair)] = order_token.as_slice()
-        );
-
+    async fn view_spot_order(&self, order_id: u64, pair: &str) -> Result<Order, BinanceError> {
+        self.rate_limiter.acquire(WEIGHT_QUERY_ORDER).await;
         let message = serde_json::json!({
-            "timestamp": chrono::Utc::now().timestamp_millis(),
+            "timestamp": self.timestamp().await,
+            "recvWindow": recv_window(),
             "orderId": order_id,
             "symbol": pair,
         });
 
         let query_string = serde_qs::to_string(&message).unwrap();
-        let signature = hmac_signature(secret_key()?, &query_string);
+        let signed = signer()?.sign("", &query_string, 0);
 
         let response_order = self
             .http_client
-            .get("https://api.binance.com/api/v3/order")
-            .header("X-MBX-APIKEY", api_key()?)
+            .get(self.urls.rest_url("/api/v3/order"))
+            .signed(&signed)
             .query(&message)
-            .query(&[("signature", signature.clone())])
+            .query(&[("signature", signed.signature.clone())])
             .send()
             .await?;
 
@@ -330,6 +454,7 @@ impl Binance {
         #[serde(rename_all = "camelCase")]
         struct Response {
             pub status: String,
+            pub orig_qty: Decimal,
             pub executed_qty: Decimal,
             pub cummulative_quote_qty: Decimal,
             pub side: String,
@@ -353,30 +478,30 @@ impl Binance {
         Ok(Order {
             state,
             executed_volume: qty - (qty * FEE_RATE),
+            remaining: response.orig_qty - response.executed_qty,
+            avg_price: (response.executed_qty > Decimal::ZERO)
+                .then(|| response.cummulative_quote_qty / response.executed_qty),
         })
     }
 
-    async fn view_futures_order(&self, order_token: OrderToken) -> Result<Order, BinanceError> {
-        unwrap_let!(OrderToken::Array(order_token) = order_token);
-        unwrap_let!(
-            [OrderToken::Number(order_id), _, OrderToken::String(pair)] = order_token.as_slice()
-        );
-
+    async fn view_futures_order(&self, order_id: u64, pair: &str) -> Result<Order, BinanceError> {
+        self.rate_limiter.acquire(WEIGHT_QUERY_ORDER).await;
         let message = serde_json::json!({
-            "timestamp": chrono::Utc::now().timestamp_millis(),
+            "timestamp": self.timestamp().await,
+            "recvWindow": recv_window(),
             "orderId": order_id,
             "symbol": pair,
         });
 
         let query_string = serde_qs::to_string(&message).unwrap();
-        let signature = hmac_signature(secret_key()?, &query_string);
+        let signed = signer()?.sign("", &query_string, 0);
 
         let response_order = self
             .http_client
-            .get("https://fapi.binance.com/fapi/v1/order")
-            .header("X-MBX-APIKEY", api_key()?)
+            .get(self.urls.futures_url("/fapi/v1/order"))
+            .signed(&signed)
             .query(&message)
-            .query(&[("signature", signature.clone())])
+            .query(&[("signature", signed.signature.clone())])
             .send()
             .await?;
 
@@ -392,7 +517,9 @@ impl Binance {
         #[serde(rename_all = "camelCase")]
         struct Response {
             pub status: String,
+            pub orig_qty: Decimal,
             pub executed_qty: Decimal,
+            pub avg_price: Decimal,
         }
 
         let response: Response = serde_json::from_str(&result)?;
@@ -404,8 +531,124 @@ impl Binance {
         Ok(Order {
             state,
             executed_volume: response.executed_qty,
+            remaining: response.orig_qty - response.executed_qty,
+            // Binance reports zero average price until the order is filled
+            avg_price: (response.avg_price > Decimal::ZERO).then_some(response.avg_price),
         })
     }
+
+    /// Fails if the order value is below the minimum notional of the pair,
+    /// fetching the filters of the markets first if they are not known yet.
+    async fn check_notional(
+        &self,
+        pair: (Currency, Currency),
+        market: Option<Market>,
+        notional: Decimal,
+    ) -> Result<(), BinanceError> {
+        if self.min_notionals.read().unwrap().is_empty() {
+            if let Err(e) = self.markets().await {
+                tracing::warn!("Binance: failed to fetch the notional filters: {}", e);
+            }
+        }
+
+        match self.min_notional(pair, market) {
+            Some(min_notional) if notional < min_notional => Err(BinanceError::OrderTooSmall {
+                notional,
+                min_notional,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The minimum order values of the `exchangeInfo` symbols that have one.
+/// Spot symbols have a `NOTIONAL` or an older `MIN_NOTIONAL` filter with `minNotional`,
+/// futures a `MIN_NOTIONAL` filter with `notional`.
+fn parse_min_notionals(
+    text: &str,
+    market: Market,
+) -> Result<Vec<(Currency, Currency, Market, Decimal)>, serde_json::Error> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Filter {
+        filter_type: String,
+        min_notional: Option<Decimal>,
+        notional: Option<Decimal>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Symbol {
+        base_asset: String,
+        quote_asset: String,
+        #[serde(default)]
+        filters: Vec<Filter>,
+    }
+
+    #[derive(Deserialize)]
+    struct Response {
+        symbols: Vec<Symbol>,
+    }
+
+    let response: Response = serde_json::from_str(text)?;
+    Ok(response
+        .symbols
+        .iter()
+        .filter_map(|symbol| {
+            let min_notional = symbol
+                .filters
+                .iter()
+                .filter(|filter| matches!(filter.filter_type.as_str(), "NOTIONAL" | "MIN_NOTIONAL"))
+                .find_map(|filter| filter.min_notional.or(filter.notional))?;
+            Some((
+                symbol.base_asset.parse().ok()?,
+                symbol.quote_asset.parse().ok()?,
+                market,
+                min_notional,
+            ))
+        })
+        .collect())
+}
+
+/// Parses `exchangeInfo`, spot and futures answer the same shape.
+/// Only symbols currently trading are listed, futures only the perpetual ones.
+fn parse_markets(
+    text: &str,
+    market: Market,
+) -> Result<Vec<(Currency, Currency, Market)>, serde_json::Error> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Symbol {
+        status: String,
+        base_asset: String,
+        quote_asset: String,
+        contract_type: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    struct Response {
+        symbols: Vec<Symbol>,
+    }
+
+    let response: Response = serde_json::from_str(text)?;
+    Ok(response
+        .symbols
+        .iter()
+        .filter(|symbol| symbol.status == "TRADING")
+        .filter(|symbol| {
+            symbol
+                .contract_type
+                .as_ref()
+                .map_or(true, |contract_type| contract_type == "PERPETUAL")
+        })
+        .filter_map(|symbol| {
+            Some((
+                symbol.base_asset.parse().ok()?,
+                symbol.quote_asset.parse().ok()?,
+                market,
+            ))
+        })
+        .collect())
 }
 
 impl Exchange for Binance {
@@ -415,10 +658,77 @@ impl Exchange for Binance {
 
     fn subscribe(
         &self,
-        pair: (Currency, Currency),
-        market: Option<Market>,
+        _pair: (Currency, Currency),
+        _market: Option<Market>,
     ) -> Subscription<RealtimeData> {
-        todo!()
+        self.realtime.subscribe()
+    }
+
+    fn connection_status(&self) -> Option<StatusHandle> {
+        None
+    }
+
+    fn streams_realtime(&self) -> bool {
+        false
+    }
+
+    fn rate_limit_utilization(&self) -> Option<f64> {
+        Some(self.rate_limiter.utilization())
+    }
+
+    fn clock_drift(&self) -> Option<i64> {
+        self.clock.drift_millis()
+    }
+
+    fn recv_window(&self) -> Option<i64> {
+        Some(recv_window() as i64)
+    }
+
+    fn user_events(&self) -> Option<Subscription<UserEvent>> {
+        signer().ok()?;
+        let events = self.user_events.get_or_init(|| {
+            let events = Broadcaster::new();
+            async_helpers::spawn(run_user_stream(
+                self.http_client.clone(),
+                self.urls.clone(),
+                events.clone(),
+            ));
+            events
+        });
+        Some(events.subscribe())
+    }
+
+    async fn markets(&self) -> Result<Vec<(Currency, Currency, Market)>, Self::Error> {
+        // The filters are kept by this instance, the markets may have been fetched by another
+        let has_filters = !self.min_notionals.read().unwrap().is_empty();
+        if let Some(markets) = markets::fresh(Self::NAME, &self.urls).filter(|_| has_filters) {
+            return Ok(markets);
+        }
+        tracing::debug!("Binance::markets()");
+
+        let mut markets = Vec::new();
+        let mut min_notionals = HashMap::new();
+        for (url, market) in [
+            (self.urls.rest_url("/api/v3/exchangeInfo"), Market::Spot),
+            (
+                self.urls.futures_url("/fapi/v1/exchangeInfo"),
+                Market::Future,
+            ),
+        ] {
+            self.rate_limiter.acquire(WEIGHT_EXCHANGE_INFO).await;
+            let request = self.http_client.get(url);
+            let text = send_with_retry(request, &Config::get().http_retry)
+                .await?
+                .text()
+                .await?;
+            markets.extend(parse_markets(&text, market)?);
+            for (base, quote, market, min_notional) in parse_min_notionals(&text, market)? {
+                min_notionals.insert((base, quote, market), min_notional);
+            }
+        }
+        *self.min_notionals.write().unwrap() = min_notionals;
+
+        Ok(markets::store(Self::NAME, &self.urls, markets))
     }
 
     async fn orderbook(
@@ -445,15 +755,124 @@ impl Exchange for Binance {
             .map(|(price, amount)| Unit { price, amount })
             .collect();
 
-        Ok(Orderbook { pair, bids, asks })
+        Ok(Orderbook { pair, bids, asks }.sorted())
     }
 
     async fn candlesticks(
         &self,
-        _pair: (Currency, Currency),
-        _market: Option<Market>,
+        pair: (Currency, Currency),
+        market: Option<Market>,
     ) -> Result<CandleSticks, Self::Error> {
-        todo!()
+        use num_traits::ToPrimitive;
+
+        let symbol = NoDelimiterCurrencyPairStringifier::stringify(pair.0, pair.1).unwrap();
+        let url = match market.unwrap_or_default() {
+            Market::Spot => self.urls.rest_url("/api/v3/klines"),
+            Market::Future => self.urls.futures_url("/fapi/v1/klines"),
+        };
+
+        self.rate_limiter.acquire(WEIGHT_KLINES).await;
+        let request = self
+            .http_client
+            .get(url)
+            .query(&[("symbol", symbol.as_str()), ("interval", "15m")]);
+        let text = send_with_retry(request, &Config::get().http_retry)
+            .await?
+            .text()
+            .await?;
+
+        // Every field of a kline is a number or a numeric string
+        let klines: Vec<Vec<Decimal>> = serde_json::from_str(&text)?;
+        let tickers = klines
+            .into_iter()
+            .filter(|kline| kline.len() >= 5)
+            .map(|kline| Ticker {
+                timestamp: kline[0].to_u64().unwrap_or_default(),
+                open: kline[1],
+                high: kline[2],
+                low: kline[3],
+                close: kline[4],
+            })
+            .collect();
+
+        Ok(CandleSticks { pair, tickers })
+    }
+
+    async fn ticker(
+        &self,
+        pair: (Currency, Currency),
+        market: Option<Market>,
+    ) -> Result<Decimal, Self::Error> {
+        let symbol = NoDelimiterCurrencyPairStringifier::stringify(pair.0, pair.1).unwrap();
+        let url = match market.unwrap_or_default() {
+            Market::Spot => self.urls.rest_url("/api/v3/ticker/price"),
+            Market::Future => self.urls.futures_url("/fapi/v1/ticker/price"),
+        };
+
+        self.rate_limiter.acquire(WEIGHT_TICKER_PRICE).await;
+        let request = self
+            .http_client
+            .get(url)
+            .query(&[("symbol", symbol.as_str())]);
+        let text = send_with_retry(request, &Config::get().http_retry)
+            .await?
+            .text()
+            .await?;
+
+        #[derive(Deserialize)]
+        struct Response {
+            price: Decimal,
+        }
+
+        let response: Response = serde_json::from_str(&text)?;
+        Ok(response.price)
+    }
+
+    async fn recent_trades(
+        &self,
+        pair: (Currency, Currency),
+        market: Option<Market>,
+        limit: usize,
+    ) -> Result<Vec<Trade>, Self::Error> {
+        let symbol = NoDelimiterCurrencyPairStringifier::stringify(pair.0, pair.1).unwrap();
+        let url = match market.unwrap_or_default() {
+            Market::Spot => self.urls.rest_url("/api/v3/trades"),
+            Market::Future => self.urls.futures_url("/fapi/v1/trades"),
+        };
+
+        self.rate_limiter.acquire(WEIGHT_TRADES).await;
+        let request = self.http_client.get(url).query(&[
+            ("symbol", symbol.as_str()),
+            ("limit", limit.to_string().as_str()),
+        ]);
+        let text = send_with_retry(request, &Config::get().http_retry)
+            .await?
+            .text()
+            .await?;
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Response {
+            id: u64,
+            price: Decimal,
+            qty: Decimal,
+            time: i64,
+            is_buyer_maker: bool,
+        }
+
+        // Oldest first, the taker bought if the maker was the seller
+        let response: Vec<Response> = serde_json::from_str(&text)?;
+        Ok(response
+            .into_iter()
+            .map(|trade| Trade {
+                pair,
+                timestamp: trade.time,
+                price: trade.price,
+                amount: trade.qty,
+                is_bid: !trade.is_buyer_maker,
+                id: Some(trade.id.to_string()),
+            })
+            .collect())
     }
 
     async fn balance(
@@ -463,7 +882,25 @@ impl Exchange for Binance {
     ) -> Result<Balance, Self::Error> {
         tracing::debug!("Binance::balance({:?})", currency);
 
-        Ok(self.get_balance(currency, market.unwrap_or_default()).await)
+        self.get_balance(currency, market.unwrap_or_default()).await
+    }
+
+    async fn balances(
+        &self,
+        market: Option<Market>,
+    ) -> Result<HashMap<Currency, Balance>, Self::Error> {
+        tracing::debug!("Binance::balances()");
+
+        self.get_balances(market.unwrap_or_default()).await
+    }
+
+    /// From the notional filter of `exchangeInfo`, None until the markets are fetched.
+    fn min_notional(&self, pair: (Currency, Currency), market: Option<Market>) -> Option<Decimal> {
+        self.min_notionals
+            .read()
+            .unwrap()
+            .get(&(pair.0, pair.1, market.unwrap_or_default()))
+            .copied()
     }
 
     async fn bid_limit(
@@ -474,10 +911,11 @@ impl Exchange for Binance {
         market: Option<Market>,
     ) -> Result<OrderToken, Self::Error> {
         tracing::info!("Binance::bid_limit({:?}, {}, {})", pair, price, amount);
+        self.check_notional(pair, market, price * amount).await?;
 
         match market.unwrap_or_default() {
             Market::Spot => {
-                self.make_spot_order(pair, "BUY", "LIMIT", Some(price), amount)
+                self.make_spot_order(pair, "BUY", "LIMIT", Some(price), None, amount)
                     .await
             }
             Market::Future => todo!("future market is not supported yet"),
@@ -490,19 +928,25 @@ impl Exchange for Binance {
         quote_qty: Decimal,
         market: Option<Market>,
     ) -> Result<OrderToken, Self::Error> {
-        let orderbook = self.orderbook(pair, market).await?;
+        let ask = self
+            .orderbook(pair, market)
+            .await?
+            .best_ask()
+            .ok_or(BinanceError::EmptyOrderbook)?;
 
         Ok(match market.unwrap_or_default() {
             Market::Spot => {
-                let qty = quote_qty / orderbook.asks[0].price;
-                let qty = round_qty(pair.0, orderbook.asks[0].price, qty);
+                self.check_notional(pair, market, quote_qty).await?;
+                let qty = quote_qty / ask;
+                let qty = round_qty(pair.0, ask, qty);
 
-                self.make_spot_order(pair, "BUY", "MARKET", None, qty)
+                self.make_spot_order(pair, "BUY", "MARKET", None, None, qty)
                     .await?
             }
             Market::Future => {
-                let qty = round_qty(pair.0, orderbook.asks[0].price, quote_qty);
-                self.make_future_order(pair, "BUY", "MARKET", None, qty)
+                let qty = round_qty(pair.0, ask, quote_qty);
+                self.check_notional(pair, market, qty * ask).await?;
+                self.make_future_order(pair, "BUY", "MARKET", None, None, qty)
                     .await?
             }
         })
@@ -516,13 +960,14 @@ impl Exchange for Binance {
         market: Option<Market>,
     ) -> Result<OrderToken, Self::Error> {
         tracing::info!("Binance::ask_limit({:?}, {}, {})", pair, price, amount);
+        self.check_notional(pair, market, price * amount).await?;
         Ok(match market.unwrap_or_default() {
             Market::Spot => {
-                self.make_spot_order(pair, "SELL", "Limit", Some(price), amount)
+                self.make_spot_order(pair, "SELL", "Limit", Some(price), None, amount)
                     .await?
             }
             Market::Future => {
-                self.make_future_order(pair, "SELL", "Limit", Some(price), amount)
+                self.make_future_order(pair, "SELL", "Limit", Some(price), None, amount)
                     .await?
             }
         })
@@ -534,35 +979,81 @@ impl Exchange for Binance {
         base_qty: Decimal,
         market: Option<Market>,
     ) -> Result<OrderToken, Self::Error> {
-        let orderbook = self.orderbook(pair, market).await?;
+        let ask = self
+            .orderbook(pair, market)
+            .await?
+            .best_ask()
+            .ok_or(BinanceError::EmptyOrderbook)?;
 
         let qty = base_qty;
-        let qty = round_qty(pair.0, orderbook.asks[0].price, qty);
+        let qty = round_qty(pair.0, ask, qty);
+        self.check_notional(pair, market, qty * ask).await?;
 
         Ok(match market.unwrap_or_default() {
             Market::Spot => {
-                self.make_spot_order(pair, "SELL", "MARKET", None, qty)
+                self.make_spot_order(pair, "SELL", "MARKET", None, None, qty)
                     .await?
             }
             Market::Future => {
-                self.make_future_order(pair, "SELL", "MARKET", None, qty)
+                self.make_future_order(pair, "SELL", "MARKET", None, None, qty)
                     .await?
             }
         })
     }
 
-    async fn view_order(&self, order_token: &OrderToken) -> Result<Order, Self::Error> {
-        let ot = order_token.clone();
-        unwrap_let!(OrderToken::Array(order_token) = order_token);
-        unwrap_let!(
-            [OrderToken::Number(order_id), OrderToken::String(market), _] = order_token.as_slice()
+    async fn stop_limit(
+        &self,
+        pair: (Currency, Currency),
+        stop_price: Decimal,
+        limit_price: Decimal,
+        amount: Decimal,
+        side: Side,
+        market: Option<Market>,
+    ) -> Result<OrderToken, Self::Error> {
+        tracing::info!(
+            "Binance::stop_limit({:?}, {}, {}, {}, {:?})",
+            pair,
+            stop_price,
+            limit_price,
+            amount,
+            side
         );
+        self.check_notional(pair, market, limit_price * amount)
+            .await?;
 
-        tracing::debug!("Binance::view_order({}, {})", order_id, market);
-        Ok(match market.as_str() {
-            "spot" => self.view_spot_order(ot).await?,
-            "future" => self.view_futures_order(ot).await?,
-            _ => unreachable!("invalid market type"),
+        let side = match side {
+            Side::Bid => "BUY",
+            Side::Ask => "SELL",
+        };
+        let (stop_price, limit_price) = (Some(stop_price), Some(limit_price));
+        match market.unwrap_or_default() {
+            Market::Spot => {
+                self.make_spot_order(
+                    pair,
+                    side,
+                    "STOP_LOSS_LIMIT",
+                    limit_price,
+                    stop_price,
+                    amount,
+                )
+                .await
+            }
+            Market::Future => {
+                self.make_future_order(pair, side, "STOP", limit_price, stop_price, amount)
+                    .await
+            }
+        }
+    }
+
+    async fn view_order(&self, order_token: &OrderToken) -> Result<Order, Self::Error> {
+        let OrderToken::Binance { id, market, symbol } = order_token else {
+            return Err(BinanceError::InvalidOrderToken);
+        };
+
+        tracing::debug!("Binance::view_order({}, {:?})", id, market);
+        Ok(match market {
+            Market::Spot => self.view_spot_order(*id, symbol).await?,
+            Market::Future => self.view_futures_order(*id, symbol).await?,
         })
     }
 
@@ -588,16 +1079,14 @@ impl Exchange for Binance {
         address1: &str,
         address2: Option<&str>,
         network: Option<&str>,
-    ) -> Result<(), Self::Error> {
+    ) -> Result<String, Self::Error> {
         if currency != Currency::USDT {
-            amount = round_qty_withdraw(
-                self.orderbook((currency, Currency::USDT), None)
-                    .await
-                    .unwrap()
-                    .asks[0]
-                    .price,
-                amount,
-            );
+            let ask = self
+                .orderbook((currency, Currency::USDT), None)
+                .await?
+                .best_ask()
+                .ok_or(BinanceError::EmptyOrderbook)?;
+            amount = round_qty_withdraw(ask, amount);
         }
 
         tracing::info!(
@@ -609,12 +1098,14 @@ impl Exchange for Binance {
             network
         );
 
+        self.rate_limiter.acquire(WEIGHT_WITHDRAW).await;
         let currency = currency.to_string();
         let mut message = serde_json::json!({
             "coin": currency,
             "address": address1,
             "amount": amount,
-            "timestamp": chrono::Utc::now().timestamp_millis(),
+            "timestamp": self.timestamp().await,
+            "recvWindow": recv_window(),
         });
 
         if let Some(address2) = address2 {
@@ -626,16 +1117,17 @@ impl Exchange for Binance {
         }
 
         let query_string = serde_qs::to_string(&message).unwrap();
-        let signature = hmac_signature(secret_key()?, &query_string);
-        message["signature"] = serde_json::json!(signature);
+        let signed = signer()?.sign("", &query_string, 0);
+        message["signature"] = serde_json::json!(signed.signature);
 
         let response = self
             .http_client
             .post(format!(
-                "https://api.binance.com/sapi/v1/capital/withdraw/apply?{}",
+                "{}/sapi/v1/capital/withdraw/apply?{}",
+                self.urls.rest,
                 serde_qs::to_string(&message).unwrap()
             ))
-            .header("X-MBX-APIKEY", api_key()?)
+            .signed(&signed)
             .body(String::new())
             .send()
             .await;
@@ -648,10 +1140,110 @@ impl Exchange for Binance {
         let result = response.text().await.unwrap();
 
         tracing::info!("Binance::withdraw() response: {}", result);
-        status
-            .is_success()
-            .then(|| ())
-            .ok_or(BinanceError::WithdrawFailed)
+        if !status.is_success() {
+            return Err(BinanceError::WithdrawFailed);
+        }
+
+        #[derive(Deserialize)]
+        struct Response {
+            id: String,
+        }
+
+        let response: Response = serde_json::from_str(&result)?;
+        Ok(response.id)
+    }
+
+    async fn withdraw_status(
+        &self,
+        currency: Currency,
+        id: &str,
+    ) -> Result<WithdrawState, Self::Error> {
+        tracing::debug!("Binance::withdraw_status({:?}, {})", currency, id);
+
+        #[derive(Deserialize)]
+        struct Withdrawal {
+            id: String,
+            status: u32,
+        }
+
+        self.rate_limiter.acquire(WEIGHT_WITHDRAW_HISTORY).await;
+        let message = serde_json::json!({
+            "coin": currency.to_string(),
+            "idList": id,
+            "timestamp": self.timestamp().await,
+            "recvWindow": recv_window(),
+        });
+
+        let withdrawals: Vec<Withdrawal> = request_userdata_trade_kind(
+            Method::GET,
+            &self.urls.rest_url("/sapi/v1/capital/withdraw/history"),
+            &self.http_client,
+            message,
+        )
+        .await?;
+
+        let withdrawal = withdrawals
+            .into_iter()
+            .find(|withdrawal| withdrawal.id == id)
+            .ok_or(BinanceError::WithdrawNotFound)?;
+
+        // 0: email sent, 1: cancelled, 2: awaiting approval, 3: rejected,
+        // 4: processing, 5: failure, 6: completed
+        Ok(match withdrawal.status {
+            4 => WithdrawState::Processing,
+            6 => WithdrawState::Done,
+            1 | 3 | 5 => WithdrawState::Failed,
+            _ => WithdrawState::Pending,
+        })
+    }
+
+    async fn withdraw_fee(
+        &self,
+        currency: Currency,
+        network: Option<&str>,
+    ) -> Result<Decimal, Self::Error> {
+        tracing::debug!("Binance::withdraw_fee({:?}, {:?})", currency, network);
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Coin {
+            coin: String,
+            network_list: Vec<Network>,
+        }
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Network {
+            network: String,
+            is_default: bool,
+            withdraw_fee: Decimal,
+        }
+
+        self.rate_limiter.acquire(WEIGHT_CAPITAL_CONFIG).await;
+        let message = serde_json::json!({
+            "timestamp": self.timestamp().await,
+            "recvWindow": recv_window(),
+        });
+
+        let coins: Vec<Coin> = request_userdata_trade_kind(
+            Method::GET,
+            &self.urls.rest_url("/sapi/v1/capital/config/getall"),
+            &self.http_client,
+            message,
+        )
+        .await?;
+
+        coins
+            .into_iter()
+            .find(|coin| coin.coin == currency.to_string())
+            .and_then(|coin| {
+                coin.network_list.into_iter().find(|n| match network {
+                    Some(network) => n.network == network,
+                    None => n.is_default,
+                })
+            })
+            .map(|n| n.withdraw_fee)
+            .ok_or(BinanceError::WithdrawFeeNotFound)
     }
 
     async fn set_leverage(
@@ -664,20 +1256,42 @@ impl Exchange for Binance {
         let pair = pair.expect("binance does not support global leverage setting");
         let pair = NoDelimiterCurrencyPairStringifier::stringify(pair.0, pair.1).unwrap();
 
+        self.rate_limiter.acquire(WEIGHT_LEVERAGE).await;
         request_userdata_trade_kind::<serde_json::Value, _>(
             Method::POST,
-            "https://fapi.binance.com/fapi/v1/leverage",
+            &self.urls.futures_url("/fapi/v1/leverage"),
             &self.http_client,
             serde_json::json!({
                 "symbol": pair,
                 "leverage": value,
-                "timestamp": chrono::Utc::now().timestamp_millis(),
+                "timestamp": self.timestamp().await,
+                "recvWindow": recv_window(),
             }),
         )
         .await?;
 
         Ok(())
     }
+
+    async fn server_time(&self) -> Result<i64, Self::Error> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Response {
+            server_time: i64,
+        }
+
+        self.rate_limiter.acquire(WEIGHT_TIME).await;
+        let sent = server_time::local_millis();
+        let request = self.http_client.get(self.urls.rest_url("/api/v3/time"));
+        let response: Response = send_with_retry(request, &Config::get().http_retry)
+            .await?
+            .json()
+            .await?;
+
+        self.clock
+            .update(response.server_time, sent, server_time::local_millis());
+        Ok(response.server_time)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -700,35 +1314,25 @@ fn numeric_digits(mut n: Decimal) -> u32 {
 }
 
 fn round_qty(currency: Currency, price: Decimal, target: Decimal) -> Decimal {
-    let (rounded, decimal) = match currency {
-        Currency::SOL | Currency::APT => (target.round_dp(2), 2),
-        Currency::XRP => (target.round_dp(0), 0),
+    let decimal = match currency {
+        Currency::SOL | Currency::APT => 2,
+        Currency::XRP => 0,
         _ => {
             if price < dec!(1) {
-                let target = target.round_dp(0);
-                (target, 0)
+                0
             } else {
                 let mut round_digit = numeric_digits(price).saturating_sub(1);
-                let mut rounded;
-                loop {
-                    rounded = target.round_dp(round_digit);
-                    if (rounded / target).abs() > dec!(0.99) {
-                        break;
-                    }
+                while (target.round_dp(round_digit) / target).abs() <= dec!(0.99) {
                     round_digit += 1;
                 }
 
-                (rounded, round_digit)
+                round_digit
             }
         }
     };
 
-    // if rounded value is greater than target, then subtract 1 from the decimal place
-    if rounded > target {
-        rounded - dec!(1) / pow(dec!(10), decimal as usize)
-    } else {
-        rounded
-    }
+    // The digits are picked with the nearest rounding, but the quantity itself is never rounded up
+    round_down_dp(target, decimal)
 }
 
 fn round_qty_withdraw(price: Decimal, target: Decimal) -> Decimal {
@@ -743,11 +1347,7 @@ fn round_qty_withdraw(price: Decimal, target: Decimal) -> Decimal {
         round_digit += 1;
     }
 
-    if rounded > target {
-        rounded - dec!(1) / pow(dec!(10), round_digit as usize)
-    } else {
-        rounded
-    }
+    round_down_dp(target, round_digit)
 }
 
 #[cfg(test)]
@@ -756,9 +1356,66 @@ mod tests {
 
     use crate::{
         currency::Currency,
-        exchange::{Binance, Exchange, Market},
+        exchange::{
+            user_stream::UserEvent, Balance, Binance, Exchange, Market, Order, OrderState,
+            OrderToken,
+        },
     };
 
+    #[test]
+    fn parse_markets() {
+        let spot = r#"{"timezone": "UTC", "symbols": [
+            {"symbol": "BTCUSDT", "status": "TRADING", "baseAsset": "BTC", "quoteAsset": "USDT"},
+            {"symbol": "ETHBTC", "status": "BREAK", "baseAsset": "ETH", "quoteAsset": "BTC"}
+        ]}"#;
+        assert_eq!(
+            super::parse_markets(spot, Market::Spot).unwrap(),
+            vec![(Currency::BTC, Currency::USDT, Market::Spot)]
+        );
+
+        let futures = r#"{"symbols": [
+            {"symbol": "BTCUSDT", "status": "TRADING", "contractType": "PERPETUAL",
+             "baseAsset": "BTC", "quoteAsset": "USDT"},
+            {"symbol": "BTCUSDT_240628", "status": "TRADING", "contractType": "CURRENT_QUARTER",
+             "baseAsset": "BTC", "quoteAsset": "USDT"}
+        ]}"#;
+        assert_eq!(
+            super::parse_markets(futures, Market::Future).unwrap(),
+            vec![(Currency::BTC, Currency::USDT, Market::Future)]
+        );
+    }
+
+    #[test]
+    fn parse_min_notionals() {
+        let spot = r#"{"symbols": [
+            {"symbol": "BTCUSDT", "baseAsset": "BTC", "quoteAsset": "USDT", "filters": [
+                {"filterType": "PRICE_FILTER", "minPrice": "0.01"},
+                {"filterType": "NOTIONAL", "minNotional": "5.00000000", "maxNotional": "9000000"}
+            ]},
+            {"symbol": "ETHBTC", "baseAsset": "ETH", "quoteAsset": "BTC", "filters": [
+                {"filterType": "MIN_NOTIONAL", "minNotional": "0.00010000"}
+            ]},
+            {"symbol": "XRPBTC", "baseAsset": "XRP", "quoteAsset": "BTC", "filters": []}
+        ]}"#;
+        assert_eq!(
+            super::parse_min_notionals(spot, Market::Spot).unwrap(),
+            vec![
+                (Currency::BTC, Currency::USDT, Market::Spot, dec!(5)),
+                (Currency::ETH, Currency::BTC, Market::Spot, dec!(0.0001)),
+            ]
+        );
+
+        let futures = r#"{"symbols": [
+            {"symbol": "BTCUSDT", "baseAsset": "BTC", "quoteAsset": "USDT", "filters": [
+                {"filterType": "MIN_NOTIONAL", "notional": "100"}
+            ]}
+        ]}"#;
+        assert_eq!(
+            super::parse_min_notionals(futures, Market::Future).unwrap(),
+            vec![(Currency::BTC, Currency::USDT, Market::Future, dec!(100))]
+        );
+    }
+
     #[test]
     fn round_qty_withdraw_test() {
         let price = dec!(8.158);
@@ -767,6 +1424,79 @@ mod tests {
         assert_eq!(rounded, dec!(0.0000000001));
     }
 
+    #[tokio::test]
+    async fn signed_order_request() {
+        use std::collections::HashMap;
+
+        use wiremock::matchers::{header, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        use crate::utils::http::BaseUrls;
+        use crate::utils::signing::Signer;
+
+        // The keys of the config file if there is one, the test keys otherwise
+        let signer = super::signer().unwrap();
+        let server = MockServer::start().await;
+        let respond = |route: &str, body: &str| {
+            Mock::given(path(route)).respond_with(ResponseTemplate::new(200).set_body_string(body))
+        };
+        respond("/api/v3/time", r#"{"serverTime": 1700000000000}"#)
+            .mount(&server)
+            .await;
+        respond(
+            "/api/v3/exchangeInfo",
+            r#"{"symbols": [
+                {"symbol": "BTCUSDT", "status": "TRADING", "baseAsset": "BTC", "quoteAsset": "USDT",
+                 "filters": [{"filterType": "NOTIONAL", "minNotional": "5.00000000"}]}
+            ]}"#,
+        )
+        .mount(&server)
+        .await;
+        respond("/fapi/v1/exchangeInfo", r#"{"symbols": []}"#)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/v3/order"))
+            .and(header("X-MBX-APIKEY", signer.api_key.as_str()))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"orderId": 42}"#))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let binance = Binance::with_urls(BaseUrls::new(&server.uri(), &server.uri(), ""));
+        let pair = (Currency::BTC, Currency::USDT);
+        let token = binance
+            .bid_limit(pair, dec!(30000), dec!(0.001), None)
+            .await
+            .unwrap();
+        assert!(matches!(token, OrderToken::Binance { id: 42, .. }));
+        // Below the minimum notional of exchangeInfo, rejected without a request
+        assert!(binance
+            .bid_limit(pair, dec!(30000), dec!(0.0001), None)
+            .await
+            .is_err());
+
+        let requests = server.received_requests().await.unwrap();
+        let order = requests
+            .iter()
+            .find(|request| request.url.path() == "/api/v3/order")
+            .unwrap();
+        let body = std::str::from_utf8(&order.body).unwrap();
+        let (query, signature) = body.rsplit_once("&signature=").unwrap();
+        assert_eq!(signature, signer.sign("", query, 0).signature);
+
+        let params: HashMap<String, String> = serde_qs::from_str(query).unwrap();
+        assert_eq!(params["symbol"], "BTCUSDT");
+        assert_eq!(params["side"], "BUY");
+        assert_eq!(params["type"], "LIMIT");
+        assert_eq!(params["timeInForce"], "GTC");
+        assert_eq!(params["price"], "30000");
+        assert_eq!(params["quantity"], "0.001");
+        // Signed with the server clock, not the local one
+        let timestamp: i64 = params["timestamp"].parse().unwrap();
+        assert!((1700000000000..1700000060000).contains(&timestamp));
+    }
+
     #[ignore]
     #[tokio::test]
     async fn spot_balance() {
@@ -791,6 +1521,99 @@ mod tests {
         print!("{:?}", balance);
     }
 
+    #[ignore]
+    #[tokio::test]
+    async fn spot_balances() {
+        let binance = Binance::new();
+        let balances = binance.balances(Some(Market::Spot)).await.unwrap();
+
+        print!("{:?}", balances);
+    }
+
+    #[ignore]
+    #[tokio::test]
+    async fn spot_candlesticks() {
+        let binance = Binance::new();
+        let candles = binance
+            .candlesticks((Currency::BTC, Currency::USDT), Some(Market::Spot))
+            .await
+            .unwrap();
+
+        print!("{:?}", candles.tickers.last());
+    }
+
+    #[test]
+    fn parse_execution_report() {
+        // From the user data stream documentation, partially filled
+        let report = r#"{
+            "e": "executionReport", "E": 1499405658658, "s": "ETHBTC", "c": "mUvoqJxFIILMdfAW5iGSOW",
+            "S": "BUY", "o": "LIMIT", "f": "GTC", "q": "1.00000000", "p": "0.10264410",
+            "P": "0.00000000", "F": "0.00000000", "g": -1, "C": "", "x": "TRADE", "X": "PARTIALLY_FILLED",
+            "r": "NONE", "i": 4293153, "l": "0.25000000", "z": "0.25000000", "L": "0.10000000",
+            "n": "0", "N": null, "T": 1499405658657, "t": 1, "I": 8641984, "w": false, "m": false,
+            "M": false, "O": 1499405658657, "Z": "0.02500000", "Y": "0.02500000", "Q": "0.00000000",
+            "W": 1499405658657, "V": "NONE"
+        }"#;
+
+        let order_token = OrderToken::Binance {
+            id: 4293153,
+            market: Market::Spot,
+            symbol: "ETHBTC".to_string(),
+        };
+        assert_eq!(
+            super::parse_user_message(report).unwrap(),
+            super::UserMessage::Event(UserEvent::Order {
+                order_token: order_token.clone(),
+                order: Order {
+                    state: OrderState::Wait,
+                    executed_volume: dec!(0.25),
+                    remaining: dec!(0.75),
+                    avg_price: Some(dec!(0.1)),
+                },
+            })
+        );
+
+        let cancelled = report.replace("PARTIALLY_FILLED", "CANCELED");
+        match super::parse_user_message(&cancelled).unwrap() {
+            super::UserMessage::Event(UserEvent::Order { order, .. }) => {
+                assert_eq!(order.state, OrderState::Closed)
+            }
+            message => panic!("unexpected {:?}", message),
+        }
+    }
+
+    #[test]
+    fn parse_account_position() {
+        let position = r#"{
+            "e": "outboundAccountPosition", "E": 1564034571105, "u": 1564034571073,
+            "B": [
+                {"a": "ETH", "f": "10000.000000", "l": "0.000000"},
+                {"a": "not a ticker", "f": "1.0", "l": "0.0"}
+            ]
+        }"#;
+        assert_eq!(
+            super::parse_user_message(position).unwrap(),
+            super::UserMessage::Event(UserEvent::Balances(vec![(
+                Currency::ETH,
+                Balance {
+                    available: dec!(10000),
+                    locked: dec!(0),
+                }
+            )]))
+        );
+
+        assert_eq!(
+            super::parse_user_message(r#"{"e": "listenKeyExpired", "E": 1576653824250}"#).unwrap(),
+            super::UserMessage::ListenKeyExpired
+        );
+        assert_eq!(
+            super::parse_user_message(r#"{"e": "balanceUpdate", "a": "BTC", "d": "100.0"}"#)
+                .unwrap(),
+            super::UserMessage::Other
+        );
+        assert!(super::parse_user_message("[]").is_err());
+    }
+
     #[ignore]
     #[tokio::test]
     async fn set_leverage() {
@@ -813,20 +1636,26 @@ where
     T: Serialize,
 {
     let query_string = serde_qs::to_string(&message).unwrap();
-    let signature = hmac_signature(secret_key()?, &query_string);
+    let signed = signer()?.sign("", &query_string, 0);
 
-    let response = client
+    // Only queries are retried, retrying an order could place it twice.
+    // Binance signs no nonce, a copy stays valid within the receive window of its timestamp.
+    let is_query = method == Method::GET;
+    let request = client
         .request(method, url)
-        .header("X-MBX-APIKEY", api_key()?)
+        .signed(&signed)
         .query(&message)
-        .query(&[("signature", signature.clone())])
-        .body(String::new())
-        .send()
-        .await
-        .unwrap();
+        .query(&[("signature", signed.signature.clone())])
+        .body(String::new());
+
+    let response = if is_query {
+        send_with_retry(request, &Config::get().http_retry).await
+    } else {
+        request.send().await
+    }?;
 
     let status = response.status();
-    let result = response.text().await.unwrap();
+    let result = response.text().await?;
 
     if !status.is_success() {
         tracing::error!("Binance::<{}> response: {}", url, result);
@@ -835,5 +1664,222 @@ where
         tracing::debug!("Binance::<{}> response: {}", url, result);
     }
 
-    Ok(serde_json::from_str(&result).unwrap())
+    Ok(serde_json::from_str(&result)?)
+}
+
+/// Creates a listen key of the user data stream, it expires after an hour unless kept alive.
+async fn create_listen_key(client: &Client, urls: &BaseUrls) -> Result<String, BinanceError> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Response {
+        listen_key: String,
+    }
+
+    let response = client
+        .post(urls.rest_url("/api/v3/userDataStream"))
+        .header("X-MBX-APIKEY", signer()?.api_key)
+        .send()
+        .await?;
+    let text = listen_key_response(response).await?;
+    Ok(serde_json::from_str::<Response>(&text)?.listen_key)
+}
+
+/// Extends the listen key by an hour, fails if it already expired.
+async fn keepalive_listen_key(
+    client: &Client,
+    urls: &BaseUrls,
+    listen_key: &str,
+) -> Result<(), BinanceError> {
+    let response = client
+        .put(urls.rest_url("/api/v3/userDataStream"))
+        .header("X-MBX-APIKEY", signer()?.api_key)
+        .query(&[("listenKey", listen_key)])
+        .send()
+        .await?;
+    listen_key_response(response).await.map(|_| ())
+}
+
+async fn listen_key_response(response: reqwest::Response) -> Result<String, BinanceError> {
+    let status = response.status();
+    let text = response.text().await?;
+    if !status.is_success() {
+        tracing::error!("Binance::<userDataStream> response: {}", text);
+        return Err(BinanceError::RequestError);
+    }
+    Ok(text)
+}
+
+/// Streams the order updates and balance changes of the spot account until the process ends.
+/// The listen key is kept alive, and replaced once it expires.
+/// Without a listen key the stream reports that it is not streaming, and tries again later.
+async fn run_user_stream(client: Client, urls: BaseUrls, events: Broadcaster<UserEvent>) {
+    loop {
+        let listen_key = match create_listen_key(&client, &urls).await {
+            Ok(listen_key) => listen_key,
+            Err(e) => {
+                tracing::warn!("Binance: no user data stream, orders are polled: {}", e);
+                events.broadcast(UserEvent::Streaming(false));
+                async_helpers::sleep(LISTEN_KEY_RETRY).await;
+                continue;
+            }
+        };
+
+        let options = WebsocketOptions {
+            quiet: true,
+            ..Default::default()
+        };
+        let websocket =
+            Websocket::new_with_options(&format!("{}/ws/{}", urls.ws, listen_key), options);
+        // Subscribed before it connects, so the first connect is not missed
+        let states = websocket.state_rx();
+
+        let mut keepalive_at =
+            server_time::local_millis() + LISTEN_KEY_KEEPALIVE.as_millis() as i64;
+        loop {
+            let wait = (keepalive_at - server_time::local_millis()).max(0) as u64;
+            let timer = Box::pin(async_helpers::sleep(Duration::from_millis(wait)));
+            let state_or_timer = future::select(Box::pin(states.recv()), timer);
+            let message = match future::select(Box::pin(websocket.recv()), state_or_timer).await {
+                Either::Left((Some(message), _)) => message,
+                Either::Left((None, _)) => break,
+                Either::Right((Either::Left((ConnState::Connected, _)), _)) => {
+                    events.broadcast(UserEvent::Streaming(true));
+                    continue;
+                }
+                Either::Right((Either::Left((ConnState::Disconnected(reason), _)), _)) => {
+                    tracing::warn!(
+                        "Binance: user data stream disconnected, orders are polled: {}",
+                        reason
+                    );
+                    events.broadcast(UserEvent::Streaming(false));
+                    continue;
+                }
+                Either::Right((Either::Left(_), _)) => continue,
+                Either::Right((Either::Right(_), _)) => {
+                    if let Err(e) = keepalive_listen_key(&client, &urls, &listen_key).await {
+                        tracing::warn!("Binance: renewing the listen key: {}", e);
+                        break;
+                    }
+                    keepalive_at += LISTEN_KEY_KEEPALIVE.as_millis() as i64;
+                    continue;
+                }
+            };
+
+            match parse_user_message(&message) {
+                Ok(UserMessage::Event(event)) => events.broadcast(event),
+                Ok(UserMessage::ListenKeyExpired) => {
+                    tracing::warn!("Binance: the listen key expired, renewing it");
+                    break;
+                }
+                Ok(UserMessage::Other) => {}
+                Err(e) => tracing::warn!("Binance: unexpected user data {}: {}", message, e),
+            }
+        }
+        // Orders are polled until the stream of the next listen key connects
+        events.broadcast(UserEvent::Streaming(false));
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum UserMessage {
+    Event(UserEvent),
+    ListenKeyExpired,
+    Other,
+}
+
+/// Order statuses of an `executionReport` after which the order never changes again.
+const CLOSED_STATUSES: [&str; 5] = [
+    "FILLED",
+    "CANCELED",
+    "REJECTED",
+    "EXPIRED",
+    "EXPIRED_IN_MATCH",
+];
+
+fn parse_user_message(text: &str) -> Result<UserMessage, serde_json::Error> {
+    #[derive(Deserialize)]
+    #[serde(tag = "e")]
+    enum Message {
+        #[serde(rename = "executionReport")]
+        ExecutionReport {
+            #[serde(rename = "s")]
+            symbol: String,
+            #[serde(rename = "i")]
+            id: u64,
+            #[serde(rename = "X")]
+            status: String,
+            #[serde(rename = "q")]
+            quantity: Decimal,
+            /// Cumulative filled quantity.
+            #[serde(rename = "z")]
+            filled: Decimal,
+            /// Cumulative quote quantity of the fills.
+            #[serde(rename = "Z")]
+            filled_quote: Decimal,
+        },
+        #[serde(rename = "outboundAccountPosition")]
+        AccountPosition {
+            #[serde(rename = "B")]
+            balances: Vec<PositionBalance>,
+        },
+        #[serde(rename = "listenKeyExpired")]
+        ListenKeyExpired,
+        #[serde(other)]
+        Other,
+    }
+
+    #[derive(Deserialize)]
+    struct PositionBalance {
+        #[serde(rename = "a")]
+        asset: String,
+        #[serde(rename = "f")]
+        free: Decimal,
+        #[serde(rename = "l")]
+        locked: Decimal,
+    }
+
+    Ok(match serde_json::from_str(text)? {
+        Message::ExecutionReport {
+            symbol,
+            id,
+            status,
+            quantity,
+            filled,
+            filled_quote,
+        } => {
+            let state = if CLOSED_STATUSES.contains(&status.as_str()) {
+                OrderState::Closed
+            } else {
+                OrderState::Wait
+            };
+            UserMessage::Event(UserEvent::Order {
+                order_token: OrderToken::Binance {
+                    id,
+                    market: Market::Spot,
+                    symbol,
+                },
+                order: Order {
+                    state,
+                    executed_volume: filled,
+                    remaining: quantity - filled,
+                    avg_price: (filled != Decimal::ZERO).then(|| filled_quote / filled),
+                },
+            })
+        }
+        Message::AccountPosition { balances } => UserMessage::Event(UserEvent::Balances(
+            balances
+                .into_iter()
+                .filter_map(|balance| {
+                    let currency = balance.asset.parse().ok()?;
+                    let balance = Balance {
+                        available: balance.free,
+                        locked: balance.locked,
+                    };
+                    Some((currency, balance))
+                })
+                .collect(),
+        )),
+        Message::ListenKeyExpired => UserMessage::ListenKeyExpired,
+        Message::Other => UserMessage::Other,
+    })
 }
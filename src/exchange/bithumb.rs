@@ -1,67 +1,65 @@
-use std::collections::HashSet;
+use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
-use unwrap_let::unwrap_let;
 
 use crate::config::Config;
 use crate::dec;
 use crate::utils::broadcaster::{Broadcaster, Subscription};
 use crate::utils::Decimal;
-use crate::websocket::Websocket;
+use crate::websocket::{StatusHandle, Websocket, WebsocketSender};
 use crate::{
     currency::Currency,
-    exchange::{Balance, Order, OrderState, Unit},
+    exchange::{Balance, Order, OrderState, Unit, WithdrawState},
     utils::async_helpers,
-    utils::http::{self, Client},
+    utils::http::{self, BaseUrls, Client},
+    utils::rate_limiter::{RateLimit, RateLimiter},
+    utils::rounding::round_down_dp,
+    utils::server_time::{self, ServerTime},
+    utils::signing::{BithumbSigner, SignRequest, Signer},
 };
 
-use super::{CandleSticks, Exchange, Market, OrderToken, Orderbook, RealtimeData, Ticker, Trade};
+use super::{
+    markets, CandleSticks, Exchange, Market, OrderToken, Orderbook, RealtimeData, Side, Ticker,
+    Trade,
+};
 
-pub fn connect_key() -> Result<&'static str, BithumbError> {
+fn signer() -> Result<BithumbSigner, BithumbError> {
     Config::get()
         .bithumb
         .as_ref()
-        .map(|config| config.connect_key.as_str())
+        .map(|config| BithumbSigner {
+            connect_key: config.connect_key.clone(),
+            secret_key: config.secret_key.clone(),
+        })
         .ok_or(BithumbError::ConfigNotFound)
 }
 
-pub fn secret_key() -> Result<&'static str, BithumbError> {
+pub fn ko_name() -> Result<String, BithumbError> {
     Config::get()
         .bithumb
         .as_ref()
-        .map(|config| config.secret_key.as_str())
+        .map(|config| config.ko_name.clone())
         .ok_or(BithumbError::ConfigNotFound)
 }
 
-pub fn ko_name() -> Result<&'static str, BithumbError> {
+pub fn en_name() -> Result<String, BithumbError> {
     Config::get()
         .bithumb
         .as_ref()
-        .map(|config| config.ko_name.as_str())
+        .map(|config| config.en_name.clone())
         .ok_or(BithumbError::ConfigNotFound)
 }
 
-pub fn en_name() -> Result<&'static str, BithumbError> {
-    Config::get()
-        .bithumb
-        .as_ref()
-        .map(|config| config.en_name.as_str())
-        .ok_or(BithumbError::ConfigNotFound)
+/// Market name used by the public api and the websocket, e.g. `ETH_BTC` for ETH quoted in BTC.
+fn market_name(pair: (Currency, Currency)) -> String {
+    format!("{}_{}", pair.0, pair.1)
 }
 
-fn gen_api_sign(endpoint: &str, query_string: &str, nonce: u64, secret_key: &str) -> String {
-    use base64::Engine;
-    use hmac::{Hmac, Mac};
-    use sha2::Sha512;
-
-    let parameters = format!("{}\0{}\0{}", endpoint, query_string, nonce);
-
-    let mut mac = Hmac::<Sha512>::new_from_slice(secret_key.as_bytes()).unwrap();
-    mac.update(parameters.as_bytes());
-    base64::engine::general_purpose::STANDARD.encode(hex::encode(mac.finalize().into_bytes()))
+fn public_url(urls: &BaseUrls, endpoint: &str, pair: (Currency, Currency)) -> String {
+    format!("{}/public/{}/{}", urls.rest, endpoint, market_name(pair))
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -72,6 +70,12 @@ pub enum BithumbError {
     #[error("serde_json error: {0}")]
     SerdeJsonError(#[from] serde_json::Error),
 
+    #[error("order of {notional} is below the minimum of {min_notional}")]
+    OrderTooSmall {
+        notional: Decimal,
+        min_notional: Decimal,
+    },
+
     #[error("bid/ask order failed")]
     OrderFailed,
 
@@ -89,23 +93,265 @@ pub enum BithumbError {
 
     #[error("withdraw failed")]
     WithdrawFailed,
+
+    #[error("withdraw fee not found")]
+    WithdrawFeeNotFound,
+
+    #[error("failed to get withdraw status")]
+    WithdrawStatusFailed,
+
+    #[error("failed to get server time")]
+    ServerTimeFailed,
+
+    #[error("invalid order token")]
+    InvalidOrderToken,
+
+    #[error("no asks in the orderbook to price the order")]
+    EmptyOrderbook,
+
+    #[error("{0} is not supported")]
+    Unsupported(&'static str),
+}
+
+/// Offset of the KST timestamps in the public api, UTC+9.
+const KST_OFFSET_MILLIS: i64 = 9 * 60 * 60 * 1000;
+
+/// Bithumb allows 15 public and private requests per second.
+const RATE_LIMIT: RateLimit = RateLimit {
+    capacity: 15,
+    refill_per_sec: 15.0,
+};
+
+/// Production endpoints, overridden by the `[base_urls.bithumb]` config section.
+fn default_urls() -> BaseUrls {
+    BaseUrls::new(
+        "https://api.bithumb.com",
+        "",
+        "wss://pubwss.bithumb.com/pub/ws",
+    )
 }
 
 pub struct Bithumb {
     broadcaster: RealtimeDataBroadcaster,
     http_client: Client,
+    rate_limiter: RateLimiter,
+    clock: ServerTime,
+    urls: BaseUrls,
 }
 
 impl Bithumb {
     pub fn new() -> Self {
-        let broadcaster = RealtimeDataBroadcaster::new();
+        Self::with_urls(BaseUrls::from_config(Self::NAME, default_urls()))
+    }
+
+    /// An exchange talking to `urls`, for testnets and mock servers.
+    pub fn with_urls(urls: BaseUrls) -> Self {
+        let broadcaster = RealtimeDataBroadcaster::new(&urls.ws);
         broadcaster.spawn_and_broadcast();
 
         Self {
             broadcaster,
             http_client: http::client(),
+            rate_limiter: RateLimiter::from_config(Self::NAME, RATE_LIMIT),
+            clock: ServerTime::new(),
+            urls,
+        }
+    }
+
+    /// Nonce of private api requests, adjusted to the server clock.
+    async fn nonce(&self) -> i64 {
+        if self.clock.needs_sync() {
+            if let Err(e) = self.server_time().await {
+                tracing::warn!("Bithumb: failed to sync server time: {}", e);
+            }
         }
+
+        self.clock.now_millis()
+    }
+
+    /// Posts a signed query to `endpoint`, retried like public requests.
+    /// Each attempt is signed again, bithumb rejects a request repeating a nonce.
+    async fn post_signed_query(
+        &self,
+        endpoint: &str,
+        payload: &str,
+    ) -> Result<http::Response, BithumbError> {
+        http::send_signed_with_retry(
+            move || async move {
+                let signed = signer()?.sign(endpoint, payload, self.nonce().await);
+                Ok::<_, BithumbError>(
+                    self.http_client
+                        .post(self.urls.rest_url(endpoint))
+                        .signed(&signed)
+                        .header("Accept", "application/json")
+                        .header("Content-Type", "application/x-www-form-urlencoded")
+                        .body(payload.to_string()),
+                )
+            },
+            &Config::get().http_retry,
+        )
+        .await
     }
+
+    /// The `user_transactions` response of `currency`, of the kind `search` selects.
+    async fn user_transactions(
+        &self,
+        currency: Currency,
+        search: u8,
+    ) -> Result<String, BithumbError> {
+        let endpoint = "/info/user_transactions";
+        let payload = serde_qs::to_string(&serde_json::json!({
+            "endpoint": endpoint,
+            "order_currency": currency.to_string(),
+            "payment_currency": "KRW",
+            "searchGb": search,
+            "count": 50,
+        }))
+        .unwrap();
+        self.rate_limiter.acquire(1).await;
+        let response = self.post_signed_query(endpoint, &payload).await?;
+
+        let status = response.status();
+        let text = response.text().await?;
+        tracing::debug!("Bithumb::user_transactions() response: {}", text);
+        if !status.is_success() {
+            return Err(BithumbError::WithdrawStatusFailed);
+        }
+        Ok(text)
+    }
+
+    /// Fails if the order value is below the minimum notional of the pair.
+    fn check_notional(
+        &self,
+        pair: (Currency, Currency),
+        market: Option<Market>,
+        notional: Decimal,
+    ) -> Result<(), BithumbError> {
+        match self.min_notional(pair, market) {
+            Some(min_notional) if notional < min_notional => Err(BithumbError::OrderTooSmall {
+                notional,
+                min_notional,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// `searchGb` of the withdrawals in progress.
+const SEARCH_WITHDRAWING: u8 = 3;
+/// `searchGb` of the completed withdrawals.
+const SEARCH_WITHDRAWN: u8 = 5;
+
+/// What `withdraw` returns as the id of a withdrawal: `<request time>:<amount>`.
+#[derive(Debug, PartialEq)]
+struct WithdrawalId {
+    /// In milliseconds.
+    requested_at: u64,
+    amount: Decimal,
+}
+
+impl WithdrawalId {
+    fn parse(id: &str) -> Option<Self> {
+        let (requested_at, amount) = id.split_once(':')?;
+        Some(Self {
+            requested_at: requested_at.parse().ok()?,
+            amount: Decimal::from_str(amount).ok()?,
+        })
+    }
+
+    /// True if a `user_transactions` response lists this withdrawal:
+    /// one made after the request, of the amount with or without the fee.
+    fn is_listed(&self, text: &str) -> Result<bool, serde_json::Error> {
+        #[derive(Deserialize)]
+        struct Response {
+            #[serde(default)]
+            data: Vec<Transaction>,
+        }
+
+        #[derive(Deserialize)]
+        struct Transaction {
+            // In microseconds, sent either as a number or a string
+            transfer_date: serde_json::Value,
+            // Withdrawals can be listed as negative, e.g. `- 0.1`
+            units: String,
+            #[serde(default)]
+            fee: Option<String>,
+        }
+
+        let amount = |value: &str| Decimal::from_str(&value.replace([' ', '-'], "")).ok();
+        let response: Response = serde_json::from_str(text)?;
+        Ok(response.data.iter().any(|transaction| {
+            let transfer_date = match &transaction.transfer_date {
+                serde_json::Value::Number(value) => value.as_u64(),
+                serde_json::Value::String(value) => value.parse().ok(),
+                _ => None,
+            };
+            let units = amount(&transaction.units);
+            let fee = transaction
+                .fee
+                .as_deref()
+                .and_then(amount)
+                .unwrap_or_default();
+            transfer_date.is_some_and(|date| date / 1000 >= self.requested_at)
+                && units.is_some_and(|units| units == self.amount || units + fee == self.amount)
+        }))
+    }
+}
+
+/// Parses `/public/withdraw/minimum/<currency>`, which lists the fee of every network.
+/// Without a network the one named after the currency is used, or the only one listed.
+fn parse_withdraw_fee(
+    text: &str,
+    currency: Currency,
+    network: Option<&str>,
+) -> Result<Option<Decimal>, serde_json::Error> {
+    #[derive(Deserialize)]
+    struct Response {
+        #[serde(default)]
+        data: Vec<Network>,
+    }
+
+    #[derive(Deserialize)]
+    struct Network {
+        net_type: String,
+        withdraw_fee: Decimal,
+    }
+
+    let response: Response = serde_json::from_str(text)?;
+    let fee = |net_type: &str| {
+        response
+            .data
+            .iter()
+            .find(|network| network.net_type.eq_ignore_ascii_case(net_type))
+            .map(|network| network.withdraw_fee)
+    };
+    Ok(match network {
+        Some(network) => fee(network),
+        None => fee(currency.as_str()).or(match response.data.as_slice() {
+            [network] => Some(network.withdraw_fee),
+            _ => None,
+        }),
+    })
+}
+
+/// Parses the `ticker/ALL_<quote>` response, its data is keyed by the base currency
+/// next to a `date` entry.
+fn parse_markets(
+    text: &str,
+    quote: Currency,
+) -> Result<Vec<(Currency, Currency, Market)>, serde_json::Error> {
+    #[derive(Deserialize)]
+    struct Response {
+        data: HashMap<String, serde_json::Value>,
+    }
+
+    let response: Response = serde_json::from_str(text)?;
+    Ok(response
+        .data
+        .iter()
+        .filter(|(_, ticker)| ticker.is_object())
+        .filter_map(|(base, _)| Some((base.parse().ok()?, quote, Market::Spot)))
+        .collect())
 }
 
 impl Exchange for Bithumb {
@@ -121,21 +367,51 @@ impl Exchange for Bithumb {
         self.broadcaster.subscribe(pair)
     }
 
+    fn connection_status(&self) -> Option<StatusHandle> {
+        // The orderbook stream, the one widgets show
+        Some(self.broadcaster.ws1.status())
+    }
+
+    fn rate_limit_utilization(&self) -> Option<f64> {
+        Some(self.rate_limiter.utilization())
+    }
+
+    fn clock_drift(&self) -> Option<i64> {
+        self.clock.drift_millis()
+    }
+
+    async fn markets(&self) -> Result<Vec<(Currency, Currency, Market)>, Self::Error> {
+        if let Some(markets) = markets::fresh(Self::NAME, &self.urls) {
+            return Ok(markets);
+        }
+        tracing::debug!("Bithumb::markets()");
+
+        let mut markets = Vec::new();
+        for quote in [Currency::KRW, Currency::BTC] {
+            self.rate_limiter.acquire(1).await;
+            let request = self
+                .http_client
+                .get(format!("{}/public/ticker/ALL_{}", self.urls.rest, quote));
+            let response = http::send_with_retry(request, &Config::get().http_retry).await?;
+            let text = response.text().await?;
+            markets.extend(parse_markets(&text, quote)?);
+        }
+
+        Ok(markets::store(Self::NAME, &self.urls, markets))
+    }
+
     async fn orderbook(
         &self,
         pair: (Currency, Currency),
         _market: Option<Market>,
     ) -> Result<Orderbook, Self::Error> {
+        self.rate_limiter.acquire(1).await;
         let request = self
             .http_client
-            .get(format!(
-                "https://api.bithumb.com/public/orderbook/{:?}_{:?}",
-                pair.0, pair.1,
-            ))
-            .send()
-            .await?;
+            .get(public_url(&self.urls, "orderbook", pair));
+        let response = http::send_with_retry(request, &Config::get().http_retry).await?;
 
-        let response = request.text().await?;
+        let response = response.text().await?;
         #[derive(Deserialize)]
         struct Response1 {
             data: Response2,
@@ -174,7 +450,7 @@ impl Exchange for Bithumb {
             })
             .collect();
 
-        Ok(Orderbook { pair, bids, asks })
+        Ok(Orderbook { pair, bids, asks }.sorted())
     }
 
     async fn balance(
@@ -184,9 +460,9 @@ impl Exchange for Bithumb {
     ) -> Result<Balance, Self::Error> {
         let endpoint = "/info/balance";
 
+        // KRW is not a valid `currency`, but its fields come with every currency
         let mut payload = serde_json::json!({
             "endpoint": endpoint,
-
         });
 
         if currency != Currency::KRW {
@@ -194,22 +470,8 @@ impl Exchange for Bithumb {
         }
 
         let payload = serde_qs::to_string(&payload).unwrap();
-        let nonce = chrono::Utc::now().timestamp_millis() as u64;
-        let api_sign = gen_api_sign(endpoint, &payload, nonce, secret_key()?);
-
-        let response = self
-            .http_client
-            .post(format!("https://api.bithumb.com/info/balance"))
-            .header("api-client-type", "0")
-            .header("Api-Key", connect_key()?)
-            .header("Api-Nonce", nonce.to_string())
-            .header("Api-Sign", api_sign)
-            .header("Accept", "application/json")
-            .header("Content-Type", "application/x-www-form-urlencoded")
-            .body(payload)
-            .send()
-            .await
-            .unwrap();
+        self.rate_limiter.acquire(1).await;
+        let response = self.post_signed_query(endpoint, &payload).await?;
 
         let status = response.status();
         let text = response.text().await.unwrap();
@@ -226,33 +488,83 @@ impl Exchange for Bithumb {
 
         let text: Resposne1 = serde_json::from_str(&text)?;
 
-        let in_use;
-        let available;
-        if currency == Currency::KRW {
-            in_use = serde_json::Value::String("0".to_string());
-            available = text.data.get("total_krw").unwrap();
-        } else {
-            in_use = text
-                .data
-                .get(format!("in_use_{}", currency.to_string().to_lowercase()))
-                .unwrap()
-                .clone();
-            available = text
+        // Every currency, KRW and BTC included, comes as `available_{currency}` and `in_use_{currency}`.
+        // `total_krw` also counts the KRW locked in orders, so it is not the available amount.
+        let name = currency.to_string().to_lowercase();
+        let field = |key: String| {
+            text.data
+                .get(&key)
+                .and_then(|value| value.as_str())
+                .and_then(|value| Decimal::from_str(value).ok())
+                .ok_or(BithumbError::BalanceFailed)
+        };
+
+        Ok(Balance {
+            locked: field(format!("in_use_{}", name))?,
+            available: field(format!("available_{}", name))?,
+        })
+    }
+
+    async fn balances(
+        &self,
+        _market: Option<Market>,
+    ) -> Result<HashMap<Currency, Balance>, Self::Error> {
+        let endpoint = "/info/balance";
+
+        let payload = serde_qs::to_string(&serde_json::json!({
+            "endpoint": endpoint,
+            "currency": "ALL",
+        }))
+        .unwrap();
+        self.rate_limiter.acquire(1).await;
+        let response = self.post_signed_query(endpoint, &payload).await?;
+
+        let status = response.status();
+        let text = response.text().await?;
+
+        tracing::debug!("Bithumb::balances() response: {}", text);
+        if !status.is_success() {
+            return Err(BithumbError::BalanceFailed);
+        }
+
+        #[derive(Deserialize)]
+        struct Response {
+            pub data: HashMap<String, serde_json::Value>,
+        }
+
+        let response: Response = serde_json::from_str(&text)?;
+        let field = |name: String| {
+            response
                 .data
-                .get(format!("available_{}", currency.to_string().to_lowercase()))
-                .unwrap();
+                .get(&name)
+                .and_then(|value| value.as_str())
+                .and_then(|value| Decimal::from_str(value).ok())
         };
 
-        unwrap_let!(serde_json::Value::String(in_use) = in_use);
-        unwrap_let!(serde_json::Value::String(available) = available);
+        // Every currency comes as `available_{currency}` and `in_use_{currency}` fields
+        Ok(response
+            .data
+            .keys()
+            .filter_map(|key| key.strip_prefix("available_"))
+            .filter_map(|name| {
+                let balance = Balance {
+                    available: field(format!("available_{}", name))?,
+                    locked: field(format!("in_use_{}", name))?,
+                };
+                if balance.is_empty() {
+                    return None;
+                }
 
-        let in_use = Decimal::from_str(&in_use).unwrap();
-        let available = Decimal::from_str(&available).unwrap();
+                Some((Currency::from_str(&name.to_uppercase()).ok()?, balance))
+            })
+            .collect())
+    }
 
-        Ok(Balance {
-            locked: in_use,
-            available,
-        })
+    fn min_notional(&self, pair: (Currency, Currency), _market: Option<Market>) -> Option<Decimal> {
+        match pair.1 {
+            Currency::KRW => Some(dec!(5000)),
+            _ => None,
+        }
     }
 
     async fn bid_limit(
@@ -260,9 +572,10 @@ impl Exchange for Bithumb {
         pair: (Currency, Currency),
         price: Decimal,
         amount: Decimal,
-        _market: Option<Market>,
+        market: Option<Market>,
     ) -> Result<OrderToken, Self::Error> {
         tracing::info!("Bithumb::bid_limit({:?})", pair);
+        self.check_notional(pair, market, price * amount)?;
         let order_currency = pair.0.to_string();
         let payment_currency = pair.1.to_string();
 
@@ -277,16 +590,14 @@ impl Exchange for Bithumb {
         }))
         .unwrap();
 
-        let nonce = chrono::Utc::now().timestamp_millis() as u64;
-        let api_sign = gen_api_sign(endpoint, &payload, nonce, secret_key()?);
+        self.rate_limiter.acquire(1).await;
+        let nonce = self.nonce().await;
+        let signed = signer()?.sign(endpoint, &payload, nonce);
 
         let response = self
             .http_client
-            .post(format!("https://api.bithumb.com/trade/place"))
-            .header("api-client-type", "0")
-            .header("Api-Key", connect_key()?)
-            .header("Api-Nonce", nonce.to_string())
-            .header("Api-Sign", api_sign)
+            .post(format!("{}/trade/place", self.urls.rest))
+            .signed(&signed)
             .header("Accept", "application/json")
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(payload)
@@ -302,14 +613,16 @@ impl Exchange for Bithumb {
             return Err(BithumbError::OrderFailed);
         }
 
-        Ok(OrderToken::Array(vec![
-            response
-                .get("order_id")
-                .ok_or(BithumbError::OrderFailed)?
-                .clone(),
-            OrderToken::String(order_currency),
-            OrderToken::String(payment_currency),
-        ]))
+        let id = response
+            .get("order_id")
+            .and_then(|id| id.as_str())
+            .ok_or(BithumbError::OrderFailed)?;
+
+        Ok(OrderToken::Bithumb {
+            id: id.to_string(),
+            order_currency,
+            payment_currency,
+        })
     }
 
     async fn bid_market(
@@ -319,8 +632,9 @@ impl Exchange for Bithumb {
         market: Option<Market>,
     ) -> Result<OrderToken, Self::Error> {
         tracing::info!("Bithumb::bid_market({:?}, {})", pair, quote_qty);
+        self.check_notional(pair, market, quote_qty)?;
         let orderbook = self.orderbook(pair, market).await?;
-        let ask = orderbook.asks.first().unwrap();
+        let ask = orderbook.best_ask().ok_or(BithumbError::EmptyOrderbook)?;
 
         let order_currency = pair.0.to_string();
         let payment_currency = pair.1.to_string();
@@ -329,22 +643,20 @@ impl Exchange for Bithumb {
 
         let payload = serde_qs::to_string(&serde_json::json!({
             "endpoint": endpoint,
-            "units": (quote_qty / ask.price).round_dp(4) - dec!(0.0001),
+            "units": round_down_dp(quote_qty / ask, 4),
             "order_currency": order_currency,
             "payment_currency": payment_currency,
         }))
         .unwrap();
 
-        let nonce = chrono::Utc::now().timestamp_millis() as u64;
-        let api_sign = gen_api_sign(endpoint, &payload, nonce, secret_key()?);
+        self.rate_limiter.acquire(1).await;
+        let nonce = self.nonce().await;
+        let signed = signer()?.sign(endpoint, &payload, nonce);
 
         let response = self
             .http_client
-            .post(format!("https://api.bithumb.com/trade/market_buy"))
-            .header("api-client-type", "0")
-            .header("Api-Key", connect_key()?)
-            .header("Api-Nonce", nonce.to_string())
-            .header("Api-Sign", api_sign)
+            .post(format!("{}/trade/market_buy", self.urls.rest))
+            .signed(&signed)
             .header("Accept", "application/json")
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(payload)
@@ -362,14 +674,16 @@ impl Exchange for Bithumb {
         }
 
         async_helpers::sleep(std::time::Duration::from_millis(250)).await;
-        Ok(OrderToken::Array(vec![
-            response
-                .get("order_id")
-                .ok_or(BithumbError::OrderFailed)?
-                .clone(),
-            OrderToken::String(order_currency),
-            OrderToken::String(payment_currency),
-        ]))
+        let id = response
+            .get("order_id")
+            .and_then(|id| id.as_str())
+            .ok_or(BithumbError::OrderFailed)?;
+
+        Ok(OrderToken::Bithumb {
+            id: id.to_string(),
+            order_currency,
+            payment_currency,
+        })
     }
 
     async fn ask_limit(
@@ -377,9 +691,10 @@ impl Exchange for Bithumb {
         pair: (Currency, Currency),
         price: Decimal,
         amount: Decimal,
-        _market: Option<Market>,
+        market: Option<Market>,
     ) -> Result<OrderToken, Self::Error> {
         tracing::info!("Bithumb::ask_limit({:?})", pair);
+        self.check_notional(pair, market, price * amount)?;
         let order_currency = pair.0.to_string();
         let payment_currency = pair.1.to_string();
 
@@ -395,16 +710,14 @@ impl Exchange for Bithumb {
         }))
         .unwrap();
 
-        let nonce = chrono::Utc::now().timestamp_millis() as u64;
-        let api_sign = gen_api_sign(endpoint, &payload, nonce, secret_key()?);
+        self.rate_limiter.acquire(1).await;
+        let nonce = self.nonce().await;
+        let signed = signer()?.sign(endpoint, &payload, nonce);
 
         let response = self
             .http_client
-            .post(format!("https://api.bithumb.com/trade/place"))
-            .header("api-client-type", "0")
-            .header("Api-Key", connect_key()?)
-            .header("Api-Nonce", nonce.to_string())
-            .header("Api-Sign", api_sign)
+            .post(format!("{}/trade/place", self.urls.rest))
+            .signed(&signed)
             .header("Accept", "application/json")
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(payload)
@@ -421,14 +734,16 @@ impl Exchange for Bithumb {
             return Err(BithumbError::OrderFailed);
         }
 
-        Ok(OrderToken::Array(vec![
-            response
-                .get("order_id")
-                .ok_or(BithumbError::OrderFailed)?
-                .clone(),
-            OrderToken::String(order_currency),
-            OrderToken::String(payment_currency),
-        ]))
+        let id = response
+            .get("order_id")
+            .and_then(|id| id.as_str())
+            .ok_or(BithumbError::OrderFailed)?;
+
+        Ok(OrderToken::Bithumb {
+            id: id.to_string(),
+            order_currency,
+            payment_currency,
+        })
     }
 
     async fn ask_market(
@@ -451,16 +766,14 @@ impl Exchange for Bithumb {
         }))
         .unwrap();
 
-        let nonce = chrono::Utc::now().timestamp_millis() as u64;
-        let api_sign = gen_api_sign(endpoint, &payload, nonce, secret_key()?);
+        self.rate_limiter.acquire(1).await;
+        let nonce = self.nonce().await;
+        let signed = signer()?.sign(endpoint, &payload, nonce);
 
         let response = self
             .http_client
-            .post(format!("https://api.bithumb.com/trade/market_sell"))
-            .header("api-client-type", "0")
-            .header("Api-Key", connect_key()?)
-            .header("Api-Nonce", nonce.to_string())
-            .header("Api-Sign", api_sign)
+            .post(format!("{}/trade/market_sell", self.urls.rest))
+            .signed(&signed)
             .header("Accept", "application/json")
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(payload)
@@ -478,19 +791,40 @@ impl Exchange for Bithumb {
         }
 
         async_helpers::sleep(std::time::Duration::from_millis(250)).await;
-        Ok(OrderToken::Array(vec![
-            response
-                .get("order_id")
-                .ok_or(BithumbError::OrderFailed)?
-                .clone(),
-            OrderToken::String(order_currency),
-            OrderToken::String(payment_currency),
-        ]))
+        let id = response
+            .get("order_id")
+            .and_then(|id| id.as_str())
+            .ok_or(BithumbError::OrderFailed)?;
+
+        Ok(OrderToken::Bithumb {
+            id: id.to_string(),
+            order_currency,
+            payment_currency,
+        })
+    }
+
+    async fn stop_limit(
+        &self,
+        _pair: (Currency, Currency),
+        _stop_price: Decimal,
+        _limit_price: Decimal,
+        _amount: Decimal,
+        _side: Side,
+        _market: Option<Market>,
+    ) -> Result<OrderToken, Self::Error> {
+        // Bithumb has no native stop orders
+        Err(BithumbError::Unsupported("stop order"))
     }
 
     async fn view_order(&self, order_token: &OrderToken) -> Result<Order, Self::Error> {
-        unwrap_let!(OrderToken::Array(order_info) = order_token);
-        unwrap_let!([order_id, order_currency, payment_currency] = order_info.as_slice());
+        let OrderToken::Bithumb {
+            id: order_id,
+            order_currency,
+            payment_currency,
+        } = order_token
+        else {
+            return Err(BithumbError::InvalidOrderToken);
+        };
 
         let endpoint = "/info/order_detail";
         let payload = serde_qs::to_string(&serde_json::json!({
@@ -501,16 +835,14 @@ impl Exchange for Bithumb {
         }))
         .unwrap();
 
-        let nonce = chrono::Utc::now().timestamp_millis() as u64;
-        let api_sign = gen_api_sign(endpoint, &payload, nonce, secret_key()?);
+        self.rate_limiter.acquire(1).await;
+        let nonce = self.nonce().await;
+        let signed = signer()?.sign(endpoint, &payload, nonce);
 
         let response = self
             .http_client
-            .post(format!("https://api.bithumb.com/info/order_detail"))
-            .header("api-client-type", "0")
-            .header("Api-Key", connect_key()?)
-            .header("Api-Nonce", nonce.to_string())
-            .header("Api-Sign", api_sign)
+            .post(format!("{}/info/order_detail", self.urls.rest))
+            .signed(&signed)
             .header("Accept", "application/json")
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(payload)
@@ -533,21 +865,32 @@ impl Exchange for Bithumb {
         #[derive(Deserialize)]
         struct Response2 {
             pub order_status: String,
+            // Market orders have no quantity
+            pub order_qty: Option<Decimal>,
             pub contract: Vec<Contract>,
         }
 
         #[derive(Deserialize)]
         struct Contract {
             pub units: Decimal,
+            pub total: Decimal,
         }
 
         let response: Resposne1 = serde_json::from_str(&text)?;
+        let units: Decimal = response.data.contract.iter().map(|c| c.units).sum();
+        let total: Decimal = response.data.contract.iter().map(|c| c.total).sum();
         let order = Order {
-            executed_volume: response.data.contract.iter().map(|c| c.units).sum(),
+            executed_volume: units,
             state: match response.data.order_status.as_str() {
                 "Completed" | "Cancel" => OrderState::Closed,
                 _ => OrderState::Wait,
             },
+            remaining: response
+                .data
+                .order_qty
+                .map(|qty| (qty - units).max(Decimal::ZERO))
+                .unwrap_or_default(),
+            avg_price: (units > Decimal::ZERO).then(|| total / units),
         };
 
         Ok(order)
@@ -571,7 +914,7 @@ impl Exchange for Bithumb {
         address1: &str,
         address2: Option<&str>,
         network: Option<&str>,
-    ) -> Result<(), Self::Error> {
+    ) -> Result<String, Self::Error> {
         let endpoint = "/trade/btc_withdrawal";
         let mut query_string = serde_json::json!({
             "endpoint": endpoint,
@@ -593,16 +936,14 @@ impl Exchange for Bithumb {
         }
 
         let payload = serde_qs::to_string(&query_string).unwrap();
-        let nonce = chrono::Utc::now().timestamp_millis() as u64;
-        let api_sign = gen_api_sign(endpoint, &payload, nonce, secret_key()?);
+        self.rate_limiter.acquire(1).await;
+        let nonce = self.nonce().await;
+        let signed = signer()?.sign(endpoint, &payload, nonce);
 
         let response = self
             .http_client
-            .post(format!("https://api.bithumb.com/trade/btc_withdrawal"))
-            .header("api-client-type", "0")
-            .header("Api-Key", connect_key()?)
-            .header("Api-Nonce", nonce.to_string())
-            .header("Api-Sign", api_sign)
+            .post(format!("{}/trade/btc_withdrawal", self.urls.rest))
+            .signed(&signed)
             .header("Accept", "application/json")
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(payload)
@@ -617,10 +958,36 @@ impl Exchange for Bithumb {
         let text = response.text().await.unwrap();
         tracing::info!("Bithumb::withdraw() response: {}", text);
 
-        status
-            .is_success()
-            .then(|| ())
-            .ok_or(BithumbError::WithdrawFailed)
+        if !status.is_success() {
+            return Err(BithumbError::WithdrawFailed);
+        }
+
+        // Bithumb does not return an id for withdrawals, the request time and the amount
+        // are used instead and `withdraw_status` looks for a withdrawal transaction matching them.
+        Ok(format!("{}:{}", nonce, amount.normalize()))
+    }
+
+    /// Bithumb lists the withdrawals in progress and the completed ones, but not the failed ones:
+    /// those stay pending until `wait_withdraw` gives up.
+    async fn withdraw_status(
+        &self,
+        currency: Currency,
+        id: &str,
+    ) -> Result<WithdrawState, Self::Error> {
+        tracing::debug!("Bithumb::withdraw_status({:?}, {})", currency, id);
+
+        let withdrawal = WithdrawalId::parse(id).ok_or(BithumbError::WithdrawStatusFailed)?;
+        let withdrawn = self.user_transactions(currency, SEARCH_WITHDRAWN).await?;
+        if withdrawal.is_listed(&withdrawn)? {
+            return Ok(WithdrawState::Done);
+        }
+
+        let withdrawing = self.user_transactions(currency, SEARCH_WITHDRAWING).await?;
+        Ok(if withdrawal.is_listed(&withdrawing)? {
+            WithdrawState::Processing
+        } else {
+            WithdrawState::Pending
+        })
     }
 
     async fn candlesticks(
@@ -630,11 +997,9 @@ impl Exchange for Bithumb {
     ) -> Result<CandleSticks, Self::Error> {
         use num_traits::ToPrimitive;
 
-        let url = format!(
-            "https://api.bithumb.com/public/candlestick/{:?}_{:?}/10m",
-            pair.0, pair.1
-        );
+        let url = format!("{}/10m", public_url(&self.urls, "candlestick", pair));
 
+        self.rate_limiter.acquire(1).await;
         let response = self.http_client.get(url).send().await?;
         let text = response.text().await?;
 
@@ -659,9 +1024,93 @@ impl Exchange for Bithumb {
         Ok(CandleSticks { pair, tickers })
     }
 
+    async fn ticker(
+        &self,
+        pair: (Currency, Currency),
+        _market: Option<Market>,
+    ) -> Result<Decimal, Self::Error> {
+        self.rate_limiter.acquire(1).await;
+        let request = self.http_client.get(public_url(&self.urls, "ticker", pair));
+        let response = http::send_with_retry(request, &Config::get().http_retry).await?;
+        let text = response.text().await?;
+
+        #[derive(Deserialize)]
+        struct Response1 {
+            data: Response2,
+        }
+
+        #[derive(Deserialize)]
+        struct Response2 {
+            closing_price: Decimal,
+        }
+
+        let response: Response1 = serde_json::from_str(&text)?;
+        Ok(response.data.closing_price)
+    }
+
+    async fn recent_trades(
+        &self,
+        pair: (Currency, Currency),
+        _market: Option<Market>,
+        limit: usize,
+    ) -> Result<Vec<Trade>, Self::Error> {
+        self.rate_limiter.acquire(1).await;
+        let request = self
+            .http_client
+            .get(public_url(&self.urls, "transaction_history", pair))
+            .query(&[("count", limit.min(100))]);
+        let response = http::send_with_retry(request, &Config::get().http_retry).await?;
+        let text = response.text().await?;
+
+        #[derive(Deserialize)]
+        struct Response1 {
+            data: Vec<Response2>,
+        }
+
+        #[derive(Deserialize)]
+        struct Response2 {
+            transaction_date: String,
+            #[serde(rename = "type")]
+            kind: String,
+            units_traded: Decimal,
+            price: Decimal,
+            #[serde(default)]
+            cont_no: Option<String>,
+        }
+
+        let response: Response1 = serde_json::from_str(&text)?;
+        let mut trades = Vec::new();
+        for trade in response.data {
+            // Bithumb reports the time in KST without an offset
+            let Ok(time) =
+                chrono::NaiveDateTime::parse_from_str(&trade.transaction_date, "%Y-%m-%d %H:%M:%S")
+            else {
+                continue;
+            };
+
+            trades.push(Trade {
+                pair,
+                timestamp: time.and_utc().timestamp_millis() - KST_OFFSET_MILLIS,
+                price: trade.price,
+                amount: trade.units_traded,
+                is_bid: trade.kind == "bid",
+                id: trade.cont_no,
+            });
+        }
+
+        trades.sort_by_key(|trade| trade.timestamp);
+        Ok(trades)
+    }
+
     async fn cancel_order(&self, order_token: &OrderToken) -> Result<Decimal, Self::Error> {
-        unwrap_let!(OrderToken::Array(order_info) = order_token);
-        unwrap_let!([order_id, order_currency, payment_currency] = order_info.as_slice());
+        let OrderToken::Bithumb {
+            id: order_id,
+            order_currency,
+            payment_currency,
+        } = order_token
+        else {
+            return Err(BithumbError::InvalidOrderToken);
+        };
 
         let endpoint = "/trade/cancel";
         let payload = serde_qs::to_string(&serde_json::json!({
@@ -672,16 +1121,14 @@ impl Exchange for Bithumb {
         }))
         .unwrap();
 
-        let nonce = chrono::Utc::now().timestamp_millis() as u64;
-        let api_sign = gen_api_sign(endpoint, &payload, nonce, secret_key()?);
+        self.rate_limiter.acquire(1).await;
+        let nonce = self.nonce().await;
+        let signed = signer()?.sign(endpoint, &payload, nonce);
 
         let response = self
             .http_client
-            .post(format!("https://api.bithumb.com/trade/cancel"))
-            .header("api-client-type", "0")
-            .header("Api-Key", connect_key()?)
-            .header("Api-Nonce", nonce.to_string())
-            .header("Api-Sign", api_sign)
+            .post(format!("{}/trade/cancel", self.urls.rest))
+            .signed(&signed)
             .header("Accept", "application/json")
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(payload)
@@ -706,6 +1153,30 @@ impl Exchange for Bithumb {
         Ok(Decimal::ZERO)
     }
 
+    async fn withdraw_fee(
+        &self,
+        currency: Currency,
+        network: Option<&str>,
+    ) -> Result<Decimal, Self::Error> {
+        tracing::debug!("Bithumb::withdraw_fee({:?}, {:?})", currency, network);
+
+        self.rate_limiter.acquire(1).await;
+        let request = self.http_client.get(
+            self.urls
+                .rest_url(&format!("/public/withdraw/minimum/{}", currency)),
+        );
+        let response = http::send_with_retry(request, &Config::get().http_retry).await?;
+
+        let status = response.status();
+        let text = response.text().await?;
+        tracing::debug!("Bithumb::withdraw_fee() response: {}", text);
+        if !status.is_success() {
+            return Err(BithumbError::WithdrawFeeNotFound);
+        }
+
+        parse_withdraw_fee(&text, currency, network)?.ok_or(BithumbError::WithdrawFeeNotFound)
+    }
+
     async fn set_leverage(
         &self,
         _pair: Option<(Currency, Currency)>,
@@ -713,6 +1184,28 @@ impl Exchange for Bithumb {
     ) -> Result<(), Self::Error> {
         unimplemented!()
     }
+
+    async fn server_time(&self) -> Result<i64, Self::Error> {
+        // Bithumb has no time api, so the time is read from the `Date` header of a public api
+        self.rate_limiter.acquire(1).await;
+        let sent = server_time::local_millis();
+        let response = self
+            .http_client
+            .get(self.urls.rest_url("/public/ticker/BTC_KRW"))
+            .send()
+            .await?;
+        let received = server_time::local_millis();
+
+        let server = response
+            .headers()
+            .get(http::header::DATE)
+            .and_then(|date| date.to_str().ok())
+            .and_then(server_time::parse_http_date)
+            .ok_or(BithumbError::ServerTimeFailed)?;
+
+        self.clock.update(server, sent, received);
+        Ok(server)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -728,149 +1221,346 @@ pub enum BithumbItem {
         buy_sell_gb: String,
         cont_amt: Decimal,
         cont_price: Decimal,
+        #[serde(default)]
+        cont_no: Option<String>,
     },
 }
 
+fn parse_symbol(symbol: &str) -> Option<(Currency, Currency)> {
+    let (base, quote) = symbol.split_once('_')?;
+    Some((
+        Currency::from_str(base).ok()?,
+        Currency::from_str(quote).ok()?,
+    ))
+}
+
+impl BithumbItem {
+    fn into_realtime_data(self) -> Option<((Currency, Currency), RealtimeData)> {
+        match self {
+            BithumbItem::OrderbookSnapshot { symbol, asks, bids } => {
+                let pair = parse_symbol(&symbol)?;
+                let orderbook = Orderbook {
+                    pair,
+                    bids: bids
+                        .into_iter()
+                        .map(|(price, amount)| Unit { price, amount })
+                        .collect(),
+                    asks: asks
+                        .into_iter()
+                        .map(|(price, amount)| Unit { price, amount })
+                        .collect(),
+                };
+                Some((pair, RealtimeData::Orderbook(orderbook.normalize())))
+            }
+            BithumbItem::Transaction {
+                symbol,
+                buy_sell_gb,
+                cont_amt,
+                cont_price,
+                cont_no,
+            } => {
+                let pair = parse_symbol(&symbol)?;
+                let trade = Trade {
+                    pair,
+                    timestamp: chrono::Utc::now().timestamp_millis(),
+                    price: cont_price,
+                    amount: cont_amt,
+                    is_bid: buy_sell_gb == "B",
+                    id: cont_no,
+                };
+                Some((pair, RealtimeData::Trade(trade)))
+            }
+        }
+    }
+}
+
+/// A broadcaster per subscribed pair, so subscribers only wake up for their own pair.
+#[derive(Default)]
+struct Topics {
+    topics: HashMap<(Currency, Currency), Broadcaster<RealtimeData>>,
+}
+
+impl Topics {
+    /// Returns the subscription, and true if the pair was not subscribed before.
+    fn subscribe(&mut self, pair: (Currency, Currency)) -> (Subscription<RealtimeData>, bool) {
+        let is_new = !self.topics.contains_key(&pair);
+        let subscription = self
+            .topics
+            .entry(pair)
+            .or_insert_with(Broadcaster::new)
+            .subscribe();
+        (subscription, is_new)
+    }
+
+    /// Sends data to the subscribers of `pair`.
+    /// Returns true if the pair has no subscribers left, in which case it is removed.
+    fn route(&mut self, pair: (Currency, Currency), data: RealtimeData) -> bool {
+        let Some(topic) = self.topics.get(&pair) else {
+            return false;
+        };
+
+        topic.broadcast(data);
+        if topic.subscriber_count() > 0 {
+            return false;
+        }
+
+        self.topics.remove(&pair);
+        true
+    }
+
+    fn symbols(&self) -> Vec<String> {
+        self.topics.keys().map(|pair| market_name(*pair)).collect()
+    }
+}
+
+/// Replaces the symbols a public websocket streams `kind` of.
+fn symbols_request(kind: &str, symbols: &[String]) -> String {
+    serde_json::json!({
+        "type": kind,
+        "symbols": symbols,
+    })
+    .to_string()
+}
+
+/// Routes the messages of the public websockets, shared with their tasks.
+/// It only holds senders of the websockets, so they stop once the broadcaster is dropped.
 #[derive(Clone)]
+struct Router {
+    topics: Arc<Mutex<Topics>>,
+
+    ws1: WebsocketSender,
+    ws2: WebsocketSender,
+}
+
+impl Router {
+    /// Replaces the symbols streamed by the websockets.
+    async fn send_symbols(&self, symbols: &[String]) {
+        self.ws1
+            .send_async(&symbols_request("orderbooksnapshot", symbols))
+            .await;
+        self.ws2
+            .send_async(&symbols_request("transaction", symbols))
+            .await;
+    }
+
+    async fn route(&self, data: &str) {
+        let Ok(item) = serde_json::from_str::<BithumbItem>(data) else {
+            return;
+        };
+        let Some((pair, data)) = item.into_realtime_data() else {
+            return;
+        };
+
+        let symbols = {
+            let mut topics = self.topics.lock().unwrap();
+            if !topics.route(pair, data) {
+                return;
+            }
+            topics.symbols()
+        };
+
+        // The last subscriber of the pair is gone, stop streaming it.
+        // With no pairs left the stale subscription is kept and its data ignored.
+        if !symbols.is_empty() {
+            self.send_symbols(&symbols).await;
+        }
+    }
+}
+
 pub struct RealtimeDataBroadcaster {
-    subscribed: Arc<Mutex<HashSet<(Currency, Currency)>>>,
-    broadcaster: Broadcaster<RealtimeData>,
+    router: Router,
 
     ws1: Websocket,
     ws2: Websocket,
 }
 
 impl RealtimeDataBroadcaster {
-    pub fn new() -> Self {
+    pub fn new(ws: &str) -> Self {
+        let (ws1, ws2) = (Websocket::new(ws), Websocket::new(ws));
         Self {
-            subscribed: Arc::new(Mutex::new(HashSet::new())),
-            broadcaster: Broadcaster::new(),
-
-            ws1: Websocket::new("wss://pubwss.bithumb.com/pub/ws"),
-            ws2: Websocket::new("wss://pubwss.bithumb.com/pub/ws"),
+            router: Router {
+                topics: Arc::new(Mutex::new(Topics::default())),
+                ws1: ws1.sender(),
+                ws2: ws2.sender(),
+            },
+            ws1,
+            ws2,
         }
     }
 
     fn spawn_and_broadcast(&self) {
-        {
-            let broadcaster = self.clone();
+        // Each websocket streams one kind of data, for the same symbols
+        for (ws, kind) in [(&self.ws1, "orderbooksnapshot"), (&self.ws2, "transaction")] {
+            let receiver = ws.receiver();
+            let router = self.router.clone();
             async_helpers::spawn(async move {
-                loop {
-                    broadcaster.recv_orderbook_data_and_broadcast().await;
+                while let Some(data) = receiver.recv().await {
+                    router.route(&data).await;
                 }
             });
-        }
-        {
-            let broadcaster = self.clone();
-            async_helpers::spawn(async move {
-                loop {
-                    broadcaster.recv_transaction_data_and_broadcast().await;
+
+            let topics = self.router.topics.clone();
+            ws.on_connected(move |ws| {
+                let symbols = topics.lock().unwrap().symbols();
+                if !symbols.is_empty() {
+                    ws.send(&symbols_request(kind, &symbols));
                 }
             });
         }
     }
 
     fn subscribe(&self, pair: (Currency, Currency)) -> Subscription<RealtimeData> {
-        let mut subscribed = self.subscribed.lock().unwrap();
-        if subscribed.insert(pair) {
-            let pairs: Vec<_> = subscribed
-                .iter()
-                .map(|(base, quote)| format!("{:?}_{:?}", base, quote))
-                .collect();
-
-            self.ws1.send(
-                &serde_json::json!({
-                    "type": "orderbooksnapshot",
-                    "symbols": pairs,
-                })
-                .to_string(),
-            );
-
-            self.ws2.send(
-                &serde_json::json!({
-                    "type": "transaction",
-                    "symbols": pairs,
-                })
-                .to_string(),
-            );
-        }
-
-        self.broadcaster.subscribe()
-    }
-
-    async fn recv_orderbook_data_and_broadcast(&self) {
-        let data = self.ws1.recv().await.unwrap();
-        let Ok(data) = serde_json::from_str::<BithumbItem>(&data) else {
-            return;
-        };
+        let mut topics = self.router.topics.lock().unwrap();
+        let (subscription, is_new) = topics.subscribe(pair);
+        if is_new {
+            let router = self.router.clone();
+            let symbols = topics.symbols();
+            async_helpers::spawn(async move {
+                router.send_symbols(&symbols).await;
+            });
+        }
 
-        let BithumbItem::OrderbookSnapshot { symbol, asks, bids } = data else {
-            unreachable!()
-        };
+        subscription
+    }
+}
 
-        let pair = {
-            let mut iter = symbol.split('_');
-            (
-                Currency::from_str(iter.next().unwrap()).unwrap(),
-                Currency::from_str(iter.next().unwrap()).unwrap(),
+#[cfg(test)]
+mod test {
+    use super::{public_url, BithumbItem, Topics};
+    use crate::exchange::{Market, RealtimeData};
+    use crate::utils::Decimal;
+    use crate::{
+        currency::Currency,
+        exchange::{Bithumb, Exchange},
+    };
+
+    #[test]
+    fn parse_withdraw_fee() {
+        let text = r#"{"status": "0000", "data": [
+            {"currency": "USDT", "net_type": "ETH", "min_withdraw": "10", "withdraw_fee": "5"},
+            {"currency": "USDT", "net_type": "TRX", "min_withdraw": "10", "withdraw_fee": "1"}
+        ]}"#;
+        let fee = |network| super::parse_withdraw_fee(text, Currency::USDT, network).unwrap();
+        assert_eq!(fee(Some("TRX")), Some(Decimal(1.into())));
+        assert_eq!(fee(Some("SOL")), None);
+        // Neither named after the currency nor the only one
+        assert_eq!(fee(None), None);
+
+        let text = r#"{"status": "0000", "data": [
+            {"currency": "XRP", "net_type": "XRP", "min_withdraw": "21", "withdraw_fee": "1"}
+        ]}"#;
+        assert_eq!(
+            super::parse_withdraw_fee(text, Currency::XRP, None).unwrap(),
+            Some(Decimal(1.into()))
+        );
+    }
+
+    #[test]
+    fn withdrawals_match_their_id() {
+        let id = super::WithdrawalId::parse("1700000000000:0.5").unwrap();
+        let listed = |units: &str, fee: &str, date: u64| {
+            format!(
+                r#"{{"status": "0000", "data": [
+                    {{"search": "5", "transfer_date": {}, "units": "{}", "fee": "{}"}}
+                ]}}"#,
+                date, units, fee
             )
         };
 
-        let data = RealtimeData::Orderbook(Orderbook {
-            pair,
-            bids: bids
-                .into_iter()
-                .map(|(price, amount)| Unit { price, amount })
-                .collect(),
-            asks: asks
-                .into_iter()
-                .map(|(price, amount)| Unit { price, amount })
-                .collect(),
-        });
-        self.broadcaster.broadcast(data);
+        assert!(id
+            .is_listed(&listed("- 0.5", "0", 1_700_000_001_000_000))
+            .unwrap());
+        assert!(id
+            .is_listed(&listed("0.499", "0.001", 1_700_000_001_000_000))
+            .unwrap());
+        // An earlier withdrawal, or another one of the same currency
+        assert!(!id
+            .is_listed(&listed("0.5", "0", 1_699_999_999_000_000))
+            .unwrap());
+        assert!(!id
+            .is_listed(&listed("0.2", "0", 1_700_000_001_000_000))
+            .unwrap());
+        assert!(!id.is_listed(r#"{"status": "0000"}"#).unwrap());
+        assert_eq!(super::WithdrawalId::parse("1700000000000"), None);
     }
 
-    async fn recv_transaction_data_and_broadcast(&self) {
-        let data = self.ws2.recv().await.unwrap();
-        let Ok(data) = serde_json::from_str::<BithumbItem>(&data) else {
-            return;
-        };
+    #[test]
+    fn parse_markets() {
+        let text = r#"{"status": "0000", "data": {
+            "BTC": {"opening_price": "50000000", "closing_price": "51000000"},
+            "NEWCOIN": {"opening_price": "10", "closing_price": "11"},
+            "date": "1700000000000"
+        }}"#;
+
+        let mut markets = super::parse_markets(text, Currency::KRW).unwrap();
+        markets.sort_by_key(|(base, _, _)| base.to_string());
+        assert_eq!(markets.len(), 2);
+        assert_eq!(markets[0], (Currency::BTC, Currency::KRW, Market::Spot));
+        assert_eq!(markets[1].0.to_string(), "NEWCOIN");
+    }
 
-        let BithumbItem::Transaction {
-            symbol,
-            buy_sell_gb,
-            cont_amt,
-            cont_price,
-        } = data
-        else {
-            unreachable!()
-        };
+    #[test]
+    fn btc_market_url() {
+        assert_eq!(
+            public_url(&default_urls(), "orderbook", (Currency::ETH, Currency::BTC)),
+            "https://api.bithumb.com/public/orderbook/ETH_BTC"
+        );
+        assert_eq!(
+            public_url(&default_urls(), "ticker", (Currency::BTC, Currency::KRW)),
+            "https://api.bithumb.com/public/ticker/BTC_KRW"
+        );
+    }
 
-        let pair = {
-            let mut iter = symbol.split('_');
-            (
-                Currency::from_str(iter.next().unwrap()).unwrap(),
-                Currency::from_str(iter.next().unwrap()).unwrap(),
-            )
+    fn route(topics: &mut Topics, message: &str) -> bool {
+        let item = serde_json::from_str::<BithumbItem>(message).unwrap();
+        let (pair, data) = item.into_realtime_data().unwrap();
+        topics.route(pair, data)
+    }
+
+    #[tokio::test]
+    async fn route_by_pair() {
+        let mut topics = Topics::default();
+        let (btc, is_new) = topics.subscribe((Currency::BTC, Currency::KRW));
+        assert!(is_new);
+        let (eth, _) = topics.subscribe((Currency::ETH, Currency::KRW));
+        let (_, is_new) = topics.subscribe((Currency::BTC, Currency::KRW));
+        assert!(!is_new);
+
+        route(
+            &mut topics,
+            r#"{"type":"transaction","content":{"symbol":"ETH_KRW","buy_sell_gb":"B","cont_amt":"1.5","cont_price":"3000000"}}"#,
+        );
+        route(
+            &mut topics,
+            r#"{"type":"orderbooksnapshot","content":{"symbol":"BTC_KRW","asks":[["101","1"],["100","2"]],"bids":[["99","1"]]}}"#,
+        );
+
+        let RealtimeData::Orderbook(orderbook) = btc.recv().await else {
+            panic!("expected an orderbook");
         };
+        assert_eq!(orderbook.pair, (Currency::BTC, Currency::KRW));
+        assert_eq!(orderbook.asks[0].price.to_string(), "100");
 
-        let data = RealtimeData::Trade(Trade {
-            pair,
-            timestamp: chrono::Utc::now().timestamp_millis(),
-            price: cont_price,
-            amount: cont_amt,
-            is_bid: buy_sell_gb == "B",
-        });
-        self.broadcaster.broadcast(data);
+        let RealtimeData::Trade(trade) = eth.recv().await else {
+            panic!("expected a trade");
+        };
+        assert_eq!(trade.pair, (Currency::ETH, Currency::KRW));
+        assert!(trade.is_bid);
     }
-}
 
-#[cfg(test)]
-mod test {
-    use crate::{
-        currency::Currency,
-        exchange::{Bithumb, Exchange},
-    };
+    #[test]
+    fn drop_last_subscriber() {
+        let mut topics = Topics::default();
+        let (first, _) = topics.subscribe((Currency::BTC, Currency::KRW));
+        let (second, _) = topics.subscribe((Currency::BTC, Currency::KRW));
+        let message = r#"{"type":"transaction","content":{"symbol":"BTC_KRW","buy_sell_gb":"S","cont_amt":"1","cont_price":"100"}}"#;
+
+        drop(first);
+        assert!(!route(&mut topics, message));
+        drop(second);
+        assert!(route(&mut topics, message));
+        assert!(topics.symbols().is_empty());
+    }
 
     #[ignore]
     #[tokio::test]
@@ -880,4 +1570,13 @@ mod test {
 
         println!("{:?}", balance);
     }
+
+    #[ignore]
+    #[tokio::test]
+    async fn balances() {
+        let exchange = Bithumb::new();
+        let balances = exchange.balances(None).await.unwrap();
+
+        println!("{:?}", balances);
+    }
 }
@@ -0,0 +1,367 @@
+//! An exchange answering repeated orderbook, ticker and balance requests from a short lived cache,
+//! so widgets and scripts polling the same data share one request.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::config::{CacheConfig, Config};
+use crate::currency::Currency;
+use crate::utils::broadcaster::Subscription;
+use crate::utils::cache::{CacheStats, TtlCache};
+use crate::utils::clock::Clock;
+use crate::utils::Decimal;
+use crate::websocket::StatusHandle;
+
+use super::health::HealthMonitor;
+use super::user_stream::UserEvent;
+
+use super::{
+    Balance, CandleSticks, Exchange, Market, Order, OrderToken, Orderbook, RealtimeData, Side,
+    Trade, WithdrawState,
+};
+
+type Pair = (Currency, Currency);
+
+/// Cache hits and misses by endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ExchangeCacheStats {
+    pub orderbook: CacheStats,
+    pub ticker: CacheStats,
+    pub balance: CacheStats,
+}
+
+/// Wraps an exchange, everything not cached is passed through.
+/// Orders and withdrawals drop the cached balances.
+/// Failed requests are recorded in the [`HealthMonitor`].
+pub struct Cached<E> {
+    inner: E,
+    config: CacheConfig,
+    orderbooks: TtlCache<(Pair, Option<Market>), Orderbook>,
+    tickers: TtlCache<(Pair, Option<Market>), Decimal>,
+    balance: TtlCache<(Currency, Option<Market>), Balance>,
+    balances: TtlCache<Option<Market>, HashMap<Currency, Balance>>,
+}
+
+impl<E> Cached<E>
+where
+    E: Exchange,
+{
+    pub fn new(inner: E) -> Self {
+        Self::with_config(inner, Config::get().cache)
+    }
+
+    pub fn with_config(inner: E, config: CacheConfig) -> Self {
+        Self {
+            inner,
+            config,
+            orderbooks: TtlCache::new(),
+            tickers: TtlCache::new(),
+            balance: TtlCache::new(),
+            balances: TtlCache::new(),
+        }
+    }
+
+    /// The wrapped exchange, requests made on it bypass the cache.
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+
+    pub fn stats(&self) -> ExchangeCacheStats {
+        let balance = self.balance.stats();
+        let balances = self.balances.stats();
+        ExchangeCacheStats {
+            orderbook: self.orderbooks.stats(),
+            ticker: self.tickers.stats(),
+            balance: CacheStats {
+                hits: balance.hits + balances.hits,
+                misses: balance.misses + balances.misses,
+            },
+        }
+    }
+
+    /// Drops the cached balances, e.g. when the exchange streams a change of them.
+    /// Balances being fetched meanwhile are not cached, they may predate the change.
+    pub fn invalidate_balances(&self) {
+        self.balance.clear();
+        self.balances.clear();
+    }
+
+    /// Passes a result through, recording a failure in the health monitor.
+    fn observed<T>(&self, result: Result<T, E::Error>) -> Result<T, E::Error> {
+        if let Err(e) = &result {
+            HealthMonitor::instance().record_error(E::NAME, e.to_string());
+        }
+        result
+    }
+
+    /// Passes the result of a write through, dropping the balances it may have changed.
+    fn written<T>(&self, result: Result<T, E::Error>) -> Result<T, E::Error> {
+        self.invalidate_balances();
+        self.observed(result)
+    }
+}
+
+impl<E> Exchange for Cached<E>
+where
+    E: Exchange,
+{
+    const NAME: &'static str = E::NAME;
+
+    type Error = E::Error;
+
+    fn subscribe(&self, pair: Pair, market: Option<Market>) -> Subscription<RealtimeData> {
+        self.inner.subscribe(pair, market)
+    }
+
+    fn connection_status(&self) -> Option<StatusHandle> {
+        self.inner.connection_status()
+    }
+
+    fn streams_realtime(&self) -> bool {
+        self.inner.streams_realtime()
+    }
+
+    fn rate_limit_utilization(&self) -> Option<f64> {
+        self.inner.rate_limit_utilization()
+    }
+
+    fn clock_drift(&self) -> Option<i64> {
+        self.inner.clock_drift()
+    }
+
+    fn recv_window(&self) -> Option<i64> {
+        self.inner.recv_window()
+    }
+
+    fn user_events(&self) -> Option<Subscription<UserEvent>> {
+        self.inner.user_events()
+    }
+
+    fn clock(&self) -> Clock {
+        self.inner.clock()
+    }
+
+    async fn markets(&self) -> Result<Vec<(Currency, Currency, Market)>, Self::Error> {
+        self.observed(self.inner.markets().await)
+    }
+
+    async fn orderbook(
+        &self,
+        pair: Pair,
+        market: Option<Market>,
+    ) -> Result<Orderbook, Self::Error> {
+        let ttl = Duration::from_millis(self.config.orderbook_ms);
+        let result = self
+            .orderbooks
+            .get_or_fetch((pair, market), ttl, || self.inner.orderbook(pair, market))
+            .await;
+        self.observed(result)
+    }
+
+    async fn candlesticks(
+        &self,
+        pair: Pair,
+        market: Option<Market>,
+    ) -> Result<CandleSticks, Self::Error> {
+        self.observed(self.inner.candlesticks(pair, market).await)
+    }
+
+    async fn ticker(&self, pair: Pair, market: Option<Market>) -> Result<Decimal, Self::Error> {
+        let ttl = Duration::from_millis(self.config.ticker_ms);
+        let result = self
+            .tickers
+            .get_or_fetch((pair, market), ttl, || self.inner.ticker(pair, market))
+            .await;
+        self.observed(result)
+    }
+
+    async fn recent_trades(
+        &self,
+        pair: Pair,
+        market: Option<Market>,
+        limit: usize,
+    ) -> Result<Vec<Trade>, Self::Error> {
+        self.observed(self.inner.recent_trades(pair, market, limit).await)
+    }
+
+    async fn balance(
+        &self,
+        currency: Currency,
+        market: Option<Market>,
+    ) -> Result<Balance, Self::Error> {
+        let ttl = Duration::from_millis(self.config.balance_ms);
+        let result = self
+            .balance
+            .get_or_fetch((currency, market), ttl, || {
+                self.inner.balance(currency, market)
+            })
+            .await;
+        self.observed(result)
+    }
+
+    async fn balances(
+        &self,
+        market: Option<Market>,
+    ) -> Result<HashMap<Currency, Balance>, Self::Error> {
+        let ttl = Duration::from_millis(self.config.balance_ms);
+        let result = self
+            .balances
+            .get_or_fetch(market, ttl, || self.inner.balances(market))
+            .await;
+        self.observed(result)
+    }
+
+    fn min_notional(&self, pair: Pair, market: Option<Market>) -> Option<Decimal> {
+        self.inner.min_notional(pair, market)
+    }
+
+    async fn bid_limit(
+        &self,
+        pair: Pair,
+        price: Decimal,
+        amount: Decimal,
+        market: Option<Market>,
+    ) -> Result<OrderToken, Self::Error> {
+        self.written(self.inner.bid_limit(pair, price, amount, market).await)
+    }
+
+    async fn bid_market(
+        &self,
+        pair: Pair,
+        quote_qty: Decimal,
+        market: Option<Market>,
+    ) -> Result<OrderToken, Self::Error> {
+        self.written(self.inner.bid_market(pair, quote_qty, market).await)
+    }
+
+    async fn ask_limit(
+        &self,
+        pair: Pair,
+        price: Decimal,
+        amount: Decimal,
+        market: Option<Market>,
+    ) -> Result<OrderToken, Self::Error> {
+        self.written(self.inner.ask_limit(pair, price, amount, market).await)
+    }
+
+    async fn ask_market(
+        &self,
+        pair: Pair,
+        base_qty: Decimal,
+        market: Option<Market>,
+    ) -> Result<OrderToken, Self::Error> {
+        self.written(self.inner.ask_market(pair, base_qty, market).await)
+    }
+
+    async fn stop_limit(
+        &self,
+        pair: Pair,
+        stop_price: Decimal,
+        limit_price: Decimal,
+        amount: Decimal,
+        side: Side,
+        market: Option<Market>,
+    ) -> Result<OrderToken, Self::Error> {
+        self.written(
+            self.inner
+                .stop_limit(pair, stop_price, limit_price, amount, side, market)
+                .await,
+        )
+    }
+
+    async fn view_order(&self, order_token: &OrderToken) -> Result<Order, Self::Error> {
+        self.observed(self.inner.view_order(order_token).await)
+    }
+
+    /// Fills change the balances, so they are dropped once the order is closed.
+    async fn wait_order(&self, order_token: &OrderToken) -> Result<Decimal, Self::Error> {
+        self.written(self.inner.wait_order(order_token).await)
+    }
+
+    async fn wait_order_timeout(
+        &self,
+        order_token: &OrderToken,
+        timeout: Duration,
+    ) -> Result<Option<Decimal>, Self::Error> {
+        self.written(self.inner.wait_order_timeout(order_token, timeout).await)
+    }
+
+    async fn cancel_order(&self, order_token: &OrderToken) -> Result<Decimal, Self::Error> {
+        self.written(self.inner.cancel_order(order_token).await)
+    }
+
+    async fn withdraw(
+        &self,
+        currency: Currency,
+        amount: Decimal,
+        address1: &str,
+        address2: Option<&str>,
+        network: Option<&str>,
+    ) -> Result<String, Self::Error> {
+        self.written(
+            self.inner
+                .withdraw(currency, amount, address1, address2, network)
+                .await,
+        )
+    }
+
+    async fn withdraw_status(
+        &self,
+        currency: Currency,
+        id: &str,
+    ) -> Result<WithdrawState, Self::Error> {
+        self.observed(self.inner.withdraw_status(currency, id).await)
+    }
+
+    async fn withdraw_fee(
+        &self,
+        currency: Currency,
+        network: Option<&str>,
+    ) -> Result<Decimal, Self::Error> {
+        self.observed(self.inner.withdraw_fee(currency, network).await)
+    }
+
+    async fn set_leverage(&self, pair: Option<Pair>, value: u64) -> Result<(), Self::Error> {
+        self.observed(self.inner.set_leverage(pair, value).await)
+    }
+
+    async fn server_time(&self) -> Result<i64, Self::Error> {
+        self.observed(self.inner.server_time().await)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Cached;
+    use crate::config::CacheConfig;
+    use crate::currency::Currency;
+    use crate::exchange::replay::Replay;
+    use crate::exchange::Exchange;
+    use crate::utils::Decimal;
+
+    const FIXTURE: &str = include_str!("../../resources/replay_fixture.jsonl");
+    const PAIR: (Currency, Currency) = (Currency::BTC, Currency::KRW);
+
+    #[tokio::test]
+    async fn orderbooks_come_from_the_cache() {
+        let cached = Cached::with_config(
+            Replay::parse(FIXTURE, 1000.0).unwrap(),
+            CacheConfig::default(),
+        );
+
+        // Failures are not cached
+        assert!(cached.orderbook(PAIR, None).await.is_err());
+        let subscription = cached.subscribe(PAIR, None);
+        subscription.recv().await;
+
+        let first = cached.orderbook(PAIR, None).await.unwrap();
+        assert_eq!(cached.orderbook(PAIR, None).await.unwrap(), first);
+        assert_eq!(cached.stats().orderbook.misses, 2);
+        assert_eq!(cached.stats().orderbook.hits, 1);
+
+        // Orders drop the balances, but not the market data
+        assert!(cached.bid_market(PAIR, Decimal::ONE, None).await.is_err());
+        assert_eq!(cached.orderbook(PAIR, None).await.unwrap(), first);
+        assert_eq!(cached.stats().orderbook.hits, 2);
+    }
+}
@@ -0,0 +1,263 @@
+use std::time::Duration;
+
+use super::{Ticker, Trade};
+
+/// Keeps candles up to date from the trade stream.
+///
+/// The history is fetched once with `Exchange::candlesticks` and every trade then updates
+/// the candle of its bucket, rolling over to a new candle when the interval boundary passes.
+/// Trades may arrive out of order, so each candle remembers the times of the trades
+/// its open and close came from.
+pub struct CandleAggregator {
+    interval: u64,
+    candles: Vec<Candle>,
+}
+
+struct Candle {
+    ticker: Ticker,
+    opened_at: u64,
+    closed_at: u64,
+}
+
+impl Candle {
+    fn from_ticker(ticker: Ticker) -> Self {
+        // Any trade in the bucket is newer than the fetched close,
+        // but never older than the fetched open.
+        Self {
+            opened_at: ticker.timestamp,
+            closed_at: ticker.timestamp,
+            ticker,
+        }
+    }
+}
+
+impl CandleAggregator {
+    pub fn new(interval: Duration, history: Vec<Ticker>) -> Self {
+        let mut aggregator = Self {
+            interval: (interval.as_millis() as u64).max(1),
+            candles: Vec::new(),
+        };
+        aggregator.reconcile(history);
+        aggregator
+    }
+
+    pub fn tickers(&self) -> impl Iterator<Item = &Ticker> {
+        self.candles.iter().map(|candle| &candle.ticker)
+    }
+
+    pub fn last(&self) -> Option<&Ticker> {
+        self.candles.last().map(|candle| &candle.ticker)
+    }
+
+    /// Replaces the candles with freshly fetched ones.
+    /// Candles newer than the fetched history are built from trades only, so they are kept.
+    pub fn reconcile(&mut self, mut history: Vec<Ticker>) {
+        history.sort_by_key(|ticker| ticker.timestamp);
+
+        let newest = history.last().map(|ticker| ticker.timestamp);
+        let newer = std::mem::take(&mut self.candles)
+            .into_iter()
+            .filter(|candle| Some(candle.ticker.timestamp) > newest);
+
+        self.candles = history
+            .into_iter()
+            .map(Candle::from_ticker)
+            .chain(newer)
+            .collect();
+    }
+
+    pub fn push(&mut self, trade: &Trade) {
+        let Ok(timestamp) = u64::try_from(trade.timestamp) else {
+            return;
+        };
+        let bucket = timestamp - timestamp % self.interval;
+
+        let idx = match self
+            .candles
+            .binary_search_by_key(&bucket, |candle| candle.ticker.timestamp)
+        {
+            Ok(idx) => idx,
+            Err(idx) => {
+                let ticker = Ticker {
+                    timestamp: bucket,
+                    open: trade.price,
+                    close: trade.price,
+                    low: trade.price,
+                    high: trade.price,
+                };
+
+                self.candles.insert(
+                    idx,
+                    Candle {
+                        ticker,
+                        opened_at: timestamp,
+                        closed_at: timestamp,
+                    },
+                );
+                return;
+            }
+        };
+
+        let candle = &mut self.candles[idx];
+        candle.ticker.high = candle.ticker.high.max(trade.price);
+        candle.ticker.low = candle.ticker.low.min(trade.price);
+
+        if timestamp < candle.opened_at {
+            candle.ticker.open = trade.price;
+            candle.opened_at = timestamp;
+        }
+
+        if timestamp >= candle.closed_at {
+            candle.ticker.close = trade.price;
+            candle.closed_at = timestamp;
+        }
+    }
+}
+
+/// Merges candles into candles of a coarser `interval`.
+/// Returns None if the candles are not spaced evenly within `interval`,
+/// e.g. 15 minute candles can not be split into 5 minute ones.
+pub fn resample(history: &[Ticker], interval: Duration) -> Option<Vec<Ticker>> {
+    let interval = (interval.as_millis() as u64).max(1);
+    let mut history = history.to_vec();
+    history.sort_by_key(|ticker| ticker.timestamp);
+
+    let spacing = history
+        .windows(2)
+        .map(|pair| pair[1].timestamp - pair[0].timestamp)
+        .filter(|spacing| *spacing > 0)
+        .min();
+    if spacing.is_some_and(|spacing| interval % spacing != 0) {
+        return None;
+    }
+
+    let mut resampled: Vec<Ticker> = Vec::new();
+    for ticker in history {
+        let bucket = ticker.timestamp - ticker.timestamp % interval;
+        match resampled.last_mut() {
+            Some(last) if last.timestamp == bucket => {
+                last.high = last.high.max(ticker.high);
+                last.low = last.low.min(ticker.low);
+                last.close = ticker.close;
+            }
+            _ => resampled.push(Ticker {
+                timestamp: bucket,
+                ..ticker
+            }),
+        }
+    }
+
+    Some(resampled)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{resample, CandleAggregator};
+    use crate::currency::Currency;
+    use crate::dec;
+    use crate::exchange::{Ticker, Trade};
+    use crate::utils::Decimal;
+
+    fn trade(timestamp: i64, price: Decimal) -> Trade {
+        Trade {
+            pair: (Currency::BTC, Currency::KRW),
+            timestamp,
+            price,
+            amount: dec!(1),
+            is_bid: true,
+            id: None,
+        }
+    }
+
+    fn ticker(
+        timestamp: u64,
+        open: Decimal,
+        close: Decimal,
+        low: Decimal,
+        high: Decimal,
+    ) -> Ticker {
+        Ticker {
+            timestamp,
+            open,
+            close,
+            low,
+            high,
+        }
+    }
+
+    #[test]
+    fn rollover() {
+        let history = vec![ticker(0, dec!(10), dec!(11), dec!(9), dec!(12))];
+        let mut aggregator = CandleAggregator::new(Duration::from_millis(1000), history);
+
+        aggregator.push(&trade(999, dec!(13)));
+        aggregator.push(&trade(1000, dec!(14)));
+        aggregator.push(&trade(1500, dec!(12)));
+
+        let tickers = aggregator.tickers().cloned().collect::<Vec<_>>();
+        assert_eq!(
+            tickers,
+            vec![
+                ticker(0, dec!(10), dec!(13), dec!(9), dec!(13)),
+                ticker(1000, dec!(14), dec!(12), dec!(12), dec!(14)),
+            ]
+        );
+    }
+
+    #[test]
+    fn out_of_order() {
+        let mut aggregator = CandleAggregator::new(Duration::from_millis(1000), Vec::new());
+
+        aggregator.push(&trade(1500, dec!(10)));
+        aggregator.push(&trade(2500, dec!(20)));
+        aggregator.push(&trade(1200, dec!(8)));
+        aggregator.push(&trade(1100, dec!(9)));
+        aggregator.push(&trade(1400, dec!(11)));
+
+        let tickers = aggregator.tickers().cloned().collect::<Vec<_>>();
+        assert_eq!(
+            tickers,
+            vec![
+                ticker(1000, dec!(9), dec!(10), dec!(8), dec!(11)),
+                ticker(2000, dec!(20), dec!(20), dec!(20), dec!(20)),
+            ]
+        );
+    }
+
+    #[test]
+    fn reconcile_keeps_newer() {
+        let mut aggregator = CandleAggregator::new(Duration::from_millis(1000), Vec::new());
+        aggregator.push(&trade(500, dec!(1)));
+        aggregator.push(&trade(1500, dec!(2)));
+
+        aggregator.reconcile(vec![ticker(0, dec!(5), dec!(5), dec!(5), dec!(5))]);
+
+        let timestamps = aggregator
+            .tickers()
+            .map(|ticker| (ticker.timestamp, ticker.close))
+            .collect::<Vec<_>>();
+        assert_eq!(timestamps, vec![(0, dec!(5)), (1000, dec!(2))]);
+    }
+
+    #[test]
+    fn resample_merges_buckets() {
+        let history = vec![
+            ticker(1000, dec!(3), dec!(4), dec!(2), dec!(5)),
+            ticker(0, dec!(1), dec!(2), dec!(1), dec!(3)),
+            ticker(2000, dec!(4), dec!(6), dec!(4), dec!(7)),
+        ];
+
+        let resampled = resample(&history, Duration::from_millis(2000)).unwrap();
+        assert_eq!(
+            resampled,
+            vec![
+                ticker(0, dec!(1), dec!(4), dec!(1), dec!(5)),
+                ticker(2000, dec!(4), dec!(6), dec!(4), dec!(7)),
+            ]
+        );
+
+        assert!(resample(&history, Duration::from_millis(500)).is_none());
+    }
+}
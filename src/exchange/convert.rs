@@ -0,0 +1,68 @@
+use crate::{config::Config, currency::Currency, utils::Decimal};
+
+use super::Exchange;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ConvertError {
+    #[error("no market route from {0} to {1}")]
+    NoRoute(Currency, Currency),
+}
+
+/// Amount received by converting `amount` of `from` through a single market,
+/// selling into `from-to` or buying on `to-from`, whichever exists.
+async fn convert_direct<E>(
+    exchange: &E,
+    from: Currency,
+    to: Currency,
+    amount: Decimal,
+) -> Option<Decimal>
+where
+    E: Exchange,
+{
+    if from == to {
+        return Some(amount);
+    }
+
+    if let Ok(orderbook) = exchange.orderbook((from, to), None).await {
+        if let Some(received) = orderbook.fill_bid(amount) {
+            return Some(received);
+        }
+    }
+
+    exchange
+        .orderbook((to, from), None)
+        .await
+        .ok()?
+        .fill_ask(amount)
+}
+
+/// Converts `amount` of `from` into `to` at the current orderbook depth of the exchange.
+///
+/// The direct market and a route through each configured bridge currency are tried,
+/// and the route that yields the most is used.
+pub async fn convert<E>(
+    exchange: &E,
+    from: Currency,
+    to: Currency,
+    amount: Decimal,
+) -> Result<Decimal, ConvertError>
+where
+    E: Exchange,
+{
+    let mut best = convert_direct(exchange, from, to, amount).await;
+
+    for &bridge in &Config::get().convert.bridges {
+        if bridge == from || bridge == to {
+            continue;
+        }
+
+        let Some(bridged) = convert_direct(exchange, from, bridge, amount).await else {
+            continue;
+        };
+
+        let received = convert_direct(exchange, bridge, to, bridged).await;
+        best = best.max(received);
+    }
+
+    best.ok_or(ConvertError::NoRoute(from, to))
+}
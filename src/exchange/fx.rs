@@ -0,0 +1,253 @@
+//! Reference exchange rates between KRW, USD, USDT and BTC, for valuing holdings across markets.
+//! KRW prices are the mid prices of the KRW exchange set in `[fx] krw_source`,
+//! USD comes from the configured api or falls back to USDT.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use crate::config::{Config, KrwSource};
+use crate::currency::Currency;
+use crate::utils::{http, server_time, Decimal};
+
+use super::{Exchange, Exchanges, Orderbook};
+
+/// Currencies priced in KRW on the source exchange.
+const PRICED: [Currency; 2] = [Currency::USDT, Currency::BTC];
+
+#[derive(thiserror::Error, Debug)]
+pub enum FxError {
+    #[error("no exchange rate for {0}")]
+    NoRate(Currency),
+
+    #[error("failed to fetch exchange rates: {0}")]
+    Source(String),
+
+    #[error("reqwest error: {0}")]
+    ReqwestError(#[from] reqwest::Error),
+
+    #[error("no KRW rate in the response of {0}")]
+    InvalidResponse(String),
+
+    #[error("exchange rates could not be refreshed for over {0}s")]
+    Stale(u64),
+}
+
+/// Prices of one unit of each currency in KRW.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rates {
+    fetched_at: i64,
+    krw: HashMap<Currency, Decimal>,
+}
+
+impl Rates {
+    pub fn new(fetched_at: i64, krw: HashMap<Currency, Decimal>) -> Self {
+        Self { fetched_at, krw }
+    }
+
+    fn krw_price(&self, currency: Currency) -> Option<Decimal> {
+        match currency {
+            Currency::KRW => Some(Decimal::ONE),
+            currency => self.krw.get(&currency).copied(),
+        }
+    }
+
+    /// Converts `amount` of `from` into `to`, triangulating through KRW.
+    pub fn convert(
+        &self,
+        amount: Decimal,
+        from: Currency,
+        to: Currency,
+    ) -> Result<Decimal, FxError> {
+        if from == to {
+            return Ok(amount);
+        }
+
+        let from = self.krw_price(from).ok_or(FxError::NoRate(from))?;
+        let to = self.krw_price(to).ok_or(FxError::NoRate(to))?;
+        Ok(amount * from / to)
+    }
+
+    /// True if the rates are older than `ttl` at `now`.
+    pub fn is_stale(&self, now: i64, ttl: Duration) -> bool {
+        now - self.fetched_at > ttl.as_millis() as i64
+    }
+}
+
+/// An amount converted at the reference rates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Conversion {
+    pub amount: Decimal,
+    /// The rates could not be refreshed and are older than the configured lifetime.
+    pub stale: bool,
+}
+
+static RATES: Lazy<Mutex<Option<Rates>>> = Lazy::new(|| Mutex::new(None));
+
+fn mid_price(orderbook: &Orderbook) -> Option<Decimal> {
+    let bid = orderbook.bids.first()?.price;
+    let ask = orderbook.asks.first()?.price;
+    Some((bid + ask) / Decimal(2.into()))
+}
+
+/// Reads the KRW rate of an api response like `{"rates": {"KRW": 1350.5}}`.
+fn parse_usd_krw(text: &str) -> Option<Decimal> {
+    let value: serde_json::Value = serde_json::from_str(text).ok()?;
+    match &value["rates"]["KRW"] {
+        serde_json::Value::Number(rate) => Decimal::from_str(&rate.to_string()).ok(),
+        serde_json::Value::String(rate) => Decimal::from_str(rate).ok(),
+        _ => None,
+    }
+}
+
+async fn fetch_usd_krw(url: &str) -> Result<Decimal, FxError> {
+    let request = http::client().get(url);
+    let response = http::send_with_retry(request, &Config::get().http_retry).await?;
+    let text = response.text().await?;
+    parse_usd_krw(&text).ok_or_else(|| FxError::InvalidResponse(url.to_string()))
+}
+
+async fn fetch<E>(source: &E) -> Result<Rates, FxError>
+where
+    E: Exchange,
+{
+    let mut krw = HashMap::new();
+    for currency in PRICED {
+        let orderbook = source
+            .orderbook((currency, Currency::KRW), None)
+            .await
+            .map_err(|e| FxError::Source(format!("{}: {}", E::NAME, e)))?;
+        let price = mid_price(&orderbook).ok_or(FxError::NoRate(currency))?;
+        krw.insert(currency, price);
+    }
+
+    let usd = match &Config::get().fx.usd_krw_url {
+        Some(url) => fetch_usd_krw(url).await?,
+        None => krw[&Currency::USDT],
+    };
+    krw.insert(Currency::USD, usd);
+
+    Ok(Rates::new(server_time::local_millis(), krw))
+}
+
+async fn fetch_configured(exchanges: &Exchanges) -> Result<Rates, FxError> {
+    match Config::get().fx.krw_source {
+        KrwSource::Upbit => fetch(exchanges.upbit.as_ref()).await,
+        KrwSource::Bithumb => fetch(exchanges.bithumb.as_ref()).await,
+    }
+}
+
+/// The current rates, fetched from the configured source when they are older than
+/// the configured lifetime. If fetching fails the previous rates are returned and flagged as stale.
+pub async fn rates(exchanges: &Exchanges) -> Result<(Rates, bool), FxError> {
+    let now = server_time::local_millis();
+    let ttl = Duration::from_secs(Config::get().fx.ttl_secs);
+    let cached = RATES.lock().clone();
+    if let Some(rates) = cached.as_ref().filter(|rates| !rates.is_stale(now, ttl)) {
+        return Ok((rates.clone(), false));
+    }
+
+    match fetch_configured(exchanges).await {
+        Ok(rates) => {
+            *RATES.lock() = Some(rates.clone());
+            Ok((rates, false))
+        }
+        Err(e) => {
+            let rates = cached.ok_or(e)?;
+            tracing::warn!("Using exchange rates fetched at {}", rates.fetched_at);
+            Ok((rates, true))
+        }
+    }
+}
+
+/// Converts `amount` of `from` into `to` at the reference rates.
+pub async fn convert(
+    exchanges: &Exchanges,
+    amount: Decimal,
+    from: Currency,
+    to: Currency,
+) -> Result<Conversion, FxError> {
+    let (rates, stale) = rates(exchanges).await?;
+    Ok(Conversion {
+        amount: rates.convert(amount, from, to)?,
+        stale,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use super::{parse_usd_krw, Rates};
+    use crate::currency::Currency;
+    use crate::utils::Decimal;
+
+    fn dec(value: &str) -> Decimal {
+        Decimal::from_str(value).unwrap()
+    }
+
+    fn rates() -> Rates {
+        let mut krw = HashMap::new();
+        krw.insert(Currency::USDT, dec("1400"));
+        krw.insert(Currency::USD, dec("1350"));
+        krw.insert(Currency::BTC, dec("140000000"));
+        Rates::new(0, krw)
+    }
+
+    #[test]
+    fn triangulate_through_krw() {
+        let rates = rates();
+
+        assert_eq!(
+            rates
+                .convert(dec("2"), Currency::USD, Currency::KRW)
+                .unwrap(),
+            dec("2700")
+        );
+        assert_eq!(
+            rates
+                .convert(dec("2800"), Currency::KRW, Currency::USDT)
+                .unwrap(),
+            dec("2")
+        );
+        assert_eq!(
+            rates
+                .convert(dec("0.5"), Currency::BTC, Currency::USDT)
+                .unwrap(),
+            dec("50000")
+        );
+        assert_eq!(
+            rates
+                .convert(dec("1"), Currency::ETH, Currency::ETH)
+                .unwrap(),
+            dec("1")
+        );
+        assert!(rates
+            .convert(dec("1"), Currency::ETH, Currency::KRW)
+            .is_err());
+    }
+
+    #[test]
+    fn stale_after_ttl() {
+        let rates = rates();
+        let ttl = Duration::from_secs(60);
+        assert!(!rates.is_stale(60 * 1000, ttl));
+        assert!(rates.is_stale(60 * 1000 + 1, ttl));
+    }
+
+    #[test]
+    fn usd_krw_response() {
+        assert_eq!(
+            parse_usd_krw(r#"{"base": "USD", "rates": {"KRW": 1350.25, "JPY": 150.1}}"#),
+            Some(dec("1350.25"))
+        );
+        assert_eq!(
+            parse_usd_krw(r#"{"rates": {"KRW": "1350"}}"#),
+            Some(dec("1350"))
+        );
+        assert_eq!(parse_usd_krw(r#"{"rates": {}}"#), None);
+    }
+}
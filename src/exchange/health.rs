@@ -0,0 +1,212 @@
+//! Health of each exchange at a glance: the websocket, the latest failed requests,
+//! how much of the rate limit is used and how far the local clock is off the server clock.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use crate::utils::async_helpers;
+use crate::utils::broadcaster::{Broadcaster, Subscription};
+use crate::utils::maybe_trait::MaybeSend;
+use crate::utils::server_time::{self, SYNC_INTERVAL_MILLIS};
+use crate::websocket::ConnectionStatus;
+
+use super::Exchange;
+
+/// Errors kept per exchange, older ones are dropped.
+const ERROR_LIMIT: usize = 50;
+
+/// How often the health of each exchange is broadcast.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Wait after a failed clock measurement before trying again.
+const DRIFT_RETRY: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HealthError {
+    /// Local time in milliseconds.
+    pub time: i64,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExchangeHealth {
+    pub exchange: &'static str,
+    /// None if the exchange does not stream.
+    pub connection: Option<ConnectionStatus>,
+    pub last_error: Option<HealthError>,
+    /// From 0.0 (idle) to 1.0 (exhausted), None without a rate limiter.
+    pub rate_limit: Option<f64>,
+    /// Server time minus local time in milliseconds, None until measured.
+    pub clock_drift: Option<i64>,
+    /// See [`Exchange::recv_window`].
+    pub recv_window: Option<i64>,
+}
+
+impl ExchangeHealth {
+    /// True if the clock drifted beyond the window signed requests are accepted in.
+    pub fn is_drift_beyond_window(&self) -> bool {
+        drift_beyond_window(self.clock_drift, self.recv_window).is_some()
+    }
+}
+
+fn drift_beyond_window(clock_drift: Option<i64>, recv_window: Option<i64>) -> Option<(i64, i64)> {
+    let (clock_drift, recv_window) = (clock_drift?, recv_window?);
+    (clock_drift.abs() > recv_window).then_some((clock_drift, recv_window))
+}
+
+/// Records the failed requests of the exchanges and broadcasts their health.
+pub struct HealthMonitor {
+    errors: Mutex<HashMap<&'static str, VecDeque<HealthError>>>,
+    broadcaster: Broadcaster<ExchangeHealth>,
+    watched: Mutex<Vec<&'static str>>,
+}
+
+impl HealthMonitor {
+    pub fn instance() -> &'static HealthMonitor {
+        static MONITOR: Lazy<HealthMonitor> = Lazy::new(|| HealthMonitor {
+            errors: Mutex::new(HashMap::new()),
+            broadcaster: Broadcaster::new(),
+            watched: Mutex::new(Vec::new()),
+        });
+
+        &MONITOR
+    }
+
+    pub fn record_error(&self, exchange: &'static str, message: String) {
+        let mut errors = self.errors.lock();
+        let errors = errors.entry(exchange).or_default();
+        errors.push_back(HealthError {
+            time: server_time::local_millis(),
+            message,
+        });
+        if errors.len() > ERROR_LIMIT {
+            errors.pop_front();
+        }
+    }
+
+    /// The latest errors of the exchange, newest first.
+    pub fn recent_errors(&self, exchange: &str) -> Vec<HealthError> {
+        self.errors
+            .lock()
+            .get(exchange)
+            .map(|errors| errors.iter().rev().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// A smaller drift is only logged by `ServerTime`, as signed requests carry the server time.
+    fn warn_drift(
+        &self,
+        exchange: &'static str,
+        clock_drift: Option<i64>,
+        recv_window: Option<i64>,
+    ) {
+        if let Some((clock_drift, recv_window)) = drift_beyond_window(clock_drift, recv_window) {
+            let message = format!(
+                "The local clock is {}ms off the server, beyond the {}ms receive window: \
+                 signed requests are rejected if the clock moves again before the next sync",
+                -clock_drift, recv_window
+            );
+            self.record_error(exchange, message);
+        }
+    }
+
+    /// Health of every watched exchange from now on.
+    pub fn subscribe(&self) -> Subscription<ExchangeHealth> {
+        self.broadcaster.subscribe()
+    }
+
+    /// The current health of the exchange.
+    pub fn check<E>(&self, exchange: &E) -> ExchangeHealth
+    where
+        E: Exchange,
+    {
+        ExchangeHealth {
+            exchange: E::NAME,
+            connection: exchange.connection_status().map(|status| status.get()),
+            last_error: self.recent_errors(E::NAME).into_iter().next(),
+            rate_limit: exchange.rate_limit_utilization(),
+            clock_drift: exchange.clock_drift(),
+            recv_window: exchange.recv_window(),
+        }
+    }
+
+    /// Starts broadcasting the health of the exchange, once per exchange.
+    /// The clock of the exchange is measured right away and then hourly,
+    /// a drift beyond the receive window of signed requests is recorded as an error.
+    pub fn watch<E>(&'static self, exchange: Arc<E>)
+    where
+        E: Exchange + MaybeSend + 'static,
+    {
+        {
+            let mut watched = self.watched.lock();
+            if watched.contains(&E::NAME) {
+                return;
+            }
+            watched.push(E::NAME);
+        }
+
+        async_helpers::spawn(async move {
+            let mut sync_at = 0;
+            loop {
+                let now = server_time::local_millis();
+                if now >= sync_at {
+                    // Updates the clock the exchange signs its requests with,
+                    // a failure is recorded by the exchange, see `Cached`
+                    sync_at = match exchange.server_time().await {
+                        Ok(_) => {
+                            self.warn_drift(
+                                E::NAME,
+                                exchange.clock_drift(),
+                                exchange.recv_window(),
+                            );
+                            now + SYNC_INTERVAL_MILLIS
+                        }
+                        Err(_) => now + DRIFT_RETRY.as_millis() as i64,
+                    };
+                }
+
+                self.broadcaster.broadcast(self.check(exchange.as_ref()));
+                async_helpers::sleep(REFRESH_INTERVAL).await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{HealthMonitor, ERROR_LIMIT};
+
+    #[test]
+    fn recent_errors_newest_first() {
+        let monitor = HealthMonitor::instance();
+        for i in 0..ERROR_LIMIT + 5 {
+            monitor.record_error("health-test", format!("error {}", i));
+        }
+
+        let errors = monitor.recent_errors("health-test");
+        assert_eq!(errors.len(), ERROR_LIMIT);
+        assert_eq!(errors[0].message, format!("error {}", ERROR_LIMIT + 4));
+        assert_eq!(errors[ERROR_LIMIT - 1].message, "error 5");
+        assert!(monitor.recent_errors("unknown").is_empty());
+    }
+
+    #[test]
+    fn drift_beyond_the_window_is_recorded() {
+        let monitor = HealthMonitor::instance();
+        monitor.warn_drift("drift-test", Some(-2500), Some(5000));
+        monitor.warn_drift("drift-test", Some(-6000), None);
+        monitor.warn_drift("drift-test", None, Some(5000));
+        assert!(monitor.recent_errors("drift-test").is_empty());
+
+        monitor.warn_drift("drift-test", Some(-6000), Some(5000));
+        assert_eq!(
+            monitor.recent_errors("drift-test")[0].message,
+            "The local clock is 6000ms off the server, beyond the 5000ms receive window: \
+             signed requests are rejected if the clock moves again before the next sync"
+        );
+    }
+}
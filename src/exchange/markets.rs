@@ -0,0 +1,60 @@
+//! Markets listed by each exchange, kept for a day.
+//! Kept per rest url, so an exchange pointed at its testnet does not reuse the production list.
+
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use crate::currency::Currency;
+use crate::utils::http::BaseUrls;
+use crate::utils::server_time;
+
+use super::Market;
+
+/// Listed pairs as `(base, quote, market)`.
+pub type Markets = Vec<(Currency, Currency, Market)>;
+
+/// Markets are fetched again once they are older than a day.
+const TTL_MILLIS: i64 = 24 * 60 * 60 * 1000;
+
+/// Markets by exchange name and rest url, with the time they were fetched.
+static MARKETS: Lazy<Mutex<HashMap<(&'static str, String), (i64, Markets)>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// The markets of `exchange` reached at `urls` if they were fetched within a day.
+pub fn fresh(exchange: &'static str, urls: &BaseUrls) -> Option<Markets> {
+    let markets = MARKETS.lock();
+    let (fetched_at, markets) = markets.get(&(exchange, urls.rest.clone()))?;
+    (server_time::local_millis() - fetched_at < TTL_MILLIS).then(|| markets.clone())
+}
+
+/// Stores freshly fetched markets, sorted and without duplicates.
+pub fn store(exchange: &'static str, urls: &BaseUrls, mut markets: Markets) -> Markets {
+    markets.sort_by_key(|(base, quote, market)| (*market as u8, quote.as_str(), base.as_str()));
+    markets.dedup();
+    MARKETS.lock().insert(
+        (exchange, urls.rest.clone()),
+        (server_time::local_millis(), markets.clone()),
+    );
+    markets
+}
+
+#[cfg(test)]
+mod test {
+    use super::{fresh, store};
+    use crate::currency::Currency;
+    use crate::exchange::Market;
+    use crate::utils::http::BaseUrls;
+
+    #[test]
+    fn kept_per_rest_url() {
+        let production = BaseUrls::new("http://markets.test", "", "");
+        let testnet = BaseUrls::new("http://testnet.markets.test", "", "");
+        let markets = vec![(Currency::BTC, Currency::USDT, Market::Spot)];
+
+        assert_eq!(store("markets", &production, markets.clone()), markets);
+        assert_eq!(fresh("markets", &production), Some(markets));
+        assert_eq!(fresh("markets", &testnet), None);
+    }
+}
@@ -0,0 +1,1498 @@
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::utils::broadcaster::{Broadcaster, Subscription};
+use crate::utils::Decimal;
+use crate::websocket::{StatusHandle, Websocket, WebsocketSender};
+use crate::{
+    currency::{Currency, CurrencyPairDelimiterStringifier, CurrencyPairStringifier},
+    exchange::{Balance, Order, OrderState, Unit, WithdrawState},
+    utils::async_helpers,
+    utils::http::{self, BaseUrls, Client, Method},
+    utils::rate_limiter::{RateLimit, RateLimiter},
+    utils::rounding::round_down_to_step,
+    utils::server_time::{self, ServerTime},
+    utils::signing::{OkxSigner, SignRequest, Signer},
+};
+
+use super::{
+    markets, CandleSticks, Exchange, Market, OrderToken, Orderbook, RealtimeData, Side, Ticker,
+    Trade,
+};
+
+fn signer() -> Result<OkxSigner, OkxError> {
+    Config::get()
+        .okx
+        .as_ref()
+        .map(|config| OkxSigner {
+            api_key: config.api_key.clone(),
+            secret_key: config.secret_key.clone(),
+            passphrase: config.passphrase.clone(),
+        })
+        .ok_or(OkxError::ConfigNotFound)
+}
+
+fn inst_id(pair: (Currency, Currency), market: Market) -> String {
+    let pair = CurrencyPairDelimiterStringifier::<'-'>::stringify(pair.0, pair.1).unwrap();
+    match market {
+        Market::Spot => pair,
+        Market::Future => format!("{}-SWAP", pair),
+    }
+}
+
+fn parse_inst_id(inst_id: &str) -> Option<(Currency, Currency)> {
+    let mut iter = inst_id.split('-');
+    let base = Currency::from_str(iter.next()?).ok()?;
+    let quote = Currency::from_str(iter.next()?).ok()?;
+    Some((base, quote))
+}
+
+fn td_mode(market: Market) -> &'static str {
+    match market {
+        Market::Spot => "cash",
+        Market::Future => "cross",
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum OkxError {
+    #[error("http error: {0}")]
+    HttpError(#[from] http::Error),
+
+    #[error("serde_json error: {0}")]
+    SerdeJsonError(#[from] serde_json::Error),
+
+    #[error("request failed: ({0}) {1}")]
+    RequestFailed(String, String),
+
+    #[error("bid/ask order failed")]
+    OrderFailed,
+
+    #[error("view order failed")]
+    ViewOrderFailed,
+
+    #[error("cancel order failed")]
+    CancelOrderFailed,
+
+    #[error("withdraw failed")]
+    WithdrawFailed,
+
+    #[error("withdraw fee not found")]
+    WithdrawFeeNotFound,
+
+    #[error("withdraw not found")]
+    WithdrawNotFound,
+
+    #[error("invalid order token")]
+    InvalidOrderToken,
+
+    #[error("{0} is not supported")]
+    Unsupported(&'static str),
+
+    #[error("order of {amount} is below one lot of {lot} contracts of {value}")]
+    OrderTooSmall {
+        amount: Decimal,
+        lot: Decimal,
+        value: Decimal,
+    },
+
+    #[error("cofnig not found")]
+    ConfigNotFound,
+}
+
+/// Every OKX v5 REST response is wrapped with `code`, `msg` and a `data` array.
+#[derive(Deserialize)]
+struct Envelope<T> {
+    code: String,
+    msg: String,
+    data: Vec<T>,
+}
+
+/// OKX allows around 20 requests per 2 seconds for most endpoints.
+const RATE_LIMIT: RateLimit = RateLimit {
+    capacity: 10,
+    refill_per_sec: 10.0,
+};
+
+/// Production endpoints, overridden by the `[base_urls.okx]` config section.
+fn default_urls() -> BaseUrls {
+    BaseUrls::new(
+        "https://www.okx.com",
+        "",
+        "wss://ws.okx.com:8443/ws/v5/public",
+    )
+}
+
+pub struct Okx {
+    broadcaster: RealtimeDataBroadcaster,
+    http_client: Client,
+    rate_limiter: RateLimiter,
+    clock: ServerTime,
+    urls: BaseUrls,
+    /// Contracts of the swap instruments ordered so far, by instId.
+    contracts: Mutex<HashMap<String, Contract>>,
+}
+
+/// Swaps are sized in contracts, each worth `value` of the base currency,
+/// and ordered in multiples of `lot` contracts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Contract {
+    value: Decimal,
+    lot: Decimal,
+}
+
+impl Contract {
+    /// Contracts worth `amount` of the base currency, rounded down to whole lots.
+    fn contracts(&self, amount: Decimal) -> Result<Decimal, OkxError> {
+        let contracts = round_down_to_step(amount / self.value, self.lot);
+        if contracts <= Decimal::ZERO {
+            return Err(OkxError::OrderTooSmall {
+                amount,
+                lot: self.lot,
+                value: self.value,
+            });
+        }
+        Ok(contracts)
+    }
+
+    /// The amount of the base currency `contracts` are worth.
+    fn amount(&self, contracts: Decimal) -> Decimal {
+        contracts * self.value
+    }
+}
+
+impl Okx {
+    pub fn new() -> Self {
+        Self::with_urls(BaseUrls::from_config(Self::NAME, default_urls()))
+    }
+
+    /// An exchange talking to `urls`, for testnets and mock servers.
+    pub fn with_urls(urls: BaseUrls) -> Self {
+        let broadcaster = RealtimeDataBroadcaster::new(&urls.ws);
+        broadcaster.spawn_and_broadcast();
+
+        Self {
+            broadcaster,
+            http_client: http::client(),
+            rate_limiter: RateLimiter::from_config(Self::NAME, RATE_LIMIT),
+            clock: ServerTime::new(),
+            urls,
+            contracts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The contract of a swap instrument, fetched once.
+    async fn contract(&self, inst_id: &str) -> Result<Contract, OkxError> {
+        if let Some(contract) = self.contracts.lock().unwrap().get(inst_id) {
+            return Ok(*contract);
+        }
+
+        self.rate_limiter.acquire(1).await;
+        let request = self
+            .http_client
+            .get(self.urls.rest_url("/api/v5/public/instruments"))
+            .query(&[("instType", "SWAP"), ("instId", inst_id)]);
+        let response = http::send_with_retry(request, &Config::get().http_retry).await?;
+        let contract = parse_contract(&response.text().await?, inst_id)?;
+
+        self.contracts
+            .lock()
+            .unwrap()
+            .insert(inst_id.to_string(), contract);
+        Ok(contract)
+    }
+
+    /// Timestamp of signed requests, adjusted to the server clock.
+    async fn timestamp(&self) -> i64 {
+        if self.clock.needs_sync() {
+            if let Err(e) = self.server_time().await {
+                tracing::warn!("Okx: failed to sync server time: {}", e);
+            }
+        }
+
+        self.clock.now_millis()
+    }
+
+    async fn request<R, T>(
+        &self,
+        method: Method,
+        path: &str,
+        message: Option<T>,
+    ) -> Result<Vec<R>, OkxError>
+    where
+        R: DeserializeOwned,
+        T: Serialize,
+    {
+        self.rate_limiter.acquire(1).await;
+
+        // GET requests carry the parameters in the query string, which is part of the signed path.
+        // Other requests carry them as a json body.
+        let (request_path, body) = match &message {
+            Some(message) if method == Method::GET => (
+                format!("{}?{}", path, serde_qs::to_string(message).unwrap()),
+                String::new(),
+            ),
+            Some(message) => (path.to_string(), serde_json::to_string(message)?),
+            None => (path.to_string(), String::new()),
+        };
+
+        // Signed again for each attempt, so the timestamp is the time it is sent at
+        let (method, request_path, body) = (&method, request_path.as_str(), body.as_str());
+        let request = move || async move {
+            let signed = signer()?.sign(
+                &format!("{}{}", method.as_str(), request_path),
+                body,
+                self.timestamp().await,
+            );
+            Ok::<_, OkxError>(
+                self.http_client
+                    .request(
+                        method.clone(),
+                        format!("{}{}", self.urls.rest, request_path),
+                    )
+                    .signed(&signed)
+                    .header("Content-Type", "application/json")
+                    .body(body.to_string()),
+            )
+        };
+
+        // Only queries are retried, retrying an order could place it twice
+        let response = if *method == Method::GET {
+            http::send_signed_with_retry(request, &Config::get().http_retry).await?
+        } else {
+            request().await?.send().await?
+        };
+
+        let status = response.status();
+        let text = response.text().await?;
+
+        if !status.is_success() {
+            tracing::error!("Okx::<{}> response: {}", path, text);
+        } else {
+            tracing::debug!("Okx::<{}> response: {}", path, text);
+        }
+
+        let envelope: Envelope<R> = serde_json::from_str(&text)?;
+        if envelope.code != "0" {
+            return Err(OkxError::RequestFailed(envelope.code, envelope.msg));
+        }
+
+        Ok(envelope.data)
+    }
+
+    async fn make_order(
+        &self,
+        pair: (Currency, Currency),
+        side: &str,
+        order_type: &str,
+        price: Option<Decimal>,
+        amount: Decimal,
+        market: Market,
+    ) -> Result<OrderToken, OkxError> {
+        let inst_id = inst_id(pair, market);
+        let size = match market {
+            Market::Spot => amount,
+            Market::Future => self.contract(&inst_id).await?.contracts(amount)?,
+        };
+
+        let mut message = serde_json::json!({
+            "instId": inst_id,
+            "tdMode": td_mode(market),
+            "side": side,
+            "ordType": order_type,
+            "sz": size.to_string(),
+        });
+
+        if let Some(price) = price {
+            message["px"] = serde_json::json!(price.to_string());
+        }
+
+        // Spot market buys are sized in the quote currency, which matches `bid_market`'s `quote_qty`
+        if market == Market::Spot && order_type == "market" {
+            message["tgtCcy"] = serde_json::json!(if side == "buy" {
+                "quote_ccy"
+            } else {
+                "base_ccy"
+            });
+        }
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Response {
+            ord_id: String,
+            s_code: String,
+        }
+
+        let response: Vec<Response> = self
+            .request(Method::POST, "/api/v5/trade/order", Some(message))
+            .await
+            .map_err(|e| {
+                tracing::error!("Okx::make_order() failed: {}", e);
+                OkxError::OrderFailed
+            })?;
+
+        tracing::info!(
+            "Okx::make_order({} {} {} {:?} {})",
+            inst_id,
+            side,
+            order_type,
+            price,
+            amount
+        );
+
+        let response = response.into_iter().next().ok_or(OkxError::OrderFailed)?;
+        if response.s_code != "0" {
+            return Err(OkxError::OrderFailed);
+        }
+
+        Ok(OrderToken::Okx {
+            id: response.ord_id,
+            inst_id,
+        })
+    }
+}
+
+/// Parses the swap `inst_id` of `/api/v5/public/instruments`.
+/// Only linear swaps, with contracts valued in the base currency, are supported.
+fn parse_contract(text: &str, inst_id: &str) -> Result<Contract, OkxError> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Instrument {
+        ct_val: Decimal,
+        ct_val_ccy: String,
+        lot_sz: Decimal,
+    }
+
+    let response: Envelope<Instrument> = serde_json::from_str(text)?;
+    if response.code != "0" {
+        return Err(OkxError::RequestFailed(response.code, response.msg));
+    }
+
+    let instrument = response.data.into_iter().next().ok_or_else(|| {
+        OkxError::RequestFailed(response.code.clone(), format!("no instrument {}", inst_id))
+    })?;
+    if !inst_id.starts_with(&format!("{}-", instrument.ct_val_ccy)) {
+        return Err(OkxError::Unsupported("inverse swap"));
+    }
+    if instrument.ct_val <= Decimal::ZERO {
+        return Err(OkxError::RequestFailed(
+            response.code,
+            format!("no contract value of {}", inst_id),
+        ));
+    }
+
+    Ok(Contract {
+        value: instrument.ct_val,
+        lot: instrument.lot_sz,
+    })
+}
+
+/// Parses `/api/v5/public/instruments`, only live instruments are listed.
+fn parse_markets(
+    text: &str,
+    market: Market,
+) -> Result<Vec<(Currency, Currency, Market)>, OkxError> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Instrument {
+        inst_id: String,
+        state: String,
+    }
+
+    let response: Envelope<Instrument> = serde_json::from_str(text)?;
+    if response.code != "0" {
+        return Err(OkxError::RequestFailed(response.code, response.msg));
+    }
+
+    Ok(response
+        .data
+        .iter()
+        .filter(|instrument| instrument.state == "live")
+        .filter_map(|instrument| {
+            let (base, quote) = parse_inst_id(&instrument.inst_id)?;
+            Some((base, quote, market))
+        })
+        .collect())
+}
+
+impl Exchange for Okx {
+    const NAME: &'static str = "okx";
+
+    type Error = OkxError;
+
+    fn subscribe(
+        &self,
+        pair: (Currency, Currency),
+        market: Option<Market>,
+    ) -> Subscription<RealtimeData> {
+        self.broadcaster.subscribe(pair, market.unwrap_or_default())
+    }
+
+    fn connection_status(&self) -> Option<StatusHandle> {
+        Some(self.broadcaster.ws.status())
+    }
+
+    fn rate_limit_utilization(&self) -> Option<f64> {
+        Some(self.rate_limiter.utilization())
+    }
+
+    fn clock_drift(&self) -> Option<i64> {
+        self.clock.drift_millis()
+    }
+
+    fn recv_window(&self) -> Option<i64> {
+        // Requests stamped more than 30 seconds off the server time are rejected
+        Some(30_000)
+    }
+
+    async fn markets(&self) -> Result<Vec<(Currency, Currency, Market)>, Self::Error> {
+        if let Some(markets) = markets::fresh(Self::NAME, &self.urls) {
+            return Ok(markets);
+        }
+        tracing::debug!("Okx::markets()");
+
+        let mut markets = Vec::new();
+        for (inst_type, market) in [("SPOT", Market::Spot), ("SWAP", Market::Future)] {
+            self.rate_limiter.acquire(1).await;
+            let request = self
+                .http_client
+                .get(self.urls.rest_url("/api/v5/public/instruments"))
+                .query(&[("instType", inst_type)]);
+            let response = http::send_with_retry(request, &Config::get().http_retry).await?;
+            let text = response.text().await?;
+            markets.extend(parse_markets(&text, market)?);
+        }
+
+        Ok(markets::store(Self::NAME, &self.urls, markets))
+    }
+
+    async fn orderbook(
+        &self,
+        pair: (Currency, Currency),
+        market: Option<Market>,
+    ) -> Result<Orderbook, Self::Error> {
+        tracing::debug!("Okx::orderbook({:?})", pair);
+
+        self.rate_limiter.acquire(1).await;
+        let request = self
+            .http_client
+            .get(self.urls.rest_url("/api/v5/market/books"))
+            .query(&[
+                ("instId", inst_id(pair, market.unwrap_or_default()).as_str()),
+                ("sz", "20"),
+            ]);
+        let response = http::send_with_retry(request, &Config::get().http_retry).await?;
+
+        #[derive(Deserialize)]
+        struct Response {
+            asks: Vec<[Decimal; 4]>,
+            bids: Vec<[Decimal; 4]>,
+        }
+
+        let text = response.text().await?;
+        let response: Envelope<Response> = serde_json::from_str(&text)?;
+        if response.code != "0" {
+            return Err(OkxError::RequestFailed(response.code, response.msg));
+        }
+
+        let Some(book) = response.data.into_iter().next() else {
+            return Err(OkxError::RequestFailed(
+                "0".to_string(),
+                "empty orderbook".to_string(),
+            ));
+        };
+
+        let bids = book
+            .bids
+            .into_iter()
+            .map(|[price, amount, ..]| Unit { price, amount })
+            .collect();
+
+        let asks = book
+            .asks
+            .into_iter()
+            .map(|[price, amount, ..]| Unit { price, amount })
+            .collect();
+
+        Ok(Orderbook { pair, bids, asks }.sorted())
+    }
+
+    async fn candlesticks(
+        &self,
+        pair: (Currency, Currency),
+        market: Option<Market>,
+    ) -> Result<CandleSticks, Self::Error> {
+        use num_traits::ToPrimitive;
+
+        self.rate_limiter.acquire(1).await;
+        let response = self
+            .http_client
+            .get(self.urls.rest_url("/api/v5/market/candles"))
+            .query(&[
+                ("instId", inst_id(pair, market.unwrap_or_default()).as_str()),
+                ("bar", "15m"),
+            ])
+            .send()
+            .await?;
+
+        let text = response.text().await?;
+        let response: Envelope<Vec<Decimal>> = serde_json::from_str(&text)?;
+        if response.code != "0" {
+            return Err(OkxError::RequestFailed(response.code, response.msg));
+        }
+
+        // OKX returns the newest candle first
+        let tickers = response
+            .data
+            .into_iter()
+            .rev()
+            .map(|item| Ticker {
+                timestamp: item[0].to_u64().unwrap(),
+                open: item[1],
+                high: item[2],
+                low: item[3],
+                close: item[4],
+            })
+            .collect();
+
+        Ok(CandleSticks { pair, tickers })
+    }
+
+    async fn ticker(
+        &self,
+        pair: (Currency, Currency),
+        market: Option<Market>,
+    ) -> Result<Decimal, Self::Error> {
+        self.rate_limiter.acquire(1).await;
+        let request = self
+            .http_client
+            .get(self.urls.rest_url("/api/v5/market/ticker"))
+            .query(&[("instId", inst_id(pair, market.unwrap_or_default()).as_str())]);
+        let response = http::send_with_retry(request, &Config::get().http_retry).await?;
+
+        #[derive(Deserialize)]
+        struct Response {
+            last: Decimal,
+        }
+
+        let text = response.text().await?;
+        let response: Envelope<Response> = serde_json::from_str(&text)?;
+        if response.code != "0" {
+            return Err(OkxError::RequestFailed(response.code, response.msg));
+        }
+
+        response
+            .data
+            .first()
+            .map(|ticker| ticker.last)
+            .ok_or_else(|| OkxError::RequestFailed("0".to_string(), "empty ticker".to_string()))
+    }
+
+    async fn recent_trades(
+        &self,
+        pair: (Currency, Currency),
+        market: Option<Market>,
+        limit: usize,
+    ) -> Result<Vec<Trade>, Self::Error> {
+        self.rate_limiter.acquire(1).await;
+        let request = self
+            .http_client
+            .get(self.urls.rest_url("/api/v5/market/trades"))
+            .query(&[
+                ("instId", inst_id(pair, market.unwrap_or_default()).as_str()),
+                ("limit", limit.to_string().as_str()),
+            ]);
+        let response = http::send_with_retry(request, &Config::get().http_retry).await?;
+
+        let text = response.text().await?;
+        let response: Envelope<OkxTrade> = serde_json::from_str(&text)?;
+        if response.code != "0" {
+            return Err(OkxError::RequestFailed(response.code, response.msg));
+        }
+
+        // OKX returns the newest trade first
+        Ok(response
+            .data
+            .into_iter()
+            .rev()
+            .map(|trade| Trade {
+                pair,
+                timestamp: trade.ts.parse().unwrap_or_default(),
+                price: trade.px,
+                amount: trade.sz,
+                is_bid: trade.side == "buy",
+                id: Some(trade.trade_id),
+            })
+            .collect())
+    }
+
+    async fn balance(
+        &self,
+        currency: Currency,
+        _market: Option<Market>,
+    ) -> Result<Balance, Self::Error> {
+        tracing::debug!("Okx::balance({:?})", currency);
+
+        // OKX uses a unified trading account, so spot and swap share the same balance.
+        #[derive(Deserialize)]
+        struct Response {
+            details: Vec<Detail>,
+        }
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Detail {
+            ccy: String,
+            avail_bal: Decimal,
+            frozen_bal: Decimal,
+        }
+
+        let response: Vec<Response> = self
+            .request(
+                Method::GET,
+                "/api/v5/account/balance",
+                Some(serde_json::json!({ "ccy": currency.to_string() })),
+            )
+            .await?;
+
+        let detail = response
+            .into_iter()
+            .flat_map(|r| r.details)
+            .find(|d| d.ccy == currency.to_string());
+
+        Ok(match detail {
+            Some(detail) => Balance {
+                available: detail.avail_bal,
+                locked: detail.frozen_bal,
+            },
+            None => Balance {
+                available: Decimal::ZERO,
+                locked: Decimal::ZERO,
+            },
+        })
+    }
+
+    async fn balances(
+        &self,
+        _market: Option<Market>,
+    ) -> Result<HashMap<Currency, Balance>, Self::Error> {
+        tracing::debug!("Okx::balances()");
+
+        #[derive(Deserialize)]
+        struct Response {
+            details: Vec<Detail>,
+        }
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Detail {
+            ccy: String,
+            avail_bal: Decimal,
+            frozen_bal: Decimal,
+        }
+
+        // Without `ccy` every currency with a balance is returned.
+        let response: Vec<Response> = self
+            .request(
+                Method::GET,
+                "/api/v5/account/balance",
+                None::<serde_json::Value>,
+            )
+            .await?;
+
+        Ok(response
+            .into_iter()
+            .flat_map(|r| r.details)
+            .filter_map(|d| {
+                let balance = Balance {
+                    available: d.avail_bal,
+                    locked: d.frozen_bal,
+                };
+                if balance.is_empty() {
+                    return None;
+                }
+
+                Some((Currency::from_str(&d.ccy).ok()?, balance))
+            })
+            .collect())
+    }
+
+    fn min_notional(
+        &self,
+        _pair: (Currency, Currency),
+        _market: Option<Market>,
+    ) -> Option<Decimal> {
+        // Okx limits the order size in the base currency, not the value
+        None
+    }
+
+    async fn bid_limit(
+        &self,
+        pair: (Currency, Currency),
+        price: Decimal,
+        amount: Decimal,
+        market: Option<Market>,
+    ) -> Result<OrderToken, Self::Error> {
+        tracing::info!("Okx::bid_limit({:?}, {}, {})", pair, price, amount);
+        self.make_order(
+            pair,
+            "buy",
+            "limit",
+            Some(price),
+            amount,
+            market.unwrap_or_default(),
+        )
+        .await
+    }
+
+    async fn bid_market(
+        &self,
+        pair: (Currency, Currency),
+        quote_qty: Decimal,
+        market: Option<Market>,
+    ) -> Result<OrderToken, Self::Error> {
+        tracing::info!("Okx::bid_market({:?}, {})", pair, quote_qty);
+        let market = market.unwrap_or_default();
+        if market == Market::Future {
+            // Swap orders are sized in contracts of the base currency, not in the quote
+            return Err(OkxError::Unsupported("futures market buy by quote amount"));
+        }
+
+        self.make_order(pair, "buy", "market", None, quote_qty, market)
+            .await
+    }
+
+    async fn ask_limit(
+        &self,
+        pair: (Currency, Currency),
+        price: Decimal,
+        amount: Decimal,
+        market: Option<Market>,
+    ) -> Result<OrderToken, Self::Error> {
+        tracing::info!("Okx::ask_limit({:?}, {}, {})", pair, price, amount);
+        self.make_order(
+            pair,
+            "sell",
+            "limit",
+            Some(price),
+            amount,
+            market.unwrap_or_default(),
+        )
+        .await
+    }
+
+    async fn ask_market(
+        &self,
+        pair: (Currency, Currency),
+        base_qty: Decimal,
+        market: Option<Market>,
+    ) -> Result<OrderToken, Self::Error> {
+        tracing::info!("Okx::ask_market({:?}, {})", pair, base_qty);
+        self.make_order(
+            pair,
+            "sell",
+            "market",
+            None,
+            base_qty,
+            market.unwrap_or_default(),
+        )
+        .await
+    }
+
+    async fn stop_limit(
+        &self,
+        _pair: (Currency, Currency),
+        _stop_price: Decimal,
+        _limit_price: Decimal,
+        _amount: Decimal,
+        _side: Side,
+        _market: Option<Market>,
+    ) -> Result<OrderToken, Self::Error> {
+        // Okx stop orders are algo orders, which the order apis here can not view or cancel
+        Err(OkxError::Unsupported("stop order"))
+    }
+
+    async fn view_order(&self, order_token: &OrderToken) -> Result<Order, Self::Error> {
+        let OrderToken::Okx {
+            id: ord_id,
+            inst_id,
+        } = order_token
+        else {
+            return Err(OkxError::InvalidOrderToken);
+        };
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Response {
+            state: String,
+            side: String,
+            sz: Decimal,
+            acc_fill_sz: Decimal,
+            avg_px: String,
+        }
+
+        let response: Vec<Response> = self
+            .request(
+                Method::GET,
+                "/api/v5/trade/order",
+                Some(serde_json::json!({
+                    "instId": inst_id,
+                    "ordId": ord_id,
+                })),
+            )
+            .await
+            .map_err(|e| {
+                tracing::error!("Okx::view_order() failed: {}", e);
+                OkxError::ViewOrderFailed
+            })?;
+
+        let mut response = response
+            .into_iter()
+            .next()
+            .ok_or(OkxError::ViewOrderFailed)?;
+        // Swap orders are sized in contracts, reported back in the base currency like spot ones
+        if inst_id.ends_with("-SWAP") {
+            let contract = self.contract(inst_id).await?;
+            response.sz = contract.amount(response.sz);
+            response.acc_fill_sz = contract.amount(response.acc_fill_sz);
+        }
+
+        let state = match response.state.as_str() {
+            "filled" | "canceled" | "mmp_canceled" => OrderState::Closed,
+            _ => OrderState::Wait,
+        };
+
+        // Keep the same convention as the other exchanges:
+        // bids report the received base amount, asks report the received quote amount.
+        // OKX reports an empty average price until the order is filled
+        let avg_px = Decimal::from_str(&response.avg_px).ok();
+        let qty = if response.side == "buy" {
+            response.acc_fill_sz
+        } else {
+            response.acc_fill_sz * avg_px.unwrap_or(Decimal::ZERO)
+        };
+
+        Ok(Order {
+            state,
+            executed_volume: qty,
+            remaining: (response.sz - response.acc_fill_sz).max(Decimal::ZERO),
+            avg_price: avg_px.filter(|px| *px > Decimal::ZERO),
+        })
+    }
+
+    async fn wait_order(&self, order_token: &OrderToken) -> Result<Decimal, Self::Error> {
+        loop {
+            let order = self.view_order(order_token).await?;
+            if order.state == OrderState::Closed {
+                return Ok(order.executed_volume);
+            }
+
+            async_helpers::sleep(Duration::from_millis(250)).await;
+        }
+    }
+
+    async fn cancel_order(&self, order_token: &OrderToken) -> Result<Decimal, Self::Error> {
+        let OrderToken::Okx {
+            id: ord_id,
+            inst_id,
+        } = order_token
+        else {
+            return Err(OkxError::InvalidOrderToken);
+        };
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Response {
+            s_code: String,
+        }
+
+        let response: Vec<Response> = self
+            .request(
+                Method::POST,
+                "/api/v5/trade/cancel-order",
+                Some(serde_json::json!({
+                    "instId": inst_id,
+                    "ordId": ord_id,
+                })),
+            )
+            .await
+            .map_err(|e| {
+                tracing::error!("Okx::cancel_order() failed: {}", e);
+                OkxError::CancelOrderFailed
+            })?;
+
+        tracing::info!("Okx::cancel_order({}, {})", inst_id, ord_id);
+        if response.first().map(|r| r.s_code.as_str()) != Some("0") {
+            return Err(OkxError::CancelOrderFailed);
+        }
+
+        // The cancel response does not carry the filled amount, so ask for it.
+        Ok(self.view_order(order_token).await?.executed_volume)
+    }
+
+    async fn withdraw(
+        &self,
+        currency: Currency,
+        amount: Decimal,
+        address1: &str,
+        address2: Option<&str>,
+        network: Option<&str>,
+    ) -> Result<String, Self::Error> {
+        tracing::info!(
+            "Okx::withdraw({:?}, {}, {}, {:?}, {:?})",
+            currency,
+            amount,
+            address1,
+            address2,
+            network
+        );
+
+        // OKX expects memo/tag based addresses to be formatted as `address:tag`
+        let to_addr = match address2 {
+            Some(address2) => format!("{}:{}", address1, address2),
+            None => address1.to_string(),
+        };
+
+        let mut message = serde_json::json!({
+            "ccy": currency.to_string(),
+            "amt": amount.to_string(),
+            "dest": "4", // on-chain withdrawal
+            "toAddr": to_addr,
+        });
+
+        if let Some(network) = network {
+            message["chain"] = serde_json::json!(format!("{}-{}", currency, network));
+        }
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Response {
+            wd_id: String,
+        }
+
+        let response: Vec<Response> = self
+            .request(Method::POST, "/api/v5/asset/withdrawal", Some(message))
+            .await
+            .map_err(|e| {
+                tracing::error!("Okx::withdraw() failed: {}", e);
+                OkxError::WithdrawFailed
+            })?;
+
+        response
+            .into_iter()
+            .next()
+            .map(|r| r.wd_id)
+            .ok_or(OkxError::WithdrawFailed)
+    }
+
+    async fn withdraw_status(
+        &self,
+        currency: Currency,
+        id: &str,
+    ) -> Result<WithdrawState, Self::Error> {
+        tracing::debug!("Okx::withdraw_status({:?}, {})", currency, id);
+
+        #[derive(Deserialize)]
+        struct Response {
+            state: String,
+        }
+
+        let response: Vec<Response> = self
+            .request(
+                Method::GET,
+                "/api/v5/asset/withdrawal-history",
+                Some(serde_json::json!({ "ccy": currency.to_string(), "wdId": id })),
+            )
+            .await?;
+
+        let response = response
+            .into_iter()
+            .next()
+            .ok_or(OkxError::WithdrawNotFound)?;
+
+        // 1: broadcasting, 2: success, -1: failed, -2: cancelled,
+        // every other state is waiting for a transfer or an approval
+        Ok(match response.state.as_str() {
+            "1" => WithdrawState::Processing,
+            "2" => WithdrawState::Done,
+            "-1" | "-2" => WithdrawState::Failed,
+            _ => WithdrawState::Pending,
+        })
+    }
+
+    async fn withdraw_fee(
+        &self,
+        currency: Currency,
+        network: Option<&str>,
+    ) -> Result<Decimal, Self::Error> {
+        tracing::debug!("Okx::withdraw_fee({:?}, {:?})", currency, network);
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Response {
+            chain: String,
+            min_fee: Decimal,
+            main_net: bool,
+        }
+
+        let response: Vec<Response> = self
+            .request(
+                Method::GET,
+                "/api/v5/asset/currencies",
+                Some(serde_json::json!({ "ccy": currency.to_string() })),
+            )
+            .await?;
+
+        // Chains are named `{currency}-{network}`, like in `withdraw`
+        let chain = network.map(|network| format!("{}-{}", currency, network));
+        response
+            .into_iter()
+            .find(|r| match &chain {
+                Some(chain) => &r.chain == chain,
+                None => r.main_net,
+            })
+            .map(|r| r.min_fee)
+            .ok_or(OkxError::WithdrawFeeNotFound)
+    }
+
+    async fn set_leverage(
+        &self,
+        pair: Option<(Currency, Currency)>,
+        value: u64,
+    ) -> Result<(), Self::Error> {
+        tracing::trace!("Okx::set_leverage({:?}, {})", pair, value);
+
+        let Some(pair) = pair else {
+            return Err(OkxError::Unsupported("leverage without a pair"));
+        };
+        self.request::<serde_json::Value, _>(
+            Method::POST,
+            "/api/v5/account/set-leverage",
+            Some(serde_json::json!({
+                "instId": inst_id(pair, Market::Future),
+                "lever": value.to_string(),
+                "mgnMode": "cross",
+            })),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn server_time(&self) -> Result<i64, Self::Error> {
+        #[derive(Deserialize)]
+        struct Response {
+            ts: String,
+        }
+
+        self.rate_limiter.acquire(1).await;
+        let sent = server_time::local_millis();
+        let request = self
+            .http_client
+            .get(self.urls.rest_url("/api/v5/public/time"));
+        let response = http::send_with_retry(request, &Config::get().http_retry).await?;
+        let received = server_time::local_millis();
+
+        let text = response.text().await?;
+        let response: Envelope<Response> = serde_json::from_str(&text)?;
+        let server = response
+            .data
+            .first()
+            .and_then(|time| time.ts.parse::<i64>().ok())
+            .ok_or(OkxError::RequestFailed(response.code, response.msg))?;
+
+        self.clock.update(server, sent, received);
+        Ok(server)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct OkxArg {
+    pub channel: String,
+    #[serde(rename = "instId")]
+    pub inst_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum OkxItem {
+    Books {
+        arg: OkxArg,
+        action: String,
+        data: Vec<OkxBook>,
+    },
+    Trades {
+        arg: OkxArg,
+        data: Vec<OkxTrade>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct OkxBook {
+    pub asks: Vec<[Decimal; 4]>,
+    pub bids: Vec<[Decimal; 4]>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct OkxTrade {
+    #[serde(rename = "tradeId")]
+    pub trade_id: String,
+    pub px: Decimal,
+    pub sz: Decimal,
+    pub side: String,
+    pub ts: String,
+}
+
+/// Local copy of an orderbook built from the `books` channel.
+/// The channel sends a full snapshot first and incremental updates after that,
+/// where a level with zero amount means the level was removed.
+#[derive(Default)]
+struct LocalBook {
+    asks: BTreeMap<Decimal, Decimal>,
+    bids: BTreeMap<Decimal, Decimal>,
+}
+
+impl LocalBook {
+    fn apply(&mut self, action: &str, book: &OkxBook) {
+        if action == "snapshot" {
+            self.asks.clear();
+            self.bids.clear();
+        }
+
+        for (levels, side) in [(&book.asks, &mut self.asks), (&book.bids, &mut self.bids)] {
+            for [price, amount, ..] in levels {
+                if *amount == Decimal::ZERO {
+                    side.remove(price);
+                } else {
+                    side.insert(*price, *amount);
+                }
+            }
+        }
+    }
+
+    fn to_orderbook(&self, pair: (Currency, Currency)) -> Orderbook {
+        Orderbook {
+            pair,
+            asks: self
+                .asks
+                .iter()
+                .take(20)
+                .map(|(price, amount)| Unit {
+                    price: *price,
+                    amount: *amount,
+                })
+                .collect(),
+            bids: self
+                .bids
+                .iter()
+                .rev()
+                .take(20)
+                .map(|(price, amount)| Unit {
+                    price: *price,
+                    amount: *amount,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Subscribes or unsubscribes (`op`) the public websocket to the books and trades of `inst_ids`.
+fn subscription_request(op: &str, inst_ids: &[&str]) -> String {
+    let args = inst_ids
+        .iter()
+        .flat_map(|inst_id| {
+            [
+                serde_json::json!({ "channel": "books", "instId": inst_id }),
+                serde_json::json!({ "channel": "trades", "instId": inst_id }),
+            ]
+        })
+        .collect::<Vec<_>>();
+    serde_json::json!({ "op": op, "args": args }).to_string()
+}
+
+/// A broadcaster per subscribed instrument. The spot and swap instruments of a pair
+/// stream the same `RealtimeData`, so routing by pair would mix their books.
+#[derive(Default)]
+struct Topics {
+    topics: HashMap<String, Broadcaster<RealtimeData>>,
+}
+
+impl Topics {
+    /// Returns the subscription, and true if the instrument was not subscribed before.
+    fn subscribe(&mut self, inst_id: &str) -> (Subscription<RealtimeData>, bool) {
+        let is_new = !self.topics.contains_key(inst_id);
+        let subscription = self
+            .topics
+            .entry(inst_id.to_string())
+            .or_insert_with(Broadcaster::new)
+            .subscribe();
+        (subscription, is_new)
+    }
+
+    /// Sends data to the subscribers of `inst_id`.
+    /// Returns true if the instrument has no subscribers left, in which case it is removed.
+    fn route(&mut self, inst_id: &str, data: RealtimeData) -> bool {
+        let Some(topic) = self.topics.get(inst_id) else {
+            return false;
+        };
+
+        topic.broadcast(data);
+        if topic.subscriber_count() > 0 {
+            return false;
+        }
+
+        self.topics.remove(inst_id);
+        true
+    }
+
+    fn inst_ids(&self) -> Vec<&str> {
+        self.topics.keys().map(String::as_str).collect()
+    }
+}
+
+impl OkxItem {
+    /// The instrument of the message and its data, the local book of the instrument
+    /// is updated with the orderbook messages.
+    fn into_realtime_data(
+        self,
+        books: &mut HashMap<String, LocalBook>,
+    ) -> Option<(String, Vec<RealtimeData>)> {
+        match self {
+            OkxItem::Books { arg, action, data } => {
+                let pair = parse_inst_id(&arg.inst_id)?;
+                let book = books.entry(arg.inst_id.clone()).or_default();
+                for item in &data {
+                    book.apply(&action, item);
+                }
+
+                let orderbook = book.to_orderbook(pair).normalize();
+                Some((arg.inst_id, vec![RealtimeData::Orderbook(orderbook)]))
+            }
+            OkxItem::Trades { arg, data } => {
+                let pair = parse_inst_id(&arg.inst_id)?;
+                let trades = data
+                    .into_iter()
+                    .map(|trade| {
+                        RealtimeData::Trade(Trade {
+                            pair,
+                            timestamp: trade.ts.parse().unwrap_or_default(),
+                            price: trade.px,
+                            amount: trade.sz,
+                            is_bid: trade.side == "buy",
+                            id: Some(trade.trade_id),
+                        })
+                    })
+                    .collect();
+                Some((arg.inst_id, trades))
+            }
+        }
+    }
+}
+
+/// Routes the messages of the public websocket, shared with its tasks.
+/// It only holds a sender of the websocket, so the websocket stops once the broadcaster is dropped.
+#[derive(Clone)]
+struct Router {
+    topics: Arc<Mutex<Topics>>,
+    books: Arc<Mutex<HashMap<String, LocalBook>>>,
+    ws: WebsocketSender,
+}
+
+impl Router {
+    fn route(&self, item: &str) {
+        // Subscription acknowledgements and errors are not market data
+        let Ok(item) = serde_json::from_str::<OkxItem>(item) else {
+            tracing::debug!("Okx: ignoring message: {}", item);
+            return;
+        };
+        let Some((inst_id, data)) = item.into_realtime_data(&mut self.books.lock().unwrap()) else {
+            return;
+        };
+
+        let mut topics = self.topics.lock().unwrap();
+        for data in data {
+            if topics.route(&inst_id, data) {
+                // The last subscriber of the instrument is gone, stop streaming it
+                self.books.lock().unwrap().remove(&inst_id);
+                self.ws
+                    .send(&subscription_request("unsubscribe", &[&inst_id]));
+                return;
+            }
+        }
+    }
+}
+
+struct RealtimeDataBroadcaster {
+    router: Router,
+
+    ws: Websocket,
+}
+
+impl RealtimeDataBroadcaster {
+    fn new(ws: &str) -> Self {
+        let ws = Websocket::new(ws);
+        Self {
+            router: Router {
+                topics: Arc::new(Mutex::new(Topics::default())),
+                books: Arc::new(Mutex::new(HashMap::new())),
+                ws: ws.sender(),
+            },
+            ws,
+        }
+    }
+
+    fn spawn_and_broadcast(&self) {
+        let receiver = self.ws.receiver();
+        let router = self.router.clone();
+        async_helpers::spawn(async move {
+            while let Some(item) = receiver.recv().await {
+                router.route(&item);
+            }
+        });
+
+        let topics = self.router.topics.clone();
+        self.ws.on_connected(move |ws| {
+            let topics = topics.lock().unwrap();
+            let inst_ids = topics.inst_ids();
+            if !inst_ids.is_empty() {
+                ws.send(&subscription_request("subscribe", &inst_ids));
+            }
+        });
+    }
+
+    fn subscribe(&self, pair: (Currency, Currency), market: Market) -> Subscription<RealtimeData> {
+        let inst_id = inst_id(pair, market);
+
+        let (subscription, is_new) = self.router.topics.lock().unwrap().subscribe(&inst_id);
+        if is_new {
+            self.ws
+                .send(&subscription_request("subscribe", &[&inst_id]));
+        }
+
+        subscription
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use futures::FutureExt;
+
+    use super::{OkxItem, Topics};
+    use crate::dec;
+    use crate::{
+        currency::Currency,
+        exchange::{Exchange, Market, Okx, RealtimeData},
+    };
+
+    fn route(topics: &mut Topics, message: &str) -> bool {
+        let item = serde_json::from_str::<OkxItem>(message).unwrap();
+        let (inst_id, data) = item.into_realtime_data(&mut HashMap::new()).unwrap();
+        data.into_iter().any(|data| topics.route(&inst_id, data))
+    }
+
+    #[tokio::test]
+    async fn route_by_instrument() {
+        let mut topics = Topics::default();
+        let (spot, is_new) = topics.subscribe("BTC-USDT");
+        assert!(is_new);
+        let (swap, is_new) = topics.subscribe("BTC-USDT-SWAP");
+        assert!(is_new);
+
+        route(
+            &mut topics,
+            r#"{"arg":{"channel":"books","instId":"BTC-USDT-SWAP"},"action":"snapshot","data":[{"asks":[["101","3","0","1"]],"bids":[["99","2","0","1"]]}]}"#,
+        );
+        route(
+            &mut topics,
+            r#"{"arg":{"channel":"trades","instId":"BTC-USDT"},"data":[{"tradeId":"1","px":"100","sz":"0.5","side":"buy","ts":"1700000000000"}]}"#,
+        );
+
+        let RealtimeData::Orderbook(orderbook) = swap.recv().await else {
+            panic!("expected an orderbook");
+        };
+        assert_eq!(orderbook.asks[0].price.to_string(), "101");
+        assert!(swap.recv().now_or_never().is_none());
+
+        let RealtimeData::Trade(trade) = spot.recv().await else {
+            panic!("expected a trade");
+        };
+        assert_eq!(trade.pair, (Currency::BTC, Currency::USDT));
+        assert!(spot.recv().now_or_never().is_none());
+    }
+
+    #[test]
+    fn drop_last_subscriber() {
+        let mut topics = Topics::default();
+        let (spot, _) = topics.subscribe("BTC-USDT");
+        let message = r#"{"arg":{"channel":"trades","instId":"BTC-USDT"},"data":[{"tradeId":"2","px":"100","sz":"1","side":"sell","ts":"1"}]}"#;
+
+        assert!(!route(&mut topics, message));
+        drop(spot);
+        assert!(route(&mut topics, message));
+        assert!(topics.inst_ids().is_empty());
+    }
+
+    #[test]
+    fn parse_markets() {
+        let text = r#"{"code": "0", "msg": "", "data": [
+            {"instId": "BTC-USDT-SWAP", "instType": "SWAP", "state": "live"},
+            {"instId": "ETH-USDT-SWAP", "instType": "SWAP", "state": "suspend"}
+        ]}"#;
+        assert_eq!(
+            super::parse_markets(text, Market::Future).unwrap(),
+            vec![(Currency::BTC, Currency::USDT, Market::Future)]
+        );
+
+        let failed = r#"{"code": "51000", "msg": "Parameter instType error", "data": []}"#;
+        assert!(super::parse_markets(failed, Market::Spot).is_err());
+    }
+
+    #[test]
+    fn contracts() {
+        let text = r#"{"code": "0", "msg": "", "data": [
+            {"instId": "BTC-USDT-SWAP", "ctVal": "0.01", "ctValCcy": "BTC", "lotSz": "0.1"}
+        ]}"#;
+        let contract = super::parse_contract(text, "BTC-USDT-SWAP").unwrap();
+        assert_eq!(contract.contracts(dec!(0.0567)).unwrap(), dec!(5.6));
+        assert_eq!(contract.amount(dec!(5.6)), dec!(0.056));
+        assert!(contract.contracts(dec!(0.0009)).is_err());
+
+        let inverse = r#"{"code": "0", "msg": "", "data": [
+            {"instId": "BTC-USD-SWAP", "ctVal": "100", "ctValCcy": "USD", "lotSz": "1"}
+        ]}"#;
+        assert!(super::parse_contract(inverse, "BTC-USD-SWAP").is_err());
+    }
+
+    #[test]
+    fn inst_id() {
+        let pair = (Currency::BTC, Currency::USDT);
+        assert_eq!(super::inst_id(pair, Market::Spot), "BTC-USDT");
+        assert_eq!(super::inst_id(pair, Market::Future), "BTC-USDT-SWAP");
+        assert_eq!(super::parse_inst_id("BTC-USDT-SWAP"), Some(pair));
+    }
+
+    #[ignore]
+    #[tokio::test]
+    async fn balance() {
+        let exchange = Okx::new();
+        let balance = exchange.balance(Currency::USDT, None).await.unwrap();
+
+        println!("{:?}", balance);
+    }
+
+    #[ignore]
+    #[tokio::test]
+    async fn balances() {
+        let exchange = Okx::new();
+        let balances = exchange.balances(None).await.unwrap();
+
+        println!("{:?}", balances);
+    }
+}
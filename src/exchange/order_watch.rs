@@ -0,0 +1,415 @@
+//! Watches placed orders until they close and tells the console and the ui,
+//! so a fill or cancellation is noticed without a script polling the order.
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::time::Duration;
+
+use num_traits::Zero;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use crate::currency::{Currency, CurrencyPair};
+use crate::utils::async_helpers;
+use crate::utils::broadcaster::{Broadcaster, Subscription};
+use crate::utils::maybe_trait::MaybeSend;
+use crate::utils::{server_time, Decimal};
+
+use super::{Order, OrderState, OrderToken, Side};
+
+/// How often a watched order is viewed.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How often a watched order is viewed while its exchange streams order updates.
+const STREAMED_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Failed views in a row before the order is given up on.
+const MAX_FAILURES: usize = 10;
+
+/// Reported orders are remembered this long, so late updates of them are not reported again.
+const REPORTED_RETENTION_MILLIS: i64 = 60 * 60 * 1000;
+
+/// What is known of an order when it is placed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlacedOrder {
+    pub pair: (Currency, Currency),
+    pub side: Side,
+    /// Limit price, None for market orders.
+    pub price: Option<Decimal>,
+}
+
+/// A watched order that closed, or filled partially.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderFilled {
+    pub exchange: &'static str,
+    pub pair: (Currency, Currency),
+    pub side: Side,
+    /// Average fill price, the limit price if the exchange does not report one.
+    pub price: Option<Decimal>,
+    /// Executed quantity.
+    pub qty: Decimal,
+    /// Closed by a cancellation, `qty` is what filled before it.
+    pub cancelled: bool,
+    /// Still open, `qty` is what filled so far.
+    pub partial: bool,
+}
+
+impl OrderFilled {
+    fn new(exchange: &'static str, placed: PlacedOrder, order: &Order) -> Self {
+        let closed = order.state == OrderState::Closed;
+        Self {
+            exchange,
+            pair: placed.pair,
+            side: placed.side,
+            price: order.avg_price.or(placed.price),
+            qty: order.executed_volume,
+            cancelled: closed && !order.remaining.is_zero(),
+            partial: !closed,
+        }
+    }
+}
+
+impl std::fmt::Display for OrderFilled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let side = match self.side {
+            Side::Bid => "bid",
+            Side::Ask => "ask",
+        };
+        let state = if self.partial {
+            "partially filled"
+        } else if self.cancelled {
+            "cancelled"
+        } else {
+            "filled"
+        };
+        write!(
+            f,
+            "{} {} {} {} {}",
+            self.exchange,
+            side,
+            state,
+            CurrencyPair::from(self.pair),
+            self.qty
+        )?;
+        if let Some(price) = self.price {
+            write!(f, " at {}", price)?;
+        }
+        Ok(())
+    }
+}
+
+enum Watch {
+    Open {
+        exchange: &'static str,
+        placed: PlacedOrder,
+        /// Volume executed as of the last report.
+        executed: Decimal,
+    },
+    Reported {
+        /// Local time of the report in milliseconds.
+        at: i64,
+    },
+}
+
+/// Drops the orders reported longer than [`REPORTED_RETENTION_MILLIS`] before `now`.
+fn forget_reported(orders: &mut HashMap<OrderToken, Watch>, now: i64) {
+    orders.retain(|_, watch| match watch {
+        Watch::Reported { at } => now - *at <= REPORTED_RETENTION_MILLIS,
+        Watch::Open { .. } => true,
+    });
+}
+
+/// Orders being watched, each reported once when it closes
+/// and whenever more of it filled while it is open.
+/// Orders are polled, and reported right away when the exchange streams an update of them.
+pub struct OrderWatcher {
+    /// Watched and reported orders, so an order is never watched or reported twice.
+    orders: Mutex<HashMap<OrderToken, Watch>>,
+    /// Exchanges streaming order updates, their orders are polled less often.
+    streaming: Mutex<HashSet<&'static str>>,
+    broadcaster: Broadcaster<OrderFilled>,
+}
+
+impl OrderWatcher {
+    pub fn instance() -> &'static OrderWatcher {
+        static WATCHER: Lazy<OrderWatcher> = Lazy::new(|| OrderWatcher {
+            orders: Mutex::new(HashMap::new()),
+            streaming: Mutex::new(HashSet::new()),
+            broadcaster: Broadcaster::new(),
+        });
+
+        &WATCHER
+    }
+
+    /// Orders of every exchange closing from now on.
+    pub fn subscribe(&self) -> Subscription<OrderFilled> {
+        self.broadcaster.subscribe()
+    }
+
+    /// Views the order with `view` until it closes, then broadcasts it.
+    /// Returns false if the order is already watched or reported.
+    pub fn watch<F, Fut>(
+        &'static self,
+        exchange: &'static str,
+        order_token: OrderToken,
+        placed: PlacedOrder,
+        view: F,
+    ) -> bool
+    where
+        F: Fn(OrderToken) -> Fut + MaybeSend + 'static,
+        Fut: Future<Output = Result<Order, String>> + MaybeSend,
+    {
+        {
+            let mut orders = self.orders.lock();
+            forget_reported(&mut orders, server_time::local_millis());
+            if orders.contains_key(&order_token) {
+                return false;
+            }
+            let watch = Watch::Open {
+                exchange,
+                placed,
+                executed: Decimal::zero(),
+            };
+            orders.insert(order_token.clone(), watch);
+        }
+
+        async_helpers::spawn(async move {
+            let mut failures = 0;
+            loop {
+                if self.is_reported(&order_token) {
+                    return;
+                }
+
+                match view(order_token.clone()).await {
+                    Ok(order) => {
+                        failures = 0;
+                        self.update(&order_token, &order);
+                    }
+                    Err(e) => {
+                        failures += 1;
+                        if failures >= MAX_FAILURES {
+                            tracing::warn!("Order: gave up watching {:?}: {}", order_token, e);
+                            self.orders.lock().remove(&order_token);
+                            return;
+                        }
+                    }
+                }
+
+                let interval = if self.streaming.lock().contains(exchange) {
+                    STREAMED_POLL_INTERVAL
+                } else {
+                    POLL_INTERVAL
+                };
+                async_helpers::sleep(interval).await;
+            }
+        });
+        true
+    }
+
+    /// The latest state of an order, from a poll or the stream of the exchange.
+    /// Reports the order if it is watched and closed or filled further,
+    /// unwatched orders are ignored.
+    pub fn update(&self, order_token: &OrderToken, order: &Order) {
+        let filled = {
+            let mut orders = self.orders.lock();
+            let Some(watch) = orders.get_mut(order_token) else {
+                return;
+            };
+            match watch {
+                Watch::Open {
+                    exchange,
+                    placed,
+                    executed,
+                } if order.state != OrderState::Closed => {
+                    if order.executed_volume <= *executed {
+                        return;
+                    }
+                    *executed = order.executed_volume;
+                    OrderFilled::new(*exchange, placed.clone(), order)
+                }
+                Watch::Open { .. } => {
+                    let reported = Watch::Reported {
+                        at: server_time::local_millis(),
+                    };
+                    match std::mem::replace(watch, reported) {
+                        Watch::Open {
+                            exchange, placed, ..
+                        } => OrderFilled::new(exchange, placed, order),
+                        Watch::Reported { .. } => return,
+                    }
+                }
+                Watch::Reported { .. } => return,
+            }
+        };
+
+        tracing::info!("Order: {}", filled);
+        self.broadcaster.broadcast(filled);
+    }
+
+    /// Marks the exchange as streaming its order updates or not,
+    /// while it does its orders are only polled to catch updates missed during reconnects.
+    pub fn set_streaming(&self, exchange: &'static str, streaming: bool) {
+        let mut exchanges = self.streaming.lock();
+        if streaming {
+            exchanges.insert(exchange);
+        } else {
+            exchanges.remove(exchange);
+        }
+    }
+
+    fn is_reported(&self, order_token: &OrderToken) -> bool {
+        matches!(
+            self.orders.lock().get(order_token),
+            Some(Watch::Reported { .. })
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::{
+        forget_reported, OrderFilled, OrderWatcher, PlacedOrder, Watch, REPORTED_RETENTION_MILLIS,
+    };
+    use crate::currency::Currency;
+    use crate::exchange::{Order, OrderState, OrderToken, Side};
+    use crate::utils::broadcaster::Subscription;
+    use crate::utils::Decimal;
+
+    fn order(state: OrderState, executed: i64, remaining: i64) -> Order {
+        Order {
+            state,
+            executed_volume: Decimal(executed.into()),
+            remaining: Decimal(remaining.into()),
+            avg_price: None,
+        }
+    }
+
+    /// The next report of `exchange`, None if there is none within a few seconds.
+    async fn next_report(
+        subscription: &Subscription<OrderFilled>,
+        exchange: &str,
+    ) -> Option<OrderFilled> {
+        let report = async {
+            loop {
+                let filled = subscription.recv().await;
+                if filled.exchange == exchange {
+                    return filled;
+                }
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), report)
+            .await
+            .ok()
+    }
+
+    #[tokio::test]
+    async fn reports_a_fill_once() {
+        let watcher = OrderWatcher::instance();
+        let subscription = watcher.subscribe();
+        let token = OrderToken::Upbit {
+            uuid: "order-watch-test".to_string(),
+        };
+        let placed = PlacedOrder {
+            pair: (Currency::BTC, Currency::KRW),
+            side: Side::Bid,
+            price: Some(Decimal(100.into())),
+        };
+
+        let views = Arc::new(AtomicUsize::new(0));
+        let view = {
+            let views = views.clone();
+            move |_| {
+                let views = views.clone();
+                async move {
+                    // Open on the first view, filled on the second
+                    Ok(match views.fetch_add(1, Ordering::Relaxed) {
+                        0 => order(OrderState::Wait, 0, 2),
+                        _ => order(OrderState::Closed, 2, 0),
+                    })
+                }
+            }
+        };
+        assert!(watcher.watch("watch-test", token.clone(), placed.clone(), view.clone()));
+        assert!(!watcher.watch("watch-test", token, placed, view));
+
+        let filled = next_report(&subscription, "watch-test").await.unwrap();
+        assert!(!filled.cancelled);
+        assert_eq!(filled.to_string(), "watch-test bid filled BTC-KRW 2 at 100");
+        assert_eq!(views.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn streamed_update_reports_once() {
+        let watcher = OrderWatcher::instance();
+        let subscription = watcher.subscribe();
+        let token = OrderToken::Upbit {
+            uuid: "order-stream-test".to_string(),
+        };
+        let placed = PlacedOrder {
+            pair: (Currency::ETH, Currency::KRW),
+            side: Side::Ask,
+            price: None,
+        };
+
+        // Never closes when polled
+        watcher.set_streaming("stream-test", true);
+        watcher.watch("stream-test", token.clone(), placed, |_| async {
+            Ok(order(OrderState::Wait, 0, 3))
+        });
+
+        let mut cancelled = order(OrderState::Closed, 1, 2);
+        cancelled.avg_price = Some(Decimal(50.into()));
+        watcher.update(&token, &order(OrderState::Wait, 1, 2));
+        watcher.update(&token, &order(OrderState::Wait, 1, 2));
+        watcher.update(&token, &cancelled);
+        watcher.update(&token, &cancelled);
+
+        // Partial fills are reported once per increase of the executed volume
+        let partial = next_report(&subscription, "stream-test").await.unwrap();
+        assert!(partial.partial && !partial.cancelled);
+        assert_eq!(
+            partial.to_string(),
+            "stream-test ask partially filled ETH-KRW 1"
+        );
+
+        let filled = next_report(&subscription, "stream-test").await.unwrap();
+        assert!(filled.cancelled);
+        assert_eq!(
+            filled.to_string(),
+            "stream-test ask cancelled ETH-KRW 1 at 50"
+        );
+        assert_eq!(next_report(&subscription, "stream-test").await, None);
+    }
+
+    #[test]
+    fn reported_orders_are_forgotten() {
+        let token = |uuid: &str| OrderToken::Upbit {
+            uuid: uuid.to_string(),
+        };
+        let mut orders = HashMap::new();
+        orders.insert(token("old"), Watch::Reported { at: 0 });
+        orders.insert(token("recent"), Watch::Reported { at: 1 });
+        orders.insert(
+            token("open"),
+            Watch::Open {
+                exchange: "forget-test",
+                placed: PlacedOrder {
+                    pair: (Currency::BTC, Currency::KRW),
+                    side: Side::Bid,
+                    price: None,
+                },
+                executed: Decimal::ZERO,
+            },
+        );
+
+        forget_reported(&mut orders, REPORTED_RETENTION_MILLIS + 1);
+        assert!(!orders.contains_key(&token("old")));
+        assert!(orders.contains_key(&token("recent")));
+        assert!(orders.contains_key(&token("open")));
+    }
+}
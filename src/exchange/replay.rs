@@ -0,0 +1,478 @@
+//! Recording of realtime data, and an exchange replaying a recording.
+//! The replay only serves market data, orders and account requests are unsupported.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::currency::Currency;
+use crate::utils::async_helpers;
+use crate::utils::broadcaster::{Broadcaster, Subscription};
+use crate::utils::server_time;
+use crate::utils::Decimal;
+use crate::websocket::StatusHandle;
+
+use super::{
+    Balance, CandleSticks, Exchange, Market, Order, OrderToken, Orderbook, RealtimeData, Side,
+    Trade, WithdrawState,
+};
+
+/// A line of a recording.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Record {
+    /// Time the data was received, in milliseconds.
+    pub timestamp: i64,
+    pub data: RealtimeData,
+}
+
+fn pair_of(data: &RealtimeData) -> (Currency, Currency) {
+    match data {
+        RealtimeData::Orderbook(orderbook) => orderbook.pair,
+        RealtimeData::Trade(trade) => trade.pair,
+    }
+}
+
+/// Running recordings by path, a recording ends once its sender is dropped.
+static RECORDINGS: Lazy<Mutex<HashMap<String, async_channel::Sender<()>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Appends everything the subscription receives to `path`, one record per line,
+/// until [`stop_recording`] is called with the same path or writing fails.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn start_recording(
+    subscription: Subscription<RealtimeData>,
+    path: String,
+) -> Result<(), String> {
+    let mut recordings = RECORDINGS.lock();
+    recordings.retain(|_, stop| !stop.is_closed());
+    if recordings.contains_key(&path) {
+        return Err(format!("Already recording to {}", path));
+    }
+
+    let (stop, stopped) = async_channel::bounded(1);
+    recordings.insert(path.clone(), stop);
+    async_helpers::spawn(async move {
+        match record(subscription, &path, stopped).await {
+            Ok(()) => tracing::info!("Stopped recording to {}", path),
+            Err(e) => tracing::error!("Failed to record to {}: {}", path, e),
+        }
+    });
+    Ok(())
+}
+
+#[cfg(any(target_arch = "wasm32"))]
+pub fn start_recording(
+    _subscription: Subscription<RealtimeData>,
+    path: String,
+) -> Result<(), String> {
+    Err(format!(
+        "Failed to record to {}: files can't be written in the browser",
+        path
+    ))
+}
+
+/// Ends the recording to `path`, returns false if there is none.
+pub fn stop_recording(path: &str) -> bool {
+    RECORDINGS.lock().remove(path).is_some()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+async fn record(
+    subscription: Subscription<RealtimeData>,
+    path: &str,
+    stopped: async_channel::Receiver<()>,
+) -> std::io::Result<()> {
+    use futures::future::{self, Either};
+    use tokio::io::AsyncWriteExt;
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    loop {
+        let data =
+            match future::select(Box::pin(subscription.recv()), Box::pin(stopped.recv())).await {
+                Either::Left((data, _)) => data,
+                // Writes of the file are finished in the background, wait for the last one
+                Either::Right(_) => return file.flush().await,
+            };
+        let record = Record {
+            timestamp: server_time::local_millis(),
+            data,
+        };
+        let mut line = serde_json::to_string(&record)?;
+        line.push('\n');
+        file.write_all(line.as_bytes()).await?;
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReplayError {
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("serde_json error: {0}")]
+    SerdeJsonError(#[from] serde_json::Error),
+
+    #[error("no data recorded for {0:?} yet")]
+    NoData((Currency, Currency)),
+
+    #[error("{0} is not supported")]
+    Unsupported(&'static str),
+}
+
+/// Trades kept per pair to answer `recent_trades`.
+const TRADE_LIMIT: usize = 1000;
+
+/// What the replay has streamed so far.
+#[derive(Default)]
+struct Seen {
+    orderbooks: HashMap<(Currency, Currency), Orderbook>,
+    trades: HashMap<(Currency, Currency), Vec<Trade>>,
+}
+
+impl Seen {
+    fn push(&mut self, data: &RealtimeData) {
+        match data {
+            RealtimeData::Orderbook(orderbook) => {
+                self.orderbooks.insert(orderbook.pair, orderbook.clone());
+            }
+            RealtimeData::Trade(trade) => {
+                let trades = self.trades.entry(trade.pair).or_default();
+                trades.push(trade.clone());
+                if trades.len() > TRADE_LIMIT {
+                    trades.remove(0);
+                }
+            }
+        }
+    }
+}
+
+/// Exchange streaming a recording, `speed` times faster than it was recorded.
+/// Playback starts with the first subscription and runs once.
+pub struct Replay {
+    records: Arc<Vec<Record>>,
+    speed: f64,
+    started: AtomicBool,
+    seen: Arc<Mutex<Seen>>,
+    broadcaster: Broadcaster<RealtimeData>,
+}
+
+impl Replay {
+    pub fn new(records: Vec<Record>, speed: f64) -> Self {
+        Self {
+            records: Arc::new(records),
+            speed,
+            started: AtomicBool::new(false),
+            seen: Arc::new(Mutex::new(Seen::default())),
+            broadcaster: Broadcaster::new(),
+        }
+    }
+
+    /// Parses a recording, blank lines are skipped.
+    pub fn parse(text: &str, speed: f64) -> Result<Self, ReplayError> {
+        let records = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<Record>, _>>()?;
+        Ok(Self::new(records, speed))
+    }
+
+    pub fn load(path: &str, speed: f64) -> Result<Self, ReplayError> {
+        Self::parse(&std::fs::read_to_string(path)?, speed)
+    }
+
+    /// Pairs in the recording, in the order they first appear.
+    pub fn pairs(&self) -> Vec<(Currency, Currency)> {
+        let mut pairs = Vec::new();
+        for record in self.records.iter() {
+            let pair = pair_of(&record.data);
+            if !pairs.contains(&pair) {
+                pairs.push(pair);
+            }
+        }
+        pairs
+    }
+
+    fn start(&self) {
+        if self.started.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        let records = self.records.clone();
+        let speed = self.speed;
+        let seen = self.seen.clone();
+        let broadcaster = self.broadcaster.clone();
+        async_helpers::spawn(async move {
+            let mut previous = records.first().map(|record| record.timestamp);
+            for record in records.iter() {
+                let elapsed = record.timestamp - previous.unwrap_or(record.timestamp);
+                if elapsed > 0 {
+                    // A tiny speed stretches the gap beyond what a Duration holds
+                    let gap = Duration::try_from_secs_f64(elapsed as f64 / 1000.0 / speed)
+                        .unwrap_or(Duration::MAX);
+                    async_helpers::sleep(gap).await;
+                }
+                previous = Some(record.timestamp);
+
+                // Seen before broadcast, so subscribers find it in `orderbook`
+                seen.lock().push(&record.data);
+                broadcaster.broadcast(record.data.clone());
+            }
+            tracing::info!("Replay finished after {} records", records.len());
+        });
+    }
+}
+
+impl Exchange for Replay {
+    const NAME: &'static str = "replay";
+
+    type Error = ReplayError;
+
+    fn subscribe(
+        &self,
+        pair: (Currency, Currency),
+        _market: Option<Market>,
+    ) -> Subscription<RealtimeData> {
+        let subscription = self
+            .broadcaster
+            .subscribe()
+            .filter(move |data| pair_of(data) == pair);
+        self.start();
+        subscription
+    }
+
+    fn connection_status(&self) -> Option<StatusHandle> {
+        None
+    }
+
+    /// The pairs in the recording.
+    async fn markets(&self) -> Result<Vec<(Currency, Currency, Market)>, Self::Error> {
+        Ok(self
+            .pairs()
+            .into_iter()
+            .map(|(base, quote)| (base, quote, Market::Spot))
+            .collect())
+    }
+
+    /// The most recent snapshot streamed so far.
+    async fn orderbook(
+        &self,
+        pair: (Currency, Currency),
+        _market: Option<Market>,
+    ) -> Result<Orderbook, Self::Error> {
+        self.seen
+            .lock()
+            .orderbooks
+            .get(&pair)
+            .cloned()
+            .ok_or(ReplayError::NoData(pair))
+    }
+
+    async fn candlesticks(
+        &self,
+        _pair: (Currency, Currency),
+        _market: Option<Market>,
+    ) -> Result<CandleSticks, Self::Error> {
+        Err(ReplayError::Unsupported("candlesticks"))
+    }
+
+    /// The price of the last trade streamed so far.
+    async fn ticker(
+        &self,
+        pair: (Currency, Currency),
+        _market: Option<Market>,
+    ) -> Result<Decimal, Self::Error> {
+        self.seen
+            .lock()
+            .trades
+            .get(&pair)
+            .and_then(|trades| trades.last())
+            .map(|trade| trade.price)
+            .ok_or(ReplayError::NoData(pair))
+    }
+
+    async fn recent_trades(
+        &self,
+        pair: (Currency, Currency),
+        _market: Option<Market>,
+        limit: usize,
+    ) -> Result<Vec<Trade>, Self::Error> {
+        let seen = self.seen.lock();
+        let trades = seen
+            .trades
+            .get(&pair)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        Ok(trades[trades.len().saturating_sub(limit)..].to_vec())
+    }
+
+    async fn balance(
+        &self,
+        _currency: Currency,
+        _market: Option<Market>,
+    ) -> Result<Balance, Self::Error> {
+        Err(ReplayError::Unsupported("balance"))
+    }
+
+    async fn balances(
+        &self,
+        _market: Option<Market>,
+    ) -> Result<HashMap<Currency, Balance>, Self::Error> {
+        Err(ReplayError::Unsupported("balance"))
+    }
+
+    fn min_notional(
+        &self,
+        _pair: (Currency, Currency),
+        _market: Option<Market>,
+    ) -> Option<Decimal> {
+        None
+    }
+
+    async fn bid_limit(
+        &self,
+        _pair: (Currency, Currency),
+        _price: Decimal,
+        _amount: Decimal,
+        _market: Option<Market>,
+    ) -> Result<OrderToken, Self::Error> {
+        Err(ReplayError::Unsupported("order"))
+    }
+
+    async fn bid_market(
+        &self,
+        _pair: (Currency, Currency),
+        _quote_qty: Decimal,
+        _market: Option<Market>,
+    ) -> Result<OrderToken, Self::Error> {
+        Err(ReplayError::Unsupported("order"))
+    }
+
+    async fn ask_limit(
+        &self,
+        _pair: (Currency, Currency),
+        _price: Decimal,
+        _amount: Decimal,
+        _market: Option<Market>,
+    ) -> Result<OrderToken, Self::Error> {
+        Err(ReplayError::Unsupported("order"))
+    }
+
+    async fn ask_market(
+        &self,
+        _pair: (Currency, Currency),
+        _base_qty: Decimal,
+        _market: Option<Market>,
+    ) -> Result<OrderToken, Self::Error> {
+        Err(ReplayError::Unsupported("order"))
+    }
+
+    async fn stop_limit(
+        &self,
+        _pair: (Currency, Currency),
+        _stop_price: Decimal,
+        _limit_price: Decimal,
+        _amount: Decimal,
+        _side: Side,
+        _market: Option<Market>,
+    ) -> Result<OrderToken, Self::Error> {
+        Err(ReplayError::Unsupported("stop order"))
+    }
+
+    async fn view_order(&self, _order_token: &OrderToken) -> Result<Order, Self::Error> {
+        Err(ReplayError::Unsupported("order"))
+    }
+
+    async fn wait_order(&self, _order_token: &OrderToken) -> Result<Decimal, Self::Error> {
+        Err(ReplayError::Unsupported("order"))
+    }
+
+    async fn cancel_order(&self, _order_token: &OrderToken) -> Result<Decimal, Self::Error> {
+        Err(ReplayError::Unsupported("order"))
+    }
+
+    async fn withdraw(
+        &self,
+        _currency: Currency,
+        _amount: Decimal,
+        _address1: &str,
+        _address2: Option<&str>,
+        _network: Option<&str>,
+    ) -> Result<String, Self::Error> {
+        Err(ReplayError::Unsupported("withdraw"))
+    }
+
+    async fn withdraw_status(
+        &self,
+        _currency: Currency,
+        _id: &str,
+    ) -> Result<WithdrawState, Self::Error> {
+        Err(ReplayError::Unsupported("withdraw"))
+    }
+
+    async fn withdraw_fee(
+        &self,
+        _currency: Currency,
+        _network: Option<&str>,
+    ) -> Result<Decimal, Self::Error> {
+        Err(ReplayError::Unsupported("withdraw"))
+    }
+
+    async fn set_leverage(
+        &self,
+        _pair: Option<(Currency, Currency)>,
+        _value: u64,
+    ) -> Result<(), Self::Error> {
+        Err(ReplayError::Unsupported("leverage"))
+    }
+
+    async fn server_time(&self) -> Result<i64, Self::Error> {
+        Ok(server_time::local_millis())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Replay;
+    use crate::currency::Currency;
+    use crate::exchange::{Exchange, RealtimeData};
+    use crate::utils::Decimal;
+
+    const FIXTURE: &str = include_str!("../../resources/replay_fixture.jsonl");
+    const PAIR: (Currency, Currency) = (Currency::BTC, Currency::KRW);
+
+    #[tokio::test]
+    async fn replay_fixture() {
+        let replay = Replay::parse(FIXTURE, 1000.0).unwrap();
+        assert_eq!(replay.pairs(), vec![PAIR, (Currency::ETH, Currency::KRW)]);
+        assert!(replay.orderbook(PAIR, None).await.is_err());
+
+        let subscription = replay.subscribe(PAIR, None);
+        let mut received = Vec::new();
+        for _ in 0..3 {
+            received.push(subscription.recv().await);
+        }
+
+        // Data of other pairs is not delivered
+        assert!(matches!(received[0], RealtimeData::Orderbook(_)));
+        assert!(matches!(received[1], RealtimeData::Trade(_)));
+        let RealtimeData::Orderbook(last) = &received[2] else {
+            panic!("expected an orderbook, got {:?}", received[2]);
+        };
+
+        assert_eq!(replay.orderbook(PAIR, None).await.unwrap(), *last);
+        assert_eq!(last.bids[0].price, Decimal(100.into()));
+        assert_eq!(
+            replay.ticker(PAIR, None).await.unwrap(),
+            Decimal(101.into())
+        );
+    }
+}
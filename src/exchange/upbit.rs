@@ -1,62 +1,59 @@
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     str::FromStr,
     sync::{Arc, Mutex},
     time::Duration,
 };
 
+use futures::future::{self, Either};
+use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use unwrap_let::unwrap_let;
 
-use super::{CandleSticks, Exchange, Market, OrderToken, Orderbook, RealtimeData, Trade};
+use super::user_stream::UserEvent;
+use super::{
+    markets, CandleSticks, Exchange, Market, OrderToken, Orderbook, RealtimeData, Side, Ticker,
+    Trade,
+};
 use crate::{
     config::Config,
-    currency::{Currency, CurrencyPairDelimiterStringifier, CurrencyPairStringifier},
+    currency::{Currency, CurrencyPair, CurrencyPairDelimiterStringifier, CurrencyPairStringifier},
     dec,
-    exchange::{Balance, Order, OrderState, Unit},
+    exchange::{Balance, Order, OrderState, Unit, WithdrawState},
     utils::{
         async_helpers,
         broadcaster::{Broadcaster, Subscription},
         http,
-        http::Client,
+        http::{BaseUrls, Client},
+        rate_limiter::{RateLimit, RateLimiter},
+        rounding::round_down_dp,
+        server_time::{self, ServerTime},
+        signing::{SignRequest, Signer, UpbitSigner},
         Decimal,
     },
-    websocket::Websocket,
+    websocket::{ConnState, ConnectionStatus, StatusHandle, Websocket, WebsocketOptions},
 };
 
-fn access_key() -> Result<&'static str, UpbitError> {
-    Config::get()
-        .upbit
-        .as_ref()
-        .map(|config| config.access_key.as_str())
-        .ok_or(UpbitError::ConfigNotFound)
+/// Upbit writes the quote first, e.g. `KRW-BTC`.
+/// Every market code is built here so the order is never swapped at a call site.
+fn market_code(pair: CurrencyPair) -> String {
+    CurrencyPairDelimiterStringifier::<'-'>::stringify(pair.quote(), pair.base()).unwrap()
 }
 
-fn secret_key() -> Result<&'static str, UpbitError> {
+fn signer() -> Result<UpbitSigner, UpbitError> {
     Config::get()
         .upbit
         .as_ref()
-        .map(|config| config.secret_key.as_str())
+        .map(|config| UpbitSigner {
+            access_key: config.access_key.clone(),
+            secret_key: config.secret_key.clone(),
+        })
         .ok_or(UpbitError::ConfigNotFound)
 }
 
-fn gen_jwt_token(access_key: &str, secret_key: &str, body_qs: &str) -> String {
-    use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
-    use sha2::{Digest, Sha512};
-
-    let body_hash = hex::encode(Sha512::digest(body_qs.as_bytes()));
-
-    let paylaod = json!({
-        "access_key": access_key,
-        "nonce": chrono::Utc::now().timestamp_millis(),
-        "query_hash": body_hash,
-        "query_hash_alg": "SHA512",
-    });
-
-    let header = Header::new(Algorithm::HS256);
-    let key = EncodingKey::from_secret(secret_key.as_ref());
-    format!("Bearer {}", encode(&header, &paylaod, &key).unwrap())
+/// Authorization of the private websocket, a new token for every connect.
+fn authorization() -> Option<String> {
+    Some(format!("Bearer {}", signer().ok()?.websocket_token()))
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -64,6 +61,21 @@ pub enum UpbitError {
     #[error("failed to get orderbook")]
     FailedToGetOrderbook,
 
+    #[error("failed to get candlesticks")]
+    FailedToGetCandlesticks,
+
+    #[error("failed to get ticker")]
+    FailedToGetTicker,
+
+    #[error("failed to get trades")]
+    FailedToGetTrades,
+
+    #[error("failed to get markets")]
+    FailedToGetMarkets,
+
+    #[error("failed to get balances")]
+    FailedToGetBalances,
+
     #[error("http client error")]
     HttpClientError(#[from] reqwest::Error),
 
@@ -73,6 +85,12 @@ pub enum UpbitError {
     #[error("bid/ask order failed")]
     OrderFailed,
 
+    #[error("order of {notional} is below the minimum of {min_notional}")]
+    OrderTooSmall {
+        notional: Decimal,
+        min_notional: Decimal,
+    },
+
     #[error("view order failed")]
     ViewOrderFailed,
 
@@ -82,25 +100,153 @@ pub enum UpbitError {
     #[error("withdraw failed")]
     WithdrawFailed,
 
+    #[error("withdraw fee not found")]
+    WithdrawFeeNotFound,
+
+    #[error("failed to get withdraw status")]
+    WithdrawStatusFailed,
+
+    #[error("failed to get server time")]
+    ServerTimeFailed,
+
+    #[error("invalid order token")]
+    InvalidOrderToken,
+
+    #[error("{0} is not supported")]
+    Unsupported(&'static str),
+
     #[error("cofnig not found")]
     ConfigNotFound,
 }
 
+/// Upbit allows 8 requests per second for the exchange api.
+const RATE_LIMIT: RateLimit = RateLimit {
+    capacity: 8,
+    refill_per_sec: 8.0,
+};
+
+/// How often `wait_order` views the order while the private websocket is down.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How often `wait_order` views the order while the private websocket streams its updates,
+/// only to catch an update missed during a reconnect.
+const STREAMED_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Production endpoints, overridden by the `[base_urls.upbit]` config section.
+fn default_urls() -> BaseUrls {
+    BaseUrls::new(
+        "https://api.upbit.com",
+        "",
+        "wss://api.upbit.com/websocket/v1",
+    )
+}
+
 pub struct Upbit {
     broadcaster: RealtimeDataBroadcaster,
     http_client: Client,
+    rate_limiter: RateLimiter,
+    clock: ServerTime,
+    urls: BaseUrls,
+    /// Started on the first subscription, see [`run_user_stream`].
+    user_stream: OnceCell<UserStream>,
+}
+
+/// The private websocket of the account.
+struct UserStream {
+    events: Broadcaster<UserEvent>,
+    status: StatusHandle,
 }
 
 impl Upbit {
     pub fn new() -> Self {
-        let broadcaster = RealtimeDataBroadcaster::new();
+        Self::with_urls(BaseUrls::from_config(Self::NAME, default_urls()))
+    }
+
+    /// An exchange talking to `urls`, for testnets and mock servers.
+    pub fn with_urls(urls: BaseUrls) -> Self {
+        let broadcaster = RealtimeDataBroadcaster::new(&urls.ws);
         broadcaster.spawn_and_broadcast();
 
         Self {
             broadcaster,
             http_client: http::client(),
+            rate_limiter: RateLimiter::from_config(Self::NAME, RATE_LIMIT),
+            clock: ServerTime::new(),
+            urls,
+            user_stream: OnceCell::new(),
         }
     }
+
+    /// The private websocket, connected on the first call. None without api keys,
+    /// and on wasm where the authorization header can not be sent.
+    fn user_stream(&self) -> Option<&UserStream> {
+        if cfg!(target_arch = "wasm32") {
+            return None;
+        }
+        signer().ok()?;
+
+        Some(self.user_stream.get_or_init(|| {
+            let options = WebsocketOptions {
+                authorization: Some(authorization),
+                quiet: true,
+                ..Default::default()
+            };
+            let websocket =
+                Websocket::new_with_options(&format!("{}/private", self.urls.ws), options);
+            let stream = UserStream {
+                events: Broadcaster::new(),
+                status: websocket.status(),
+            };
+            // Subscribed before it connects, so the first connect is not missed
+            let states = websocket.state_rx();
+            async_helpers::spawn(run_user_stream(websocket, states, stream.events.clone()));
+            stream
+        }))
+    }
+
+    /// Nonce of private api requests, adjusted to the server clock.
+    async fn nonce(&self) -> i64 {
+        if self.clock.needs_sync() {
+            if let Err(e) = self.server_time().await {
+                tracing::warn!("Upbit: failed to sync server time: {}", e);
+            }
+        }
+
+        self.clock.now_millis()
+    }
+
+    /// Fails if the order value is below the minimum notional of the pair.
+    fn check_notional(
+        &self,
+        pair: (Currency, Currency),
+        market: Option<Market>,
+        notional: Decimal,
+    ) -> Result<(), UpbitError> {
+        match self.min_notional(pair, market) {
+            Some(min_notional) if notional < min_notional => Err(UpbitError::OrderTooSmall {
+                notional,
+                min_notional,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Parses `/v1/market/all`, markets are named quote first like `KRW-BTC`.
+fn parse_markets(text: &str) -> Result<Vec<(Currency, Currency, Market)>, serde_json::Error> {
+    #[derive(Deserialize)]
+    struct Response {
+        market: String,
+    }
+
+    let response: Vec<Response> = serde_json::from_str(text)?;
+    Ok(response
+        .iter()
+        .filter_map(|response| {
+            let (quote, base) = response.market.split_once('-')?;
+            Some((base.parse().ok()?, quote.parse().ok()?, Market::Spot))
+        })
+        .collect())
 }
 
 impl Exchange for Upbit {
@@ -116,6 +262,46 @@ impl Exchange for Upbit {
         self.broadcaster.subscribe(pair)
     }
 
+    fn connection_status(&self) -> Option<StatusHandle> {
+        Some(self.broadcaster.ws.status())
+    }
+
+    fn rate_limit_utilization(&self) -> Option<f64> {
+        Some(self.rate_limiter.utilization())
+    }
+
+    fn clock_drift(&self) -> Option<i64> {
+        self.clock.drift_millis()
+    }
+
+    fn user_events(&self) -> Option<Subscription<UserEvent>> {
+        Some(self.user_stream()?.events.subscribe())
+    }
+
+    async fn markets(&self) -> Result<Vec<(Currency, Currency, Market)>, Self::Error> {
+        if let Some(markets) = markets::fresh(Self::NAME, &self.urls) {
+            return Ok(markets);
+        }
+        tracing::debug!("Upbit::markets()");
+
+        self.rate_limiter.acquire(1).await;
+        let request = self.http_client.get(self.urls.rest_url("/v1/market/all"));
+        let response = http::send_with_retry(request, &Config::get().http_retry).await?;
+
+        let status = response.status();
+        let response = response.text().await?;
+        if !status.is_success() {
+            tracing::warn!("Upbit::markets() response: {}", response);
+            return Err(UpbitError::FailedToGetMarkets);
+        }
+
+        Ok(markets::store(
+            Self::NAME,
+            &self.urls,
+            parse_markets(&response)?,
+        ))
+    }
+
     async fn orderbook(
         &self,
         pair: (Currency, Currency),
@@ -123,16 +309,13 @@ impl Exchange for Upbit {
     ) -> Result<Orderbook, Self::Error> {
         tracing::debug!("Upbit::orderbook({:?})", pair);
 
-        let pair_stringified =
-            CurrencyPairDelimiterStringifier::<'-'>::stringify(pair.1, pair.0).unwrap();
-        let response = self
-            .http_client
-            .get(&format!(
-                "https://api.upbit.com/v1/orderbook?markets={}",
-                pair_stringified
-            ))
-            .send()
-            .await?;
+        let pair_stringified = market_code(pair.into());
+        self.rate_limiter.acquire(1).await;
+        let request = self.http_client.get(&format!(
+            "{}/v1/orderbook?markets={}",
+            self.urls.rest, pair_stringified
+        ));
+        let response = http::send_with_retry(request, &Config::get().http_retry).await?;
 
         #[derive(Deserialize)]
         struct Response {
@@ -164,33 +347,181 @@ impl Exchange for Upbit {
             asks.push(ask);
         }
 
-        Ok(Orderbook { pair, bids, asks })
+        Ok(Orderbook { pair, bids, asks }.sorted())
     }
 
     async fn candlesticks(
         &self,
-        _pair: (Currency, Currency),
+        pair: (Currency, Currency),
         _market: Option<Market>,
     ) -> Result<CandleSticks, Self::Error> {
-        todo!()
+        tracing::debug!("Upbit::candlesticks({:?})", pair);
+
+        let pair_stringified = market_code(pair.into());
+        self.rate_limiter.acquire(1).await;
+        let request = self.http_client.get(&format!(
+            "{}/v1/candles/minutes/15?market={}&count=200",
+            self.urls.rest, pair_stringified
+        ));
+        let response = http::send_with_retry(request, &Config::get().http_retry).await?;
+
+        #[derive(Deserialize)]
+        struct Response {
+            candle_date_time_utc: String,
+            opening_price: Decimal,
+            high_price: Decimal,
+            low_price: Decimal,
+            trade_price: Decimal,
+        }
+
+        let status = response.status();
+        let response = response.text().await?;
+        if !status.is_success() {
+            tracing::warn!("Upbit::candlesticks() response: {}", response);
+            return Err(UpbitError::FailedToGetCandlesticks);
+        }
+
+        // Upbit returns the newest candle first
+        let response: Vec<Response> = serde_json::from_str(&response)?;
+        let mut tickers = Vec::new();
+        for candle in response.into_iter().rev() {
+            let Ok(opened_at) = chrono::NaiveDateTime::parse_from_str(
+                &candle.candle_date_time_utc,
+                "%Y-%m-%dT%H:%M:%S",
+            ) else {
+                return Err(UpbitError::FailedToGetCandlesticks);
+            };
+
+            tickers.push(Ticker {
+                timestamp: opened_at.and_utc().timestamp_millis() as u64,
+                open: candle.opening_price,
+                high: candle.high_price,
+                low: candle.low_price,
+                close: candle.trade_price,
+            });
+        }
+
+        Ok(CandleSticks { pair, tickers })
+    }
+
+    async fn ticker(
+        &self,
+        pair: (Currency, Currency),
+        _market: Option<Market>,
+    ) -> Result<Decimal, Self::Error> {
+        tracing::debug!("Upbit::ticker({:?})", pair);
+
+        let pair_stringified = market_code(pair.into());
+        self.rate_limiter.acquire(1).await;
+        let request = self.http_client.get(&format!(
+            "{}/v1/ticker?markets={}",
+            self.urls.rest, pair_stringified
+        ));
+        let response = http::send_with_retry(request, &Config::get().http_retry).await?;
+
+        #[derive(Deserialize)]
+        struct Response {
+            trade_price: Decimal,
+        }
+
+        let status = response.status();
+        let response = response.text().await?;
+        if !status.is_success() {
+            tracing::warn!("Upbit::ticker() response: {}", response);
+            return Err(UpbitError::FailedToGetTicker);
+        }
+
+        let response: Vec<Response> = serde_json::from_str(&response)?;
+        response
+            .first()
+            .map(|ticker| ticker.trade_price)
+            .ok_or(UpbitError::FailedToGetTicker)
+    }
+
+    async fn recent_trades(
+        &self,
+        pair: (Currency, Currency),
+        _market: Option<Market>,
+        limit: usize,
+    ) -> Result<Vec<Trade>, Self::Error> {
+        tracing::debug!("Upbit::recent_trades({:?}, {})", pair, limit);
+
+        let pair_stringified = market_code(pair.into());
+        self.rate_limiter.acquire(1).await;
+        let request = self.http_client.get(&format!(
+            "{}/v1/trades/ticks?market={}&count={}",
+            self.urls.rest, pair_stringified, limit
+        ));
+        let response = http::send_with_retry(request, &Config::get().http_retry).await?;
+
+        #[derive(Deserialize)]
+        struct Response {
+            timestamp: i64,
+            trade_price: Decimal,
+            trade_volume: Decimal,
+            ask_bid: String,
+            sequential_id: u64,
+        }
+
+        let status = response.status();
+        let response = response.text().await?;
+        if !status.is_success() {
+            tracing::warn!("Upbit::recent_trades() response: {}", response);
+            return Err(UpbitError::FailedToGetTrades);
+        }
+
+        // Upbit returns the newest trade first
+        let response: Vec<Response> = serde_json::from_str(&response)?;
+        Ok(response
+            .into_iter()
+            .rev()
+            .map(|trade| Trade {
+                pair,
+                timestamp: trade.timestamp,
+                price: trade.trade_price,
+                amount: trade.trade_volume,
+                is_bid: trade.ask_bid == "BID",
+                id: Some(trade.sequential_id.to_string()),
+            })
+            .collect())
     }
 
     async fn balance(
         &self,
         currency: Currency,
-        _market: Option<Market>,
+        market: Option<Market>,
     ) -> Result<Balance, Self::Error> {
         tracing::debug!("Upbit::balance({:?})", currency);
 
-        let response = self
-            .http_client
-            .get("https://api.upbit.com/v1/accounts")
-            .header(
-                "Authorization",
-                gen_jwt_token(access_key()?, secret_key()?, ""),
-            )
-            .send()
-            .await?;
+        Ok(self
+            .balances(market)
+            .await?
+            .remove(&currency)
+            .unwrap_or(Balance {
+                available: dec!(0),
+                locked: dec!(0),
+            }))
+    }
+
+    async fn balances(
+        &self,
+        _market: Option<Market>,
+    ) -> Result<HashMap<Currency, Balance>, Self::Error> {
+        tracing::debug!("Upbit::balances()");
+
+        self.rate_limiter.acquire(1).await;
+
+        let response = http::send_signed_with_retry(
+            move || async move {
+                Ok::<_, UpbitError>(
+                    self.http_client
+                        .get(self.urls.rest_url("/v1/accounts"))
+                        .signed(&signer()?.sign("", "", self.nonce().await)),
+                )
+            },
+            &Config::get().http_retry,
+        )
+        .await?;
 
         #[derive(Deserialize)]
         struct Response {
@@ -201,25 +532,35 @@ impl Exchange for Upbit {
 
         let status = response.status();
         let response = response.text().await?;
-        tracing::debug!("Upbit::balance() response: {}", response);
+        tracing::debug!("Upbit::balances() response: {}", response);
         if !status.is_success() {
-            todo!("handle error")
+            return Err(UpbitError::FailedToGetBalances);
         }
 
-        let response: Vec<Response> = serde_json::from_str(&response).unwrap();
-        let response = response
+        let response: Vec<Response> = serde_json::from_str(&response)?;
+        Ok(response
             .into_iter()
-            .find(|r| r.currency == currency.to_string())
-            .unwrap_or_else(|| Response {
-                currency: currency.to_string(),
-                balance: dec!(0),
-                locked: dec!(0),
-            });
+            .filter_map(|r| {
+                let balance = Balance {
+                    available: r.balance,
+                    locked: r.locked,
+                };
+                if balance.is_empty() {
+                    return None;
+                }
+
+                Some((Currency::from_str(&r.currency).ok()?, balance))
+            })
+            .collect())
+    }
 
-        Ok(Balance {
-            available: response.balance,
-            locked: response.locked,
-        })
+    fn min_notional(&self, pair: (Currency, Currency), _market: Option<Market>) -> Option<Decimal> {
+        match pair.1 {
+            Currency::KRW => Some(dec!(5000)),
+            Currency::BTC => Some(dec!(0.00005)),
+            Currency::USDT => Some(dec!(0.5)),
+            _ => None,
+        }
     }
 
     async fn bid_limit(
@@ -227,11 +568,12 @@ impl Exchange for Upbit {
         pair: (Currency, Currency),
         price: Decimal,
         amount: Decimal,
-        _market: Option<Market>,
+        market: Option<Market>,
     ) -> Result<OrderToken, Self::Error> {
         tracing::info!("Upbit::bid_limit({:?}, {}, {})", pair, price, amount);
+        self.check_notional(pair, market, price * amount)?;
 
-        let pair = CurrencyPairDelimiterStringifier::<'-'>::stringify(pair.1, pair.0).unwrap();
+        let pair = market_code(pair.into());
         let message = json!({
             "side": "bid",
             "market": pair,
@@ -246,13 +588,11 @@ impl Exchange for Upbit {
         }
 
         let query_string = serde_qs::to_string(&message).unwrap();
+        self.rate_limiter.acquire(1).await;
         let response = self
             .http_client
-            .post("https://api.upbit.com/v1/orders")
-            .header(
-                "Authorization",
-                gen_jwt_token(access_key()?, secret_key()?, &query_string),
-            )
+            .post(self.urls.rest_url("/v1/orders"))
+            .signed(&signer()?.sign("", &query_string, self.nonce().await))
             .body(serde_json::to_string(&message).unwrap())
             .send()
             .await?;
@@ -267,18 +607,21 @@ impl Exchange for Upbit {
 
         async_helpers::sleep(Duration::from_millis(250)).await;
         let response: Response = serde_json::from_str(&text)?;
-        Ok(OrderToken::String(response.uuid))
+        Ok(OrderToken::Upbit {
+            uuid: response.uuid,
+        })
     }
 
     async fn bid_market(
         &self,
         pair: (Currency, Currency),
         quote_qty: Decimal,
-        _market: Option<Market>,
+        market: Option<Market>,
     ) -> Result<OrderToken, Self::Error> {
         tracing::info!("Upbit::bid_market({:?}, {})", pair, quote_qty);
+        self.check_notional(pair, market, quote_qty)?;
 
-        let pair = CurrencyPairDelimiterStringifier::<'-'>::stringify(pair.1, pair.0).unwrap();
+        let pair = market_code(pair.into());
         let message = json!({
             "side": "bid",
             "market": pair,
@@ -292,13 +635,11 @@ impl Exchange for Upbit {
         }
 
         let query_string = serde_qs::to_string(&message).unwrap();
+        self.rate_limiter.acquire(1).await;
         let response = self
             .http_client
-            .post("https://api.upbit.com/v1/orders")
-            .header(
-                "Authorization",
-                gen_jwt_token(access_key()?, secret_key()?, &query_string),
-            )
+            .post(self.urls.rest_url("/v1/orders"))
+            .signed(&signer()?.sign("", &query_string, self.nonce().await))
             .body(serde_json::to_string(&message).unwrap())
             .send()
             .await?;
@@ -313,7 +654,9 @@ impl Exchange for Upbit {
 
         async_helpers::sleep(Duration::from_millis(250)).await;
         let response: Response = serde_json::from_str(&text)?;
-        Ok(OrderToken::String(response.uuid))
+        Ok(OrderToken::Upbit {
+            uuid: response.uuid,
+        })
     }
 
     async fn ask_limit(
@@ -321,11 +664,12 @@ impl Exchange for Upbit {
         pair: (Currency, Currency),
         price: Decimal,
         amount: Decimal,
-        _market: Option<Market>,
+        market: Option<Market>,
     ) -> Result<OrderToken, Self::Error> {
         tracing::info!("Upbit::ask_limit({:?}, {}, {})", pair, price, amount);
+        self.check_notional(pair, market, price * amount)?;
 
-        let pair = CurrencyPairDelimiterStringifier::<'-'>::stringify(pair.1, pair.0).unwrap();
+        let pair = market_code(pair.into());
         let message = json!({
             "side": "ask",
             "market": pair,
@@ -340,13 +684,11 @@ impl Exchange for Upbit {
         }
 
         let query_string = serde_qs::to_string(&message).unwrap();
+        self.rate_limiter.acquire(1).await;
         let response = self
             .http_client
-            .post("https://api.upbit.com/v1/orders")
-            .header(
-                "Authorization",
-                gen_jwt_token(access_key()?, secret_key()?, &query_string),
-            )
+            .post(self.urls.rest_url("/v1/orders"))
+            .signed(&signer()?.sign("", &query_string, self.nonce().await))
             .body(serde_json::to_string(&message).unwrap())
             .send()
             .await?;
@@ -361,7 +703,9 @@ impl Exchange for Upbit {
 
         async_helpers::sleep(Duration::from_millis(250)).await;
         let response: Response = serde_json::from_str(&text)?;
-        Ok(OrderToken::String(response.uuid))
+        Ok(OrderToken::Upbit {
+            uuid: response.uuid,
+        })
     }
 
     async fn ask_market(
@@ -372,7 +716,7 @@ impl Exchange for Upbit {
     ) -> Result<OrderToken, Self::Error> {
         tracing::info!("Upbit::ask_market({:?}, {})", pair, base_qty);
 
-        let pair = CurrencyPairDelimiterStringifier::<'-'>::stringify(pair.1, pair.0).unwrap();
+        let pair = market_code(pair.into());
         let message = json!({
             "side": "ask",
             "market": pair,
@@ -386,13 +730,11 @@ impl Exchange for Upbit {
         }
 
         let query_string = serde_qs::to_string(&message).unwrap();
+        self.rate_limiter.acquire(1).await;
         let response = self
             .http_client
-            .post("https://api.upbit.com/v1/orders")
-            .header(
-                "Authorization",
-                gen_jwt_token(access_key()?, secret_key()?, &query_string),
-            )
+            .post(self.urls.rest_url("/v1/orders"))
+            .signed(&signer()?.sign("", &query_string, self.nonce().await))
             .body(serde_json::to_string(&message).unwrap())
             .send()
             .await?;
@@ -407,26 +749,42 @@ impl Exchange for Upbit {
 
         async_helpers::sleep(Duration::from_millis(250)).await;
         let response: Response = serde_json::from_str(&text)?;
-        Ok(OrderToken::String(response.uuid))
+        Ok(OrderToken::Upbit {
+            uuid: response.uuid,
+        })
+    }
+
+    async fn stop_limit(
+        &self,
+        _pair: (Currency, Currency),
+        _stop_price: Decimal,
+        _limit_price: Decimal,
+        _amount: Decimal,
+        _side: Side,
+        _market: Option<Market>,
+    ) -> Result<OrderToken, Self::Error> {
+        // Upbit has no native stop orders
+        Err(UpbitError::Unsupported("stop order"))
     }
 
     async fn view_order(&self, order_token: &OrderToken) -> Result<Order, Self::Error> {
-        unwrap_let!(OrderToken::String(order_token) = order_token);
+        let OrderToken::Upbit { uuid } = order_token else {
+            return Err(UpbitError::InvalidOrderToken);
+        };
         let payload = json!({
-            "uuid": order_token,
+            "uuid": uuid,
         });
 
         let query_string = serde_qs::to_string(&payload).unwrap();
+        self.rate_limiter.acquire(1).await;
         let response = self
             .http_client
             .get(&format!(
-                "https://api.upbit.com/v1/order?{}",
+                "{}/v1/order?{}",
+                self.urls.rest,
                 serde_qs::to_string(&payload).unwrap()
             ))
-            .header(
-                "Authorization",
-                gen_jwt_token(access_key()?, secret_key()?, &query_string),
-            )
+            .signed(&signer()?.sign("", &query_string, self.nonce().await))
             .send()
             .await?;
 
@@ -436,10 +794,13 @@ impl Exchange for Upbit {
             pub state: String,
             pub trades: Vec<Trade>,
             pub executed_volume: Decimal,
+            // Market bid orders have no volume
+            pub remaining_volume: Option<Decimal>,
         }
 
         #[derive(Serialize, Deserialize, Debug)]
         struct Trade {
+            pub volume: Decimal,
             pub funds: Decimal,
         }
 
@@ -463,20 +824,42 @@ impl Exchange for Upbit {
             response.trades.iter().map(|t| t.funds).sum()
         };
 
+        let funds: Decimal = response.trades.iter().map(|t| t.funds).sum();
+        let volume: Decimal = response.trades.iter().map(|t| t.volume).sum();
+
         Ok(Order {
             state: order_state,
             executed_volume: qty,
+            remaining: response.remaining_volume.unwrap_or_default(),
+            avg_price: (volume > Decimal::ZERO).then(|| funds / volume),
         })
     }
 
+    /// Resolved as soon as the private websocket reports the order closed,
+    /// the order is still viewed now and then in case the update is missed.
     async fn wait_order(&self, order_token: &OrderToken) -> Result<Decimal, Self::Error> {
+        // Subscribed before the first view, so a close right after it is not missed
+        let stream = self
+            .user_stream()
+            .map(|stream| (stream.status.clone(), stream.events.subscribe()));
         loop {
             let order = self.view_order(order_token).await?;
             if order.state == OrderState::Closed {
                 return Ok(order.executed_volume);
             }
 
-            async_helpers::sleep(Duration::from_millis(250)).await;
+            match &stream {
+                Some((status, updates))
+                    if matches!(status.get(), ConnectionStatus::Connected { .. }) =>
+                {
+                    if let Some(order) =
+                        streamed_close(updates, order_token, STREAMED_POLL_INTERVAL).await
+                    {
+                        return Ok(order.executed_volume);
+                    }
+                }
+                _ => async_helpers::sleep(POLL_INTERVAL).await,
+            }
         }
     }
 
@@ -487,11 +870,8 @@ impl Exchange for Upbit {
         address1: &str,
         address2: Option<&str>,
         network: Option<&str>,
-    ) -> Result<(), Self::Error> {
-        let mut amount_rounded = amount.round_dp(6);
-        if amount_rounded > amount {
-            amount_rounded -= dec!(0.000001);
-        }
+    ) -> Result<String, Self::Error> {
+        let amount_rounded = round_down_dp(amount, 6);
 
         tracing::info!(
             "Upbit::withdraw({:?}, {}, {}, {:?}, {:?})",
@@ -519,17 +899,16 @@ impl Exchange for Upbit {
 
         println!("{:?}", message);
 
+        self.rate_limiter.acquire(1).await;
+
         let response = self
             .http_client
-            .post("https://api.upbit.com/v1/withdraws/coin")
-            .header(
-                "Authorization",
-                gen_jwt_token(
-                    access_key()?,
-                    secret_key()?,
-                    &serde_qs::to_string(&message).unwrap(),
-                ),
-            )
+            .post(self.urls.rest_url("/v1/withdraws/coin"))
+            .signed(&signer()?.sign(
+                "",
+                &serde_qs::to_string(&message).unwrap(),
+                self.nonce().await,
+            ))
             .body(serde_json::to_string(&message).unwrap())
             .send()
             .await;
@@ -542,29 +921,84 @@ impl Exchange for Upbit {
         let response = response.text().await.unwrap();
         tracing::info!("Upbit::withdraw() response: {}", response);
 
-        status
-            .is_success()
-            .then(|| ())
-            .ok_or(UpbitError::WithdrawFailed)
+        if !status.is_success() {
+            return Err(UpbitError::WithdrawFailed);
+        }
+
+        #[derive(Deserialize)]
+        struct Response {
+            uuid: String,
+        }
+
+        let response: Response = serde_json::from_str(&response)?;
+        Ok(response.uuid)
+    }
+
+    async fn withdraw_status(
+        &self,
+        currency: Currency,
+        id: &str,
+    ) -> Result<WithdrawState, Self::Error> {
+        tracing::debug!("Upbit::withdraw_status({:?}, {})", currency, id);
+
+        let payload = json!({
+            "uuid": id,
+        });
+
+        let query_string = serde_qs::to_string(&payload).unwrap();
+        let query_string = query_string.as_str();
+        self.rate_limiter.acquire(1).await;
+        let response = http::send_signed_with_retry(
+            move || async move {
+                Ok::<_, UpbitError>(
+                    self.http_client
+                        .get(&format!("{}/v1/withdraw?{}", self.urls.rest, query_string))
+                        .signed(&signer()?.sign("", query_string, self.nonce().await)),
+                )
+            },
+            &Config::get().http_retry,
+        )
+        .await?;
+
+        #[derive(Deserialize)]
+        struct Response {
+            state: String,
+        }
+
+        let status = response.status();
+        let response = response.text().await?;
+        tracing::debug!("Upbit::withdraw_status() response: {}", response);
+        if !status.is_success() {
+            return Err(UpbitError::WithdrawStatusFailed);
+        }
+
+        let response: Response = serde_json::from_str(&response)?;
+        Ok(match response.state.as_str() {
+            "PROCESSING" => WithdrawState::Processing,
+            "DONE" => WithdrawState::Done,
+            "FAILED" | "CANCELED" | "CANCELLED" | "REJECTED" => WithdrawState::Failed,
+            _ => WithdrawState::Pending,
+        })
     }
 
     async fn cancel_order(&self, order_token: &OrderToken) -> Result<Decimal, Self::Error> {
-        unwrap_let!(OrderToken::String(order_token) = order_token);
+        let OrderToken::Upbit { uuid } = order_token else {
+            return Err(UpbitError::InvalidOrderToken);
+        };
         let payload = json!({
-            "uuid": order_token,
+            "uuid": uuid,
         });
 
         let query_string = serde_qs::to_string(&payload).unwrap();
+        self.rate_limiter.acquire(1).await;
         let response = self
             .http_client
             .delete(&format!(
-                "https://api.upbit.com/v1/order?{}",
+                "{}/v1/order?{}",
+                self.urls.rest,
                 serde_qs::to_string(&payload).unwrap()
             ))
-            .header(
-                "Authorization",
-                gen_jwt_token(access_key()?, secret_key()?, &query_string),
-            )
+            .signed(&signer()?.sign("", &query_string, self.nonce().await))
             .send()
             .await?;
 
@@ -584,6 +1018,60 @@ impl Exchange for Upbit {
         Ok(response.executed_volume)
     }
 
+    async fn withdraw_fee(
+        &self,
+        currency: Currency,
+        network: Option<&str>,
+    ) -> Result<Decimal, Self::Error> {
+        tracing::debug!("Upbit::withdraw_fee({:?}, {:?})", currency, network);
+
+        let mut payload = json!({
+            "currency": currency.to_string(),
+        });
+
+        if let Some(network) = network {
+            payload["net_type"] = json!(network);
+        }
+
+        let query_string = serde_qs::to_string(&payload).unwrap();
+        let query_string = query_string.as_str();
+        self.rate_limiter.acquire(1).await;
+        let response = http::send_signed_with_retry(
+            move || async move {
+                Ok::<_, UpbitError>(
+                    self.http_client
+                        .get(&format!(
+                            "{}/v1/withdraws/chance?{}",
+                            self.urls.rest, query_string
+                        ))
+                        .signed(&signer()?.sign("", query_string, self.nonce().await)),
+                )
+            },
+            &Config::get().http_retry,
+        )
+        .await?;
+
+        #[derive(Deserialize)]
+        struct Response {
+            currency: CurrencyInfo,
+        }
+
+        #[derive(Deserialize)]
+        struct CurrencyInfo {
+            withdraw_fee: Decimal,
+        }
+
+        let status = response.status();
+        let response = response.text().await?;
+        tracing::debug!("Upbit::withdraw_fee() response: {}", response);
+        if !status.is_success() {
+            return Err(UpbitError::WithdrawFeeNotFound);
+        }
+
+        let response: Response = serde_json::from_str(&response)?;
+        Ok(response.currency.withdraw_fee)
+    }
+
     async fn set_leverage(
         &self,
         _pair: Option<(Currency, Currency)>,
@@ -591,6 +1079,28 @@ impl Exchange for Upbit {
     ) -> Result<(), Self::Error> {
         unimplemented!()
     }
+
+    async fn server_time(&self) -> Result<i64, Self::Error> {
+        // Upbit has no time api, so the time is read from the `Date` header of a public api
+        self.rate_limiter.acquire(1).await;
+        let sent = server_time::local_millis();
+        let response = self
+            .http_client
+            .get(self.urls.rest_url("/v1/ticker?markets=KRW-BTC"))
+            .send()
+            .await?;
+        let received = server_time::local_millis();
+
+        let server = response
+            .headers()
+            .get(http::header::DATE)
+            .and_then(|date| date.to_str().ok())
+            .and_then(server_time::parse_http_date)
+            .ok_or(UpbitError::ServerTimeFailed)?;
+
+        self.clock.update(server, sent, received);
+        Ok(server)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -602,6 +1112,7 @@ pub enum UpbitItem {
         trade_volume: Decimal,
         ask_bid: String,
         timestamp: i64,
+        sequential_id: u64,
     },
     Orderbook {
         code: String,
@@ -626,56 +1137,44 @@ struct RealtimeDataBroadcaster {
 }
 
 impl RealtimeDataBroadcaster {
-    fn new() -> Self {
+    fn new(ws: &str) -> Self {
         Self {
             subscribed: Arc::new(Mutex::new(HashSet::new())),
             broadcaster: Broadcaster::new(),
-            ws: Websocket::new("wss://api.upbit.com/websocket/v1"),
+            ws: Websocket::new(ws),
         }
     }
 
+    /// The tasks only hold handles of the websocket, so it stops once the broadcaster is dropped.
     fn spawn_and_broadcast(&self) {
-        let broadcaster = self.clone();
+        let receiver = self.ws.receiver();
+        let broadcaster = self.broadcaster.clone();
         async_helpers::spawn(async move {
-            loop {
-                broadcaster.recv_and_broadcast().await;
+            while let Some(item) = receiver.recv().await {
+                Self::broadcast_item(&broadcaster, &item);
+            }
+        });
+
+        let subscribed = self.subscribed.clone();
+        self.ws.on_connected(move |ws| {
+            let subscribed = subscribed.lock().unwrap();
+            if !subscribed.is_empty() {
+                ws.send(&subscription_request(&subscribed));
             }
         });
     }
 
     fn subscribe(&self, pair: (Currency, Currency)) -> Subscription<RealtimeData> {
         let mut subscribed = self.subscribed.lock().unwrap();
-        if !subscribed.insert(pair) {
-            return self.broadcaster.subscribe();
+        if subscribed.insert(pair) {
+            self.ws.send(&subscription_request(&subscribed));
         }
-
-        let subscribed = subscribed
-            .iter()
-            .cloned()
-            .map(|(c1, c2)| CurrencyPairDelimiterStringifier::<'-'>::stringify(c2, c1))
-            .collect::<Vec<_>>();
-
-        let message = json!([
-            {
-                "ticket": "rsader"
-            },
-            {
-                "type": "trade",
-                "codes": subscribed,
-            },
-            {
-                "type": "orderbook",
-                "codes": subscribed,
-            }
-        ]);
-
-        self.ws.send(&message.to_string());
         self.broadcaster.subscribe()
     }
 
-    async fn recv_and_broadcast(&self) {
-        let item = self.ws.recv().await.unwrap();
-        let Ok(item) = serde_json::from_str::<UpbitItem>(&item) else {
+    /// Parses a message of the public websocket and broadcasts its data.
+    fn broadcast_item(broadcaster: &Broadcaster<RealtimeData>, item: &str) {
+        let Ok(item) = serde_json::from_str::<UpbitItem>(item) else {
             tracing::error!("Upbit: failed to parse item: {}", item);
             return;
         };
@@ -694,48 +1193,324 @@ impl RealtimeDataBroadcaster {
                 trade_volume,
                 ask_bid,
                 timestamp,
+                sequential_id,
             } => RealtimeData::Trade(Trade {
                 pair: into_pair(&code),
                 timestamp,
                 price: trade_price,
                 amount: trade_volume,
                 is_bid: ask_bid == "BID",
+                id: Some(sequential_id.to_string()),
             }),
             UpbitItem::Orderbook {
                 code,
                 orderbook_units,
-            } => RealtimeData::Orderbook(Orderbook {
-                pair: into_pair(&code),
-                bids: orderbook_units
-                    .iter()
-                    .map(|unit| Unit {
-                        price: unit.bid_price,
-                        amount: unit.bid_size,
-                    })
-                    .collect(),
-                asks: orderbook_units
-                    .iter()
-                    .map(|unit| Unit {
-                        price: unit.ask_price,
-                        amount: unit.ask_size,
-                    })
-                    .collect(),
-            }),
+            } => RealtimeData::Orderbook(
+                Orderbook {
+                    pair: into_pair(&code),
+                    bids: orderbook_units
+                        .iter()
+                        .map(|unit| Unit {
+                            price: unit.bid_price,
+                            amount: unit.bid_size,
+                        })
+                        .collect(),
+                    asks: orderbook_units
+                        .iter()
+                        .map(|unit| Unit {
+                            price: unit.ask_price,
+                            amount: unit.ask_size,
+                        })
+                        .collect(),
+                }
+                .normalize(),
+            ),
         };
 
-        self.broadcaster.broadcast(realtime_data);
+        broadcaster.broadcast(realtime_data);
     }
 }
 
+/// Replaces the pairs streamed by the public websocket with `subscribed`.
+fn subscription_request(subscribed: &HashSet<(Currency, Currency)>) -> String {
+    let subscribed = subscribed
+        .iter()
+        .map(|pair| market_code((*pair).into()))
+        .collect::<Vec<_>>();
+
+    json!([
+        {
+            "ticket": "rsader"
+        },
+        {
+            "type": "trade",
+            "codes": subscribed,
+        },
+        {
+            "type": "orderbook",
+            "codes": subscribed,
+        }
+    ])
+    .to_string()
+}
+
+/// The order once the private websocket reports it closed, None if it does not within `timeout`.
+async fn streamed_close(
+    updates: &Subscription<UserEvent>,
+    order_token: &OrderToken,
+    timeout: Duration,
+) -> Option<Order> {
+    let closed = async {
+        loop {
+            if let UserEvent::Order {
+                order_token: token,
+                order,
+            } = updates.recv().await
+            {
+                if token == *order_token && order.state == OrderState::Closed {
+                    return order;
+                }
+            }
+        }
+    };
+    match future::select(Box::pin(closed), Box::pin(async_helpers::sleep(timeout))).await {
+        Either::Left((order, _)) => Some(order),
+        Either::Right(_) => None,
+    }
+}
+
+/// Forwards the order and asset updates of the private websocket until the process ends.
+/// The websocket reconnects by itself, the channels are subscribed again on every connect.
+async fn run_user_stream(
+    websocket: Websocket,
+    states: Subscription<ConnState>,
+    events: Broadcaster<UserEvent>,
+) {
+    let request = json!([
+        {
+            "ticket": "rsader-private"
+        },
+        {
+            "type": "myOrder"
+        },
+        {
+            "type": "myAsset"
+        }
+    ])
+    .to_string();
+
+    loop {
+        let message =
+            match future::select(Box::pin(websocket.recv()), Box::pin(states.recv())).await {
+                Either::Left((Some(message), _)) => message,
+                Either::Left((None, _)) => return,
+                Either::Right((ConnState::Connected, _)) => {
+                    websocket.send(&request);
+                    events.broadcast(UserEvent::Streaming(true));
+                    continue;
+                }
+                Either::Right((ConnState::Disconnected(reason), _)) => {
+                    tracing::warn!(
+                        "Upbit: private websocket disconnected, orders are polled: {}",
+                        reason
+                    );
+                    events.broadcast(UserEvent::Streaming(false));
+                    continue;
+                }
+                Either::Right(_) => continue,
+            };
+
+        match parse_user_message(&message) {
+            Ok(Some(event)) => events.broadcast(event),
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Upbit: unexpected private message {}: {}", message, e),
+        }
+    }
+}
+
+/// An order or asset update of the private websocket, None for other messages.
+fn parse_user_message(text: &str) -> Result<Option<UserEvent>, serde_json::Error> {
+    #[derive(Deserialize)]
+    #[serde(tag = "type")]
+    enum Message {
+        #[serde(rename = "myOrder")]
+        MyOrder {
+            uuid: String,
+            ask_bid: String,
+            state: String,
+            executed_volume: Decimal,
+            executed_funds: Decimal,
+            // Market bid orders have no volume
+            remaining_volume: Option<Decimal>,
+            avg_price: Option<Decimal>,
+        },
+        #[serde(rename = "myAsset")]
+        MyAsset { assets: Vec<Asset> },
+        #[serde(other)]
+        Other,
+    }
+
+    #[derive(Deserialize)]
+    struct Asset {
+        currency: String,
+        balance: Decimal,
+        locked: Decimal,
+    }
+
+    Ok(match serde_json::from_str(text)? {
+        Message::MyOrder {
+            uuid,
+            ask_bid,
+            state,
+            executed_volume,
+            executed_funds,
+            remaining_volume,
+            avg_price,
+        } => {
+            let state = match state.as_str() {
+                "done" | "cancel" => OrderState::Closed,
+                _ => OrderState::Wait,
+            };
+            // Same as `view_order`, asks report the funds received
+            let executed_volume = if ask_bid == "BID" {
+                executed_volume
+            } else {
+                executed_funds
+            };
+            Some(UserEvent::Order {
+                order_token: OrderToken::Upbit { uuid },
+                order: Order {
+                    state,
+                    executed_volume,
+                    remaining: remaining_volume.unwrap_or_default(),
+                    avg_price: avg_price.filter(|price| *price > Decimal::ZERO),
+                },
+            })
+        }
+        Message::MyAsset { assets } => Some(UserEvent::Balances(
+            assets
+                .into_iter()
+                .filter_map(|asset| {
+                    let currency = asset.currency.parse().ok()?;
+                    let balance = Balance {
+                        available: asset.balance,
+                        locked: asset.locked,
+                    };
+                    Some((currency, balance))
+                })
+                .collect(),
+        )),
+        Message::Other => None,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use crate::dec;
 
     use crate::{
-        currency::Currency,
-        exchange::{Exchange, Upbit},
+        currency::{Currency, CurrencyPair},
+        exchange::{
+            user_stream::UserEvent, Balance, Exchange, Market, OrderState, OrderToken, Upbit,
+        },
+        utils::Decimal,
     };
 
+    #[test]
+    fn market_code_puts_quote_first() {
+        let pair = CurrencyPair::new(Currency::BTC, Currency::KRW);
+        assert_eq!(super::market_code(pair), "KRW-BTC");
+
+        // Market codes are only stringified in `market_code`
+        let source = include_str!("upbit.rs");
+        let calls = concat!("Stringifier::<'-'>::", "stringify(");
+        assert_eq!(source.matches(calls).count(), 1);
+    }
+
+    #[test]
+    fn parse_markets() {
+        let text = r#"[
+            {"market": "KRW-BTC", "korean_name": "비트코인", "english_name": "Bitcoin"},
+            {"market": "BTC-ETH", "korean_name": "이더리움", "english_name": "Ethereum"},
+            {"market": "KRW-NEWCOIN", "korean_name": "신규", "english_name": "New Coin"}
+        ]"#;
+
+        let markets = super::parse_markets(text).unwrap();
+        assert_eq!(markets.len(), 3);
+        assert_eq!(markets[0], (Currency::BTC, Currency::KRW, Market::Spot));
+        assert_eq!(markets[1], (Currency::ETH, Currency::BTC, Market::Spot));
+        assert_eq!(markets[2].0.to_string(), "NEWCOIN");
+    }
+
+    #[test]
+    fn parse_my_order() {
+        // In the format of the Upbit websocket documentation, a limit bid partially filled
+        let text = r#"{
+            "type": "myOrder", "code": "KRW-BTC", "uuid": "ac2dc2a3-fce9-40a2-a4f6-5987c25c438f",
+            "ask_bid": "BID", "order_type": "limit", "state": "trade",
+            "trade_uuid": "68315169-fba4-4175-ade3-aff14a616657",
+            "price": 95000000, "avg_price": 95000000, "volume": 0.02,
+            "remaining_volume": 0.015, "executed_volume": 0.005, "trades_count": 1,
+            "reserved_fee": 950, "remaining_fee": 712.5, "paid_fee": 237.5, "locked": 1425712.5,
+            "executed_funds": 475000, "trade_fee": 237.5, "is_maker": true, "identifier": null,
+            "trade_timestamp": 1710751590421, "order_timestamp": 1710751590000,
+            "timestamp": 1710751597500, "stream_type": "REALTIME"
+        }"#;
+
+        let Some(UserEvent::Order { order_token, order }) =
+            super::parse_user_message(text).unwrap()
+        else {
+            panic!("not an order update");
+        };
+        assert_eq!(
+            order_token,
+            OrderToken::Upbit {
+                uuid: "ac2dc2a3-fce9-40a2-a4f6-5987c25c438f".to_string()
+            }
+        );
+        assert_eq!(order.state, OrderState::Wait);
+        assert_eq!(order.executed_volume, dec!(0.005));
+        assert_eq!(order.remaining, dec!(0.015));
+        assert_eq!(order.avg_price, Some(dec!(95000000)));
+
+        // Closed asks report the funds, and market bids have no remaining volume
+        let text = r#"{
+            "type": "myOrder", "uuid": "order-2", "ask_bid": "ASK", "state": "done",
+            "avg_price": null, "remaining_volume": null,
+            "executed_volume": 0.5, "executed_funds": 25000000
+        }"#;
+        let Some(UserEvent::Order { order, .. }) = super::parse_user_message(text).unwrap() else {
+            panic!("not an order update");
+        };
+        assert_eq!(order.state, OrderState::Closed);
+        assert_eq!(order.executed_volume, dec!(25000000));
+        assert_eq!(order.remaining, Decimal::ZERO);
+        assert_eq!(order.avg_price, None);
+    }
+
+    #[test]
+    fn parse_my_asset() {
+        let text = r#"{
+            "type": "myAsset", "asset_uuid": "e635f223-1609-4969-8fb6-4376937baad6",
+            "assets": [{"currency": "KRW", "balance": 1386929.37231066, "locked": 10329.670127489}],
+            "asset_timestamp": 1710146517259, "timestamp": 1710146517267, "stream_type": "REALTIME"
+        }"#;
+        assert_eq!(
+            super::parse_user_message(text).unwrap(),
+            Some(UserEvent::Balances(vec![(
+                Currency::KRW,
+                Balance {
+                    available: dec!(1386929.37231066),
+                    locked: dec!(10329.670127489),
+                }
+            )]))
+        );
+        assert_eq!(
+            super::parse_user_message(r#"{"type": "myTrade"}"#).unwrap(),
+            None
+        );
+    }
+
     #[ignore]
     #[tokio::test]
     async fn create_and_cancel_order() {
@@ -749,11 +1524,15 @@ mod tests {
 
         println!("lowest bid price: {}", lowest_bid_price);
 
+        // Some margin above the minimum, the amount is rounded by the exchange
+        let min_notional = exchange
+            .min_notional((Currency::XRP, Currency::KRW), None)
+            .unwrap();
         let order_token = exchange
             .bid_limit(
                 (Currency::XRP, Currency::KRW),
                 lowest_bid_price,
-                dec!(5500) / lowest_bid_price, // the minium order amount is 5000 krw
+                min_notional * dec!(1.1) / lowest_bid_price,
                 None,
             )
             .await
@@ -765,6 +1544,69 @@ mod tests {
         assert_eq!(executed_volume, dec!(0));
     }
 
+    #[tokio::test]
+    async fn signed_balance_request() {
+        use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+        use sha2::{Digest, Sha512};
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        use crate::utils::http::BaseUrls;
+
+        // The keys of the config file if there is one, the test keys otherwise
+        let signer = super::signer().unwrap();
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v1/accounts"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"[
+                    {"currency": "KRW", "balance": "1000.5", "locked": "10", "avg_buy_price": "0"},
+                    {"currency": "BTC", "balance": "0", "locked": "0", "avg_buy_price": "0"}
+                ]"#,
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        // The websocket is never connected, nothing listens on the discard port
+        let exchange = Upbit::with_urls(BaseUrls::new(&server.uri(), "", "ws://127.0.0.1:9"));
+        let balance = exchange.balance(Currency::KRW, None).await.unwrap();
+        assert_eq!(
+            balance,
+            Balance {
+                available: dec!(1000.5),
+                locked: dec!(10),
+            }
+        );
+
+        let requests = server.received_requests().await.unwrap();
+        let accounts = requests
+            .iter()
+            .find(|request| request.url.path() == "/v1/accounts")
+            .unwrap();
+        assert_eq!(accounts.url.query(), None);
+
+        let token = accounts
+            .headers
+            .get("Authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap();
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.required_spec_claims.clear();
+        validation.validate_exp = false;
+        let claims = decode::<serde_json::Value>(
+            token,
+            &DecodingKey::from_secret(signer.secret_key.as_bytes()),
+            &validation,
+        )
+        .unwrap()
+        .claims;
+        assert_eq!(claims["access_key"], signer.access_key.as_str());
+        assert_eq!(claims["query_hash"], hex::encode(Sha512::digest(b"")));
+        assert!(claims["nonce"].is_i64());
+    }
+
     #[cfg(test)]
     mod test {
         use crate::{
@@ -779,5 +1621,38 @@ mod tests {
             let balance = exchange.balance(Currency::KRW, None).await.unwrap();
             println!("{:?}", balance);
         }
+
+        #[ignore]
+        #[tokio::test]
+        async fn balances() {
+            let exchange = Upbit::new();
+            let balances = exchange.balances(None).await.unwrap();
+            println!("{:?}", balances);
+        }
+
+        #[ignore]
+        #[tokio::test]
+        async fn ticker() {
+            let exchange = Upbit::new();
+            let price = exchange
+                .ticker((Currency::BTC, Currency::KRW), None)
+                .await
+                .unwrap();
+            println!("{}", price);
+        }
+
+        #[ignore]
+        #[tokio::test]
+        async fn candlesticks() {
+            let exchange = Upbit::new();
+            let candles = exchange
+                .candlesticks((Currency::BTC, Currency::KRW), None)
+                .await
+                .unwrap();
+            assert!(candles
+                .tickers
+                .windows(2)
+                .all(|w| w[0].timestamp < w[1].timestamp));
+        }
     }
 }
@@ -0,0 +1,54 @@
+//! Private streams of the accounts: order updates and balance changes pushed by the exchanges,
+//! so orders and balances don't have to be polled to notice them.
+
+use std::sync::Arc;
+
+use crate::currency::Currency;
+use crate::utils::async_helpers;
+use crate::utils::maybe_trait::MaybeSend;
+
+use super::cache::Cached;
+use super::order_watch::OrderWatcher;
+use super::{Balance, Exchange, Exchanges, Order, OrderToken};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UserEvent {
+    /// The stream connected (true) or can not be started (false),
+    /// orders are polled as usual while it is not streaming.
+    Streaming(bool),
+    Order {
+        order_token: OrderToken,
+        order: Order,
+    },
+    /// New balances of the currencies that changed.
+    Balances(Vec<(Currency, Balance)>),
+}
+
+/// Feeds the private stream of the exchange, if it has one, to the order watcher and its balance cache.
+pub fn forward<E>(exchange: Arc<Cached<E>>)
+where
+    E: Exchange + MaybeSend + 'static,
+{
+    let Some(events) = exchange.user_events() else {
+        return;
+    };
+
+    async_helpers::spawn(async move {
+        let watcher = OrderWatcher::instance();
+        loop {
+            match events.recv().await {
+                UserEvent::Streaming(streaming) => watcher.set_streaming(E::NAME, streaming),
+                UserEvent::Order { order_token, order } => watcher.update(&order_token, &order),
+                UserEvent::Balances(_) => exchange.invalidate_balances(),
+            }
+        }
+    });
+}
+
+/// Starts forwarding the private streams of every exchange, called once on startup.
+pub fn forward_all(exchanges: &Exchanges) {
+    forward(exchanges.upbit.clone());
+    forward(exchanges.binance.clone());
+    forward(exchanges.bithumb.clone());
+    forward(exchanges.okx.clone());
+}
@@ -1,3 +1,4 @@
+mod alert;
 mod config;
 mod currency;
 mod exchange;
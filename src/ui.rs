@@ -1,6 +1,10 @@
 mod main_window;
 pub use main_window::*;
+pub mod keybinds;
+pub mod layout;
+pub mod palette;
 pub mod style;
 pub mod sub_window;
+pub mod theme;
 pub mod utils;
 pub mod widgets;
@@ -0,0 +1,241 @@
+//! Keyboard shortcuts of the main window, configured in the `[keybinds]` section
+//! as text like `Ctrl+Space` or `Ctrl+Shift+W`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use dioxus::prelude::{Code, Modifiers};
+use serde::{Deserialize, Serialize};
+
+/// Modifiers in the order they are written.
+const MODIFIERS: [(Modifiers, &str); 4] = [
+    (Modifiers::CONTROL, "Ctrl"),
+    (Modifiers::ALT, "Alt"),
+    (Modifiers::SHIFT, "Shift"),
+    (Modifiers::META, "Meta"),
+];
+
+/// A key, by its physical position, pressed with exactly these modifiers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Keybind {
+    pub modifiers: Modifiers,
+    pub code: Code,
+}
+
+impl Keybind {
+    pub fn new(modifiers: Modifiers, code: Code) -> Self {
+        Self { modifiers, code }
+    }
+
+    /// The keybind of a key press, None for a modifier pressed alone.
+    pub fn pressed(modifiers: Modifiers, code: Code) -> Option<Self> {
+        let modifier_codes = [
+            Code::ControlLeft,
+            Code::ControlRight,
+            Code::AltLeft,
+            Code::AltRight,
+            Code::ShiftLeft,
+            Code::ShiftRight,
+            Code::MetaLeft,
+            Code::MetaRight,
+        ];
+        (!modifier_codes.contains(&code)).then_some(Self::new(modifiers, code))
+    }
+}
+
+impl fmt::Display for Keybind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in MODIFIERS {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{}+", name)?;
+            }
+        }
+
+        // `KeyW` and `Digit1` are shown as `W` and `1`
+        let code = self.code.to_string();
+        let key = code
+            .strip_prefix("Key")
+            .or_else(|| code.strip_prefix("Digit"))
+            .unwrap_or(&code);
+        write!(f, "{}", key)
+    }
+}
+
+impl FromStr for Keybind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('+').map(str::trim).collect::<Vec<_>>();
+        let key = parts
+            .pop()
+            .filter(|key| !key.is_empty())
+            .ok_or("Missing key")?;
+
+        let mut modifiers = Modifiers::empty();
+        for part in parts {
+            let (modifier, _) = MODIFIERS
+                .iter()
+                .find(|(_, name)| name.eq_ignore_ascii_case(part))
+                .ok_or_else(|| format!("Unknown modifier {}", part))?;
+            modifiers |= *modifier;
+        }
+
+        let code = [
+            key.to_string(),
+            format!("Key{}", key.to_uppercase()),
+            format!("Digit{}", key),
+        ]
+        .iter()
+        .find_map(|code| Code::from_str(code).ok())
+        .filter(|code| *code != Code::Unidentified)
+        .ok_or_else(|| format!("Unknown key {}", key))?;
+
+        Ok(Self::new(modifiers, code))
+    }
+}
+
+impl TryFrom<String> for Keybind {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Keybind> for String {
+    fn from(keybind: Keybind) -> Self {
+        keybind.to_string()
+    }
+}
+
+/// Digits focusing the windows in the order they appear, pressed with [`WINDOW_DIGIT_MODIFIERS`].
+const WINDOW_DIGITS: [Code; 9] = [
+    Code::Digit1,
+    Code::Digit2,
+    Code::Digit3,
+    Code::Digit4,
+    Code::Digit5,
+    Code::Digit6,
+    Code::Digit7,
+    Code::Digit8,
+    Code::Digit9,
+];
+
+const WINDOW_DIGIT_MODIFIERS: Modifiers = Modifiers::CONTROL;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    OpenCommandPalette,
+    CloseWindow,
+    FocusNext,
+    FocusPrevious,
+    /// Focuses the window at this index, in the order the windows appear.
+    FocusWindow(usize),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct KeybindsConfig {
+    pub open_command_palette: Keybind,
+    /// Closes the focused window, Alt+Shift+W by default in the browser.
+    pub close_window: Keybind,
+    /// Moves the focus to the next window.
+    pub focus_next: Keybind,
+    /// Moves the focus to the previous window.
+    pub focus_previous: Keybind,
+}
+
+impl Default for KeybindsConfig {
+    fn default() -> Self {
+        Self {
+            open_command_palette: Keybind::new(Modifiers::CONTROL, Code::Space),
+            close_window: if cfg!(target_arch = "wasm32") {
+                // The browser closes itself on Ctrl+Shift+W before the page sees the keys
+                Keybind::new(Modifiers::ALT | Modifiers::SHIFT, Code::KeyW)
+            } else {
+                Keybind::new(Modifiers::CONTROL | Modifiers::SHIFT, Code::KeyW)
+            },
+            focus_next: Keybind::new(Modifiers::CONTROL, Code::Backquote),
+            focus_previous: Keybind::new(Modifiers::CONTROL | Modifiers::SHIFT, Code::Backquote),
+        }
+    }
+}
+
+impl KeybindsConfig {
+    /// The action bound to the key press, if any.
+    /// Ctrl+1 to Ctrl+9 focus the first nine windows, unless bound to an action.
+    pub fn action(&self, modifiers: Modifiers, code: Code) -> Option<Action> {
+        let pressed = Keybind::new(modifiers, code);
+        [
+            (self.open_command_palette, Action::OpenCommandPalette),
+            (self.close_window, Action::CloseWindow),
+            (self.focus_next, Action::FocusNext),
+            (self.focus_previous, Action::FocusPrevious),
+        ]
+        .into_iter()
+        .find(|(keybind, _)| *keybind == pressed)
+        .map(|(_, action)| action)
+        .or_else(|| {
+            let idx = WINDOW_DIGITS.iter().position(|digit| *digit == code)?;
+            (modifiers == WINDOW_DIGIT_MODIFIERS).then_some(Action::FocusWindow(idx))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use dioxus::prelude::{Code, Modifiers};
+
+    use super::{Action, Keybind, KeybindsConfig};
+
+    #[test]
+    fn keybind_text_round_trip() {
+        for text in [
+            "Ctrl+Space",
+            "Ctrl+Shift+W",
+            "Alt+1",
+            "F2",
+            "Meta+Backquote",
+        ] {
+            let keybind = text.parse::<Keybind>().unwrap();
+            assert_eq!(keybind.to_string(), text);
+        }
+
+        assert_eq!(
+            "ctrl + w".parse::<Keybind>(),
+            Ok(Keybind::new(Modifiers::CONTROL, Code::KeyW))
+        );
+        assert!("Ctrl+".parse::<Keybind>().is_err());
+        assert!("Hyper+W".parse::<Keybind>().is_err());
+        assert!("Ctrl+Nothing".parse::<Keybind>().is_err());
+    }
+
+    #[test]
+    fn actions_from_the_config() {
+        let config: KeybindsConfig = toml::from_str(r#"focus_next = "Alt+Tab""#).unwrap();
+
+        assert_eq!(
+            config.action(Modifiers::CONTROL, Code::Space),
+            Some(Action::OpenCommandPalette)
+        );
+        assert_eq!(
+            config.action(Modifiers::ALT, Code::Tab),
+            Some(Action::FocusNext)
+        );
+        assert_eq!(
+            config.action(Modifiers::CONTROL | Modifiers::SHIFT, Code::Backquote),
+            Some(Action::FocusPrevious)
+        );
+        assert_eq!(
+            config.action(Modifiers::CONTROL, Code::Digit3),
+            Some(Action::FocusWindow(2))
+        );
+        assert_eq!(config.action(Modifiers::ALT, Code::Digit3), None);
+        assert_eq!(config.action(Modifiers::empty(), Code::Space), None);
+        assert_eq!(
+            Keybind::pressed(Modifiers::CONTROL, Code::ControlLeft),
+            None
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ui::widgets::WidgetDescriptor;
+use crate::utils::storage;
+
+/// Bump this when the layout format changes, older layouts are discarded.
+const LAYOUT_VERSION: u32 = 1;
+
+const LAYOUT_KEY: &str = "layout";
+
+/// Persisted sub window layout, the split tree with a descriptor for each widget.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Layout {
+    pub version: u32,
+    pub root: SplitLayout,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SplitLayout {
+    pub horizontal: bool,
+    /// Children with their ratio in the split.
+    pub children: Vec<(f64, SplitLayoutItem)>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum SplitLayoutItem {
+    Widget(WidgetDescriptor),
+    Split(SplitLayout),
+}
+
+impl Layout {
+    pub fn new(root: SplitLayout) -> Self {
+        Self {
+            version: LAYOUT_VERSION,
+            root,
+        }
+    }
+
+    /// Loads the saved layout.
+    /// Returns None if there is no saved layout or it can not be used.
+    pub fn load() -> Option<Self> {
+        Self::parse(&storage::read(LAYOUT_KEY)?)
+    }
+
+    pub fn save(&self) {
+        match serde_json::to_string(self) {
+            Ok(text) => storage::write(LAYOUT_KEY, &text),
+            Err(e) => tracing::warn!("Failed to serialize layout: {}", e),
+        }
+    }
+
+    fn parse(text: &str) -> Option<Self> {
+        let layout: Self = match serde_json::from_str(text) {
+            Ok(layout) => layout,
+            Err(e) => {
+                tracing::warn!("Discarding corrupted layout: {}", e);
+                return None;
+            }
+        };
+
+        if layout.version != LAYOUT_VERSION {
+            tracing::warn!(
+                "Discarding layout of version {}, expected {}",
+                layout.version,
+                LAYOUT_VERSION
+            );
+            return None;
+        }
+
+        Some(layout)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Layout, SplitLayout, SplitLayoutItem};
+    use crate::ui::widgets::WidgetDescriptor;
+
+    #[test]
+    fn layout_roundtrip() {
+        let layout = Layout::new(SplitLayout {
+            horizontal: false,
+            children: vec![(
+                1.0,
+                SplitLayoutItem::Widget(WidgetDescriptor {
+                    name: "Dummy".to_string(),
+                    params: serde_json::Value::Null,
+                }),
+            )],
+        });
+
+        let text = serde_json::to_string(&layout).unwrap();
+        assert_eq!(Layout::parse(&text), Some(layout));
+    }
+
+    #[test]
+    fn layout_fallback() {
+        assert_eq!(Layout::parse("{ not a layout"), None);
+        assert_eq!(
+            Layout::parse(r#"{"version":0,"root":{"horizontal":false,"children":[]}}"#),
+            None
+        );
+    }
+}
@@ -1,16 +1,35 @@
 
 use std::sync::Arc;
+use std::time::Duration;
 
 use dioxus::prelude::*;
 
-use crate::currency::Currency;
+use crate::alert::{Alert, Alerts, Direction};
+use crate::config::{secrets, Config};
+use crate::currency::{Currency, CurrencyPair};
 use crate::exchange::binance::Binance;
 use crate::exchange::bithumb::Bithumb;
+use crate::exchange::cache::Cached;
+use crate::exchange::okx::Okx;
+use crate::exchange::replay::{self, Replay};
 use crate::exchange::upbit::Upbit;
-use crate::exchange::{execute_if, Exchange};
+use crate::exchange::user_stream;
+use crate::exchange::{execute_if, Exchange, Exchanges};
+use crate::ui::keybinds::Action;
+use crate::ui::palette::{
+    listings, refresh_markets, suggestions, unlisted_pair, usage, History,
+};
 use crate::ui::style::*;
 use crate::ui::sub_window::{SubWindowEvent, SubWindowMgr, SubWindowMgrState};
-use crate::ui::widgets::{Dummy, OrderbookWidget};
+use crate::ui::theme::StyleTheme;
+use crate::ui::widgets::{
+    AlertsWidget, CandleChartWidget, ConsoleWidget, DepthWidget, Dummy, HealthStrip,
+    OrderToasts, OrderbookWidget, PortfolioWidget, ReportWidget, SecretsAction, SecretsWidget,
+    SettingsWidget, TradesWidget,
+};
+use crate::utils::async_helpers::{self, TaskClass};
+use crate::utils::{export, Decimal};
+use crate::vm::backtest;
 use crate::vm::exchange::install_exchange;
 use crate::{include_style, select_ex};
 
@@ -21,15 +40,51 @@ pub fn App() -> Element {
     initialize_keydown_events(keydown_events);
 
     // Exchanges
-    let upbit = use_hook(|| Arc::new(Upbit::new()));
-    let binance = use_hook(|| Arc::new(Binance::new()));
-    let bithumb = use_hook(|| Arc::new(Bithumb::new()));
+    let upbit = use_hook(|| Arc::new(Cached::new(Upbit::new())));
+    let binance = use_hook(|| Arc::new(Cached::new(Binance::new())));
+    let bithumb = use_hook(|| Arc::new(Cached::new(Bithumb::new())));
+    let okx = use_hook(|| Arc::new(Cached::new(Okx::new())));
+
+    // Sub windows restore their widgets from the saved layout with these
+    let exchanges = use_context_provider(|| Exchanges {
+        upbit: upbit.clone(),
+        binance: binance.clone(),
+        bithumb: bithumb.clone(),
+        okx: okx.clone(),
+    });
+
+    // Restored alerts keep watching in the background
+    use_hook(|| Alerts::instance().watch_all(&exchanges));
+
+    // Order updates and balance changes pushed by the exchanges
+    use_hook(|| user_stream::forward_all(&exchanges));
+
+    // Edits of the config file apply without a restart
+    #[cfg(not(target_arch = "wasm32"))]
+    use_hook(Config::watch);
+
+    // Finished backtests open their report
+    use_future(|| async {
+        let reports = backtest::finished();
+        loop {
+            let report = reports.recv().await;
+            SubWindowMgrState::open(ReportWidget::new(report).into());
+        }
+    });
+
+    // Trading stays disabled until the encrypted api keys are unlocked
+    use_hook(|| {
+        if secrets::is_locked() {
+            SubWindowMgrState::open(SecretsWidget::new(SecretsAction::Unlock).into());
+        }
+    });
 
     let ctx = MainWindowContext {
         keydown_events,
         upbit,
         binance,
         bithumb,
+        okx,
     };
 
     rsx! {
@@ -38,10 +93,17 @@ pub fn App() -> Element {
         StyleMainWindow {}
         StyleFont {}
         StyleColor {}
-        StyleButton { dark_mode: true }
+        StyleButton {}
+        StyleTheme {}
 
         div { class: "main-window", width: "100%", height: "100%",
-            MainWindow { ctx }
+            div { style: "display: flex; flex-direction: column; width: 100%; height: 100%;",
+                div { style: "display: flex; flex: 1; min-height: 0;",
+                    MainWindow { ctx }
+                }
+                HealthStrip {}
+            }
+            OrderToasts {}
         }
     }
 }
@@ -73,10 +135,55 @@ fn initialize_keydown_events(signal: Signal<Vec<(Key, Modifiers, Code)>>) {
 
     #[cfg(not(target_arch = "wasm32"))]
     mod desktop {
+        use std::str::FromStr;
+
         use dioxus::prelude::*;
 
+        /// Forwards the keydown events of the webview document through eval.
+        /// Cmd is reported as META and never as CONTROL, so Cmd+Space is left to Spotlight on macOS.
         pub fn init(signal: Signal<Vec<(Key, Modifiers, Code)>>) {
-            todo!()
+            use_hook(move || {
+                let mut listener = eval(
+                    r#"
+                    document.addEventListener("keydown", (e) => {
+                        dioxus.send([e.key, e.code, e.ctrlKey, e.shiftKey, e.altKey, e.metaKey]);
+                    });
+                    "#,
+                );
+
+                spawn(async move {
+                    let mut signal = signal;
+                    while let Ok(value) = listener.recv().await {
+                        let Some(event) = parse(&value) else {
+                            continue;
+                        };
+                        signal.write().push(event);
+                    }
+                });
+            });
+        }
+
+        fn parse(value: &serde_json::Value) -> Option<(Key, Modifiers, Code)> {
+            let [key, code, ctrl, shift, alt, meta] = value.as_array()?.as_slice() else {
+                return None;
+            };
+
+            let key = Key::from_str(key.as_str()?).unwrap_or(Key::Unidentified);
+            let code = Code::from_str(code.as_str()?).unwrap_or(Code::Unidentified);
+
+            let mut modifiers = Modifiers::empty();
+            for (pressed, modifier) in [
+                (ctrl, Modifiers::CONTROL),
+                (shift, Modifiers::SHIFT),
+                (alt, Modifiers::ALT),
+                (meta, Modifiers::META),
+            ] {
+                if pressed.as_bool().unwrap_or_default() {
+                    modifiers.insert(modifier);
+                }
+            }
+
+            Some((key, modifiers, code))
         }
     }
 
@@ -93,9 +200,10 @@ pub struct MainWindowContext {
     keydown_events: Signal<Vec<(Key, Modifiers, Code)>>,
 
     // Exchanges
-    upbit: Arc<Upbit>,
-    binance: Arc<Binance>,
-    bithumb: Arc<Bithumb>,
+    upbit: Arc<Cached<Upbit>>,
+    binance: Arc<Cached<Binance>>,
+    bithumb: Arc<Cached<Bithumb>>,
+    okx: Arc<Cached<Okx>>,
 }
 
 /// This is a dummy implementation of PartialEq for MainWindowContext
@@ -112,11 +220,24 @@ fn MainWindow(mut ctx: MainWindowContext) -> Element {
 
     if !ctx.keydown_events.read().is_empty() {
         let events = ctx.keydown_events.take();
-        for (key, modifiers, code) in events {
-            match (key, modifiers, code) {
-                (_, Modifiers::CONTROL, Code::Space) => *is_command_palette_open.write() = true,
-                (_, _, Code::Escape) => *is_command_palette_open.write() = false,
-                _ => {}
+        let keybinds = Config::get().keybinds;
+        for (_, modifiers, code) in events {
+            if code == Code::Escape {
+                *is_command_palette_open.write() = false;
+                continue;
+            }
+
+            match keybinds.action(modifiers, code) {
+                Some(Action::OpenCommandPalette) => *is_command_palette_open.write() = true,
+                Some(Action::CloseWindow) => SubWindowMgrState::send(SubWindowEvent::CloseFocused),
+                Some(Action::FocusNext) => SubWindowMgrState::send(SubWindowEvent::FocusNext),
+                Some(Action::FocusPrevious) => {
+                    SubWindowMgrState::send(SubWindowEvent::FocusPrevious)
+                }
+                Some(Action::FocusWindow(idx)) => {
+                    SubWindowMgrState::send(SubWindowEvent::FocusIndex(idx))
+                }
+                None => {}
             }
         }
     }
@@ -134,6 +255,130 @@ fn MainWindow(mut ctx: MainWindowContext) -> Element {
                             SubWindowMgrState::open(widget.into());
                         }
                     }
+                    Command::Depth(ex_name, (base, quote)) => {
+                        if let Some(widget) = select_ex!(ctx, ex_name, |exchange| {
+                            DepthWidget::new((base, quote), exchange)
+                        }) {
+                            SubWindowMgrState::open(widget.into());
+                        }
+                    }
+                    Command::Chart(ex_name, pair) => {
+                        let exchanges = Exchanges {
+                            upbit: ctx.upbit.clone(),
+                            binance: ctx.binance.clone(),
+                            bithumb: ctx.bithumb.clone(),
+                            okx: ctx.okx.clone(),
+                        };
+                        if let Some(widget) = CandleChartWidget::new(&ex_name, pair, exchanges) {
+                            SubWindowMgrState::open(widget.into());
+                        }
+                    }
+                    Command::Trades(ex_name, pair) => {
+                        let exchanges = Exchanges {
+                            upbit: ctx.upbit.clone(),
+                            binance: ctx.binance.clone(),
+                            bithumb: ctx.bithumb.clone(),
+                            okx: ctx.okx.clone(),
+                        };
+                        if let Some(widget) = TradesWidget::new(&ex_name, pair, exchanges) {
+                            SubWindowMgrState::open(widget.into());
+                        }
+                    }
+                    Command::Portfolio(quote) => {
+                        let exchanges = Exchanges {
+                            upbit: ctx.upbit.clone(),
+                            binance: ctx.binance.clone(),
+                            bithumb: ctx.bithumb.clone(),
+                            okx: ctx.okx.clone(),
+                        };
+                        SubWindowMgrState::open(PortfolioWidget::new(quote, exchanges).into());
+                    }
+                    Command::Alert(ex_name, pair, direction, price) => {
+                        let exchanges = Exchanges {
+                            upbit: ctx.upbit.clone(),
+                            binance: ctx.binance.clone(),
+                            bithumb: ctx.bithumb.clone(),
+                            okx: ctx.okx.clone(),
+                        };
+                        let alert = Alert::new(ex_name, pair, direction, price);
+                        if let Err(e) = Alerts::instance().add(alert, &exchanges) {
+                            tracing::error!("Failed to add the alert: {}", e);
+                        }
+                    }
+                    Command::Alerts => {
+                        SubWindowMgrState::open(AlertsWidget::new().into());
+                    }
+                    Command::Console => {
+                        let exchanges = Exchanges {
+                            upbit: ctx.upbit.clone(),
+                            binance: ctx.binance.clone(),
+                            bithumb: ctx.bithumb.clone(),
+                            okx: ctx.okx.clone(),
+                        };
+                        SubWindowMgrState::open(ConsoleWidget::new(exchanges).into());
+                    }
+                    Command::Reload => Config::reload_or_report(),
+                    Command::Secrets(action) => {
+                        SubWindowMgrState::open(SecretsWidget::new(action).into());
+                    }
+                    Command::Settings => {
+                        SubWindowMgrState::open(SettingsWidget::new().into());
+                    }
+                    Command::Export(Export::Candles(ex_name, pair, interval, path)) => {
+                        select_ex!(ctx, ex_name, |exchange| {
+                            // Each exchange gets its own closure, so they can't all move the path
+                            let path = path.clone();
+                            async_helpers::spawn_in(TaskClass::Action, async move {
+                                let result =
+                                    export::export_candles(&*exchange, pair, interval, &path).await;
+                                match result {
+                                    Ok(count) => {
+                                        tracing::info!("Exported {} candles to {}", count, path)
+                                    }
+                                    Err(e) => tracing::error!("Failed to export candles: {}", e),
+                                }
+                            });
+                        });
+                    }
+                    Command::Export(Export::Book(ex_name, pair, path)) => {
+                        select_ex!(ctx, ex_name, |exchange| {
+                            let path = path.clone();
+                            async_helpers::spawn_in(TaskClass::Action, async move {
+                                match export::export_orderbook(&*exchange, pair, &path).await {
+                                    Ok(()) => tracing::info!("Exported the orderbook to {}", path),
+                                    Err(e) => {
+                                        tracing::error!("Failed to export the orderbook: {}", e)
+                                    }
+                                }
+                            });
+                        });
+                    }
+                    Command::Record(ex_name, pair, path) => {
+                        select_ex!(ctx, ex_name, |exchange| {
+                            let subscription = exchange.subscribe(pair, None);
+                            if let Err(e) = replay::start_recording(subscription, path.clone()) {
+                                tracing::error!("{}", e);
+                            }
+                        });
+                    }
+                    Command::StopRecording(path) => {
+                        if !replay::stop_recording(&path) {
+                            tracing::warn!("Not recording to {}", path);
+                        }
+                    }
+                    Command::Replay(path, speed) => match Replay::load(&path, speed) {
+                        Ok(replay) => {
+                            let replay = Arc::new(replay);
+                            for pair in replay.pairs() {
+                                let widget = OrderbookWidget::new(pair, replay.clone());
+                                SubWindowMgrState::open(widget.into());
+                            }
+                        }
+                        Err(e) => tracing::error!("Failed to load the replay {}: {}", path, e),
+                    },
+                    Command::Close => {
+                        SubWindowMgrState::send(SubWindowEvent::CloseFocused);
+                    }
                 }
                 
                 commands.take();
@@ -153,8 +398,29 @@ fn MainWindow(mut ctx: MainWindowContext) -> Element {
 }
 
 #[derive(Debug)]
-enum Command {
+pub enum Command {
     Orderbook(String, (Currency, Currency)),
+    Depth(String, (Currency, Currency)),
+    Chart(String, (Currency, Currency)),
+    Trades(String, (Currency, Currency)),
+    Portfolio(Currency),
+    Alert(String, (Currency, Currency), Direction, Decimal),
+    Alerts,
+    Console,
+    Reload,
+    Secrets(SecretsAction),
+    Settings,
+    Export(Export),
+    Record(String, (Currency, Currency), String),
+    StopRecording(String),
+    Replay(String, f64),
+    Close,
+}
+
+/// Data written by the `export` command.
+pub enum Export {
+    Candles(String, (Currency, Currency), Duration, String),
+    Book(String, (Currency, Currency), String),
 }
 
 impl Command {
@@ -162,12 +428,88 @@ impl Command {
         let command = command.trim().split_whitespace().collect::<Vec<_>>();
         match command.as_slice() {
             ["orderbook", ex_name, pair] => {
-                let mut pair = pair.split('-');
-                let base = pair.next()?.to_uppercase().parse().ok()?;
-                let quote = pair.next()?.to_uppercase().parse().ok()?;
+                let pair = pair.parse::<CurrencyPair>().ok()?.into();
+
+                Some(Command::Orderbook(ex_name.to_string(), pair))
+            }
+            ["depth", ex_name, pair] => {
+                let pair = pair.parse::<CurrencyPair>().ok()?.into();
+
+                Some(Command::Depth(ex_name.to_string(), pair))
+            }
+            ["chart", ex_name, pair] => {
+                let pair = pair.parse::<CurrencyPair>().ok()?.into();
+
+                Some(Command::Chart(ex_name.to_string(), pair))
+            }
+            ["trades", ex_name, pair] => {
+                let pair = pair.parse::<CurrencyPair>().ok()?.into();
+
+                Some(Command::Trades(ex_name.to_string(), pair))
+            }
+            ["portfolio", quote] => {
+                let quote = quote.to_uppercase().parse().ok()?;
+
+                Some(Command::Portfolio(quote))
+            }
+            ["alert", ex_name, pair, direction, price] => {
+                let pair = pair.parse::<CurrencyPair>().ok()?.into();
+                let direction = match *direction {
+                    ">" => Direction::Above,
+                    "<" => Direction::Below,
+                    _ => return None,
+                };
+                let price = Decimal::from_str(price).ok()?;
+
+                Some(Command::Alert(
+                    ex_name.to_string(),
+                    pair,
+                    direction,
+                    price,
+                ))
+            }
+            ["alerts"] => Some(Command::Alerts),
+            ["console"] => Some(Command::Console),
+            ["reload"] => Some(Command::Reload),
+            ["secrets", "encrypt"] => Some(Command::Secrets(SecretsAction::Encrypt)),
+            ["secrets", "rotate"] => Some(Command::Secrets(SecretsAction::Rotate)),
+            ["settings"] => Some(Command::Settings),
+            ["export", "candles", ex_name, pair, interval, path] => {
+                let pair = pair.parse::<CurrencyPair>().ok()?.into();
+                let interval = export::parse_interval(interval)?;
+
+                Some(Command::Export(Export::Candles(
+                    ex_name.to_string(),
+                    pair,
+                    interval,
+                    path.to_string(),
+                )))
+            }
+            ["export", "book", ex_name, pair, path] => {
+                let pair = pair.parse::<CurrencyPair>().ok()?.into();
+
+                Some(Command::Export(Export::Book(
+                    ex_name.to_string(),
+                    pair,
+                    path.to_string(),
+                )))
+            }
+            ["record", "stop", path] => Some(Command::StopRecording(path.to_string())),
+            ["record", ex_name, pair, path] => {
+                let pair = pair.parse::<CurrencyPair>().ok()?.into();
+
+                Some(Command::Record(
+                    ex_name.to_string(),
+                    pair,
+                    path.to_string(),
+                ))
+            }
+            ["replay", path, speed] => {
+                let speed = speed.parse::<f64>().ok().filter(|speed| *speed > 0.0)?;
 
-                Some(Command::Orderbook(ex_name.to_string(), (base, quote)))
+                Some(Command::Replay(path.to_string(), speed))
             }
+            ["close"] => Some(Command::Close),
             _ => None,
         }
     }
@@ -180,6 +522,42 @@ fn CommandPalette(commands: Signal<String>) -> Element {
         "../../resources/CommandPaletteStyle.css"
     );
 
+    let mut input = use_signal(String::new);
+    let mut error = use_signal(|| None::<String>);
+    let mut selected = use_signal(|| 0usize);
+    let mut history = use_signal(History::load);
+    let exchanges = use_context::<Exchanges>();
+    use_hook(move || refresh_markets(&exchanges));
+
+    let suggestions = suggestions(&input.read(), &history.read(), &listings());
+    let selected_idx = (*selected.read()).min(suggestions.len().saturating_sub(1));
+    let selected_suggestion = suggestions.get(selected_idx).cloned();
+    let options = suggestions
+        .into_iter()
+        .enumerate()
+        .map(|(idx, suggestion)| {
+            let class = if idx == selected_idx { "option selected" } else { "option" };
+            (class, suggestion)
+        })
+        .collect::<Vec<_>>();
+    let error_text = error.read().clone().unwrap_or_default();
+    let usage_text = usage(&input.read()).unwrap_or_default();
+
+    let mut submit = move || {
+        let line = input.peek().trim().to_string();
+        if Command::parse(&line).is_none() {
+            error.set(Some(format!("Unknown command: {}", line)));
+            return;
+        }
+        if let Some(unlisted) = unlisted_pair(&line, &listings()) {
+            error.set(Some(unlisted));
+            return;
+        }
+
+        history.write().push(&line);
+        *commands.write() = line + "\n";
+    };
+
     rsx! {
         CommandPaletteStyle {}
         div {
@@ -195,14 +573,38 @@ fn CommandPalette(commands: Signal<String>) -> Element {
                             r#type: "text",
                             placeholder: "Command...",
                             spellcheck: "false",
+                            value: "{input}",
 
-                            oninput: move |input| {
-                                *commands.write() = input.value();
+                            oninput: move |event| {
+                                input.set(event.value());
+                                history.write().reset();
+                                error.set(None);
+                                selected.set(0);
                             },
 
-                            onkeydown: move |input| {
-                                if input.key() == Key::Enter {
-                                    *commands.write() += "\n";
+                            onkeydown: move |event| {
+                                let browsing = input.peek().is_empty() || history.peek().is_browsing();
+                                match event.key() {
+                                    Key::Enter => submit(),
+                                    Key::ArrowRight => {
+                                        if let Some(suggestion) = selected_suggestion.clone() {
+                                            input.set(suggestion);
+                                            selected.set(0);
+                                        }
+                                    }
+                                    Key::ArrowUp if browsing => {
+                                        let entry = history.write().prev().map(str::to_string);
+                                        if let Some(entry) = entry {
+                                            input.set(entry);
+                                        }
+                                    }
+                                    Key::ArrowDown if browsing => {
+                                        let entry = history.write().next().map(str::to_string);
+                                        input.set(entry.unwrap_or_default());
+                                    }
+                                    Key::ArrowUp => selected.set(selected_idx.saturating_sub(1)),
+                                    Key::ArrowDown => selected.set(selected_idx + 1),
+                                    _ => {}
                                 }
                             },
 
@@ -213,6 +615,20 @@ fn CommandPalette(commands: Signal<String>) -> Element {
                             }
                         }
                     }
+                    if !error_text.is_empty() {
+                        div { class: "error", "{error_text}" }
+                    } else if !usage_text.is_empty() {
+                        div { class: "usage", "{usage_text}" }
+                    }
+                    div { class: "options",
+                        for (class, suggestion) in options.into_iter() {
+                            div {
+                                class: "{class}",
+                                onclick: move |_| input.set(suggestion.clone()),
+                                "{suggestion}"
+                            }
+                        }
+                    }
                 }
             }
         }
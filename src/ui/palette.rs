@@ -0,0 +1,489 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use crate::currency::{Currency, CurrencyPair};
+use crate::exchange::markets::Markets;
+use crate::exchange::{
+    binance::Binance, bithumb::Bithumb, okx::Okx, upbit::Upbit, Exchange, Exchanges,
+};
+use crate::utils::maybe_trait::MaybeSend;
+use crate::utils::{async_helpers, storage};
+
+const HISTORY_KEY: &str = "history";
+const HISTORY_LIMIT: usize = 100;
+const SUGGESTION_LIMIT: usize = 8;
+
+/// A command known to the palette.
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub usage: &'static str,
+    /// The exchange name and a pair follow the command, or its subcommand for `export`.
+    pub takes_pair: bool,
+}
+
+/// Commands known to the palette, in the order they are suggested.
+/// `Command::parse` must accept every command listed here.
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "orderbook",
+        usage: "orderbook <exchange> <base-quote>",
+        takes_pair: true,
+    },
+    CommandSpec {
+        name: "depth",
+        usage: "depth <exchange> <base-quote>",
+        takes_pair: true,
+    },
+    CommandSpec {
+        name: "chart",
+        usage: "chart <exchange> <base-quote>",
+        takes_pair: true,
+    },
+    CommandSpec {
+        name: "trades",
+        usage: "trades <exchange> <base-quote>",
+        takes_pair: true,
+    },
+    CommandSpec {
+        name: "portfolio",
+        usage: "portfolio <quote>",
+        takes_pair: false,
+    },
+    CommandSpec {
+        name: "alert",
+        usage: "alert <exchange> <base-quote> <direction> <price>",
+        takes_pair: true,
+    },
+    CommandSpec {
+        name: "alerts",
+        usage: "alerts",
+        takes_pair: false,
+    },
+    CommandSpec {
+        name: "console",
+        usage: "console",
+        takes_pair: false,
+    },
+    CommandSpec {
+        name: "reload",
+        usage: "reload",
+        takes_pair: false,
+    },
+    CommandSpec {
+        name: "secrets",
+        usage: "secrets <encrypt|rotate>",
+        takes_pair: false,
+    },
+    CommandSpec {
+        name: "settings",
+        usage: "settings",
+        takes_pair: false,
+    },
+    CommandSpec {
+        name: "export",
+        // Also `export book <exchange> <base-quote> <path>`
+        usage: "export candles <exchange> <base-quote> <interval> <path>",
+        takes_pair: true,
+    },
+    CommandSpec {
+        name: "record",
+        // Also `record stop <path>`
+        usage: "record <exchange> <base-quote> <path>",
+        takes_pair: true,
+    },
+    CommandSpec {
+        name: "replay",
+        usage: "replay <path> <speed>",
+        takes_pair: false,
+    },
+    CommandSpec {
+        name: "close",
+        usage: "close",
+        takes_pair: false,
+    },
+];
+
+fn find_command(name: &str) -> Option<&'static CommandSpec> {
+    COMMANDS.iter().find(|command| command.name == name)
+}
+
+fn takes_pair(name: &str) -> bool {
+    find_command(name).is_some_and(|command| command.takes_pair)
+}
+
+const EXPORT_SUBCOMMANDS: &[&str] = &["candles", "book"];
+
+/// Words after the command of a command taking a pair, starting with the exchange.
+/// None if the command takes no pair, or `export` is missing its subcommand.
+fn pair_arguments<'a, 'b>(words: &'a [&'b str]) -> Option<&'a [&'b str]> {
+    match words {
+        ["export", subcommand, arguments @ ..] if EXPORT_SUBCOMMANDS.contains(subcommand) => {
+            Some(arguments)
+        }
+        ["export", ..] => None,
+        [command, arguments @ ..] if takes_pair(command) => Some(arguments),
+        _ => None,
+    }
+}
+
+/// Usage of the command being typed.
+pub fn usage(input: &str) -> Option<&'static str> {
+    let name = input.split_whitespace().next()?;
+    find_command(name).map(|command| command.usage)
+}
+
+const EXCHANGES: &[&str] = &[Upbit::NAME, Binance::NAME, Bithumb::NAME, Okx::NAME];
+
+/// Markets of the exchanges the palette runs commands on, filled by [`refresh_markets`].
+static LISTINGS: Lazy<Mutex<Listings>> = Lazy::new(|| Mutex::new(Listings::default()));
+
+/// Markets listed by exchange name, for suggestions and validation.
+#[derive(Debug, Clone, Default)]
+pub struct Listings(HashMap<&'static str, Arc<Markets>>);
+
+impl Listings {
+    pub fn insert(&mut self, exchange: &'static str, markets: Markets) {
+        self.0.insert(exchange, Arc::new(markets));
+    }
+
+    fn get(&self, exchange: &str) -> Option<&Markets> {
+        self.0.get(exchange).map(|markets| markets.as_ref())
+    }
+}
+
+/// The markets fetched so far by [`refresh_markets`].
+pub fn listings() -> Listings {
+    LISTINGS.lock().clone()
+}
+
+/// Fetches the markets of every exchange in the background, for suggestions and validation.
+/// Markets fetched within a day are not requested again.
+pub fn refresh_markets(exchanges: &Exchanges) {
+    fn refresh<E>(exchange: Arc<E>)
+    where
+        E: Exchange + MaybeSend + 'static,
+    {
+        async_helpers::spawn(async move {
+            match exchange.markets().await {
+                Ok(markets) => LISTINGS.lock().insert(E::NAME, markets),
+                Err(e) => tracing::warn!("{}: failed to get markets: {}", E::NAME, e),
+            }
+        });
+    }
+
+    refresh(exchanges.upbit.clone());
+    refresh(exchanges.binance.clone());
+    refresh(exchanges.bithumb.clone());
+    refresh(exchanges.okx.clone());
+}
+
+/// Pairs listed on `exchange` as typed in commands, e.g. `btc-krw`.
+fn listed_pairs(listings: &Listings, exchange: &str) -> Vec<String> {
+    let mut pairs: Vec<String> = Vec::new();
+    for (base, quote, _) in listings.get(exchange).into_iter().flatten() {
+        let pair = format!("{}-{}", base, quote).to_lowercase();
+        if !pairs.contains(&pair) {
+            pairs.push(pair);
+        }
+    }
+    pairs
+}
+
+fn parse_pair(pair: &str) -> Option<(Currency, Currency)> {
+    pair.parse::<CurrencyPair>().ok().map(Into::into)
+}
+
+/// Error for a command whose pair is not listed on its exchange.
+/// Nothing is reported while the markets of the exchange are unknown.
+pub fn unlisted_pair(input: &str, listings: &Listings) -> Option<String> {
+    let words = input.split_whitespace().collect::<Vec<_>>();
+    let Some([exchange, pair, ..]) = pair_arguments(&words) else {
+        return None;
+    };
+    let currencies = parse_pair(pair)?;
+    let markets = listings.get(exchange)?;
+    if markets
+        .iter()
+        .any(|(base, quote, _)| (*base, *quote) == currencies)
+    {
+        return None;
+    }
+
+    let similar = listed_pairs(listings, exchange)
+        .into_iter()
+        .filter(|listed| fuzzy_match(pair, listed))
+        .take(3)
+        .collect::<Vec<_>>();
+    if similar.is_empty() {
+        return Some(format!("{} is not listed on {}", pair, exchange));
+    }
+    Some(format!(
+        "{} is not listed on {}, did you mean {}?",
+        pair,
+        exchange,
+        similar.join(", ")
+    ))
+}
+
+/// Previously run commands, oldest first, persisted across restarts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct History {
+    /// Storage key the entries are persisted under.
+    key: &'static str,
+    entries: Vec<String>,
+    /// Index of the entry shown while browsing with Up/Down.
+    cursor: Option<usize>,
+}
+
+impl History {
+    pub fn new(entries: Vec<String>) -> Self {
+        Self {
+            key: HISTORY_KEY,
+            entries,
+            cursor: None,
+        }
+    }
+
+    /// Loads the command palette history.
+    pub fn load() -> Self {
+        Self::load_from(HISTORY_KEY)
+    }
+
+    /// Loads a history persisted under `key`, for inputs other than the command palette.
+    pub fn load_from(key: &'static str) -> Self {
+        let entries = storage::read(key)
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default();
+        Self {
+            key,
+            entries,
+            cursor: None,
+        }
+    }
+
+    pub fn push(&mut self, command: &str) {
+        self.cursor = None;
+        self.entries.retain(|entry| entry != command);
+        self.entries.push(command.to_string());
+        if self.entries.len() > HISTORY_LIMIT {
+            self.entries.remove(0);
+        }
+
+        match serde_json::to_string(&self.entries) {
+            Ok(text) => storage::write(self.key, &text),
+            Err(e) => tracing::warn!("Failed to serialize command history: {}", e),
+        }
+    }
+
+    /// Moves to the previous (older) entry.
+    pub fn prev(&mut self) -> Option<&str> {
+        let cursor = match self.cursor {
+            Some(cursor) => cursor.checked_sub(1)?,
+            None => self.entries.len().checked_sub(1)?,
+        };
+        self.cursor = Some(cursor);
+        self.entries.get(cursor).map(String::as_str)
+    }
+
+    /// Moves to the next (newer) entry, None when moving past the newest.
+    pub fn next(&mut self) -> Option<&str> {
+        let cursor = self.cursor? + 1;
+        if cursor >= self.entries.len() {
+            self.cursor = None;
+            return None;
+        }
+
+        self.cursor = Some(cursor);
+        self.entries.get(cursor).map(String::as_str)
+    }
+
+    pub fn is_browsing(&self) -> bool {
+        self.cursor.is_some()
+    }
+
+    /// Stops browsing, the next `prev` starts from the newest entry again.
+    pub fn reset(&mut self) {
+        self.cursor = None;
+    }
+
+    /// Pairs used in the history, most recent first.
+    fn recent_pairs(&self) -> Vec<&str> {
+        let mut pairs = Vec::new();
+        for entry in self.entries.iter().rev() {
+            let words = entry.split_whitespace().collect::<Vec<_>>();
+            if let Some([_, pair, ..]) = pair_arguments(&words) {
+                if !pairs.contains(pair) {
+                    pairs.push(*pair);
+                }
+            }
+        }
+        pairs
+    }
+}
+
+/// Returns true if all characters of `pattern` appear in `text` in order.
+fn fuzzy_match(pattern: &str, text: &str) -> bool {
+    let mut text = text.chars();
+    pattern
+        .chars()
+        .all(|c| text.any(|t| t.eq_ignore_ascii_case(&c)))
+}
+
+/// Completions of the word being typed, as full command lines.
+/// Prefix matches come before fuzzy matches.
+pub fn suggestions(input: &str, history: &History, listings: &Listings) -> Vec<String> {
+    let words = input.split_whitespace().collect::<Vec<_>>();
+    let typing_new_word = input.is_empty() || input.ends_with(char::is_whitespace);
+    let (done, current) = match (typing_new_word, words.split_last()) {
+        (false, Some((current, done))) => (done, *current),
+        _ => (words.as_slice(), ""),
+    };
+
+    let arguments = pair_arguments(done);
+    let listed = match arguments {
+        Some([exchange]) => listed_pairs(listings, exchange),
+        _ => Vec::new(),
+    };
+    let candidates = match (done, arguments) {
+        ([], _) => COMMANDS.iter().map(|command| command.name).collect(),
+        (_, Some([])) => EXCHANGES.to_vec(),
+        (_, Some([_])) => {
+            // Recently used pairs first, then the rest of the listed ones
+            let mut pairs = history.recent_pairs();
+            for pair in &listed {
+                if !pairs.iter().any(|recent| recent.eq_ignore_ascii_case(pair)) {
+                    pairs.push(pair);
+                }
+            }
+            pairs
+        }
+        (["alert", _, _], _) => vec![">", "<"],
+        (["secrets"], _) => vec!["encrypt", "rotate"],
+        (["export"], _) => EXPORT_SUBCOMMANDS.to_vec(),
+        _ => Vec::new(),
+    };
+
+    let (mut prefixed, fuzzy): (Vec<_>, Vec<_>) = candidates
+        .into_iter()
+        .filter(|candidate| *candidate != current && fuzzy_match(current, candidate))
+        .partition(|candidate| candidate.starts_with(current));
+    prefixed.extend(fuzzy);
+
+    prefixed
+        .into_iter()
+        .take(SUGGESTION_LIMIT)
+        .map(|candidate| {
+            let mut line = done.join(" ");
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line + candidate + " "
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{suggestions, unlisted_pair, usage, History, Listings, COMMANDS};
+    use crate::currency::Currency;
+    use crate::exchange::{okx::Okx, Exchange, Market};
+    use crate::ui::Command;
+
+    #[test]
+    fn history_navigation() {
+        let mut history = History::new(vec!["a".to_string(), "b".to_string()]);
+
+        assert_eq!(history.prev(), Some("b"));
+        assert_eq!(history.prev(), Some("a"));
+        assert_eq!(history.prev(), None);
+        assert_eq!(history.next(), Some("b"));
+        assert_eq!(history.next(), None);
+        assert!(!history.is_browsing());
+    }
+
+    #[test]
+    fn suggest_commands_and_arguments() {
+        let history = History::new(vec!["orderbook upbit btc-krw".to_string()]);
+        let listings = Listings::default();
+
+        assert_eq!(suggestions("dep", &history, &listings), vec!["depth "]);
+        assert_eq!(suggestions("obk", &history, &listings), vec!["orderbook "]);
+        assert_eq!(
+            suggestions("depth bin", &history, &listings),
+            vec!["depth binance "]
+        );
+        assert_eq!(
+            suggestions("export ", &history, &listings),
+            vec!["export candles ", "export book "]
+        );
+        assert_eq!(
+            suggestions("export candles bin", &history, &listings),
+            vec!["export candles binance "]
+        );
+        assert_eq!(
+            suggestions("depth upbit ", &history, &listings),
+            vec!["depth upbit btc-krw "]
+        );
+        assert!(suggestions("portfolio usdt ", &history, &listings).is_empty());
+    }
+
+    #[test]
+    fn listed_markets() {
+        let mut listings = Listings::default();
+        listings.insert(
+            Okx::NAME,
+            vec![
+                (Currency::BTC, Currency::USDT, Market::Spot),
+                (Currency::ETH, Currency::USDT, Market::Spot),
+                (Currency::BTC, Currency::USDT, Market::Future),
+            ],
+        );
+        let history = History::new(vec!["depth okx eth-usdt".to_string()]);
+
+        assert_eq!(
+            suggestions("depth okx ", &history, &listings),
+            vec!["depth okx eth-usdt ", "depth okx btc-usdt "]
+        );
+        assert_eq!(unlisted_pair("depth okx btc-usdt", &listings), None);
+        assert_eq!(
+            unlisted_pair("depth okx btc-usd", &listings),
+            Some("btc-usd is not listed on okx, did you mean btc-usdt?".to_string())
+        );
+        // Markets of upbit were never fetched
+        assert_eq!(unlisted_pair("depth upbit btc-usd", &listings), None);
+
+        assert_eq!(
+            suggestions("export book okx ", &history, &listings),
+            vec!["export book okx eth-usdt ", "export book okx btc-usdt "]
+        );
+        assert!(unlisted_pair("export candles okx btc-usd 1h candles.csv", &listings).is_some());
+        assert_eq!(unlisted_pair("export okx btc-usd", &listings), None);
+    }
+
+    #[test]
+    fn usage_of_typed_command() {
+        assert_eq!(usage("depth up"), Some("depth <exchange> <base-quote>"));
+        assert_eq!(usage("nothing"), None);
+    }
+
+    #[test]
+    fn registry_matches_parser() {
+        for command in COMMANDS {
+            let example = command
+                .usage
+                .replace("<exchange>", "upbit")
+                .replace("<base-quote>", "btc-krw")
+                .replace("<quote>", "krw")
+                .replace("<direction>", ">")
+                .replace("<price>", "100")
+                .replace("<encrypt|rotate>", "encrypt")
+                .replace("<interval>", "15m")
+                .replace("<path>", "export.csv")
+                .replace("<speed>", "10");
+            assert!(Command::parse(&example).is_some(), "{}", example);
+        }
+    }
+}
@@ -34,38 +34,33 @@ pub fn StyleMainWindow() -> Element {
     }
 }
 
-/// A component that defines the style for a button.
+/// A component that defines the style for a button, in the bid and ask colors of the theme.
 ///
 /// Classes
 /// - `rbutton`
 /// - `gbutton`
 #[component]
-pub fn StyleButton(dark_mode: bool) -> Element {
+pub fn StyleButton() -> Element {
     #[component]
-    pub fn gen_button_style(
-        class: String,
-        color: String,
-        color_hover: String,
-        color_click: String,
-    ) -> Element {
+    pub fn gen_button_style(class: String, color: String) -> Element {
         rsx! { "
 .{class} {{
     background-color: {color};
     border: none;
 }}
 .{class}:hover {{
-    background-color: {color_hover};
+    filter: brightness(85%);
 }}
 .{class}:active {{
-    background-color: {color_click};
+    filter: none;
 }}" 
         }
     }
 
     rsx! {
         style {
-            gen_button_style { class: "gbutton", color: "#25a750", color_hover: "#258d47", color_click: "#25a750" }
-            gen_button_style { class: "rbutton", color: "#ca3f64", color_hover: "#a93957", color_click: "#ca3f64" }
+            gen_button_style { class: "gbutton", color: "var(--bid)" }
+            gen_button_style { class: "rbutton", color: "var(--ask)" }
         }
     }
 }
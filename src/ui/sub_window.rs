@@ -1,12 +1,21 @@
 use std::collections::HashMap;
+use std::time::Duration;
 
+use crate::exchange::Exchanges;
+use crate::ui::layout::{Layout, SplitLayout, SplitLayoutItem};
 use crate::ui::widgets::{BoxedWidget, WidgetElement};
+use crate::utils::async_helpers;
 
 use async_channel::{Receiver, Sender};
 use dioxus::prelude::*;
 
 use super::utils::MountedDataStorge;
 
+const TITLE_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Panes can not be resized smaller than this, in pixels.
+const MIN_PANE_SIZE: f64 = 100.0;
+
 pub struct SubWindow {
     uuid: uuid::Uuid,
     mount_data: MountedDataStorge,
@@ -26,9 +35,10 @@ impl SubWindow {
 }
 
 impl SubWindow {
-    fn render(&self) -> Element {
+    /// `drop_side` is where a dragged window would be dropped, if it is dragged over this one.
+    fn render(&self, drop_side: Option<SplitSide>) -> Element {
         let uuid = self.uuid;
-        let name = self.widget.name();
+        let preview_class = drop_side.map(|side| format!("drop-preview {}", side.preview_class()));
 
         let mount_data = self.mount_data.clone();
         rsx! {
@@ -37,10 +47,13 @@ impl SubWindow {
                 onmousedown: move |_| SubWindowMgrState::send(SubWindowEvent::Focus(uuid)),
                 onmounted: move |data| { mount_data.set(data.data()) },
 
-                SubwindowBar { name, uuid }
+                SubwindowBar { widget: self.widget.clone(), uuid }
                 div { class: "widget-item",
                     WidgetElement { widget: self.widget.clone() }
                 }
+                for class in preview_class.into_iter() {
+                    div { class: "{class}" }
+                }
             }
         }
     }
@@ -61,7 +74,22 @@ impl SubWindow {
 }
 
 #[component]
-fn SubwindowBar(name: String, uuid: uuid::Uuid) -> Element {
+fn SubwindowBar(widget: BoxedWidget, uuid: uuid::Uuid) -> Element {
+    // Titles may show live values such as the last price, so they are refreshed periodically
+    let mut name = use_signal(|| widget.name());
+    use_future(move || {
+        let widget = widget.clone();
+        async move {
+            loop {
+                async_helpers::sleep(TITLE_REFRESH_INTERVAL).await;
+                let latest = widget.name();
+                if *name.peek() != latest {
+                    name.set(latest);
+                }
+            }
+        }
+    });
+
     rsx! {
         div {
             onmousedown: move |_| SubWindowMgrState::send(SubWindowEvent::DragStart(uuid)),
@@ -71,12 +99,14 @@ fn SubwindowBar(name: String, uuid: uuid::Uuid) -> Element {
                 class: "font-color-main unselectable",
                 style: "padding: 4px; cursor: move; width: 100%;",
 
-                {name}
+                "{name}"
             }
             div {
                 class: "font-color-main unselectable",
                 style: "padding: 4px; cursor: pointer; width: 20px; text-align: center;",
 
+                // Pressing the close button must not start dragging the window
+                onmousedown: move |e| e.stop_propagation(),
                 onmouseup: move |e| {
                     e.stop_propagation();
                     SubWindowMgrState::send(SubWindowEvent::Close(uuid));
@@ -150,6 +180,38 @@ fn StylePrelude() -> Element {
     height: 6px;
     background: #333;
 }
+.drop-preview {
+    position: absolute;
+    z-index: 4;
+    pointer-events: none;
+    background: rgba(147, 159, 175, 0.25);
+    border: 2px solid rgba(147, 159, 175, 0.6);
+    box-sizing: border-box;
+}
+.drop-left {
+    top: 0;
+    bottom: 0;
+    left: 0;
+    width: 50%;
+}
+.drop-right {
+    top: 0;
+    bottom: 0;
+    right: 0;
+    width: 50%;
+}
+.drop-top {
+    top: 0;
+    left: 0;
+    right: 0;
+    height: 50%;
+}
+.drop-bottom {
+    bottom: 0;
+    left: 0;
+    right: 0;
+    height: 50%;
+}
 "#;
 
     rsx! {
@@ -160,10 +222,20 @@ fn StylePrelude() -> Element {
 pub enum SubWindowEvent {
     DragStart(uuid::Uuid),
     ResizeStart(uuid::Uuid, f64, f64),
+    /// Resets the ratios of the split containing the window to equal.
+    ResizeReset(uuid::Uuid),
     OnMouseMove(f64, f64),
     OnMouseUp(f64, f64),
     Close(uuid::Uuid),
+    /// Closes the focused window, if there is one.
+    CloseFocused,
     Focus(uuid::Uuid),
+    /// Moves the focus to the next window, back to the first after the last.
+    FocusNext,
+    /// Moves the focus to the previous window, to the last before the first.
+    FocusPrevious,
+    /// Focuses the window at this index, in the order the windows appear.
+    FocusIndex(usize),
     WindowCreation(BoxedWidget),
 }
 
@@ -175,24 +247,38 @@ pub struct SubWindowMgrState {
     root: Split, // Tree of splits
 
     dragging: Option<uuid::Uuid>,
+    /// Window and side the dragged window would be dropped on.
+    drop_preview: Option<(uuid::Uuid, SplitSide)>,
     resizing: Option<(uuid::Uuid, f64, f64)>,
     focused: uuid::Uuid,
     changed: bool,
 }
 
 impl SubWindowMgrState {
-    pub fn new() -> Self {
+    /// Creates the state, restoring the saved layout if there is one.
+    pub fn new(exchanges: &Exchanges) -> Self {
+        let mut windows = HashMap::new();
+        let root = Layout::load()
+            .map(|layout| Split::from_layout(layout.root, exchanges, &mut windows))
+            .unwrap_or_else(Split::new);
+        let focused = root.first().unwrap_or(uuid::Uuid::nil());
+
         Self {
-            windows: HashMap::new(),
-            root: Split::new(),
+            windows,
+            root,
 
             dragging: None,
+            drop_preview: None,
             resizing: None,
-            focused: uuid::Uuid::nil(),
+            focused,
             changed: true,
         }
     }
 
+    fn save_layout(&self) {
+        Layout::new(self.root.to_layout(&self.windows)).save();
+    }
+
     fn append(&mut self, widget: BoxedWidget) {
         let window = SubWindow::new(widget);
         let window_uuid = window.uuid;
@@ -209,12 +295,14 @@ impl SubWindowMgrState {
         }
 
         self.windows.insert(window_uuid, window);
+        self.save_layout();
         self.mark_changed();
     }
 
     fn remove(&mut self, uuid: uuid::Uuid) {
         self.root.remove(uuid);
-        assert!(self.windows.remove(&uuid).is_some());
+        let window = self.windows.remove(&uuid).expect("removed window exists");
+        window.widget.on_close();
 
         // If the focused window is removed, then set the focused window to the first window
         if self.focused == uuid {
@@ -223,6 +311,11 @@ impl SubWindowMgrState {
 
         if self.dragging == Some(uuid) {
             self.dragging = None;
+            self.drop_preview = None;
+        }
+
+        if self.drop_preview.is_some_and(|(target, _)| target == uuid) {
+            self.drop_preview = None;
         }
 
         if let Some((target, _, _)) = self.resizing {
@@ -231,6 +324,7 @@ impl SubWindowMgrState {
             }
         }
 
+        self.save_layout();
         self.mark_changed();
     }
 
@@ -247,9 +341,44 @@ impl SubWindowMgrState {
         None
     }
 
+    /// Returns the window under the cursor and the side `dragged` would be dropped on.
+    async fn find_drop_target(
+        &self,
+        dragged: uuid::Uuid,
+        x: f64,
+        y: f64,
+    ) -> Option<(uuid::Uuid, SplitSide)> {
+        let target = self.find_window_at(x, y).await?;
+        if target == dragged || !self.windows.contains_key(&dragged) {
+            return None;
+        }
+
+        let (target_x, target_y) = self.windows[&target].position().await;
+        let (target_w, target_h) = self.windows[&target].size().await;
+
+        // Calculate where cursor is relative to the target window
+        let rel_x = x - target_x;
+        let rel_y = y - target_y;
+
+        // The top and bottom thirds split vertically, the middle splits horizontally
+        let side = if rel_y < target_h / 3.0 {
+            SplitSide::Top
+        } else if rel_y > target_h * 2.0 / 3.0 {
+            SplitSide::Bottom
+        } else if rel_x < target_w / 2.0 {
+            SplitSide::Left
+        } else {
+            SplitSide::Right
+        };
+
+        Some((target, side))
+    }
+
     async fn render_inner(&mut self) -> Element {
         self.changed = false;
-        self.root.render_element(&self.windows).await
+        self.root
+            .render_element(&self.windows, self.drop_preview)
+            .await
     }
 
     fn dispatch_drag_start(&mut self, uuid: uuid::Uuid) {
@@ -260,34 +389,22 @@ impl SubWindowMgrState {
         self.resizing = Some((uuid, x, y));
     }
 
+    fn dispatch_resize_reset(&mut self, uuid: uuid::Uuid) {
+        if self.root.reset_ratio(uuid) {
+            self.save_layout();
+            self.mark_changed();
+        }
+    }
+
     pub async fn dispatch_mouse_move(&mut self, x: f64, y: f64) {
         // subwindow dragging logic
-        if let Some(_) = self.dragging {
-            let Some(id) = self.find_window_at(x, y).await else {
-                return;
-            };
-
-            let (target_x, target_y) = self.windows[&id].position().await;
-            let (target_w, target_h) = self.windows[&id].size().await;
-
-            // Calculate where cursor is relative to the target window
-            let rel_x = x - target_x;
-            let rel_y = y - target_y;
-
-            // Calculate the split side
-            let side = if rel_y < target_h * 0.3 {
-                SplitSide::Top
-            } else if rel_y > target_h * 0.7 {
-                SplitSide::Bottom
-            } else {
-                if rel_x < target_w / 2.0 {
-                    SplitSide::Left
-                } else {
-                    SplitSide::Right
-                }
-            };
-
-            self.mark_changed();
+        if let Some(dragged) = self.dragging {
+            // Only re-render when the preview moves to another window or side
+            let preview = self.find_drop_target(dragged, x, y).await;
+            if preview != self.drop_preview {
+                self.drop_preview = preview;
+                self.mark_changed();
+            }
         }
 
         if let Some((target, start_x, start_y)) = &mut self.resizing {
@@ -304,44 +421,23 @@ impl SubWindowMgrState {
     async fn dispatch_mouse_up(&mut self, x: f64, y: f64) {
         // Subwindow dragging logic
         if let Some(id) = self.dragging.take() {
-            let Some(target) = self.find_window_at(x, y).await else {
-                return;
-            };
-
-            if self.windows.get(&id).is_none() || self.windows.get(&target).is_none() {
-                return;
+            if self.drop_preview.take().is_some() {
+                self.mark_changed();
             }
 
-            if target == id {
+            let Some((target, side)) = self.find_drop_target(id, x, y).await else {
                 return;
-            }
-
-            let (target_x, target_y) = self.windows[&target].position().await;
-            let (target_w, target_h) = self.windows[&target].size().await;
-
-            // Calculate where cursor is relative to the target window
-            let rel_x = x - target_x;
-            let rel_y = y - target_y;
-
-            // Calculate the split side
-            let side = if rel_y < target_h * 0.2 {
-                SplitSide::Top
-            } else if rel_y > target_h * 0.8 {
-                SplitSide::Bottom
-            } else {
-                if rel_x < target_w / 2.0 {
-                    SplitSide::Left
-                } else {
-                    SplitSide::Right
-                }
             };
 
             assert!(self.root.remove(id));
             self.root.split_append(target, id, side);
+            self.save_layout();
             self.mark_changed();
         }
 
-        self.resizing = None;
+        if self.resizing.take().is_some() {
+            self.save_layout();
+        }
     }
 
     pub fn mark_changed(&mut self) {
@@ -374,55 +470,86 @@ impl SubWindowMgrState {
 
 #[component]
 pub fn SubWindowMgr() -> Element {
-    let mut pre_rendered = use_resource(|| async {
-        let mut state = use_signal(|| SubWindowMgrState::new());
-        let rx = SubWindowMgrState::rx();
-
-        let mut state = state.write();
-        while !state.is_changed() {
-            // Do not dispatch more than 64 events at once
-            // This is to increase update rate of the UI
-            let mut events = vec![rx.recv().await.unwrap()];
-            for _ in 0..64 {
-                if let Ok(event) = rx.try_recv() {
-                    events.push(event);
-                } else {
-                    break;
+    let exchanges = use_context::<Exchanges>();
+    let mut pre_rendered = use_resource(move || {
+        let exchanges = exchanges.clone();
+        async move {
+            let mut state = use_signal(|| SubWindowMgrState::new(&exchanges));
+            let rx = SubWindowMgrState::rx();
+
+            let mut state = state.write();
+            while !state.is_changed() {
+                // Do not dispatch more than 64 events at once
+                // This is to increase update rate of the UI
+                let mut events = vec![rx.recv().await.unwrap()];
+                for _ in 0..64 {
+                    if let Ok(event) = rx.try_recv() {
+                        events.push(event);
+                    } else {
+                        break;
+                    }
                 }
-            }
 
-            for event in events {
-                match event {
-                    SubWindowEvent::DragStart(uuid) => {
-                        state.dispatch_drag_start(uuid);
-                    }
-                    SubWindowEvent::ResizeStart(uuid, x, y) => {
-                        state.dispatch_resize_start(uuid, x, y);
-                    }
-                    SubWindowEvent::OnMouseMove(x, y) => {
-                        state.dispatch_mouse_move(x, y).await;
-                    }
-                    SubWindowEvent::OnMouseUp(x, y) => {
-                        state.dispatch_mouse_up(x, y).await;
-                    }
-                    SubWindowEvent::Close(uuid) => {
-                        state.remove(uuid);
-                    }
-                    SubWindowEvent::Focus(uuid) => {
-                        state.focused = uuid;
-                        state.mark_changed();
-                    }
-                    SubWindowEvent::WindowCreation(widget) => {
-                        state.append(widget);
+                for event in events {
+                    match event {
+                        SubWindowEvent::DragStart(uuid) => {
+                            state.dispatch_drag_start(uuid);
+                        }
+                        SubWindowEvent::ResizeStart(uuid, x, y) => {
+                            state.dispatch_resize_start(uuid, x, y);
+                        }
+                        SubWindowEvent::ResizeReset(uuid) => {
+                            state.dispatch_resize_reset(uuid);
+                        }
+                        SubWindowEvent::OnMouseMove(x, y) => {
+                            state.dispatch_mouse_move(x, y).await;
+                        }
+                        SubWindowEvent::OnMouseUp(x, y) => {
+                            state.dispatch_mouse_up(x, y).await;
+                        }
+                        SubWindowEvent::Close(uuid) => {
+                            state.remove(uuid);
+                        }
+                        SubWindowEvent::CloseFocused => {
+                            let focused = state.focused;
+                            if state.windows.contains_key(&focused) {
+                                state.remove(focused);
+                            }
+                        }
+                        SubWindowEvent::Focus(uuid) => {
+                            state.focused = uuid;
+                            state.mark_changed();
+                        }
+                        SubWindowEvent::FocusNext => {
+                            if let Some(next) = state.root.next(state.focused) {
+                                state.focused = next;
+                                state.mark_changed();
+                            }
+                        }
+                        SubWindowEvent::FocusPrevious => {
+                            if let Some(previous) = state.root.previous(state.focused) {
+                                state.focused = previous;
+                                state.mark_changed();
+                            }
+                        }
+                        SubWindowEvent::FocusIndex(idx) => {
+                            if let Some(window) = state.root.windows().get(idx) {
+                                state.focused = *window;
+                                state.mark_changed();
+                            }
+                        }
+                        SubWindowEvent::WindowCreation(widget) => {
+                            state.append(widget);
+                        }
                     }
                 }
             }
-        }
 
-        let element = state.render_inner().await;
-        wait_for_next_render().await;
+            let element = state.render_inner().await;
+            wait_for_next_render().await;
 
-        element
+            element
+        }
     });
 
     let element = pre_rendered.read().clone();
@@ -450,7 +577,7 @@ pub fn SubWindowMgr() -> Element {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum SplitSide {
     Left,
     Right,
@@ -467,6 +594,16 @@ impl SplitSide {
             SplitSide::Bottom => SplitSide::Left,
         }
     }
+
+    /// Css class of the drop preview, highlighting the half the window would take.
+    fn preview_class(&self) -> &'static str {
+        match self {
+            SplitSide::Left => "drop-left",
+            SplitSide::Right => "drop-right",
+            SplitSide::Top => "drop-top",
+            SplitSide::Bottom => "drop-bottom",
+        }
+    }
 }
 
 enum SplitItem {
@@ -515,6 +652,76 @@ impl Split {
         }
     }
 
+    fn to_layout(&self, windows: &HashMap<uuid::Uuid, SubWindow>) -> SplitLayout {
+        let children = self
+            .children
+            .iter()
+            .zip(self.children_ratio.iter())
+            .filter_map(|(item, ratio)| {
+                let item = match item {
+                    // Widgets without a descriptor are not persisted
+                    SplitItem::Widget(uuid) => {
+                        SplitLayoutItem::Widget(windows.get(uuid)?.widget.to_descriptor()?)
+                    }
+                    SplitItem::Split(split) => SplitLayoutItem::Split(split.to_layout(windows)),
+                };
+
+                Some((*ratio, item))
+            })
+            .collect();
+
+        SplitLayout {
+            horizontal: self.horizontal,
+            children,
+        }
+    }
+
+    fn from_layout(
+        layout: SplitLayout,
+        exchanges: &Exchanges,
+        windows: &mut HashMap<uuid::Uuid, SubWindow>,
+    ) -> Self {
+        let mut split = Split::new();
+        split.horizontal = layout.horizontal;
+
+        for (ratio, item) in layout.children {
+            let item = match item {
+                SplitLayoutItem::Widget(descriptor) => {
+                    let Some(widget) = BoxedWidget::from_descriptor(&descriptor, exchanges) else {
+                        tracing::warn!("Failed to restore widget {:?}", descriptor);
+                        continue;
+                    };
+
+                    let window = SubWindow::new(widget);
+                    let uuid = window.uuid;
+                    windows.insert(uuid, window);
+                    SplitItem::Widget(uuid)
+                }
+                SplitLayoutItem::Split(layout) => {
+                    let child = Split::from_layout(layout, exchanges, windows);
+                    if child.children.is_empty() {
+                        continue;
+                    }
+
+                    SplitItem::Split(child)
+                }
+            };
+
+            split.children.push(item);
+            split.children_ratio.push(ratio.max(0.0));
+        }
+
+        // Skipped children leave a gap, so scale the ratios back to 1
+        let total = split.children_ratio.iter().sum::<f64>();
+        if total > 0.0 {
+            for ratio in split.children_ratio.iter_mut() {
+                *ratio /= total;
+            }
+        }
+
+        split
+    }
+
     fn first(&self) -> Option<uuid::Uuid> {
         match self.children.first() {
             Some(SplitItem::Widget(uuid)) => Some(*uuid),
@@ -523,6 +730,40 @@ impl Split {
         }
     }
 
+    /// Windows of the split tree, in the order they appear.
+    fn windows(&self) -> Vec<uuid::Uuid> {
+        self.children
+            .iter()
+            .flat_map(|item| match item {
+                SplitItem::Widget(uuid) => vec![*uuid],
+                SplitItem::Split(split) => split.windows(),
+            })
+            .collect()
+    }
+
+    /// The window after the given one, the first window after the last or an unknown one.
+    fn next(&self, id: uuid::Uuid) -> Option<uuid::Uuid> {
+        let windows = self.windows();
+        let next = windows
+            .iter()
+            .position(|window| *window == id)
+            .map_or(0, |idx| idx + 1);
+        windows.get(next).or(windows.first()).copied()
+    }
+
+    /// The window before the given one, the last window before the first or an unknown one.
+    fn previous(&self, id: uuid::Uuid) -> Option<uuid::Uuid> {
+        let windows = self.windows();
+        let previous = windows
+            .iter()
+            .position(|window| *window == id)
+            .and_then(|idx| idx.checked_sub(1));
+        previous
+            .and_then(|idx| windows.get(idx))
+            .or(windows.last())
+            .copied()
+    }
+
     /// Remove the window with the given id from the split tree
     /// Returns true if the window is removed
     fn remove(&mut self, id: uuid::Uuid) -> bool {
@@ -562,11 +803,12 @@ impl Split {
         if self.children_ratio.is_empty() {
             self.children_ratio.push(1.0);
         } else {
-            let ratio = 1.0 / (self.children_ratio.len() + 1) as f64;
+            // Shrink the others proportionally so the total stays 1
+            let count = self.children_ratio.len() as f64;
             for r in self.children_ratio.iter_mut() {
-                *r -= ratio;
+                *r *= count / (count + 1.0);
             }
-            self.children_ratio.push(ratio);
+            self.children_ratio.push(1.0 / (count + 1.0));
         }
     }
 
@@ -585,11 +827,10 @@ impl Split {
 
     #[async_recursion::async_recursion(?Send)]
     async fn resize(&mut self, id: uuid::Uuid, w: f64, h: f64) {
-        let amount_px = if self.horizontal { w } else { h };
-        let amount = amount_px * self.px_per_ratio().await;
         if let Some(target) = self.find(id) {
-            self.children_ratio[target] -= amount;
-            self.children_ratio[target - 1] += amount;
+            let amount_px = if self.horizontal { w } else { h };
+            let px_per_ratio = self.px_per_ratio().await;
+            self.resize_child(target, amount_px, px_per_ratio);
         } else {
             for item in self.children.iter_mut() {
                 if let SplitItem::Split(split) = item {
@@ -599,6 +840,93 @@ impl Split {
         }
     }
 
+    /// Moves the divider before the child at `target` by `amount_px`.
+    /// Neither child of the divider is resized below `MIN_PANE_SIZE`.
+    fn resize_child(&mut self, target: usize, amount_px: f64, px_per_ratio: f64) {
+        // The first child has no divider before it
+        if target == 0 || target >= self.children_ratio.len() {
+            return;
+        }
+
+        let min_ratio = self.min_ratio(px_per_ratio);
+        self.sanitize_ratio(min_ratio);
+
+        let grow = (self.children_ratio[target - 1] - min_ratio).max(0.0);
+        let shrink = (self.children_ratio[target] - min_ratio).max(0.0);
+        let amount = (amount_px * px_per_ratio).clamp(-grow, shrink);
+        self.children_ratio[target] -= amount;
+        self.children_ratio[target - 1] += amount;
+    }
+
+    /// Ratio of `MIN_PANE_SIZE`, lowered if the split is too small to fit every child at that size.
+    fn min_ratio(&self, px_per_ratio: f64) -> f64 {
+        let fit = 1.0 / self.children_ratio.len().max(1) as f64;
+        (MIN_PANE_SIZE * px_per_ratio).min(fit)
+    }
+
+    /// Scales the ratios to a total of 1 and raises children below `min_ratio` to it.
+    /// The deficit is taken from the larger children, in proportion to how far they are above the minimum.
+    fn sanitize_ratio(&mut self, min_ratio: f64) {
+        let total = self
+            .children_ratio
+            .iter()
+            .map(|ratio| ratio.max(0.0))
+            .sum::<f64>();
+        if total <= 0.0 {
+            self.reset_ratio_here();
+            return;
+        }
+
+        for ratio in self.children_ratio.iter_mut() {
+            *ratio = ratio.max(0.0) / total;
+        }
+
+        let min_ratio = min_ratio.min(1.0 / self.children_ratio.len() as f64);
+        let mut debt = 0.0;
+        for ratio in self.children_ratio.iter_mut() {
+            if *ratio < min_ratio {
+                debt += min_ratio - *ratio;
+                *ratio = min_ratio;
+            }
+        }
+        if debt <= 0.0 {
+            return;
+        }
+
+        let surplus = self
+            .children_ratio
+            .iter()
+            .map(|ratio| ratio - min_ratio)
+            .sum::<f64>();
+        if surplus <= 0.0 {
+            return;
+        }
+        for ratio in self.children_ratio.iter_mut() {
+            *ratio -= (*ratio - min_ratio) / surplus * debt;
+        }
+    }
+
+    fn reset_ratio_here(&mut self) {
+        let ratio = 1.0 / self.children_ratio.len() as f64;
+        for r in self.children_ratio.iter_mut() {
+            *r = ratio;
+        }
+    }
+
+    /// Gives every child of the split containing `id` the same ratio.
+    /// Returns true if the split is found.
+    fn reset_ratio(&mut self, id: uuid::Uuid) -> bool {
+        if self.find(id).is_some() {
+            self.reset_ratio_here();
+            return true;
+        }
+
+        self.children.iter_mut().any(|item| match item {
+            SplitItem::Split(split) => split.reset_ratio(id),
+            SplitItem::Widget(_) => false,
+        })
+    }
+
     fn split_append(&mut self, target: uuid::Uuid, id: uuid::Uuid, side: SplitSide) -> bool {
         for (idx, item) in self.children.iter_mut().enumerate() {
             let ratio = self.children_ratio[idx];
@@ -643,7 +971,11 @@ impl Split {
     }
 
     #[async_recursion::async_recursion(?Send)]
-    async fn render_element(&self, nodes: &HashMap<uuid::Uuid, SubWindow>) -> Element {
+    async fn render_element(
+        &self,
+        nodes: &HashMap<uuid::Uuid, SubWindow>,
+        drop_preview: Option<(uuid::Uuid, SplitSide)>,
+    ) -> Element {
         if self.children.is_empty() {
             return None;
         }
@@ -658,8 +990,12 @@ impl Split {
         for (idx, item) in self.children.iter().enumerate() {
             let uuid = item.uuid();
             let inner = match item {
-                SplitItem::Widget(uuid) => nodes[uuid].render(),
-                SplitItem::Split(split) => split.render_element(nodes).await,
+                SplitItem::Widget(uuid) => nodes[uuid].render(
+                    drop_preview
+                        .filter(|(target, _)| target == uuid)
+                        .map(|(_, side)| side),
+                ),
+                SplitItem::Split(split) => split.render_element(nodes, drop_preview).await,
             };
 
             rendered_elements.push(rsx! {
@@ -673,6 +1009,10 @@ impl Split {
                                 e.stop_propagation();
                                 let coords = e.client_coordinates();
                                 SubWindowMgrState::send(SubWindowEvent::ResizeStart(uuid, coords.x, coords.y));
+                            },
+                            ondoubleclick: move |e| {
+                                e.stop_propagation();
+                                SubWindowMgrState::send(SubWindowEvent::ResizeReset(uuid));
                             }
                         }
                     }
@@ -708,3 +1048,108 @@ impl Split {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Split, SplitItem};
+
+    fn split(ratios: &[f64]) -> Split {
+        let mut split = Split::new();
+        for _ in ratios {
+            split.append(uuid::Uuid::new_v4());
+        }
+        split.children_ratio = ratios.to_vec();
+        split
+    }
+
+    fn assert_ratios(split: &Split, expected: &[f64]) {
+        assert_eq!(split.children_ratio.len(), expected.len());
+        for (ratio, expected) in split.children_ratio.iter().zip(expected) {
+            assert!(
+                (ratio - expected).abs() < 1e-9,
+                "{:?}",
+                split.children_ratio
+            );
+        }
+    }
+
+    #[test]
+    fn next_wraps_around_nested_splits() {
+        let mut root = split(&[0.5, 0.5]);
+        let nested = split(&[0.5, 0.5]);
+        let nested_windows = nested.windows();
+        root.children.push(SplitItem::Split(nested));
+        root.children_ratio = vec![1.0 / 3.0; 3];
+
+        let windows = root.windows();
+        assert_eq!(windows.len(), 4);
+        assert_eq!(&windows[2..], &nested_windows[..]);
+
+        assert_eq!(root.next(windows[1]), Some(windows[2]));
+        assert_eq!(root.next(windows[3]), Some(windows[0]));
+        assert_eq!(root.next(uuid::Uuid::nil()), Some(windows[0]));
+        assert_eq!(Split::new().next(uuid::Uuid::nil()), None);
+
+        assert_eq!(root.previous(windows[2]), Some(windows[1]));
+        assert_eq!(root.previous(windows[0]), Some(windows[3]));
+        assert_eq!(root.previous(uuid::Uuid::nil()), Some(windows[3]));
+        assert_eq!(Split::new().previous(uuid::Uuid::nil()), None);
+    }
+
+    #[test]
+    fn append_keeps_total() {
+        let mut split = Split::new();
+        for _ in 0..3 {
+            split.append(uuid::Uuid::new_v4());
+        }
+        assert_ratios(&split, &[1.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0]);
+    }
+
+    #[test]
+    fn sanitize_redistributes_deficit() {
+        let mut split = split(&[0.05, 0.55, 0.4]);
+        split.sanitize_ratio(0.1);
+        // 0.05 is taken from 0.55 and 0.4 in proportion to their 0.45 and 0.3 above the minimum
+        assert_ratios(&split, &[0.1, 0.52, 0.38]);
+    }
+
+    #[test]
+    fn sanitize_normalizes_total() {
+        let mut split = split(&[1.0, 1.0, -0.5]);
+        split.sanitize_ratio(0.0);
+        assert_ratios(&split, &[0.5, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn resize_clamps_to_min_size() {
+        // 1000px tall, so the minimum of 100px is a ratio of 0.1
+        let px_per_ratio = 1.0 / 1000.0;
+        let mut split = split(&[0.5, 0.5]);
+
+        split.resize_child(1, 100.0, px_per_ratio);
+        assert_ratios(&split, &[0.6, 0.4]);
+
+        split.resize_child(1, 1000.0, px_per_ratio);
+        assert_ratios(&split, &[0.9, 0.1]);
+
+        split.resize_child(1, -2000.0, px_per_ratio);
+        assert_ratios(&split, &[0.1, 0.9]);
+    }
+
+    #[test]
+    fn resize_in_small_split() {
+        // 150px can not fit two panes of 100px, so both stay at half
+        let mut split = split(&[0.5, 0.5]);
+        split.resize_child(1, 50.0, 1.0 / 150.0);
+        assert_ratios(&split, &[0.5, 0.5]);
+    }
+
+    #[test]
+    fn reset_to_equal() {
+        let mut split = split(&[0.7, 0.2, 0.1]);
+        let id = split.children[1].uuid();
+        assert!(split.reset_ratio(id));
+        assert_ratios(&split, &[1.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0]);
+        assert!(!split.reset_ratio(uuid::Uuid::new_v4()));
+    }
+}
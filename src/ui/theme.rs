@@ -0,0 +1,275 @@
+//! Colors of the ui, from a built-in palette with optional overrides in the `[theme]` config section.
+//! They are applied as css variables by [`StyleTheme`], so widgets refer to `var(--bid)` and the like
+//! instead of fixed colors.
+
+use std::time::Duration;
+
+use dioxus::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::utils::async_helpers;
+
+/// How often the config is checked for a changed theme.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Palette {
+    #[default]
+    Dark,
+    Light,
+}
+
+impl std::fmt::Display for Palette {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Palette::Dark => write!(f, "dark"),
+            Palette::Light => write!(f, "light"),
+        }
+    }
+}
+
+impl std::str::FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "dark" => Ok(Palette::Dark),
+            "light" => Ok(Palette::Light),
+            _ => Err("Expected dark or light".to_string()),
+        }
+    }
+}
+
+/// Any css color is accepted, e.g. `#4a959f` or `rgb(74, 149, 159)`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct ThemeConfig {
+    pub palette: Palette,
+    /// Title bars of the windows.
+    pub accent: Option<String>,
+    pub bid: Option<String>,
+    pub ask: Option<String>,
+    pub background: Option<String>,
+    pub text: Option<String>,
+}
+
+/// True for a hex color, a color function like `rgb(74, 149, 159)` or a color name.
+/// Anything else could end the css rule the color is inserted into.
+pub fn is_css_color(value: &str) -> bool {
+    if let Some(hex) = value.strip_prefix('#') {
+        return matches!(hex.len(), 3 | 4 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit());
+    }
+    if let Some((function, arguments)) = value.split_once('(') {
+        return ["rgb", "rgba", "hsl", "hsla"].contains(&function)
+            && arguments.strip_suffix(')').is_some_and(|arguments| {
+                arguments
+                    .chars()
+                    .all(|c| c.is_ascii_digit() || " ,.%/-".contains(c))
+            });
+    }
+    !value.is_empty() && value.chars().all(|c| c.is_ascii_alphabetic())
+}
+
+impl ThemeConfig {
+    /// Fails with the first override that is not a css color.
+    pub fn validate(&self) -> Result<(), String> {
+        let overrides = [
+            ("accent", &self.accent),
+            ("bid", &self.bid),
+            ("ask", &self.ask),
+            ("background", &self.background),
+            ("text", &self.text),
+        ];
+        for (name, value) in overrides {
+            if let Some(value) = value.as_ref().filter(|value| !is_css_color(value)) {
+                return Err(format!("{} = {:?}", name, value));
+            }
+        }
+        Ok(())
+    }
+
+    /// The palette with the configured colors replacing its own, invalid colors are skipped.
+    pub fn theme(&self) -> Theme {
+        let mut theme = Theme::of(self.palette);
+        let overrides = [
+            (&mut theme.accent, &self.accent),
+            (&mut theme.bid, &self.bid),
+            (&mut theme.ask, &self.ask),
+            (&mut theme.background, &self.background),
+            (&mut theme.text, &self.text),
+        ];
+        for (color, value) in overrides {
+            if let Some(value) = value.as_ref().filter(|value| is_css_color(value)) {
+                *color = value.clone();
+            }
+        }
+        theme
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub accent: String,
+    pub bid: String,
+    pub ask: String,
+    /// Behind the bid levels of the orderbook and the depth chart.
+    pub bid_fill: String,
+    pub ask_fill: String,
+    pub whale_fill: String,
+    pub background: String,
+    /// Windows and inputs, from the darkest to the lightest in the dark palette.
+    pub surfaces: [String; 3],
+    pub text: String,
+}
+
+impl Theme {
+    pub fn of(palette: Palette) -> Self {
+        let theme = |colors: [&str; 11]| {
+            let [accent, bid, ask, bid_fill, ask_fill, whale_fill, background, surface1, surface2, surface3, text] =
+                colors.map(str::to_string);
+            Self {
+                accent,
+                bid,
+                ask,
+                bid_fill,
+                ask_fill,
+                whale_fill,
+                background,
+                surfaces: [surface1, surface2, surface3],
+                text,
+            }
+        };
+
+        // Accent, bid, ask, their fills and the whale fill, background, surfaces and text
+        match palette {
+            Palette::Dark => theme([
+                "#4a959f", "#228a44", "#a63654", "#152f1e", "#361b22", "#3a3317", "#000000",
+                "#121212", "#202020", "#505050", "#ffffff",
+            ]),
+            Palette::Light => theme([
+                "#2b7a84", "#1a7f3c", "#c0392b", "#d9f2e1", "#f8dde3", "#f5ecc8", "#ffffff",
+                "#f4f4f4", "#e8e8e8", "#c8c8c8", "#1e1e1e",
+            ]),
+        }
+    }
+
+    /// The css variables of the theme, with the color classes using them.
+    pub fn css(&self) -> String {
+        let [surface1, surface2, surface3] = &self.surfaces;
+        format!(
+            r#"
+:root {{
+    --accent: {};
+    --bid: {};
+    --ask: {};
+    --bid-fill: {};
+    --ask-fill: {};
+    --whale-fill: {};
+    --background: {};
+    --surface-1: {};
+    --surface-2: {};
+    --surface-3: {};
+    --text: {};
+}}
+.main-window {{ background-color: var(--background); }}
+.color-0 {{ background-color: var(--background); }}
+.color-1 {{ background-color: var(--surface-1); }}
+.color-2 {{ background-color: var(--surface-2); }}
+.color-3 {{ background-color: var(--surface-3); }}
+.font-color-main {{ color: var(--text); }}
+.widget-bar {{ border-bottom: 2px solid var(--accent); }}
+"#,
+            self.accent,
+            self.bid,
+            self.ask,
+            self.bid_fill,
+            self.ask_fill,
+            self.whale_fill,
+            self.background,
+            surface1,
+            surface2,
+            surface3,
+            self.text,
+        )
+    }
+}
+
+/// Applies the configured theme, and follows changes of the config.
+/// Placed after the other styles, so its colors take precedence.
+#[component]
+pub fn StyleTheme() -> Element {
+    let mut theme = use_signal(|| Config::get().theme.theme());
+    use_future(move || async move {
+        loop {
+            async_helpers::sleep(REFRESH_INTERVAL).await;
+            let current = Config::get().theme.theme();
+            if *theme.peek() != current {
+                theme.set(current);
+            }
+        }
+    });
+
+    let text = theme.read().css();
+    rsx! {
+        style { { text } }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_css_color, Palette, Theme, ThemeConfig};
+
+    #[test]
+    fn overrides_replace_palette_colors() {
+        let config: ThemeConfig = toml::from_str(
+            r##"
+            palette = "light"
+            bid = "#00ff00"
+            "##,
+        )
+        .unwrap();
+
+        let theme = config.theme();
+        assert_eq!(theme.bid, "#00ff00");
+        assert_eq!(theme.ask, Theme::of(Palette::Light).ask);
+        assert!(theme.css().contains("--bid: #00ff00;"));
+    }
+
+    #[test]
+    fn only_css_colors_are_applied() {
+        for color in [
+            "#fff",
+            "#4a959f",
+            "#4a959f80",
+            "rgb(74, 149, 159)",
+            "hsla(0 0% 50% / 0.5)",
+            "teal",
+        ] {
+            assert!(is_css_color(color), "{}", color);
+        }
+        for color in [
+            "",
+            "#12345",
+            "#ggg",
+            "red; } body { display: none",
+            "url(x)",
+            "rgb(1, 2, 3); x",
+        ] {
+            assert!(!is_css_color(color), "{}", color);
+        }
+
+        let config: ThemeConfig = toml::from_str(r#"ask = "red;}""#).unwrap();
+        assert!(config.validate().is_err());
+        assert_eq!(config.theme(), Theme::of(Palette::Dark));
+    }
+
+    #[test]
+    fn defaults_to_the_dark_palette() {
+        let theme = ThemeConfig::default().theme();
+        assert_eq!(theme, Theme::of(Palette::Dark));
+        assert_eq!(theme.accent, "#4a959f");
+        assert_eq!("Light".parse::<Palette>(), Ok(Palette::Light));
+    }
+}
@@ -1,7 +1,20 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use dioxus::prelude::*;
 
+use crate::{
+    currency::Currency,
+    exchange::Exchange,
+    utils::{async_helpers, maybe_trait::MaybeSend, Decimal},
+};
+
+const LAST_PRICE_INTERVAL: Duration = Duration::from_secs(5);
+
 #[derive(Clone)]
 pub struct MountedDataStorge {
     mounted_data: Rc<RefCell<Option<Rc<MountedData>>>>,
@@ -23,6 +36,55 @@ impl MountedDataStorge {
     }
 }
 
+/// Last traded price of a pair, polled in the background for window titles.
+/// Polling stops once every clone is dropped.
+#[derive(Clone)]
+pub struct LastPrice {
+    price: Arc<Mutex<Option<Decimal>>>,
+}
+
+impl LastPrice {
+    pub fn watch<E>(exchange: Arc<E>, pair: (Currency, Currency)) -> Self
+    where
+        E: Exchange + MaybeSend + 'static,
+    {
+        let price = Arc::new(Mutex::new(None));
+        let weak = Arc::downgrade(&price);
+
+        async_helpers::spawn(async move {
+            loop {
+                let result = exchange.ticker(pair, None).await;
+                let Some(price) = weak.upgrade() else {
+                    break;
+                };
+
+                match result {
+                    Ok(last) => *price.lock().unwrap() = Some(last),
+                    Err(e) => {
+                        tracing::warn!("{}: failed to get ticker of {:?}: {}", E::NAME, pair, e)
+                    }
+                }
+                drop(price);
+
+                async_helpers::sleep(LAST_PRICE_INTERVAL).await;
+            }
+        });
+
+        Self { price }
+    }
+
+    pub fn get(&self) -> Option<Decimal> {
+        *self.price.lock().unwrap()
+    }
+
+    /// The price as a title suffix, empty until the first poll finished.
+    pub fn title_suffix(&self) -> String {
+        self.get()
+            .map(|price| format!(" {}", price.normalize()))
+            .unwrap_or_default()
+    }
+}
+
 #[macro_export]
 macro_rules! include_style {
     ($($key:ident: $file:expr),*) => {
@@ -4,8 +4,40 @@ mod orderbook;
 pub use orderbook::*;
 mod dummy;
 pub use dummy::*;
+mod depth;
+pub use depth::*;
+mod candle_chart;
+pub use candle_chart::*;
+mod trades;
+pub use trades::*;
+mod portfolio;
+pub use portfolio::*;
+mod alerts;
+pub use alerts::*;
+mod console;
+pub use console::*;
+mod secrets;
+pub use secrets::*;
+mod settings;
+pub use settings::*;
+mod health;
+pub use health::*;
+mod order_toasts;
+pub use order_toasts::*;
+mod report;
+pub use report::*;
 
 use dioxus::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::exchange::Exchanges;
+
+/// Describes how to re-create a widget, which is used to persist the layout.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WidgetDescriptor {
+    pub name: String,
+    pub params: serde_json::Value,
+}
 
 /// A trait for all widgets
 pub trait Widget {
@@ -17,6 +49,14 @@ pub trait Widget {
         // We assume that the widget is changed after render by default
         true
     }
+
+    /// Returns the descriptor to re-create the widget, or None if the widget can not be restored
+    fn descriptor(&self) -> Option<WidgetDescriptor> {
+        None
+    }
+
+    /// Called when the window of the widget is closed, to drop what the widget persisted.
+    fn on_close(&self) {}
 }
 
 #[derive(Clone)]
@@ -31,6 +71,28 @@ where
     }
 }
 
+impl BoxedWidget {
+    pub fn to_descriptor(&self) -> Option<WidgetDescriptor> {
+        self.0.descriptor()
+    }
+
+    pub fn from_descriptor(descriptor: &WidgetDescriptor, exchanges: &Exchanges) -> Option<Self> {
+        match descriptor.name.as_str() {
+            OrderbookWidget::NAME => OrderbookWidget::from_descriptor(descriptor, exchanges),
+            DepthWidget::NAME => DepthWidget::from_descriptor(descriptor, exchanges),
+            CandleChartWidget::NAME => CandleChartWidget::from_descriptor(descriptor, exchanges),
+            TradesWidget::NAME => TradesWidget::from_descriptor(descriptor, exchanges),
+            PortfolioWidget::NAME => PortfolioWidget::from_descriptor(descriptor, exchanges),
+            AlertsWidget::NAME => AlertsWidget::from_descriptor(descriptor, exchanges),
+            ConsoleWidget::NAME => ConsoleWidget::from_descriptor(descriptor, exchanges),
+            SettingsWidget::NAME => SettingsWidget::from_descriptor(descriptor, exchanges),
+            HealthWidget::NAME => HealthWidget::from_descriptor(descriptor, exchanges),
+            Dummy::NAME => Some(Dummy::new().into()),
+            _ => None,
+        }
+    }
+}
+
 impl PartialEq for BoxedWidget {
     fn eq(&self, other: &Self) -> bool {
         Arc::ptr_eq(&self.0, &other.0) && !self.0.is_changed_after_render()
@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+use crate::alert::{Alerts, Direction};
+use crate::exchange::Exchanges;
+use crate::utils::async_helpers;
+
+use super::{BoxedWidget, Widget, WidgetDescriptor};
+
+use dioxus::prelude::*;
+
+/// Lists the active alerts with a button to delete each.
+pub struct AlertsWidget;
+
+impl AlertsWidget {
+    pub const NAME: &'static str = "Alerts";
+
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn from_descriptor(
+        _descriptor: &WidgetDescriptor,
+        _exchanges: &Exchanges,
+    ) -> Option<BoxedWidget> {
+        Some(AlertsWidget::new().into())
+    }
+}
+
+impl Widget for AlertsWidget {
+    fn render(&self) -> Element {
+        // Alerts are added from the command palette and fire in the background,
+        // so the list is polled instead of being pushed.
+        let mut alerts = use_signal(|| Alerts::instance().list());
+        use_future(move || async move {
+            loop {
+                async_helpers::sleep(Duration::from_secs(1)).await;
+                let latest = Alerts::instance().list();
+                if *alerts.peek() != latest {
+                    alerts.set(latest);
+                }
+            }
+        });
+
+        let rows = alerts
+            .read()
+            .iter()
+            .map(|alert| {
+                let sign = match alert.direction {
+                    Direction::Above => ">",
+                    Direction::Below => "<",
+                };
+                let fired = if alert.armed { "" } else { " (fired)" };
+                let label = format!(
+                    "{} {}-{} {} {}{}",
+                    alert.exchange, alert.pair.0, alert.pair.1, sign, alert.price, fired
+                );
+                (alert.id, label)
+            })
+            .collect::<Vec<_>>();
+
+        rsx! {
+            ul { class: "font2 font-color-main", style: "list-style: none; padding: 0; margin: 0;",
+                if rows.is_empty() {
+                    li { style: "padding: 4px 10px;", "No alerts" }
+                }
+                for (id, label) in rows.into_iter() {
+                    li { style: "display: flex; justify-content: space-between; padding: 4px 10px;",
+                        span { "{label}" }
+                        button {
+                            class: "font-color-main color-3",
+                            style: "border: none; cursor: pointer;",
+                            onclick: move |_| {
+                                Alerts::instance().remove(id);
+                                alerts.set(Alerts::instance().list());
+                            },
+                            "Delete"
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn name(&self) -> String {
+        "Alerts".to_string()
+    }
+
+    fn descriptor(&self) -> Option<WidgetDescriptor> {
+        Some(WidgetDescriptor {
+            name: Self::NAME.to_string(),
+            params: serde_json::Value::Null,
+        })
+    }
+}
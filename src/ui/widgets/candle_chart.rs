@@ -0,0 +1,349 @@
+use std::time::Duration;
+
+use futures::FutureExt;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    currency::Currency,
+    exchange::{
+        candle::{self, CandleAggregator},
+        execute_if, Exchange, Exchanges, RealtimeData, Ticker, Trade,
+    },
+    select_ex,
+    ui::utils::LastPrice,
+    utils::{broadcaster::Subscription, format::format_price, Decimal},
+};
+
+use super::{BoxedWidget, Widget, WidgetDescriptor};
+
+use dioxus::prelude::*;
+
+// Size of the svg view box, the chart is stretched to fill the widget.
+const WIDTH: f64 = 1000.0;
+const HEIGHT: f64 = 400.0;
+
+/// Number of candle slots, the newest candle is drawn at the right edge.
+const VISIBLE_CANDLES: usize = 100;
+
+/// Trades kept to rebuild the candles when the interval changes.
+const TRADE_LIMIT: usize = 10_000;
+
+const INTERVALS: &[(&str, Duration)] = &[
+    ("1m", Duration::from_secs(60)),
+    ("5m", Duration::from_secs(5 * 60)),
+    ("15m", Duration::from_secs(15 * 60)),
+    ("1h", Duration::from_secs(60 * 60)),
+];
+
+/// Index of the interval selected when the widget opens.
+/// Exchanges serve 10 or 15 minute history, which can be merged into 15 minute candles or coarser.
+const DEFAULT_INTERVAL: usize = 2;
+
+/// Candlestick chart fed by the candle history and the live trades.
+pub struct CandleChartWidget {
+    pair: (Currency, Currency),
+    exchange_name: String,
+    exchanges: Exchanges,
+    subscription: Subscription<RealtimeData>,
+    last_price: LastPrice,
+}
+
+impl CandleChartWidget {
+    pub const NAME: &'static str = "CandleChart";
+
+    pub fn new(
+        exchange_name: &str,
+        pair: (Currency, Currency),
+        exchanges: Exchanges,
+    ) -> Option<Self> {
+        let name = exchange_name.to_string();
+        let (subscription, last_price) = select_ex!(exchanges, name, |exchange| {
+            (
+                exchange.subscribe(pair, None),
+                LastPrice::watch(exchange, pair),
+            )
+        })?;
+
+        Some(Self {
+            pair,
+            exchange_name: name,
+            exchanges,
+            subscription,
+            last_price,
+        })
+    }
+
+    pub fn from_descriptor(
+        descriptor: &WidgetDescriptor,
+        exchanges: &Exchanges,
+    ) -> Option<BoxedWidget> {
+        let params: CandleChartParams = serde_json::from_value(descriptor.params.clone()).ok()?;
+        CandleChartWidget::new(&params.exchange, params.pair, exchanges.clone()).map(Into::into)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CandleChartParams {
+    exchange: String,
+    pair: (Currency, Currency),
+}
+
+/// Builds the candles of `interval` from the fetched history and the trades received since.
+fn build(history: &[Ticker], trades: &[Trade], interval: Duration) -> CandleAggregator {
+    // History coarser than the interval can not be split, the candles then come from trades only
+    let history = candle::resample(history, interval).unwrap_or_default();
+    let mut chart = CandleAggregator::new(interval, history);
+    for trade in trades {
+        chart.push(trade);
+    }
+    chart
+}
+
+fn readout(ticker: &Ticker) -> String {
+    let time = chrono::DateTime::from_timestamp_millis(ticker.timestamp as i64)
+        .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default();
+    format!(
+        "{} O {} H {} L {} C {}",
+        time,
+        format_price(ticker.open, None),
+        format_price(ticker.high, None),
+        format_price(ticker.low, None),
+        format_price(ticker.close, None)
+    )
+}
+
+/// Maps prices into the view box.
+struct Scale {
+    min_price: f64,
+    price_range: f64,
+}
+
+impl Scale {
+    fn y(&self, price: Decimal) -> f64 {
+        HEIGHT - (price.to_f64_lossy() - self.min_price) / self.price_range * HEIGHT
+    }
+}
+
+/// Svg geometry of a single candle.
+struct Shape {
+    x: f64,
+    center: f64,
+    body_y: f64,
+    body_height: f64,
+    high_y: f64,
+    low_y: f64,
+    close_y: f64,
+    color: &'static str,
+    ticker: Ticker,
+}
+
+impl Widget for CandleChartWidget {
+    fn render(&self) -> Element {
+        let subscription = self.subscription.clone();
+        let exchanges = self.exchanges.clone();
+        let exchange_name = self.exchange_name.clone();
+        let pair = self.pair;
+
+        let mut interval = use_signal(|| INTERVALS[DEFAULT_INTERVAL].1);
+        let mut history = use_signal(Vec::<Ticker>::new);
+        let mut trades = use_signal(Vec::<Trade>::new);
+        let mut chart =
+            use_signal(|| CandleAggregator::new(INTERVALS[DEFAULT_INTERVAL].1, Vec::new()));
+        let mut error = use_signal(|| None::<String>);
+        let mut hovered = use_signal(|| None::<(f64, f64, Ticker)>);
+
+        use_future(move || {
+            let exchanges = exchanges.clone();
+            let exchange_name = exchange_name.clone();
+            async move {
+                let fetch = select_ex!(exchanges, exchange_name, |exchange| {
+                    async move {
+                        exchange
+                            .candlesticks(pair, None)
+                            .await
+                            .map(|candles| candles.tickers)
+                            .map_err(|e| e.to_string())
+                    }
+                    .boxed_local()
+                });
+                let Some(fetch) = fetch else {
+                    return;
+                };
+
+                match fetch.await {
+                    Ok(tickers) => {
+                        history.set(tickers);
+                        chart.set(build(&history.peek(), &trades.peek(), *interval.peek()));
+                    }
+                    Err(e) => error.set(Some(e)),
+                }
+            }
+        });
+
+        use_future(move || {
+            let subscription = subscription.clone();
+            async move {
+                loop {
+                    let RealtimeData::Trade(trade) = subscription.recv().await else {
+                        continue;
+                    };
+                    if trade.pair != pair {
+                        continue;
+                    }
+
+                    chart.write().push(&trade);
+                    let mut received = trades.write();
+                    received.push(trade);
+                    if received.len() > TRADE_LIMIT {
+                        let excess = received.len() - TRADE_LIMIT;
+                        received.drain(..excess);
+                    }
+                }
+            }
+        });
+
+        let selected = *interval.read();
+        let buttons = INTERVALS
+            .iter()
+            .map(|(label, duration)| {
+                let opacity = if *duration == selected { "1" } else { "0.5" };
+                (*label, *duration, opacity)
+            })
+            .collect::<Vec<_>>();
+
+        let candles = chart.read().tickers().cloned().collect::<Vec<_>>();
+        let visible = &candles[candles.len().saturating_sub(VISIBLE_CANDLES)..];
+
+        let min_price = visible.iter().map(|ticker| ticker.low).min();
+        let max_price = visible.iter().map(|ticker| ticker.high).max();
+        let scale = Scale {
+            min_price: min_price.map(Decimal::to_f64_lossy).unwrap_or_default(),
+            price_range: (max_price.map(Decimal::to_f64_lossy).unwrap_or_default()
+                - min_price.map(Decimal::to_f64_lossy).unwrap_or_default())
+            .max(f64::EPSILON),
+        };
+
+        let slot = WIDTH / VISIBLE_CANDLES as f64;
+        let offset = VISIBLE_CANDLES - visible.len();
+        let shapes = visible
+            .iter()
+            .enumerate()
+            .map(|(idx, ticker)| {
+                let x = (offset + idx) as f64 * slot;
+                let open_y = scale.y(ticker.open);
+                let close_y = scale.y(ticker.close);
+                let color = if ticker.close >= ticker.open {
+                    "var(--bid)"
+                } else {
+                    "var(--ask)"
+                };
+
+                Shape {
+                    x,
+                    center: x + slot / 2.0,
+                    body_y: open_y.min(close_y),
+                    body_height: (open_y - close_y).abs().max(1.0),
+                    high_y: scale.y(ticker.high),
+                    low_y: scale.y(ticker.low),
+                    close_y,
+                    color,
+                    ticker: ticker.clone(),
+                }
+            })
+            .collect::<Vec<_>>();
+        let body_width = slot * 0.7;
+
+        // The crosshair follows the hovered candle and its close
+        let crosshair = hovered.read().as_ref().map(|(x, y, _)| (*x, *y));
+        let status = match (
+            error.read().as_ref(),
+            hovered.read().as_ref(),
+            visible.last(),
+        ) {
+            (Some(e), _, _) => format!("Failed to load candles: {}", e),
+            (None, Some((_, _, ticker)), _) => readout(ticker),
+            (None, None, Some(ticker)) => readout(ticker),
+            (None, None, None) => "Waiting for trades...".to_string(),
+        };
+
+        rsx! {
+            div { class: "font2 font-color-main", style: "display: flex; justify-content: space-between; padding: 4px 10px; height: 20px;",
+                span { "{status}" }
+                div {
+                    for (label, duration, opacity) in buttons.into_iter() {
+                        button {
+                            class: "font-color-main color-3",
+                            style: "border: none; cursor: pointer; margin-left: 4px; opacity: {opacity};",
+                            onclick: move |_| {
+                                interval.set(duration);
+                                hovered.set(None);
+                                chart.set(build(&history.peek(), &trades.peek(), duration));
+                            },
+                            "{label}"
+                        }
+                    }
+                }
+            }
+            svg {
+                view_box: "0 0 {WIDTH} {HEIGHT}",
+                preserve_aspect_ratio: "none",
+                style: "width: 100%; height: 100%;",
+                onmouseleave: move |_| hovered.set(None),
+
+                for shape in shapes.into_iter() {
+                    line {
+                        x1: "{shape.center}",
+                        y1: "{shape.high_y}",
+                        x2: "{shape.center}",
+                        y2: "{shape.low_y}",
+                        style: "stroke: {shape.color};"
+                    }
+                    rect {
+                        x: "{shape.center - body_width / 2.0}",
+                        y: "{shape.body_y}",
+                        width: "{body_width}",
+                        height: "{shape.body_height}",
+                        style: "fill: {shape.color};"
+                    }
+                    rect {
+                        x: "{shape.x}",
+                        y: "0",
+                        width: "{slot}",
+                        height: "{HEIGHT}",
+                        fill: "transparent",
+                        onmouseenter: move |_| {
+                            hovered.set(Some((shape.center, shape.close_y, shape.ticker.clone())))
+                        }
+                    }
+                }
+                for (x, y) in crosshair.into_iter() {
+                    line { x1: "{x}", y1: "0", x2: "{x}", y2: "{HEIGHT}", stroke: "#888888", stroke_dasharray: "4 4", pointer_events: "none" }
+                    line { x1: "0", y1: "{y}", x2: "{WIDTH}", y2: "{y}", stroke: "#888888", stroke_dasharray: "4 4", pointer_events: "none" }
+                }
+            }
+        }
+    }
+
+    fn name(&self) -> String {
+        format!(
+            "{} {}-{} chart{}",
+            self.exchange_name,
+            self.pair.0,
+            self.pair.1,
+            self.last_price.title_suffix()
+        )
+    }
+
+    fn descriptor(&self) -> Option<WidgetDescriptor> {
+        let params = CandleChartParams {
+            exchange: self.exchange_name.clone(),
+            pair: self.pair,
+        };
+
+        Some(WidgetDescriptor {
+            name: Self::NAME.to_string(),
+            params: serde_json::to_value(params).ok()?,
+        })
+    }
+}
@@ -0,0 +1,450 @@
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::exchange::order_watch::OrderWatcher;
+use crate::exchange::Exchanges;
+use crate::ui::palette::History;
+use crate::utils::{async_helpers, storage};
+use crate::vm::completion::Completer;
+use crate::vm::console::Console;
+
+use super::{BoxedWidget, Widget, WidgetDescriptor};
+
+use dioxus::prelude::*;
+use serde::{Deserialize, Serialize};
+
+const HISTORY_KEY: &str = "console_history";
+
+/// A changed scrollback is saved once no line arrived for this long,
+/// the whole scrollback is written each time.
+const SAVE_QUIET: Duration = Duration::from_secs(2);
+/// Consoles printing without a pause are saved this often.
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+const INPUT_COLOR: &str = "#939faf";
+const OUTPUT_COLOR: &str = "inherit";
+const RESULT_COLOR: &str = "#228a44";
+const ERROR_COLOR: &str = "#a63654";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum LineKind {
+    Input,
+    Output,
+    Result,
+    Error,
+}
+
+impl LineKind {
+    fn color(self) -> &'static str {
+        match self {
+            LineKind::Input => INPUT_COLOR,
+            LineKind::Output => OUTPUT_COLOR,
+            LineKind::Result => RESULT_COLOR,
+            LineKind::Error => ERROR_COLOR,
+        }
+    }
+}
+
+/// Lines of the output area, the oldest are dropped past the limit.
+#[derive(Debug, Clone, PartialEq)]
+struct Scrollback {
+    lines: VecDeque<(LineKind, String)>,
+    limit: usize,
+    /// Lines dropped so far, the first kept line is number `dropped`.
+    dropped: u64,
+}
+
+impl Scrollback {
+    fn new(limit: usize) -> Self {
+        Self {
+            lines: VecDeque::new(),
+            limit,
+            dropped: 0,
+        }
+    }
+
+    /// Restores the saved lines, trimmed to the limit.
+    fn parse(text: &str, limit: usize) -> Self {
+        let mut scrollback = Self::new(limit);
+        let lines: Vec<(LineKind, String)> = serde_json::from_str(text).unwrap_or_else(|e| {
+            tracing::warn!("Discarding unreadable console scrollback: {}", e);
+            Vec::new()
+        });
+        for (kind, text) in lines {
+            scrollback.push(kind, text);
+        }
+        scrollback
+    }
+
+    fn push(&mut self, kind: LineKind, text: String) {
+        self.lines.push_back((kind, text));
+        while self.lines.len() > self.limit {
+            self.lines.pop_front();
+            self.dropped += 1;
+        }
+    }
+
+    /// The newest `count` lines, newest first, with their line numbers to key the rows.
+    fn newest(&self, count: usize) -> impl Iterator<Item = (u64, LineKind, &str)> {
+        let first = self.dropped;
+        self.lines
+            .iter()
+            .enumerate()
+            .rev()
+            .take(count)
+            .map(move |(index, (kind, text))| (first + index as u64, *kind, text.as_str()))
+    }
+
+    fn to_json(&self) -> String {
+        serde_json::to_string(&self.lines).expect("lines are serializable")
+    }
+}
+
+fn storage_key(id: &uuid::Uuid) -> String {
+    format!("console_scrollback_{}", id)
+}
+
+fn input_id(id: &uuid::Uuid) -> String {
+    format!("console_input_{}", id)
+}
+
+/// Keeps Tab in the input, which would move the focus, and sends the value with the cursor.
+/// The cursor is placed after the completed word once it is sent back.
+fn tab_script(input_id: &str) -> String {
+    format!(
+        r#"
+        const input = document.getElementById("{input_id}");
+        input.addEventListener("keydown", (e) => {{
+            if (e.key !== "Tab") {{
+                return;
+            }}
+            e.preventDefault();
+            dioxus.send([input.value, input.selectionStart]);
+        }});
+        while (true) {{
+            const cursor = await dioxus.recv();
+            requestAnimationFrame(() => input.setSelectionRange(cursor, cursor));
+        }}
+        "#
+    )
+}
+
+fn parse_tab(value: &serde_json::Value) -> Option<(&str, usize)> {
+    let [text, cursor] = value.as_array()?.as_slice() else {
+        return None;
+    };
+    Some((text.as_str()?, cursor.as_u64()? as usize))
+}
+
+/// Evaluates scripts against the exchanges and shows what they print.
+pub struct ConsoleWidget {
+    /// Kept in the layout, so the saved scrollback is found again when the layout is restored.
+    id: uuid::Uuid,
+    exchanges: Exchanges,
+}
+
+impl ConsoleWidget {
+    pub const NAME: &'static str = "Console";
+
+    pub fn new(exchanges: Exchanges) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4(),
+            exchanges,
+        }
+    }
+
+    pub fn from_descriptor(
+        descriptor: &WidgetDescriptor,
+        exchanges: &Exchanges,
+    ) -> Option<BoxedWidget> {
+        let mut widget = ConsoleWidget::new(exchanges.clone());
+        // Layouts saved before consoles had an id, or with a broken one, get a fresh scrollback
+        match descriptor.params["id"].as_str().map(str::parse) {
+            Some(Ok(id)) => widget.id = id,
+            Some(Err(e)) => tracing::warn!("Console with an invalid id, starting afresh: {}", e),
+            None => {}
+        }
+        Some(widget.into())
+    }
+}
+
+fn push(mut scrollback: Signal<Scrollback>, mut pushed: Signal<u64>, kind: LineKind, text: String) {
+    scrollback.write().push(kind, text);
+    pushed += 1;
+}
+
+/// Echoes the line and evaluates it in the background, the result is pushed when it is done.
+fn evaluate(
+    console: Rc<Console>,
+    line: String,
+    scrollback: Signal<Scrollback>,
+    pushed: Signal<u64>,
+) {
+    push(scrollback, pushed, LineKind::Input, format!("> {}", line));
+    spawn(async move {
+        match console.evaluate(&line).await {
+            Ok(value) => push(scrollback, pushed, LineKind::Result, value),
+            Err(e) => push(scrollback, pushed, LineKind::Error, e),
+        }
+    });
+}
+
+impl Widget for ConsoleWidget {
+    fn render(&self) -> Element {
+        let exchanges = self.exchanges.clone();
+        let (console, output) = use_hook(move || {
+            let (console, output) = Console::new(&exchanges);
+            (Rc::new(console), output)
+        });
+
+        let id = self.id;
+        let config = Config::get().console;
+        let scrollback = use_signal(|| {
+            let saved = config
+                .persist_scrollback
+                .then(|| storage::read(&storage_key(&id)))
+                .flatten();
+            match saved {
+                Some(text) => Scrollback::parse(&text, config.scrollback_lines),
+                None => Scrollback::new(config.scrollback_lines),
+            }
+        });
+        // Lines pushed so far, written on every line but not read while rendering,
+        // so it does not trigger renders
+        let pushed = use_signal(|| 0u64);
+        // Rendering thousands of lines lags the page, only the newest are rendered until asked for more
+        let mut rendered = use_signal(|| config.rendered_lines());
+        let mut input = use_signal(String::new);
+        let mut history = use_signal(|| History::load_from(HISTORY_KEY));
+        let mut completer = use_signal(Completer::default);
+
+        // Scripts print from wherever they run, the output arrives through the channel
+        use_future(move || {
+            let output = output.clone();
+            async move {
+                while let Ok(text) = output.recv().await {
+                    push(scrollback, pushed, LineKind::Output, text);
+                }
+            }
+        });
+
+        use_future(move || async move {
+            if !config.persist_scrollback {
+                return;
+            }
+            let (mut saved, mut seen) = (0, 0);
+            let mut unsaved_for = Duration::ZERO;
+            loop {
+                async_helpers::sleep(SAVE_QUIET).await;
+                let count = *pushed.peek();
+                if count == saved {
+                    continue;
+                }
+                unsaved_for += SAVE_QUIET;
+                if count == seen || unsaved_for >= SAVE_INTERVAL {
+                    storage::write(&storage_key(&id), &scrollback.peek().to_json());
+                    saved = count;
+                    unsaved_for = Duration::ZERO;
+                }
+                seen = count;
+            }
+        });
+
+        use_future(move || async move {
+            let subscription = OrderWatcher::instance().subscribe();
+            loop {
+                let filled = subscription.recv().await;
+                push(scrollback, pushed, LineKind::Result, filled.to_string());
+            }
+        });
+
+        // A config edit that fails to load would otherwise only show up in the log
+        use_future(move || async move {
+            let subscription = Config::reload_errors();
+            loop {
+                let error = subscription.recv().await;
+                push(scrollback, pushed, LineKind::Error, error);
+            }
+        });
+
+        let stop_console = console.clone();
+        let mut submit = move || {
+            let line = input.peek().trim().to_string();
+            if line.is_empty() {
+                return;
+            }
+
+            history.write().push(&line);
+            input.set(String::new());
+            completer.write().reset();
+            rendered.set(config.rendered_lines());
+            evaluate(console.clone(), line, scrollback, pushed);
+        };
+
+        // Rendered newest first in a reversed column, which keeps the view scrolled to the bottom
+        let (rows, hidden) = {
+            let scrollback = scrollback.read();
+            let rows = scrollback
+                .newest(rendered())
+                .map(|(number, kind, text)| (number, kind.color(), text.to_string()))
+                .collect::<Vec<_>>();
+            let hidden = scrollback.lines.len() - rows.len();
+            (rows, hidden)
+        };
+        let more = hidden.min(config.rendered_lines());
+        let completions = completer
+            .read()
+            .candidates()
+            .map(|(candidates, index)| {
+                candidates
+                    .iter()
+                    .enumerate()
+                    .map(|(i, candidate)| {
+                        let color = if i == index { RESULT_COLOR } else { INPUT_COLOR };
+                        (color, candidate.clone())
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let input_element = input_id(&id);
+
+        rsx! {
+            div { class: "font2 font-color-main", style: "display: flex; flex-direction: column; height: 100%;",
+                div { style: "flex: 1; overflow-y: auto; display: flex; flex-direction: column-reverse; padding: 4px 10px;",
+                    for (number, color, text) in rows.into_iter() {
+                        div { key: "{number}", style: "white-space: pre-wrap; color: {color};", "{text}" }
+                    }
+                    if hidden > 0 {
+                        div {
+                            style: "cursor: pointer; padding-bottom: 4px; color: {INPUT_COLOR};",
+                            onclick: move |_| rendered += config.rendered_lines(),
+                            "Show {more} more of {hidden} older lines"
+                        }
+                    }
+                }
+                if !completions.is_empty() {
+                    div { style: "display: flex; flex-wrap: wrap; gap: 0 12px; padding: 2px 10px;",
+                        for (color, candidate) in completions.into_iter() {
+                            span { style: "color: {color};", "{candidate}" }
+                        }
+                    }
+                }
+                div { style: "display: flex;",
+                    input {
+                        id: "{input_element}",
+                        class: "font2 font-color-main color-3",
+                        style: "flex: 1; border: none; padding: 4px 10px; outline: none;",
+                        r#type: "text",
+                        placeholder: "Script...",
+                        spellcheck: "false",
+                        value: "{input}",
+
+                        oninput: move |event| {
+                            input.set(event.value());
+                            history.write().reset();
+                            completer.write().reset();
+                        },
+
+                        onmounted: move |_| {
+                            let mut tab = eval(&tab_script(&input_id(&id)));
+                            spawn(async move {
+                                while let Ok(value) = tab.recv().await {
+                                    let Some((text, cursor)) = parse_tab(&value) else {
+                                        continue;
+                                    };
+                                    let completed = completer.write().complete(text, cursor);
+                                    if let Some((completed, cursor)) = completed {
+                                        input.set(completed);
+                                        let _ = tab.send(cursor.into());
+                                    }
+                                }
+                            });
+                        },
+
+                        onkeydown: move |event| {
+                            match event.key() {
+                                Key::Enter => submit(),
+                                Key::ArrowUp => {
+                                    let entry = history.write().prev().map(str::to_string);
+                                    if let Some(entry) = entry {
+                                        input.set(entry);
+                                    }
+                                }
+                                Key::ArrowDown => {
+                                    let entry = history.write().next().map(str::to_string);
+                                    input.set(entry.unwrap_or_default());
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                    button {
+                        class: "font2 color-3",
+                        style: "border: none; cursor: pointer; color: {ERROR_COLOR};",
+                        title: "Stop the scripts of this console and cancel their open orders",
+                        onclick: move |_| {
+                            evaluate(stop_console.clone(), "stopall".to_string(), scrollback, pushed)
+                        },
+                        "Stop all"
+                    }
+                }
+            }
+        }
+    }
+
+    fn name(&self) -> String {
+        "Console".to_string()
+    }
+
+    fn descriptor(&self) -> Option<WidgetDescriptor> {
+        Some(WidgetDescriptor {
+            name: Self::NAME.to_string(),
+            params: serde_json::json!({ "id": self.id.to_string() }),
+        })
+    }
+
+    fn on_close(&self) {
+        storage::remove(&storage_key(&self.id));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{LineKind, Scrollback};
+
+    #[test]
+    fn scrollback_keeps_the_newest_lines() {
+        let mut scrollback = Scrollback::new(2);
+        scrollback.push(LineKind::Input, "> 1 + 1".to_string());
+        scrollback.push(LineKind::Result, "2".to_string());
+        scrollback.push(LineKind::Error, "oops".to_string());
+        assert_eq!(scrollback.lines.len(), 2);
+        assert_eq!(scrollback.lines[0], (LineKind::Result, "2".to_string()));
+
+        // Restored with a smaller limit, the oldest saved lines are dropped
+        let restored = Scrollback::parse(&scrollback.to_json(), 1);
+        assert_eq!(
+            restored.lines.into_iter().collect::<Vec<_>>(),
+            vec![(LineKind::Error, "oops".to_string())]
+        );
+        assert_eq!(Scrollback::parse("not json", 10), Scrollback::new(10));
+    }
+
+    #[test]
+    fn newest_lines_keep_their_numbers() {
+        let mut scrollback = Scrollback::new(3);
+        for line in 0..5 {
+            scrollback.push(LineKind::Output, line.to_string());
+        }
+
+        let newest = scrollback.newest(2).collect::<Vec<_>>();
+        assert_eq!(
+            newest,
+            vec![(4, LineKind::Output, "4"), (3, LineKind::Output, "3")]
+        );
+        assert_eq!(scrollback.newest(10).count(), 3);
+    }
+}
@@ -0,0 +1,218 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    currency::Currency,
+    exchange::{execute_if, Exchange, Exchanges, RealtimeData, Unit},
+    select_ex,
+    utils::{broadcaster::Subscription, Decimal},
+};
+
+use super::{BoxedWidget, Widget, WidgetDescriptor};
+
+use dioxus::prelude::*;
+
+// Size of the svg view box, the chart is stretched to fill the widget.
+const WIDTH: f64 = 1000.0;
+const HEIGHT: f64 = 400.0;
+
+/// Two-sided area chart of the cumulative bid and ask amounts.
+pub struct DepthWidget {
+    pair: (Currency, Currency),
+    exchange_name: String,
+    subscription: Subscription<RealtimeData>,
+}
+
+impl DepthWidget {
+    pub const NAME: &'static str = "Depth";
+
+    pub fn new<E>(pair: (Currency, Currency), exchange: Arc<E>) -> Self
+    where
+        E: Exchange + 'static,
+    {
+        Self {
+            pair,
+            exchange_name: E::NAME.to_string(),
+            // Only the latest book of the pair is rendered
+            subscription: exchange.subscribe(pair, None).filter(
+                move |data| matches!(data, RealtimeData::Orderbook(book) if book.pair == pair),
+            ),
+        }
+    }
+
+    pub fn from_descriptor(
+        descriptor: &WidgetDescriptor,
+        exchanges: &Exchanges,
+    ) -> Option<BoxedWidget> {
+        let params: DepthParams = serde_json::from_value(descriptor.params.clone()).ok()?;
+        // The book of an exchange without realtime streams would stay empty
+        select_ex!(exchanges, params.exchange, |exchange| {
+            exchange
+                .streams_realtime()
+                .then(|| BoxedWidget::from(DepthWidget::new(params.pair, exchange)))
+        })
+        .flatten()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct DepthParams {
+    exchange: String,
+    pair: (Currency, Currency),
+}
+
+/// Maps prices and cumulative amounts into the view box.
+struct Scale {
+    min_price: f64,
+    price_range: f64,
+    max_amount: f64,
+}
+
+impl Scale {
+    fn x(&self, price: Decimal) -> f64 {
+        (price.to_f64_lossy() - self.min_price) / self.price_range * WIDTH
+    }
+
+    fn y(&self, amount: Decimal) -> f64 {
+        HEIGHT - amount.to_f64_lossy() / self.max_amount * HEIGHT
+    }
+
+    /// Svg path of the step area under a cumulative side, starting from the best price.
+    fn area(&self, units: &[Unit]) -> String {
+        let Some(first) = units.first() else {
+            return String::new();
+        };
+
+        let mut path = format!("M {} {}", self.x(first.price), HEIGHT);
+        let mut last_y = HEIGHT;
+        for unit in units {
+            let x = self.x(unit.price);
+            path += &format!(" L {} {} L {} {}", x, last_y, x, self.y(unit.amount));
+            last_y = self.y(unit.amount);
+        }
+
+        let last = units.last().unwrap();
+        path += &format!(" L {} {} Z", self.x(last.price), HEIGHT);
+        path
+    }
+
+    /// Hover bands of each level, from its price to the price of the next level.
+    fn bands(&self, units: &[Unit]) -> Vec<(f64, f64, Unit)> {
+        units
+            .iter()
+            .enumerate()
+            .map(|(idx, unit)| {
+                let x = self.x(unit.price);
+                let next = units.get(idx + 1).map(|next| self.x(next.price));
+                let (from, to) = match next {
+                    Some(next) => (x.min(next), x.max(next)),
+                    None => (x, x),
+                };
+
+                // Keep the outermost level hoverable
+                (from, (to - from).max(4.0), unit.clone())
+            })
+            .collect()
+    }
+}
+
+impl Widget for DepthWidget {
+    fn render(&self) -> Element {
+        let subscription = self.subscription.clone();
+        let mut hovered = use_signal(|| None::<(bool, Unit)>);
+
+        let mut data = use_resource(move || {
+            let subscription = subscription.clone();
+            async move {
+                loop {
+                    if let RealtimeData::Orderbook(value) = subscription.recv_latest().await {
+                        return value;
+                    }
+                }
+            }
+        });
+
+        if data.finished() {
+            data.restart();
+        }
+
+        let data = data.read();
+        let orderbook = data.as_ref()?;
+        let (bids, asks) = orderbook.cumulative();
+
+        let min_price = bids.last()?.price.to_f64_lossy();
+        let max_price = asks.last()?.price.to_f64_lossy();
+        let scale = Scale {
+            min_price,
+            price_range: (max_price - min_price).max(f64::EPSILON),
+            max_amount: bids
+                .last()?
+                .amount
+                .max(asks.last()?.amount)
+                .to_f64_lossy()
+                .max(f64::EPSILON),
+        };
+
+        let bid_area = scale.area(&bids);
+        let ask_area = scale.area(&asks);
+        let bands = scale
+            .bands(&bids)
+            .into_iter()
+            .map(|band| (true, band))
+            .chain(scale.bands(&asks).into_iter().map(|band| (false, band)))
+            .collect::<Vec<_>>();
+
+        let readout = match hovered.read().as_ref() {
+            Some((is_bid, unit)) => {
+                let side = if *is_bid { "Bid" } else { "Ask" };
+                format!("{} {} cumulative {}", side, unit.price, unit.amount)
+            }
+            None => String::new(),
+        };
+
+        rsx! {
+            div { class: "font2 font-color-main", style: "padding: 4px 10px; height: 20px;",
+                "{readout}"
+            }
+            svg {
+                view_box: "0 0 {WIDTH} {HEIGHT}",
+                preserve_aspect_ratio: "none",
+                style: "width: 100%; height: 100%;",
+                onmouseleave: move |_| hovered.set(None),
+
+                path { d: "{bid_area}", style: "fill: var(--bid-fill); stroke: var(--bid);" }
+                path { d: "{ask_area}", style: "fill: var(--ask-fill); stroke: var(--ask);" }
+                for (is_bid, (x, width, unit)) in bands.into_iter() {
+                    rect {
+                        x: "{x}",
+                        y: "0",
+                        width: "{width}",
+                        height: "{HEIGHT}",
+                        fill: "transparent",
+                        onmouseenter: move |_| hovered.set(Some((is_bid, unit.clone())))
+                    }
+                }
+            }
+        }
+    }
+
+    fn name(&self) -> String {
+        format!(
+            "{} {}-{} depth",
+            self.exchange_name, self.pair.0, self.pair.1
+        )
+    }
+
+    fn descriptor(&self) -> Option<WidgetDescriptor> {
+        let params = DepthParams {
+            exchange: self.exchange_name.clone(),
+            pair: self.pair,
+        };
+
+        Some(WidgetDescriptor {
+            name: Self::NAME.to_string(),
+            params: serde_json::to_value(params).ok()?,
+        })
+    }
+}
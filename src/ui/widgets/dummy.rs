@@ -1,4 +1,4 @@
-use super::Widget;
+use super::{Widget, WidgetDescriptor};
 
 use dioxus::prelude::*;
 
@@ -7,6 +7,8 @@ pub struct Dummy {
 }
 
 impl Dummy {
+    pub const NAME: &'static str = "Dummy";
+
     pub fn new() -> Self {
         Self {
             uuid: uuid::Uuid::new_v4(),
@@ -30,4 +32,11 @@ impl Widget for Dummy {
     fn is_changed_after_render(&self) -> bool {
         false
     }
+
+    fn descriptor(&self) -> Option<WidgetDescriptor> {
+        Some(WidgetDescriptor {
+            name: Self::NAME.to_string(),
+            params: serde_json::Value::Null,
+        })
+    }
 }
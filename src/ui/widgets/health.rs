@@ -0,0 +1,212 @@
+use std::time::Duration;
+
+use crate::exchange::health::{ExchangeHealth, HealthMonitor};
+use crate::exchange::Exchanges;
+use crate::ui::sub_window::SubWindowMgrState;
+use crate::utils::async_helpers;
+use crate::websocket::ConnectionStatus;
+
+use super::{BoxedWidget, Widget, WidgetDescriptor};
+
+use dioxus::prelude::*;
+
+/// Rate limit usage shown as a warning.
+const RATE_LIMIT_WARNING: f64 = 0.8;
+
+/// Color and text of the indicator of an exchange.
+fn indicator(health: &ExchangeHealth) -> (&'static str, String) {
+    let (mut color, connection) = match health.connection {
+        Some(ConnectionStatus::Connected { .. }) => ("var(--bid)", "connected"),
+        Some(ConnectionStatus::Stale { .. }) => ("var(--ask)", "stale"),
+        Some(ConnectionStatus::Reconnecting) => ("var(--ask)", "reconnecting"),
+        None => ("inherit", "rest"),
+    };
+
+    let mut parts = vec![format!("{} {}", health.exchange, connection)];
+    if let Some(rate_limit) = health.rate_limit {
+        parts.push(format!("{:.0}%", rate_limit * 100.0));
+        if rate_limit >= RATE_LIMIT_WARNING {
+            color = "var(--ask)";
+        }
+    }
+    if let Some(drift) = health.clock_drift {
+        parts.push(format!("{:+}ms", drift));
+        if health.is_drift_beyond_window() {
+            color = "var(--ask)";
+        }
+    }
+    if health.last_error.is_some() {
+        parts.push("!".to_string());
+    }
+    (color, parts.join(" "))
+}
+
+/// One indicator per exchange along the bottom of the main window,
+/// clicking one opens the recent errors of the exchange.
+#[component]
+pub fn HealthStrip() -> Element {
+    let exchanges = use_context::<Exchanges>();
+    use_hook(move || {
+        let monitor = HealthMonitor::instance();
+        monitor.watch(exchanges.upbit.clone());
+        monitor.watch(exchanges.binance.clone());
+        monitor.watch(exchanges.bithumb.clone());
+        monitor.watch(exchanges.okx.clone());
+    });
+
+    let mut states = use_signal(Vec::<ExchangeHealth>::new);
+    use_future(move || async move {
+        let subscription = HealthMonitor::instance().subscribe();
+        loop {
+            let health = subscription.recv().await;
+            let mut states = states.write();
+            match states.iter_mut().find(|s| s.exchange == health.exchange) {
+                Some(state) => *state = health,
+                None => {
+                    states.push(health);
+                    states.sort_by_key(|state| state.exchange);
+                }
+            }
+        }
+    });
+
+    let indicators = states
+        .read()
+        .iter()
+        .map(|health| {
+            let (color, text) = indicator(health);
+            (health.exchange, color, text)
+        })
+        .collect::<Vec<_>>();
+
+    rsx! {
+        div { class: "font2 font-size-12 color-1", style: "display: flex; gap: 16px; padding: 2px 10px; flex-shrink: 0;",
+            for (exchange, color, text) in indicators.into_iter() {
+                span {
+                    class: "unselectable",
+                    style: "color: {color}; cursor: pointer;",
+                    onclick: move |_| SubWindowMgrState::open(HealthWidget::new(exchange).into()),
+                    "{text}"
+                }
+            }
+        }
+    }
+}
+
+/// Recent failed requests of an exchange.
+pub struct HealthWidget {
+    exchange: String,
+}
+
+impl HealthWidget {
+    pub const NAME: &'static str = "Health";
+
+    pub fn new(exchange: &str) -> Self {
+        Self {
+            exchange: exchange.to_string(),
+        }
+    }
+
+    pub fn from_descriptor(
+        descriptor: &WidgetDescriptor,
+        _exchanges: &Exchanges,
+    ) -> Option<BoxedWidget> {
+        let exchange = descriptor.params["exchange"].as_str()?;
+        Some(HealthWidget::new(exchange).into())
+    }
+}
+
+impl Widget for HealthWidget {
+    fn render(&self) -> Element {
+        let exchange = self.exchange.clone();
+        let mut errors = use_signal(|| HealthMonitor::instance().recent_errors(&exchange));
+        use_future(move || {
+            let exchange = exchange.clone();
+            async move {
+                loop {
+                    async_helpers::sleep(Duration::from_secs(1)).await;
+                    let latest = HealthMonitor::instance().recent_errors(&exchange);
+                    if *errors.peek() != latest {
+                        errors.set(latest);
+                    }
+                }
+            }
+        });
+
+        let rows = errors
+            .read()
+            .iter()
+            .map(|error| {
+                let time = chrono::DateTime::from_timestamp_millis(error.time)
+                    .map(|time| time.format("%H:%M:%S").to_string())
+                    .unwrap_or_default();
+                (time, error.message.clone())
+            })
+            .collect::<Vec<_>>();
+
+        rsx! {
+            ul { class: "font2 font-color-main", style: "list-style: none; padding: 0; margin: 0; overflow-y: auto; height: 100%;",
+                if rows.is_empty() {
+                    li { style: "padding: 4px 10px;", "No errors" }
+                }
+                for (time, message) in rows.into_iter() {
+                    li { style: "padding: 4px 10px;",
+                        span { style: "color: var(--ask); margin-right: 8px;", "{time}" }
+                        span { "{message}" }
+                    }
+                }
+            }
+        }
+    }
+
+    fn name(&self) -> String {
+        format!("{} health", self.exchange)
+    }
+
+    fn descriptor(&self) -> Option<WidgetDescriptor> {
+        Some(WidgetDescriptor {
+            name: Self::NAME.to_string(),
+            params: serde_json::json!({ "exchange": self.exchange }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::indicator;
+    use crate::exchange::health::{ExchangeHealth, HealthError};
+    use crate::websocket::ConnectionStatus;
+
+    #[test]
+    fn indicator_warns_on_limits_and_drift() {
+        let mut health = ExchangeHealth {
+            exchange: "binance",
+            connection: Some(ConnectionStatus::Connected { last_message: 0 }),
+            last_error: None,
+            rate_limit: Some(0.25),
+            clock_drift: Some(-12),
+            recv_window: Some(5000),
+        };
+        assert_eq!(
+            indicator(&health),
+            ("var(--bid)", "binance connected 25% -12ms".to_string())
+        );
+
+        health.rate_limit = Some(0.9);
+        health.last_error = Some(HealthError {
+            time: 0,
+            message: "timeout".to_string(),
+        });
+        assert_eq!(
+            indicator(&health),
+            ("var(--ask)", "binance connected 90% -12ms !".to_string())
+        );
+
+        health.connection = None;
+        health.rate_limit = None;
+        health.clock_drift = Some(1500);
+        assert_eq!(indicator(&health).0, "inherit");
+        health.clock_drift = Some(5500);
+        assert_eq!(indicator(&health).0, "var(--ask)");
+    }
+}
@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+use crate::exchange::order_watch::{OrderFilled, OrderWatcher};
+use crate::utils::{async_helpers, server_time};
+
+use dioxus::prelude::*;
+
+/// How long a closed order stays on screen.
+const TOAST_MILLIS: i64 = 5000;
+
+/// Closed watched orders, stacked in the bottom right corner for a few seconds.
+#[component]
+pub fn OrderToasts() -> Element {
+    let mut toasts = use_signal(Vec::<(i64, OrderFilled)>::new);
+    use_future(move || async move {
+        let subscription = OrderWatcher::instance().subscribe();
+        loop {
+            let filled = subscription.recv().await;
+            toasts.write().push((server_time::local_millis(), filled));
+        }
+    });
+    use_future(move || async move {
+        loop {
+            async_helpers::sleep(Duration::from_secs(1)).await;
+            let expired = server_time::local_millis() - TOAST_MILLIS;
+            if toasts.peek().iter().any(|(shown, _)| *shown < expired) {
+                toasts.write().retain(|(shown, _)| *shown >= expired);
+            }
+        }
+    });
+
+    let rows = toasts
+        .read()
+        .iter()
+        .map(|(_, filled)| {
+            let color = if filled.cancelled {
+                "var(--ask)"
+            } else {
+                "var(--bid)"
+            };
+            (color, filled.to_string())
+        })
+        .collect::<Vec<_>>();
+
+    rsx! {
+        div { style: "position: fixed; right: 16px; bottom: 32px; display: flex; flex-direction: column; gap: 6px; z-index: 100;",
+            for (color, text) in rows.into_iter() {
+                div { class: "font2 font-color-main color-2", style: "padding: 6px 12px; border-left: 3px solid {color};",
+                    "{text}"
+                }
+            }
+        }
+    }
+}
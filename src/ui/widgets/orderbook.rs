@@ -1,21 +1,36 @@
 use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    config::Config,
     currency::Currency,
     dec,
-    exchange::{Exchange, RealtimeData},
-    utils::{broadcaster::Subscription, flag::Flag},
+    exchange::{execute_if, Exchange, Exchanges, RealtimeData, Unit},
+    select_ex,
+    ui::utils::LastPrice,
+    utils::{
+        async_helpers, broadcaster::Subscription, flag::Flag, format, maybe_trait::MaybeSend,
+        server_time,
+    },
+    websocket::{ConnectionStatus, StatusHandle},
 };
 
-use super::Widget;
+use super::{BoxedWidget, Widget, WidgetDescriptor};
 
 use crate::utils::Decimal;
+
+/// How often the connection status is checked to gray out a stale book.
+const STATUS_INTERVAL: Duration = Duration::from_secs(1);
 use dioxus::prelude::*;
 
 pub struct OrderbookWidget {
     pair: (Currency, Currency),
     exchange_name: String,
     subscription: Subscription<RealtimeData>,
+    last_price: LastPrice,
+    status: Option<StatusHandle>,
 
     need_rerender: Flag<bool>,
 }
@@ -23,32 +38,90 @@ pub struct OrderbookWidget {
 impl OrderbookWidget {
     pub fn new<E>(pair: (Currency, Currency), exchange: Arc<E>) -> Self
     where
-        E: Exchange + 'static,
+        E: Exchange + MaybeSend + 'static,
     {
         Self {
             pair,
             exchange_name: E::NAME.to_string(),
-            subscription: exchange.subscribe(pair, None),
+            // Only the latest book of the pair is rendered
+            subscription: exchange.subscribe(pair, None).filter(
+                move |data| matches!(data, RealtimeData::Orderbook(book) if book.pair == pair),
+            ),
+            status: exchange.connection_status(),
+            last_price: LastPrice::watch(exchange, pair),
 
             need_rerender: Flag::new(),
         }
     }
 }
 
+#[derive(Serialize, Deserialize)]
+struct OrderbookParams {
+    exchange: String,
+    pair: (Currency, Currency),
+}
+
+impl OrderbookWidget {
+    pub const NAME: &'static str = "Orderbook";
+
+    pub fn from_descriptor(
+        descriptor: &WidgetDescriptor,
+        exchanges: &Exchanges,
+    ) -> Option<BoxedWidget> {
+        let params: OrderbookParams = serde_json::from_value(descriptor.params.clone()).ok()?;
+        // The book of an exchange without realtime streams would stay empty
+        select_ex!(exchanges, params.exchange, |exchange| {
+            exchange
+                .streams_realtime()
+                .then(|| BoxedWidget::from(OrderbookWidget::new(params.pair, exchange)))
+        })
+        .flatten()
+    }
+}
+
 impl Widget for OrderbookWidget {
     fn render(&self) -> Element {
         let subscription = self.subscription.clone();
-        let pair = self.pair.clone();
+        let status = self.status.clone();
+        let mut cumulative = use_signal(|| false);
+        // None while live, otherwise why the book is not
+        let mut stale = use_signal(|| None::<String>);
+
+        use_future(move || {
+            let status = status.clone();
+            async move {
+                let Some(status) = status else {
+                    return;
+                };
+                loop {
+                    let reason = match status.get() {
+                        ConnectionStatus::Connected { .. } => None,
+                        ConnectionStatus::Reconnecting => Some("Reconnecting"),
+                        ConnectionStatus::Stale { .. } => Some("Stale"),
+                    };
+                    // Tells how old the shown book is
+                    let reason = reason.map(|reason| match status.metrics().last_recv_at {
+                        Some(at) => format!(
+                            "{}, last update {}s ago",
+                            reason,
+                            (server_time::local_millis() - at) / 1000
+                        ),
+                        None => reason.to_string(),
+                    });
+                    if *stale.peek() != reason {
+                        stale.set(reason);
+                    }
+                    async_helpers::sleep(STATUS_INTERVAL).await;
+                }
+            }
+        });
 
         let mut data = use_resource(move || {
             let subscription = subscription.clone();
-            let pair = pair.clone();
             async move {
                 loop {
-                    if let RealtimeData::Orderbook(value) = subscription.recv().await {
-                        if value.pair == pair {
-                            return value;
-                        }
+                    if let RealtimeData::Orderbook(value) = subscription.recv_latest().await {
+                        return value;
                     }
                 }
             }
@@ -62,38 +135,123 @@ impl Widget for OrderbookWidget {
         let orderbook = data.as_ref()?;
 
         let min_length = orderbook.asks.len().min(orderbook.bids.len());
-        let asks = orderbook.asks.iter().take(min_length).rev();
-        let bids = orderbook.bids.iter().take(min_length);
+        let best_ask = orderbook.asks.first()?.price;
+        let best_bid = orderbook.bids.first()?.price;
+        let mid_price = ((best_ask + best_bid) / dec!(2)).normalize();
+        let spread = best_ask - best_bid;
+        let spread_percent = if mid_price > Decimal::ZERO {
+            (spread / mid_price * dec!(100)).round_dp(3)
+        } else {
+            Decimal::ZERO
+        };
+
+        // Every price of the book is shown at the precision of the finest one
+        let tick_size = format::tick_size(
+            orderbook
+                .asks
+                .iter()
+                .chain(&orderbook.bids)
+                .map(|unit| unit.price),
+        );
+        let best_ask = format::format_price(best_ask, tick_size);
+        let best_bid = format::format_price(best_bid, tick_size);
+        let spread = format::format_price(spread, tick_size);
+        let mid_price = format::format_price(mid_price, None);
 
-        let max_ask = asks.clone().map(|x| x.amount).max();
-        let max_bid = bids.clone().map(|x| x.amount).max();
-        let max = max_ask.max(max_bid)?;
+        // Rows of (price, displayed amount, level amount) from the best price outward
+        let is_cumulative = *cumulative.read();
+        let (cumulative_bids, cumulative_asks) = orderbook.cumulative();
+        let rows = |units: &[Unit], sums: &[Unit]| {
+            units
+                .iter()
+                .zip(sums)
+                .take(min_length)
+                .map(|(unit, sum)| {
+                    let shown = if is_cumulative {
+                        sum.amount
+                    } else {
+                        unit.amount
+                    };
+                    (unit.price, shown, unit.amount)
+                })
+                .collect::<Vec<_>>()
+        };
+        let asks = rows(&orderbook.asks, &cumulative_asks);
+        let bids = rows(&orderbook.bids, &cumulative_bids);
+
+        let max = asks.iter().chain(bids.iter()).map(|row| row.1).max()?;
+
+        // Whale levels are only highlighted in the cumulative mode,
+        // where large levels are otherwise hidden in the running sum.
+        let whale_threshold = Config::get().orderbook.whale_threshold;
+        let stale = stale.read().clone();
+        let opacity = if stale.is_some() { 0.4 } else { 1.0 };
+        let stale_reason = stale.unwrap_or_default();
+        let is_whale = |amount: Decimal| {
+            is_cumulative && whale_threshold.is_some_and(|threshold| amount >= threshold)
+        };
 
         rsx! {
             OrderbookBarStyle {}
-            ul { style: "list-style: none;  display: flex; flex-direction: column; padding: 0; margin: 0; align-content: center;",
-                for ask in orderbook.asks.iter().take(min_length).rev() {
+            div { class: "orderbook-header font2 font-color-main",
+                span { "Bid {best_bid}" }
+                span { "Ask {best_ask}" }
+                span { "Spread {spread} ({spread_percent}%)" }
+                span { "Mid {mid_price}" }
+                span { style: "color: var(--ask);", "{stale_reason}" }
+                button {
+                    class: "font-color-main color-3",
+                    style: "border: none; cursor: pointer;",
+                    onclick: move |_| {
+                        let value = *cumulative.peek();
+                        cumulative.set(!value);
+                    },
+                    if is_cumulative { "Per level" } else { "Cumulative" }
+                }
+            }
+            ul { style: "list-style: none;  display: flex; flex-direction: column; padding: 0; margin: 0; align-content: center; opacity: {opacity};",
+                for (price, amount, level) in asks.iter().rev().copied() {
                     OrderbookBar {
                         is_green: false,
-                        price: ask.price,
-                        amount: ask.amount,
-                        ratio: ask.amount / max
+                        is_whale: is_whale(level),
+                        price: price,
+                        tick_size: tick_size,
+                        amount: amount,
+                        ratio: amount / max
                     }
                 }
-                for bid in orderbook.bids.iter().take(min_length) {
-                    OrderbookBar { is_green: true, price: bid.price, amount: bid.amount, ratio: bid.amount / max }
+                for (price, amount, level) in bids.iter().copied() {
+                    OrderbookBar { is_green: true, is_whale: is_whale(level), price: price, tick_size: tick_size, amount: amount, ratio: amount / max }
                 }
             }
         }
     }
 
     fn name(&self) -> String {
-        format!("{} {}-{}", self.exchange_name, self.pair.0, self.pair.1)
+        format!(
+            "{} {}-{}{}",
+            self.exchange_name,
+            self.pair.0,
+            self.pair.1,
+            self.last_price.title_suffix()
+        )
     }
 
     fn is_changed_after_render(&self) -> bool {
         self.need_rerender.get().unwrap_or_default()
     }
+
+    fn descriptor(&self) -> Option<WidgetDescriptor> {
+        let params = OrderbookParams {
+            exchange: self.exchange_name.clone(),
+            pair: self.pair,
+        };
+
+        Some(WidgetDescriptor {
+            name: Self::NAME.to_string(),
+            params: serde_json::to_value(params).ok()?,
+        })
+    }
 }
 
 #[component]
@@ -108,20 +266,30 @@ fn OrderbookBarStyle() -> Element {
         z-index: 1;
     }
     .color-obb-green {
-        background-color: #152f1e;
+        background-color: var(--bid-fill);
     }
     .color-obb-red {
-        background-color: #361b22;
+        background-color: var(--ask-fill);
     }
     .orderbook-bar-text {
         line-height: 30px;
         z-index: 2;
     }
     .color-obb-font-green {
-        color: #228a44
+        color: var(--bid)
     }
     .color-obb-font-red {
-        color: #a63654
+        color: var(--ask)
+    }
+    .color-obb-whale {
+        background-color: var(--whale-fill);
+    }
+    .orderbook-header {
+        display: flex;
+        justify-content: space-between;
+        align-items: center;
+        padding: 4px 10px;
+        gap: 10px;
     }
     "#;
     rsx! {
@@ -130,7 +298,14 @@ fn OrderbookBarStyle() -> Element {
 }
 
 #[component]
-fn OrderbookBar(is_green: bool, price: Decimal, amount: Decimal, ratio: Decimal) -> Element {
+fn OrderbookBar(
+    is_green: bool,
+    is_whale: bool,
+    price: Decimal,
+    tick_size: Option<Decimal>,
+    amount: Decimal,
+    ratio: Decimal,
+) -> Element {
     let obb_font_color = if is_green {
         "color-obb-font-green"
     } else {
@@ -143,11 +318,15 @@ fn OrderbookBar(is_green: bool, price: Decimal, amount: Decimal, ratio: Decimal)
         "color-obb-red"
     };
 
+    let whale_color = if is_whale { "color-obb-whale" } else { "" };
+
     let ratio = ratio * dec!(100);
+    let price = format::format_price(price, tick_size);
+    let amount = format::format_amount(amount, false);
 
     rsx! {
         li {
-            class: "bar-height",
+            class: "bar-height {whale_color}",
             style: "display:flex; align-items: center; justify-content: space-between;",
             div {
                 class: "bar-height orderbook-bar {obb_color}",
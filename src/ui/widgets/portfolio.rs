@@ -0,0 +1,335 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    currency::Currency,
+    exchange::{
+        binance::Binance, bithumb::Bithumb, execute_if, fx, okx::Okx, upbit::Upbit, Exchange,
+        Exchanges,
+    },
+    select_ex,
+    ui::sub_window::SubWindowMgrState,
+    utils::{
+        async_helpers,
+        format::{format_amount, format_price},
+        Decimal,
+    },
+};
+
+use super::{BoxedWidget, OrderbookWidget, Widget, WidgetDescriptor};
+
+use dioxus::prelude::*;
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Holdings of every configured exchange valued in a single quote currency.
+pub struct PortfolioWidget {
+    quote: Currency,
+    exchanges: Exchanges,
+}
+
+impl PortfolioWidget {
+    pub const NAME: &'static str = "Portfolio";
+
+    pub fn new(quote: Currency, exchanges: Exchanges) -> Self {
+        Self { quote, exchanges }
+    }
+
+    pub fn from_descriptor(
+        descriptor: &WidgetDescriptor,
+        exchanges: &Exchanges,
+    ) -> Option<BoxedWidget> {
+        let params: PortfolioParams = serde_json::from_value(descriptor.params.clone()).ok()?;
+        Some(PortfolioWidget::new(params.quote, exchanges.clone()).into())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct PortfolioParams {
+    quote: Currency,
+}
+
+#[derive(Clone, PartialEq)]
+struct Holding {
+    exchange: &'static str,
+    currency: Currency,
+    quantity: Decimal,
+    /// Price in the quote currency, None if no market could value it.
+    price: Option<Decimal>,
+    /// The price was converted at exchange rates that could not be refreshed.
+    stale: bool,
+}
+
+impl Holding {
+    fn value(&self) -> Option<Decimal> {
+        self.price.map(|price| (price * self.quantity).round_dp(2))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum SortBy {
+    Exchange,
+    Currency,
+    Value,
+}
+
+#[derive(Default)]
+struct Portfolio {
+    holdings: Vec<Holding>,
+    /// Exchanges that were skipped, with the reason.
+    notes: Vec<String>,
+}
+
+fn has_api_keys(name: &str) -> bool {
+    let config = Config::get();
+    match name {
+        Upbit::NAME => config.upbit.is_some(),
+        Binance::NAME => config.binance.is_some(),
+        Bithumb::NAME => config.bithumb.is_some(),
+        Okx::NAME => config.okx.is_some(),
+        _ => false,
+    }
+}
+
+/// Price of `currency` in `quote` on the exchange.
+/// Coins without a direct market are routed through BTC.
+async fn price_in<E>(exchange: &E, currency: Currency, quote: Currency) -> Option<Decimal>
+where
+    E: Exchange,
+{
+    if currency == quote {
+        return Some(Decimal::ONE);
+    }
+
+    if let Ok(price) = exchange.ticker((currency, quote), None).await {
+        return Some(price);
+    }
+
+    if currency == Currency::BTC || quote == Currency::BTC {
+        return None;
+    }
+
+    let in_btc = exchange
+        .ticker((currency, Currency::BTC), None)
+        .await
+        .ok()?;
+    let btc = exchange.ticker((Currency::BTC, quote), None).await.ok()?;
+    Some(in_btc * btc)
+}
+
+/// Quotes tried when the exchange has no market in the quote currency,
+/// the price is then converted at the reference exchange rates.
+const FX_BRIDGES: [Currency; 3] = [Currency::USDT, Currency::KRW, Currency::BTC];
+
+/// Price of `currency` in `quote` through a bridge quote and the reference exchange rates,
+/// e.g. binance holdings valued in KRW.
+async fn price_via_fx<E>(
+    exchange: &E,
+    exchanges: &Exchanges,
+    currency: Currency,
+    quote: Currency,
+) -> Option<fx::Conversion>
+where
+    E: Exchange,
+{
+    for bridge in FX_BRIDGES {
+        if bridge == quote {
+            continue;
+        }
+
+        let Some(price) = price_in(exchange, currency, bridge).await else {
+            continue;
+        };
+        if let Ok(conversion) = fx::convert(exchanges, price, bridge, quote).await {
+            return Some(conversion);
+        }
+    }
+
+    None
+}
+
+async fn holdings<E>(
+    exchange: Arc<E>,
+    exchanges: &Exchanges,
+    quote: Currency,
+) -> Result<Vec<Holding>, String>
+where
+    E: Exchange,
+{
+    if !has_api_keys(E::NAME) {
+        return Err(format!("{}: no API keys configured, skipped", E::NAME));
+    }
+
+    let balances = exchange
+        .balances(None)
+        .await
+        .map_err(|e| format!("{}: {}", E::NAME, e))?;
+
+    let mut holdings = Vec::new();
+    for (currency, balance) in balances {
+        let (price, stale) = match price_in(exchange.as_ref(), currency, quote).await {
+            Some(price) => (Some(price), false),
+            None => match price_via_fx(exchange.as_ref(), exchanges, currency, quote).await {
+                Some(conversion) => (Some(conversion.amount), conversion.stale),
+                None => (None, false),
+            },
+        };
+
+        holdings.push(Holding {
+            exchange: E::NAME,
+            currency,
+            quantity: balance.total(),
+            price,
+            stale,
+        });
+    }
+
+    Ok(holdings)
+}
+
+async fn portfolio(exchanges: Exchanges, quote: Currency) -> Portfolio {
+    let results = [
+        holdings(exchanges.upbit.clone(), &exchanges, quote).await,
+        holdings(exchanges.binance.clone(), &exchanges, quote).await,
+        holdings(exchanges.bithumb.clone(), &exchanges, quote).await,
+        holdings(exchanges.okx.clone(), &exchanges, quote).await,
+    ];
+
+    let mut portfolio = Portfolio::default();
+    for result in results {
+        match result {
+            Ok(holdings) => portfolio.holdings.extend(holdings),
+            Err(note) => portfolio.notes.push(note),
+        }
+    }
+
+    if portfolio.holdings.iter().any(|h| h.stale) {
+        portfolio.notes.push(format!(
+            "Exchange rates are older than {}s, values marked * may be off",
+            Config::get().fx.ttl_secs
+        ));
+    }
+
+    portfolio
+}
+
+impl Widget for PortfolioWidget {
+    fn render(&self) -> Element {
+        let exchanges = self.exchanges.clone();
+        let quote = self.quote;
+        let mut sort_by = use_signal(|| SortBy::Value);
+
+        let mut data = use_resource(move || portfolio(exchanges.clone(), quote));
+
+        use_future(move || async move {
+            loop {
+                async_helpers::sleep(REFRESH_INTERVAL).await;
+                data.restart();
+            }
+        });
+
+        let data = data.read();
+        let portfolio = data.as_ref()?;
+
+        let mut holdings = portfolio.holdings.clone();
+        match *sort_by.read() {
+            SortBy::Exchange => holdings.sort_by_key(|h| (h.exchange, h.currency.to_string())),
+            SortBy::Currency => holdings.sort_by_key(|h| (h.currency.to_string(), h.exchange)),
+            SortBy::Value => holdings.sort_by(|a, b| b.value().cmp(&a.value())),
+        }
+
+        let total = holdings
+            .iter()
+            .filter_map(|h| h.value())
+            .fold(Decimal::ZERO, |sum, value| sum + value);
+        let total = format_price(total, None);
+        let notes = portfolio.notes.clone();
+
+        rsx! {
+            table { class: "font2 font-color-main", style: "width: 100%; border-collapse: collapse;",
+                thead {
+                    tr { style: "cursor: pointer; text-align: left;",
+                        th { onclick: move |_| sort_by.set(SortBy::Exchange), "Exchange" }
+                        th { onclick: move |_| sort_by.set(SortBy::Currency), "Currency" }
+                        th { "Quantity" }
+                        th { "Price ({quote})" }
+                        th { onclick: move |_| sort_by.set(SortBy::Value), "Value ({quote})" }
+                    }
+                }
+                tbody {
+                    for holding in holdings.into_iter() {
+                        PortfolioRow { holding: holding, quote: quote }
+                    }
+                    tr {
+                        td { "Total" }
+                        td {}
+                        td {}
+                        td {}
+                        td { "{total}" }
+                    }
+                }
+            }
+            for note in notes.into_iter() {
+                div { class: "font2 font-color-main", style: "padding: 4px 10px; opacity: 0.6;",
+                    "{note}"
+                }
+            }
+        }
+    }
+
+    fn name(&self) -> String {
+        format!("Portfolio ({})", self.quote)
+    }
+
+    fn descriptor(&self) -> Option<WidgetDescriptor> {
+        let params = PortfolioParams { quote: self.quote };
+
+        Some(WidgetDescriptor {
+            name: Self::NAME.to_string(),
+            params: serde_json::to_value(params).ok()?,
+        })
+    }
+}
+
+#[component]
+fn PortfolioRow(holding: Holding, quote: Currency) -> Element {
+    let exchanges = use_context::<Exchanges>();
+    let price = holding
+        .price
+        .map(|price| format_price(price, None))
+        .unwrap_or_else(|| "-".to_string());
+    let stale = if holding.stale { "*" } else { "" };
+    let value = holding
+        .value()
+        .map(|value| format!("{}{}", format_price(value, None), stale))
+        .unwrap_or_else(|| "-".to_string());
+    let quantity = format_amount(holding.quantity, false);
+
+    let exchange_name = holding.exchange.to_string();
+    let pair = (holding.currency, quote);
+
+    rsx! {
+        tr {
+            style: "cursor: pointer;",
+            onclick: move |_| {
+                if pair.0 == pair.1 {
+                    return;
+                }
+
+                if let Some(widget) = select_ex!(exchanges, exchange_name, |exchange| {
+                    BoxedWidget::from(OrderbookWidget::new(pair, exchange))
+                }) {
+                    SubWindowMgrState::open(widget);
+                }
+            },
+            td { "{holding.exchange}" }
+            td { "{holding.currency}" }
+            td { "{quantity}" }
+            td { "{price}" }
+            td { "{value}" }
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use crate::currency::CurrencyPair;
+use crate::exchange::backtest::BacktestReport;
+use crate::exchange::Side;
+use crate::utils::format::format_percent;
+use crate::utils::Decimal;
+
+use super::Widget;
+
+use dioxus::prelude::*;
+
+// Size of the svg view box, the chart is stretched to fill its area.
+const WIDTH: f64 = 1000.0;
+const HEIGHT: f64 = 300.0;
+
+/// Equity curve, stats and fills of a finished backtest. Not restored with the layout.
+pub struct ReportWidget {
+    report: BacktestReport,
+}
+
+impl ReportWidget {
+    pub fn new(report: BacktestReport) -> Self {
+        Self { report }
+    }
+}
+
+/// Svg path of the equity over time, scaled into the view box.
+fn equity_path(equity: &[(u64, Decimal)]) -> String {
+    let (Some((start, _)), Some((end, _))) = (equity.first(), equity.last()) else {
+        return String::new();
+    };
+    let values = equity.iter().map(|(_, value)| value.to_f64_lossy());
+    let min = values.clone().fold(f64::INFINITY, f64::min);
+    let max = values.fold(f64::NEG_INFINITY, f64::max);
+    let time_range = ((end - start) as f64).max(1.0);
+    let value_range = (max - min).max(f64::EPSILON);
+
+    equity
+        .iter()
+        .enumerate()
+        .map(|(idx, (time, value))| {
+            let x = (time - start) as f64 / time_range * WIDTH;
+            let y = HEIGHT - (value.to_f64_lossy() - min) / value_range * HEIGHT;
+            let command = if idx == 0 { "M" } else { "L" };
+            format!("{} {} {}", command, x, y)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl Widget for ReportWidget {
+    fn render(&self) -> Element {
+        let report = &self.report;
+        let path = equity_path(&report.equity);
+
+        let stats = [
+            ("Pnl", report.pnl().round_dp(8).normalize().to_string()),
+            (
+                "Total return",
+                report
+                    .total_return()
+                    .map(format_percent)
+                    .unwrap_or_default(),
+            ),
+            (
+                "Sharpe (per candle)",
+                report
+                    .sharpe()
+                    .map(|sharpe| format!("{:.3}", sharpe))
+                    .unwrap_or_default(),
+            ),
+            ("Max drawdown", format_percent(report.max_drawdown())),
+            (
+                "Win rate",
+                report.win_rate().map(format_percent).unwrap_or_default(),
+            ),
+            ("Fills", report.fills.len().to_string()),
+            (
+                "Average fee",
+                report
+                    .average_fee()
+                    .map(|fee| fee.round_dp(8).normalize().to_string())
+                    .unwrap_or_default(),
+            ),
+        ];
+
+        let rows = report
+            .fills
+            .iter()
+            .zip(report.realized())
+            .map(|(fill, realized)| {
+                let time = chrono::DateTime::from_timestamp_millis(fill.timestamp as i64)
+                    .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_default();
+                let (side, color) = match fill.side {
+                    Side::Bid => ("Bid", "var(--bid)"),
+                    Side::Ask => ("Ask", "var(--ask)"),
+                };
+                (
+                    time,
+                    side,
+                    color,
+                    fill.price.normalize(),
+                    fill.qty.normalize(),
+                    fill.fee.round_dp(8).normalize(),
+                    realized
+                        .map(|pnl| pnl.round_dp(8).normalize().to_string())
+                        .unwrap_or_default(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        rsx! {
+            div { class: "font2 font-color-main", style: "display: flex; flex-direction: column; height: 100%;",
+                svg {
+                    view_box: "0 0 {WIDTH} {HEIGHT}",
+                    preserve_aspect_ratio: "none",
+                    style: "width: 100%; height: 40%; flex-shrink: 0;",
+                    path { d: "{path}", style: "fill: none; stroke: var(--bid);" }
+                }
+                div { style: "display: flex; flex-wrap: wrap; gap: 4px 16px; padding: 4px 10px;",
+                    for (name, value) in stats.into_iter() {
+                        div { "{name}: {value}" }
+                    }
+                }
+                div { style: "flex: 1; overflow-y: auto;",
+                    table { style: "width: 100%; border-collapse: collapse;",
+                        thead {
+                            tr { style: "text-align: left;",
+                                th { "Time" }
+                                th { "Side" }
+                                th { "Price" }
+                                th { "Qty" }
+                                th { "Fee" }
+                                th { "Pnl" }
+                            }
+                        }
+                        tbody {
+                            for (time, side, color, price, qty, fee, pnl) in rows.into_iter() {
+                                tr {
+                                    td { "{time}" }
+                                    td { style: "color: {color};", "{side}" }
+                                    td { "{price}" }
+                                    td { "{qty}" }
+                                    td { "{fee}" }
+                                    td { "{pnl}" }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn name(&self) -> String {
+        format!("{} backtest", CurrencyPair::from(self.report.pair))
+    }
+
+    fn is_changed_after_render(&self) -> bool {
+        false
+    }
+}
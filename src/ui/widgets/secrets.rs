@@ -0,0 +1,142 @@
+use crate::config::secrets::{self, SecretsError};
+
+use super::Widget;
+
+use dioxus::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecretsAction {
+    /// Decrypts the api keys for this session.
+    Unlock,
+    /// Encrypts the plaintext api keys of the config.
+    Encrypt,
+    /// Changes the passphrase of the encrypted api keys.
+    Rotate,
+}
+
+/// Asks for the passphrase of the encrypted api keys.
+/// Not persisted in the layout, it is opened at startup while the keys are locked.
+pub struct SecretsWidget {
+    action: SecretsAction,
+}
+
+impl SecretsWidget {
+    pub fn new(action: SecretsAction) -> Self {
+        Self { action }
+    }
+}
+
+fn submit(
+    action: SecretsAction,
+    current: &str,
+    new: &str,
+    confirm: &str,
+) -> Result<&'static str, String> {
+    if action != SecretsAction::Unlock && new != confirm {
+        return Err("The passphrases do not match".to_string());
+    }
+
+    let result: Result<(), SecretsError> = match action {
+        SecretsAction::Unlock => secrets::unlock(current),
+        SecretsAction::Encrypt => secrets::encrypt(new),
+        SecretsAction::Rotate => secrets::rotate(current, new),
+    };
+    result.map_err(|e| e.to_string())?;
+
+    Ok(match action {
+        SecretsAction::Unlock => "Unlocked, trading is enabled",
+        SecretsAction::Encrypt => "The api keys are encrypted",
+        SecretsAction::Rotate => "The passphrase is changed",
+    })
+}
+
+impl Widget for SecretsWidget {
+    fn render(&self) -> Element {
+        let action = self.action;
+
+        let mut current = use_signal(String::new);
+        let mut new = use_signal(String::new);
+        let mut confirm = use_signal(String::new);
+        let mut status = use_signal(|| None::<Result<&'static str, String>>);
+
+        let asks_current = action != SecretsAction::Encrypt;
+        let asks_new = action != SecretsAction::Unlock;
+        let button = match action {
+            SecretsAction::Unlock => "Unlock",
+            SecretsAction::Encrypt => "Encrypt",
+            SecretsAction::Rotate => "Change",
+        };
+        let (status_text, status_color) = match status.read().as_ref() {
+            Some(Ok(message)) => (message.to_string(), "#228a44"),
+            Some(Err(e)) => (e.clone(), "#a63654"),
+            None => (String::new(), "inherit"),
+        };
+
+        let mut run = move || {
+            let result = submit(action, &current.peek(), &new.peek(), &confirm.peek());
+            // The passphrases are not kept around once used
+            current.set(String::new());
+            new.set(String::new());
+            confirm.set(String::new());
+            status.set(Some(result));
+        };
+
+        rsx! {
+            div { class: "font2 font-color-main", style: "display: flex; flex-direction: column; gap: 6px; padding: 10px;",
+                if asks_current {
+                    input {
+                        class: "font2 font-color-main color-3",
+                        style: "border: none; padding: 4px 10px; outline: none;",
+                        r#type: "password",
+                        placeholder: "Passphrase",
+                        value: "{current}",
+                        oninput: move |event| current.set(event.value()),
+                        onkeydown: move |event| {
+                            if event.key() == Key::Enter {
+                                run();
+                            }
+                        }
+                    }
+                }
+                if asks_new {
+                    input {
+                        class: "font2 font-color-main color-3",
+                        style: "border: none; padding: 4px 10px; outline: none;",
+                        r#type: "password",
+                        placeholder: "New passphrase",
+                        value: "{new}",
+                        oninput: move |event| new.set(event.value())
+                    }
+                    input {
+                        class: "font2 font-color-main color-3",
+                        style: "border: none; padding: 4px 10px; outline: none;",
+                        r#type: "password",
+                        placeholder: "Confirm new passphrase",
+                        value: "{confirm}",
+                        oninput: move |event| confirm.set(event.value()),
+                        onkeydown: move |event| {
+                            if event.key() == Key::Enter {
+                                run();
+                            }
+                        }
+                    }
+                }
+                button {
+                    class: "font-color-main color-3",
+                    style: "border: none; cursor: pointer; padding: 4px 10px;",
+                    onclick: move |_| run(),
+                    "{button}"
+                }
+                span { style: "color: {status_color};", "{status_text}" }
+            }
+        }
+    }
+
+    fn name(&self) -> String {
+        match self.action {
+            SecretsAction::Unlock => "Unlock api keys".to_string(),
+            SecretsAction::Encrypt => "Encrypt api keys".to_string(),
+            SecretsAction::Rotate => "Change passphrase".to_string(),
+        }
+    }
+}
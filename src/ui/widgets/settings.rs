@@ -0,0 +1,576 @@
+use crate::config::{secrets, Config};
+use crate::currency::Currency;
+use crate::exchange::{binance::Binance, bithumb::Bithumb, okx::Okx, upbit::Upbit, Exchange};
+use crate::ui::keybinds::Keybind;
+use crate::utils::rate_limiter::RateLimit;
+use crate::utils::Decimal;
+
+use super::{BoxedWidget, Widget, WidgetDescriptor};
+
+use dioxus::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldKind {
+    Text,
+    /// Api keys, masked and hidden while the keys are stored encrypted.
+    Secret,
+    /// Set by pressing the keys instead of typing, see [`Keybind`].
+    Keybind,
+}
+
+/// An editable value of the config, shown as a text input.
+struct Field {
+    label: &'static str,
+    kind: FieldKind,
+    get: fn(&Config) -> String,
+    /// Parses the input into the config, the error is shown under the input.
+    set: fn(&mut Config, &str) -> Result<(), String>,
+}
+
+fn text(value: Option<&impl ToString>) -> String {
+    value.map(ToString::to_string).unwrap_or_default()
+}
+
+fn section<T: Default>(section: &mut Option<T>) -> &mut T {
+    section.get_or_insert_with(T::default)
+}
+
+fn parse_decimal(value: &str) -> Result<Decimal, String> {
+    Decimal::from_str(value.trim()).map_err(|e| e.to_string())
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value.trim().parse().map_err(|_| "Not a number".to_string())
+}
+
+fn rate_limit(config: &Config, name: &str) -> String {
+    config
+        .rate_limit
+        .get(name)
+        .map(|limit| format!("{}/{}", limit.capacity, limit.refill_per_sec))
+        .unwrap_or_default()
+}
+
+/// Parses `capacity/refill_per_sec`, an empty input removes the override.
+fn set_rate_limit(config: &mut Config, name: &str, value: &str) -> Result<(), String> {
+    let value = value.trim();
+    if value.is_empty() {
+        config.rate_limit.remove(name);
+        return Ok(());
+    }
+
+    let (capacity, refill) = value
+        .split_once('/')
+        .ok_or("Expected capacity/refill per second")?;
+    let limit = RateLimit {
+        capacity: capacity.trim().parse().map_err(|_| "Invalid capacity")?,
+        refill_per_sec: refill.trim().parse().map_err(|_| "Invalid refill rate")?,
+    };
+    config.rate_limit.insert(name.to_string(), limit);
+    Ok(())
+}
+
+const FIELDS: &[Field] = &[
+    Field {
+        label: "Upbit access key",
+        kind: FieldKind::Secret,
+        get: |c| text(c.upbit.as_ref().map(|s| &s.access_key)),
+        set: |c, v| {
+            section(&mut c.upbit).access_key = v.trim().to_string();
+            Ok(())
+        },
+    },
+    Field {
+        label: "Upbit secret key",
+        kind: FieldKind::Secret,
+        get: |c| text(c.upbit.as_ref().map(|s| &s.secret_key)),
+        set: |c, v| {
+            section(&mut c.upbit).secret_key = v.trim().to_string();
+            Ok(())
+        },
+    },
+    Field {
+        label: "Binance api key",
+        kind: FieldKind::Secret,
+        get: |c| text(c.binance.as_ref().map(|s| &s.api_key)),
+        set: |c, v| {
+            section(&mut c.binance).api_key = v.trim().to_string();
+            Ok(())
+        },
+    },
+    Field {
+        label: "Binance secret key",
+        kind: FieldKind::Secret,
+        get: |c| text(c.binance.as_ref().map(|s| &s.secret_key)),
+        set: |c, v| {
+            section(&mut c.binance).secret_key = v.trim().to_string();
+            Ok(())
+        },
+    },
+    Field {
+        label: "Bithumb connect key",
+        kind: FieldKind::Secret,
+        get: |c| text(c.bithumb.as_ref().map(|s| &s.connect_key)),
+        set: |c, v| {
+            section(&mut c.bithumb).connect_key = v.trim().to_string();
+            Ok(())
+        },
+    },
+    Field {
+        label: "Bithumb secret key",
+        kind: FieldKind::Secret,
+        get: |c| text(c.bithumb.as_ref().map(|s| &s.secret_key)),
+        set: |c, v| {
+            section(&mut c.bithumb).secret_key = v.trim().to_string();
+            Ok(())
+        },
+    },
+    Field {
+        label: "Okx api key",
+        kind: FieldKind::Secret,
+        get: |c| text(c.okx.as_ref().map(|s| &s.api_key)),
+        set: |c, v| {
+            section(&mut c.okx).api_key = v.trim().to_string();
+            Ok(())
+        },
+    },
+    Field {
+        label: "Okx secret key",
+        kind: FieldKind::Secret,
+        get: |c| text(c.okx.as_ref().map(|s| &s.secret_key)),
+        set: |c, v| {
+            section(&mut c.okx).secret_key = v.trim().to_string();
+            Ok(())
+        },
+    },
+    Field {
+        label: "Okx passphrase",
+        kind: FieldKind::Secret,
+        get: |c| text(c.okx.as_ref().map(|s| &s.passphrase)),
+        set: |c, v| {
+            section(&mut c.okx).passphrase = v.to_string();
+            Ok(())
+        },
+    },
+    Field {
+        label: "Upbit rate limit",
+        kind: FieldKind::Text,
+        get: |c| rate_limit(c, Upbit::NAME),
+        set: |c, v| set_rate_limit(c, Upbit::NAME, v),
+    },
+    Field {
+        label: "Binance rate limit",
+        kind: FieldKind::Text,
+        get: |c| rate_limit(c, Binance::NAME),
+        set: |c, v| set_rate_limit(c, Binance::NAME, v),
+    },
+    Field {
+        label: "Bithumb rate limit",
+        kind: FieldKind::Text,
+        get: |c| rate_limit(c, Bithumb::NAME),
+        set: |c, v| set_rate_limit(c, Bithumb::NAME, v),
+    },
+    Field {
+        label: "Okx rate limit",
+        kind: FieldKind::Text,
+        get: |c| rate_limit(c, Okx::NAME),
+        set: |c, v| set_rate_limit(c, Okx::NAME, v),
+    },
+    Field {
+        label: "Http timeout (ms)",
+        kind: FieldKind::Text,
+        get: |c| c.http.timeout_ms.to_string(),
+        set: |c, v| {
+            c.http.timeout_ms = parse_number(v)?;
+            Ok(())
+        },
+    },
+    Field {
+        label: "Http user agent",
+        kind: FieldKind::Text,
+        get: |c| c.http.user_agent.clone(),
+        set: |c, v| {
+            c.http.user_agent = v.trim().to_string();
+            Ok(())
+        },
+    },
+    Field {
+        label: "Http retry attempts",
+        kind: FieldKind::Text,
+        get: |c| c.http_retry.max_attempts.to_string(),
+        set: |c, v| {
+            c.http_retry.max_attempts = parse_number(v)?;
+            Ok(())
+        },
+    },
+    Field {
+        label: "Http retry base delay (ms)",
+        kind: FieldKind::Text,
+        get: |c| c.http_retry.base_delay_ms.to_string(),
+        set: |c, v| {
+            c.http_retry.base_delay_ms = parse_number(v)?;
+            Ok(())
+        },
+    },
+    Field {
+        label: "Http retry max delay (ms)",
+        kind: FieldKind::Text,
+        get: |c| c.http_retry.max_delay_ms.to_string(),
+        set: |c, v| {
+            c.http_retry.max_delay_ms = parse_number(v)?;
+            Ok(())
+        },
+    },
+    Field {
+        label: "Theme (dark or light)",
+        kind: FieldKind::Text,
+        get: |c| c.theme.palette.to_string(),
+        set: |c, v| {
+            c.theme.palette = v.parse()?;
+            Ok(())
+        },
+    },
+    Field {
+        label: "Alert hysteresis",
+        kind: FieldKind::Text,
+        get: |c| c.alert.hysteresis.to_string(),
+        set: |c, v| {
+            c.alert.hysteresis = parse_decimal(v)?;
+            Ok(())
+        },
+    },
+    Field {
+        label: "Orderbook whale threshold",
+        kind: FieldKind::Text,
+        get: |c| text(c.orderbook.whale_threshold.as_ref()),
+        set: |c, v| {
+            c.orderbook.whale_threshold = match v.trim() {
+                "" => None,
+                v => Some(parse_decimal(v)?),
+            };
+            Ok(())
+        },
+    },
+    Field {
+        label: "Convert bridges",
+        kind: FieldKind::Text,
+        get: |c| {
+            c.convert
+                .bridges
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        },
+        set: |c, v| {
+            c.convert.bridges = v
+                .split(',')
+                .map(str::trim)
+                .filter(|currency| !currency.is_empty())
+                .map(|currency| {
+                    currency
+                        .to_uppercase()
+                        .parse::<Currency>()
+                        .map_err(|_| format!("Unknown currency {}", currency))
+                })
+                .collect::<Result<_, _>>()?;
+            Ok(())
+        },
+    },
+    Field {
+        label: "Console scrollback (lines)",
+        kind: FieldKind::Text,
+        get: |c| c.console.scrollback_lines.to_string(),
+        set: |c, v| {
+            c.console.scrollback_lines = parse_number(v)?;
+            Ok(())
+        },
+    },
+    Field {
+        label: "Open command palette",
+        kind: FieldKind::Keybind,
+        get: |c| c.keybinds.open_command_palette.to_string(),
+        set: |c, v| {
+            c.keybinds.open_command_palette = v.parse()?;
+            Ok(())
+        },
+    },
+    Field {
+        label: "Close window",
+        kind: FieldKind::Keybind,
+        get: |c| c.keybinds.close_window.to_string(),
+        set: |c, v| {
+            c.keybinds.close_window = v.parse()?;
+            Ok(())
+        },
+    },
+    Field {
+        label: "Focus next window",
+        kind: FieldKind::Keybind,
+        get: |c| c.keybinds.focus_next.to_string(),
+        set: |c, v| {
+            c.keybinds.focus_next = v.parse()?;
+            Ok(())
+        },
+    },
+    Field {
+        label: "Focus previous window",
+        kind: FieldKind::Keybind,
+        get: |c| c.keybinds.focus_previous.to_string(),
+        set: |c, v| {
+            c.keybinds.focus_previous = v.parse()?;
+            Ok(())
+        },
+    },
+];
+
+/// Exchange sections left without any key are removed, instead of failing validation.
+fn prune_sections(config: &mut Config) {
+    if config
+        .upbit
+        .as_ref()
+        .is_some_and(|s| s.access_key.is_empty() && s.secret_key.is_empty())
+    {
+        config.upbit = None;
+    }
+    if config
+        .binance
+        .as_ref()
+        .is_some_and(|s| s.api_key.is_empty() && s.secret_key.is_empty())
+    {
+        config.binance = None;
+    }
+    if config
+        .bithumb
+        .as_ref()
+        .is_some_and(|s| s.connect_key.is_empty() && s.secret_key.is_empty())
+    {
+        config.bithumb = None;
+    }
+    if config
+        .okx
+        .as_ref()
+        .is_some_and(|s| s.api_key.is_empty() && s.secret_key.is_empty())
+    {
+        config.okx = None;
+    }
+}
+
+/// Applies the inputs to a copy of `config`.
+/// Returns the error of each field that could not be parsed, None for the fields that could.
+fn apply(
+    config: &Config,
+    values: &[String],
+    skip_secrets: bool,
+) -> Result<Config, Vec<Option<String>>> {
+    let mut config = config.clone();
+    let errors = FIELDS
+        .iter()
+        .zip(values)
+        .map(|(field, value)| {
+            if field.kind == FieldKind::Secret && skip_secrets {
+                return None;
+            }
+            (field.set)(&mut config, value).err()
+        })
+        .collect::<Vec<_>>();
+
+    if errors.iter().any(Option::is_some) {
+        return Err(errors);
+    }
+
+    prune_sections(&mut config);
+    Ok(config)
+}
+
+/// Edits the config, saving writes `config.toml` and reloads it.
+pub struct SettingsWidget;
+
+impl SettingsWidget {
+    pub const NAME: &'static str = "Settings";
+
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn from_descriptor(
+        _descriptor: &WidgetDescriptor,
+        _exchanges: &crate::exchange::Exchanges,
+    ) -> Option<BoxedWidget> {
+        Some(SettingsWidget::new().into())
+    }
+}
+
+impl Widget for SettingsWidget {
+    fn render(&self) -> Element {
+        let encrypted = use_hook(secrets::is_encrypted);
+        let mut values = use_signal(|| {
+            let config = Config::get();
+            FIELDS
+                .iter()
+                .map(|field| (field.get)(&config))
+                .collect::<Vec<_>>()
+        });
+        let mut errors = use_signal(|| vec![None::<String>; FIELDS.len()]);
+        let mut status = use_signal(|| None::<Result<&'static str, String>>);
+
+        let save = move |_| {
+            let config = match apply(&Config::get(), &values.peek(), encrypted) {
+                Ok(config) => config,
+                Err(field_errors) => {
+                    errors.set(field_errors);
+                    status.set(None);
+                    return;
+                }
+            };
+
+            errors.set(vec![None; FIELDS.len()]);
+            status.set(Some(
+                config.save().map(|_| "Saved").map_err(|e| e.to_string()),
+            ));
+        };
+
+        let rows = FIELDS
+            .iter()
+            .enumerate()
+            .filter(|(_, field)| !(field.kind == FieldKind::Secret && encrypted))
+            .map(|(idx, field)| {
+                let kind = match field.kind {
+                    FieldKind::Secret => "password",
+                    FieldKind::Text | FieldKind::Keybind => "text",
+                };
+                let keybind = field.kind == FieldKind::Keybind;
+                let value = values.read()[idx].clone();
+                let error = errors.read()[idx].clone().unwrap_or_default();
+                (idx, field.label, kind, keybind, value, error)
+            })
+            .collect::<Vec<_>>();
+        let note = if encrypted {
+            "The api keys are encrypted, change them with `secrets rotate`"
+        } else {
+            ""
+        };
+        let (status_text, status_color) = match status.read().as_ref() {
+            Some(Ok(message)) => (message.to_string(), "#228a44"),
+            Some(Err(e)) => (e.clone(), "#a63654"),
+            None => (String::new(), "inherit"),
+        };
+
+        rsx! {
+            div { class: "font2 font-color-main", style: "display: flex; flex-direction: column; gap: 4px; padding: 10px; overflow-y: auto; height: 100%;",
+                span { "{note}" }
+                for (idx, label, kind, keybind, value, error) in rows.into_iter() {
+                    div { style: "display: flex; align-items: center; gap: 8px;",
+                        span { style: "width: 200px;", "{label}" }
+                        if !keybind {
+                            input {
+                                class: "font2 font-color-main color-3",
+                                style: "flex: 1; border: none; padding: 4px 10px; outline: none;",
+                                r#type: "{kind}",
+                                spellcheck: "false",
+                                value: "{value}",
+                                oninput: move |event| values.write()[idx] = event.value()
+                            }
+                        } else {
+                            // The next key press, with its modifiers, becomes the value
+                            input {
+                                class: "font2 font-color-main color-3",
+                                style: "flex: 1; border: none; padding: 4px 10px; outline: none;",
+                                r#type: "text",
+                                readonly: true,
+                                placeholder: "Press the keys",
+                                value: "{value}",
+                                prevent_default: "onkeydown",
+                                onkeydown: move |event| {
+                                    if let Some(keybind) = Keybind::pressed(event.modifiers(), event.code()) {
+                                        values.write()[idx] = keybind.to_string();
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    span { style: "color: #a63654; margin-left: 208px;", "{error}" }
+                }
+                button {
+                    class: "font-color-main color-3",
+                    style: "border: none; cursor: pointer; padding: 4px 10px;",
+                    onclick: save,
+                    "Save"
+                }
+                span { style: "color: {status_color};", "{status_text}" }
+            }
+        }
+    }
+
+    fn name(&self) -> String {
+        "Settings".to_string()
+    }
+
+    fn descriptor(&self) -> Option<WidgetDescriptor> {
+        Some(WidgetDescriptor {
+            name: Self::NAME.to_string(),
+            params: serde_json::Value::Null,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{apply, FIELDS};
+    use crate::config::Config;
+
+    fn values(config: &Config) -> Vec<String> {
+        FIELDS.iter().map(|field| (field.get)(config)).collect()
+    }
+
+    #[test]
+    fn fields_round_trip() {
+        let config: Config = toml::from_str(
+            r#"
+            [rate_limit.upbit]
+            capacity = 10
+            refill_per_sec = 8.0
+
+            [http]
+            timeout_ms = 3000
+
+            [orderbook]
+            whale_threshold = 12.5
+
+            [convert]
+            bridges = ["USDT", "BTC"]
+
+            [keybinds]
+            close_window = "Alt+Q"
+
+            [console]
+            scrollback_lines = 200
+            "#,
+        )
+        .unwrap();
+
+        let edited = apply(&config, &values(&config), false).unwrap();
+        assert_eq!(values(&edited), values(&config));
+
+        // And through the file format
+        let text = toml::to_string(&edited).unwrap();
+        let parsed: Config = toml::from_str(&text).unwrap();
+        assert_eq!(values(&parsed), values(&config));
+        assert!(parsed.upbit.is_none());
+    }
+
+    #[test]
+    fn invalid_values_are_reported_per_field() {
+        let config: Config = toml::from_str("").unwrap();
+        let mut values = values(&config);
+        let timeout = FIELDS
+            .iter()
+            .position(|field| field.label == "Http timeout (ms)")
+            .unwrap();
+        values[timeout] = "soon".to_string();
+
+        let errors = apply(&config, &values, false).unwrap_err();
+        assert!(errors[timeout].is_some());
+        assert_eq!(errors.iter().filter(|e| e.is_some()).count(), 1);
+    }
+}
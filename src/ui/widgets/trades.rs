@@ -0,0 +1,218 @@
+use futures::FutureExt;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    currency::Currency,
+    exchange::{execute_if, Exchange, Exchanges, RealtimeData, Trade},
+    select_ex,
+    utils::broadcaster::Subscription,
+};
+
+use super::{BoxedWidget, Widget, WidgetDescriptor};
+
+use dioxus::prelude::*;
+
+/// Number of trades shown, also the number fetched to seed the tape.
+const TAPE_LIMIT: usize = 100;
+
+/// Latest public trades of a pair, newest on top.
+pub struct TradesWidget {
+    pair: (Currency, Currency),
+    exchange_name: String,
+    exchanges: Exchanges,
+    subscription: Subscription<RealtimeData>,
+}
+
+impl TradesWidget {
+    pub const NAME: &'static str = "Trades";
+
+    pub fn new(
+        exchange_name: &str,
+        pair: (Currency, Currency),
+        exchanges: Exchanges,
+    ) -> Option<Self> {
+        let name = exchange_name.to_string();
+        let subscription = select_ex!(exchanges, name, |exchange| exchange.subscribe(pair, None))?;
+
+        Some(Self {
+            pair,
+            exchange_name: name,
+            exchanges,
+            subscription,
+        })
+    }
+
+    pub fn from_descriptor(
+        descriptor: &WidgetDescriptor,
+        exchanges: &Exchanges,
+    ) -> Option<BoxedWidget> {
+        let params: TradesParams = serde_json::from_value(descriptor.params.clone()).ok()?;
+        TradesWidget::new(&params.exchange, params.pair, exchanges.clone()).map(Into::into)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct TradesParams {
+    exchange: String,
+    pair: (Currency, Currency),
+}
+
+/// Merges trades into the tape, newest first.
+/// The same trade can arrive from both the backfill and the stream, so trades already
+/// on the tape are dropped by their id. Trades without an id can't be told apart
+/// from equal ones made in the same millisecond, so all of them are kept.
+fn merge(tape: &mut Vec<Trade>, trades: impl IntoIterator<Item = Trade>) {
+    for trade in trades {
+        let is_known = trade.id.is_some() && tape.iter().any(|known| known.id == trade.id);
+        if !is_known {
+            tape.push(trade);
+        }
+    }
+    // Stable, trades of the same millisecond stay in the order they arrived
+    tape.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+    tape.truncate(TAPE_LIMIT);
+}
+
+impl Widget for TradesWidget {
+    fn render(&self) -> Element {
+        let subscription = self.subscription.clone();
+        let exchanges = self.exchanges.clone();
+        let exchange_name = self.exchange_name.clone();
+        let pair = self.pair;
+
+        let mut tape = use_signal(Vec::<Trade>::new);
+
+        // Backfill, the stream only delivers trades made after subscribing
+        use_future(move || {
+            let exchanges = exchanges.clone();
+            let exchange_name = exchange_name.clone();
+            async move {
+                let fetch = select_ex!(exchanges, exchange_name, |exchange| {
+                    async move {
+                        exchange
+                            .recent_trades(pair, None, TAPE_LIMIT)
+                            .await
+                            .map_err(|e| e.to_string())
+                    }
+                    .boxed_local()
+                });
+                let Some(fetch) = fetch else {
+                    return;
+                };
+
+                match fetch.await {
+                    Ok(trades) => merge(&mut tape.write(), trades),
+                    Err(e) => tracing::warn!("Failed to backfill trades of {:?}: {}", pair, e),
+                }
+            }
+        });
+
+        use_future(move || {
+            let subscription = subscription.clone();
+            async move {
+                loop {
+                    let RealtimeData::Trade(trade) = subscription.recv().await else {
+                        continue;
+                    };
+                    if trade.pair == pair {
+                        merge(&mut tape.write(), [trade]);
+                    }
+                }
+            }
+        });
+
+        let rows = tape
+            .read()
+            .iter()
+            .map(|trade| {
+                let time = chrono::DateTime::from_timestamp_millis(trade.timestamp)
+                    .map(|time| time.format("%H:%M:%S").to_string())
+                    .unwrap_or_default();
+                let color = if trade.is_bid {
+                    "var(--bid)"
+                } else {
+                    "var(--ask)"
+                };
+                (
+                    time,
+                    trade.price.normalize(),
+                    trade.amount.normalize(),
+                    color,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        rsx! {
+            table { class: "font2 font-color-main", style: "width: 100%; border-collapse: collapse;",
+                thead {
+                    tr { style: "text-align: left;",
+                        th { "Time" }
+                        th { "Price" }
+                        th { "Amount" }
+                    }
+                }
+                tbody {
+                    for (time, price, amount, color) in rows.into_iter() {
+                        tr {
+                            td { "{time}" }
+                            td { style: "color: {color};", "{price}" }
+                            td { "{amount}" }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn name(&self) -> String {
+        format!(
+            "{} {}-{} trades",
+            self.exchange_name, self.pair.0, self.pair.1
+        )
+    }
+
+    fn descriptor(&self) -> Option<WidgetDescriptor> {
+        let params = TradesParams {
+            exchange: self.exchange_name.clone(),
+            pair: self.pair,
+        };
+
+        Some(WidgetDescriptor {
+            name: Self::NAME.to_string(),
+            params: serde_json::to_value(params).ok()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use super::merge;
+    use crate::currency::Currency;
+    use crate::exchange::Trade;
+    use crate::utils::Decimal;
+
+    #[test]
+    fn duplicates_are_dropped_by_id() {
+        let trade = |id: Option<&str>| Trade {
+            pair: (Currency::BTC, Currency::KRW),
+            timestamp: 1_000,
+            price: Decimal::from_str("100").unwrap(),
+            amount: Decimal::ONE,
+            is_bid: true,
+            id: id.map(str::to_string),
+        };
+
+        let mut tape = vec![trade(Some("1")), trade(Some("2"))];
+        // Equal trades of the same millisecond are kept, unless they have the same id
+        merge(&mut tape, [trade(Some("1")), trade(Some("3")), trade(Some("2"))]);
+        assert_eq!(
+            tape,
+            vec![trade(Some("1")), trade(Some("2")), trade(Some("3"))]
+        );
+
+        merge(&mut tape, [trade(None), trade(None)]);
+        assert_eq!(tape.len(), 5);
+    }
+}
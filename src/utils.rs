@@ -1,8 +1,20 @@
 pub mod async_helpers;
 pub mod broadcaster;
+pub mod cache;
+pub mod clock;
+pub mod compression;
+pub mod export;
 pub mod flag;
+pub mod format;
 pub mod http;
+pub mod ledger;
 pub mod maybe_trait;
+pub mod rate_limiter;
+pub mod rounding;
+pub mod server_time;
+pub mod signing;
+pub mod storage;
+pub mod throttle;
 
 mod decimal;
 pub use decimal::Decimal;
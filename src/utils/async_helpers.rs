@@ -7,6 +7,8 @@ use std::{
 
 use futures::Future;
 
+use crate::utils::maybe_trait::MaybeSend;
+
 #[cfg(not(target_arch = "wasm32"))]
 pub async fn sleep(duration: Duration) {
     tokio::time::sleep(duration).await;
@@ -17,28 +19,65 @@ pub async fn sleep(duration: Duration) {
     gloo_timers::future::sleep(duration).await;
 }
 
+/// Which runtime a task runs on, so busy actions can't delay the market data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskClass {
+    /// Websockets, broadcasting and anything feeding the views.
+    MarketData,
+    /// Scripts, exports and other user started work.
+    Action,
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 static RUNTIME: once_cell::sync::Lazy<tokio::runtime::Runtime> = once_cell::sync::Lazy::new(|| {
     tokio::runtime::Builder::new_multi_thread()
+        .thread_name("market-data")
         .enable_all()
         .build()
         .unwrap()
 });
 
+/// Runs the actions on at most half of the cores, the rest stay free for the market data.
 #[cfg(not(target_arch = "wasm32"))]
+static ACTION_RUNTIME: once_cell::sync::Lazy<tokio::runtime::Runtime> =
+    once_cell::sync::Lazy::new(|| {
+        let cores = std::thread::available_parallelism().map_or(2, |cores| cores.get());
+        tokio::runtime::Builder::new_multi_thread()
+            .thread_name("action")
+            .worker_threads((cores / 2).max(1))
+            .enable_all()
+            .build()
+            .unwrap()
+    });
+
+/// Spawns a market data task, see [`spawn_in`].
 pub fn spawn<T>(future: T) -> AsyncHandle<T::Output>
+where
+    T: Future + MaybeSend + 'static,
+    T::Output: MaybeSend + 'static,
+{
+    spawn_in(TaskClass::MarketData, future)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn spawn_in<T>(class: TaskClass, future: T) -> AsyncHandle<T::Output>
 where
     T: Future + Send + 'static,
     T::Output: Send + 'static,
 {
-    let join_handle = RUNTIME.spawn(future);
+    let runtime = match class {
+        TaskClass::MarketData => &RUNTIME,
+        TaskClass::Action => &ACTION_RUNTIME,
+    };
+    let join_handle = runtime.spawn(future);
     AsyncHandle {
         handle: join_handle,
     }
 }
 
+/// Browsers run everything on one thread, so both classes are spawned locally.
 #[cfg(any(target_arch = "wasm32"))]
-pub fn spawn<T>(future: T) -> AsyncHandle<T::Output>
+pub fn spawn_in<T>(_class: TaskClass, future: T) -> AsyncHandle<T::Output>
 where
     T: Future + 'static,
     T::Output: 'static,
@@ -134,3 +173,62 @@ where
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use super::{block_on, sleep, spawn_in, TaskClass};
+    use crate::utils::broadcaster::Broadcaster;
+
+    // Measures wall-clock latency while hogging the shared action runtime,
+    // so it flakes on a loaded machine and slows the script tests running beside it
+    #[ignore]
+    #[test]
+    fn busy_actions_do_not_delay_broadcasts() {
+        let stop = Arc::new(AtomicBool::new(false));
+        let actions = (0..50)
+            .map(|_| {
+                let stop = stop.clone();
+                spawn_in(TaskClass::Action, async move {
+                    while !stop.load(Ordering::Relaxed) {
+                        // Hog the worker for a while before yielding, like a heavy script
+                        let started = Instant::now();
+                        while started.elapsed() < Duration::from_millis(5) {}
+                        tokio::task::yield_now().await;
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let broadcaster = Broadcaster::new();
+        let subscription = broadcaster.subscribe();
+        let received = spawn_in(TaskClass::MarketData, async move {
+            let mut worst = Duration::ZERO;
+            for _ in 0..20 {
+                let sent_at: Instant = subscription.recv().await;
+                worst = worst.max(sent_at.elapsed());
+            }
+            worst
+        });
+        spawn_in(TaskClass::MarketData, async move {
+            for _ in 0..20 {
+                broadcaster.broadcast(Instant::now());
+                sleep(Duration::from_millis(10)).await;
+            }
+        });
+
+        let worst = block_on(received.await_handle());
+        stop.store(true, Ordering::Relaxed);
+        for action in actions {
+            block_on(action.await_handle());
+        }
+        assert!(
+            worst < Duration::from_millis(50),
+            "broadcast took {:?}",
+            worst
+        );
+    }
+}
@@ -1,11 +1,18 @@
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
 
 use async_channel::{Receiver as Rx, Sender as Tx};
 use parking_lot::Mutex;
 
+/// Number of items buffered per subscriber before the oldest ones are dropped.
+const DEFAULT_CAPACITY: usize = 1024;
+
 #[derive(Clone)]
 pub struct Broadcaster<T> {
-    subscriptions: Arc<Mutex<Vec<Tx<T>>>>,
+    subscriptions: Arc<Mutex<Vec<(Tx<T>, Arc<AtomicUsize>)>>>,
+    capacity: usize,
 }
 
 impl<T> Broadcaster<T>
@@ -13,27 +20,50 @@ where
     T: Clone,
 {
     pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    /// Creates a broadcaster buffering at most `capacity` items per subscriber.
+    pub fn with_capacity(capacity: usize) -> Self {
         Self {
             subscriptions: Arc::new(Mutex::new(Vec::new())),
+            capacity: capacity.max(1),
         }
     }
 
     /// Broadcasts data to all subscribers.
+    /// If a subscriber's buffer is full, its oldest item is dropped and counted as lagged.
     /// If a subscriber is no longer listening, it is removed from the list.
     pub fn broadcast(&self, data: T) {
         let mut subscriptions = self.subscriptions.lock();
-        subscriptions.retain(|tx| tx.try_send(data.clone()).is_ok());
+        subscriptions.retain(|(tx, lagged)| match tx.force_send(data.clone()) {
+            Ok(Some(_)) => {
+                lagged.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            Ok(None) => true,
+            Err(_) => false,
+        });
+    }
+
+    /// Number of subscriptions still listening.
+    pub fn subscriber_count(&self) -> usize {
+        let mut subscriptions = self.subscriptions.lock();
+        subscriptions.retain(|(tx, _)| !tx.is_closed());
+        subscriptions.len()
     }
 
     /// Subscribes to the broadcaster.
     /// Returns a receiver that will receive all data broadcasted.
     pub fn subscribe(&self) -> Subscription<T> {
         let mut subscriptions = self.subscriptions.lock();
-        let (tx, rx) = async_channel::unbounded();
-        subscriptions.push(tx);
+        let (tx, rx) = async_channel::bounded(self.capacity);
+        let lagged = Arc::new(AtomicUsize::new(0));
+        subscriptions.push((tx, lagged.clone()));
         Subscription {
             receiver: rx,
             filter: None,
+            lagged,
         }
     }
 }
@@ -42,22 +72,47 @@ where
 pub struct Subscription<T> {
     receiver: Rx<T>,
     filter: Option<Arc<dyn Fn(&T) -> bool + Send + Sync>>,
+    lagged: Arc<AtomicUsize>,
 }
 
 impl<T> Subscription<T> {
+    fn accepts(&self, data: &T) -> bool {
+        self.filter.as_ref().map_or(true, |filter| filter(data))
+    }
+
     /// Receives data from the broadcaster.
     /// The function will panic if the broadcaster has been dropped.
     pub async fn recv(&self) -> T {
+        self.recv_or_closed()
+            .await
+            .expect("the broadcaster was dropped")
+    }
+
+    /// Like `recv`, but returns None once the broadcaster has been dropped.
+    pub async fn recv_or_closed(&self) -> Option<T> {
         loop {
-            let data = self.receiver.recv().await.unwrap();
-            if let Some(filter) = &self.filter {
-                if filter(&data) {
-                    return data;
-                }
-            } else {
-                return data;
+            let data = self.receiver.recv().await.ok()?;
+            if self.accepts(&data) {
+                return Some(data);
+            }
+        }
+    }
+
+    /// Receives the newest data, skipping everything buffered before it.
+    /// Waits if nothing is buffered. Useful for snapshots like orderbooks.
+    pub async fn recv_latest(&self) -> T {
+        let mut latest = self.recv().await;
+        while let Ok(data) = self.receiver.try_recv() {
+            if self.accepts(&data) {
+                latest = data;
             }
         }
+        latest
+    }
+
+    /// Number of items dropped because this subscription fell behind.
+    pub fn lagged(&self) -> usize {
+        self.lagged.load(Ordering::Relaxed)
     }
 
     /// Clears the subscription buffer.
@@ -77,6 +132,55 @@ impl<T> Subscription<T> {
         Self {
             receiver: self.receiver,
             filter: Some(Arc::new(filter)),
+            lagged: self.lagged,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::Broadcaster;
+
+    #[tokio::test]
+    async fn slow_subscriber_stays_bounded() {
+        let broadcaster = Broadcaster::with_capacity(4);
+        let subscription = broadcaster.subscribe();
+
+        for i in 0..1000 {
+            broadcaster.broadcast(i);
+        }
+
+        assert_eq!(subscription.receiver.len(), 4);
+        assert_eq!(subscription.lagged(), 996);
+        // The oldest items were overwritten
+        assert_eq!(subscription.recv().await, 996);
+    }
+
+    #[tokio::test]
+    async fn closed_once_the_broadcaster_is_dropped() {
+        let broadcaster = Broadcaster::new();
+        let subscription = broadcaster.subscribe();
+
+        broadcaster.broadcast(1);
+        drop(broadcaster);
+        assert_eq!(subscription.recv_or_closed().await, Some(1));
+        assert_eq!(subscription.recv_or_closed().await, None);
+    }
+
+    #[tokio::test]
+    async fn recv_latest_skips_intermediates() {
+        let broadcaster = Broadcaster::new();
+        let subscription = broadcaster.subscribe().filter(|i| i % 2 == 0);
+
+        for i in 1..=5 {
+            broadcaster.broadcast(i);
+        }
+
+        assert_eq!(subscription.recv_latest().await, 4);
+        assert!(subscription.receiver.is_empty());
+
+        broadcaster.broadcast(6);
+        assert_eq!(subscription.recv_latest().await, 6);
+        assert_eq!(subscription.lagged(), 0);
+    }
+}
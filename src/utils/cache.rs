@@ -0,0 +1,306 @@
+//! Values kept for a short time, so repeated requests for the same data share one fetch.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use async_channel::{Receiver as AsyncRx, Sender as AsyncTx};
+use parking_lot::Mutex;
+
+use crate::utils::server_time;
+
+/// Hits and misses of a cache since it was created.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    /// Requests answered from the cache, including those that waited for another's fetch.
+    pub hits: u64,
+    /// Requests that fetched.
+    pub misses: u64,
+}
+
+struct Slot<V> {
+    /// The value and the time it was fetched.
+    value: Option<(i64, V)>,
+    /// Closed once the fetch in flight is done, either way.
+    in_flight: Option<AsyncRx<()>>,
+    /// Bumped when the value is dropped, a fetch started before it is not stored.
+    generation: u64,
+}
+
+impl<V> Default for Slot<V> {
+    fn default() -> Self {
+        Self {
+            value: None,
+            in_flight: None,
+            generation: 0,
+        }
+    }
+}
+
+impl<V> Slot<V> {
+    fn invalidate(&mut self) {
+        self.value = None;
+        self.generation += 1;
+    }
+}
+
+/// Cache of fetched values, each kept for the ttl it is requested with.
+/// Concurrent requests for a key that is not cached wait for a single fetch.
+pub struct TtlCache<K, V> {
+    slots: Mutex<HashMap<K, Slot<V>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<K, V> Default for TtlCache<K, V> {
+    fn default() -> Self {
+        Self {
+            slots: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+}
+
+/// Marks the fetch of `key` done when dropped, also if the fetching future is dropped.
+struct InFlight<'a, K: Eq + Hash, V> {
+    cache: &'a TtlCache<K, V>,
+    key: K,
+    _done: AsyncTx<()>,
+}
+
+impl<K: Eq + Hash, V> Drop for InFlight<'_, K, V> {
+    fn drop(&mut self) {
+        if let Some(slot) = self.cache.slots.lock().get_mut(&self.key) {
+            slot.in_flight = None;
+        }
+    }
+}
+
+impl<K, V> TtlCache<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The value of `key` if it was fetched within `ttl`, otherwise the result of `fetch`.
+    /// Failed fetches are not cached, requests waiting for one fetch again.
+    pub async fn get_or_fetch<F, Fut, E>(&self, key: K, ttl: Duration, fetch: F) -> Result<V, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>>,
+    {
+        let (in_flight, generation) = loop {
+            let fetching = {
+                let mut slots = self.slots.lock();
+                let slot = slots.entry(key.clone()).or_default();
+                if let Some((fetched_at, value)) = &slot.value {
+                    if server_time::local_millis() - fetched_at < ttl.as_millis() as i64 {
+                        self.hits.fetch_add(1, Ordering::Relaxed);
+                        return Ok(value.clone());
+                    }
+                }
+
+                match slot.in_flight.clone() {
+                    Some(fetching) => fetching,
+                    None => {
+                        let (done, fetching) = async_channel::bounded(1);
+                        slot.in_flight = Some(fetching);
+                        let in_flight = InFlight {
+                            cache: self,
+                            key: key.clone(),
+                            _done: done,
+                        };
+                        break (in_flight, slot.generation);
+                    }
+                }
+            };
+
+            // Nothing is ever sent, this returns once the fetch is done
+            let _ = fetching.recv().await;
+        };
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let result = fetch().await;
+        if let Ok(value) = &result {
+            // Invalidated while fetching, the value may predate what invalidated it
+            match self.slots.lock().get_mut(&key) {
+                Some(slot) if slot.generation == generation => {
+                    slot.value = Some((server_time::local_millis(), value.clone()));
+                }
+                _ => {}
+            }
+        }
+        drop(in_flight);
+        result
+    }
+
+    /// Drops the cached value of `key`, the next request fetches it again.
+    /// A fetch in flight is still returned to its requests, but not cached.
+    pub fn invalidate(&self, key: &K) {
+        if let Some(slot) = self.slots.lock().get_mut(key) {
+            slot.invalidate();
+        }
+    }
+
+    /// Drops every cached value, fetches in flight are not cached either.
+    pub fn clear(&self) {
+        for slot in self.slots.lock().values_mut() {
+            slot.invalidate();
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::{CacheStats, TtlCache};
+    use crate::utils::async_helpers;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    async fn fetch_slowly(fetches: &AtomicUsize, value: u32) -> Result<u32, String> {
+        fetches.fetch_add(1, Ordering::Relaxed);
+        async_helpers::sleep(Duration::from_millis(50)).await;
+        Ok(value)
+    }
+
+    #[tokio::test]
+    async fn concurrent_requests_share_one_fetch() {
+        let cache = Arc::new(TtlCache::<&str, u32>::new());
+        let fetches = Arc::new(AtomicUsize::new(0));
+
+        let requests = (0..10)
+            .map(|_| {
+                let cache = cache.clone();
+                let fetches = fetches.clone();
+                tokio::spawn(async move {
+                    cache
+                        .get_or_fetch("book", TTL, || fetch_slowly(&fetches, 7))
+                        .await
+                })
+            })
+            .collect::<Vec<_>>();
+        for request in requests {
+            assert_eq!(request.await.unwrap(), Ok(7));
+        }
+
+        assert_eq!(fetches.load(Ordering::Relaxed), 1);
+        assert_eq!(cache.stats(), CacheStats { hits: 9, misses: 1 });
+    }
+
+    #[tokio::test]
+    async fn expired_and_invalidated_values_are_fetched_again() {
+        let cache = TtlCache::<&str, u32>::new();
+        let fetches = AtomicUsize::new(0);
+
+        let short = Duration::from_millis(20);
+        assert_eq!(
+            cache
+                .get_or_fetch("ticker", short, || fetch_slowly(&fetches, 1))
+                .await,
+            Ok(1)
+        );
+        assert_eq!(
+            cache
+                .get_or_fetch("ticker", short, || fetch_slowly(&fetches, 2))
+                .await,
+            Ok(1)
+        );
+        async_helpers::sleep(Duration::from_millis(30)).await;
+        assert_eq!(
+            cache
+                .get_or_fetch("ticker", short, || fetch_slowly(&fetches, 3))
+                .await,
+            Ok(3)
+        );
+
+        cache.invalidate(&"ticker");
+        assert_eq!(
+            cache
+                .get_or_fetch("ticker", TTL, || fetch_slowly(&fetches, 4))
+                .await,
+            Ok(4)
+        );
+        assert_eq!(fetches.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn fetch_in_flight_during_clear_is_not_cached() {
+        let cache = Arc::new(TtlCache::<&str, u32>::new());
+        let fetches = Arc::new(AtomicUsize::new(0));
+
+        let stale = {
+            let cache = cache.clone();
+            let fetches = fetches.clone();
+            tokio::spawn(async move {
+                cache
+                    .get_or_fetch("balance", TTL, || fetch_slowly(&fetches, 1))
+                    .await
+            })
+        };
+        async_helpers::sleep(Duration::from_millis(10)).await;
+        cache.clear();
+        assert_eq!(stale.await.unwrap(), Ok(1));
+
+        assert_eq!(
+            cache
+                .get_or_fetch("balance", TTL, || fetch_slowly(&fetches, 2))
+                .await,
+            Ok(2)
+        );
+        assert_eq!(fetches.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn failures_are_not_cached() {
+        let cache = TtlCache::<&str, u32>::new();
+
+        let failed = cache
+            .get_or_fetch("balance", TTL, || async {
+                Err::<u32, _>("timeout".to_string())
+            })
+            .await;
+        assert_eq!(failed, Err("timeout".to_string()));
+
+        let fetched = cache
+            .get_or_fetch("balance", TTL, || async { Ok::<_, String>(5) })
+            .await;
+        assert_eq!(fetched, Ok(5));
+        assert_eq!(cache.stats(), CacheStats { hits: 0, misses: 2 });
+    }
+
+    #[tokio::test]
+    async fn dropped_fetch_does_not_block_others() {
+        let cache = TtlCache::<&str, u32>::new();
+        let fetches = AtomicUsize::new(0);
+
+        let abandoned = cache.get_or_fetch("book", TTL, || fetch_slowly(&fetches, 1));
+        assert!(tokio::time::timeout(Duration::from_millis(10), abandoned)
+            .await
+            .is_err());
+
+        let fetched = tokio::time::timeout(
+            Duration::from_secs(1),
+            cache.get_or_fetch("book", TTL, || fetch_slowly(&fetches, 2)),
+        )
+        .await
+        .expect("waited for an abandoned fetch");
+        assert_eq!(fetched, Ok(2));
+    }
+}
@@ -0,0 +1,95 @@
+//! Time as scripts and orders see it. Scripts run on the real clock,
+//! backtests run them on a virtual clock that jumps ahead instead of sleeping.
+
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::{async_helpers, server_time};
+
+#[derive(thiserror::Error, Debug)]
+#[error("the backtest reached the end of its data")]
+pub struct Ended;
+
+#[derive(Clone, Default)]
+pub enum Clock {
+    #[default]
+    Real,
+    Virtual(Arc<VirtualClock>),
+}
+
+/// Starts in the past and only moves when slept on, it ends at `end`.
+pub struct VirtualClock {
+    now: AtomicI64,
+    end: i64,
+}
+
+impl Clock {
+    /// A virtual clock from `start` to `end`, in milliseconds.
+    pub fn virtual_between(start: i64, end: i64) -> Self {
+        Clock::Virtual(Arc::new(VirtualClock {
+            now: AtomicI64::new(start),
+            end,
+        }))
+    }
+
+    pub fn is_virtual(&self) -> bool {
+        matches!(self, Clock::Virtual(_))
+    }
+
+    pub fn now_millis(&self) -> i64 {
+        match self {
+            Clock::Real => server_time::local_millis(),
+            Clock::Virtual(clock) => clock.now.load(Ordering::Relaxed),
+        }
+    }
+
+    /// A virtual clock past its end, the real clock never ends.
+    pub fn ended(&self) -> bool {
+        match self {
+            Clock::Real => false,
+            Clock::Virtual(clock) => clock.now.load(Ordering::Relaxed) > clock.end,
+        }
+    }
+
+    /// Sleeps on the real clock, a virtual clock is moved ahead right away.
+    pub async fn sleep(&self, duration: Duration) {
+        match self {
+            Clock::Real => async_helpers::sleep(duration).await,
+            Clock::Virtual(_) => self.skip(duration),
+        }
+    }
+
+    /// Moves a virtual clock ahead, the real clock can't be.
+    pub fn skip(&self, duration: Duration) {
+        if let Clock::Virtual(clock) = self {
+            let millis = i64::try_from(duration.as_millis()).unwrap_or(i64::MAX);
+            let _ = clock
+                .now
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |now| {
+                    Some(now.saturating_add(millis))
+                });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::Clock;
+
+    #[tokio::test]
+    async fn virtual_sleeps_pass_at_once() {
+        let clock = Clock::virtual_between(1_000, 3_600_000);
+        let started = Instant::now();
+
+        clock.sleep(Duration::from_secs(60 * 60)).await;
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(clock.now_millis(), 3_601_000);
+        assert!(clock.ended());
+
+        assert!(!Clock::Real.ended());
+        assert!(!Clock::Real.is_virtual());
+    }
+}
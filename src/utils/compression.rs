@@ -0,0 +1,104 @@
+//! Decompression of the binary frames some exchanges push over websockets.
+
+use std::io::Read;
+
+use flate2::read::{DeflateDecoder, GzDecoder};
+use serde::{Deserialize, Serialize};
+
+/// Compression of the binary frames of a websocket.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Codec {
+    #[default]
+    None,
+    Gzip,
+    /// Raw deflate without a zlib header.
+    Deflate,
+}
+
+/// Decompresses a gzip frame, as pushed by Huobi.
+pub fn gunzip(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    GzDecoder::new(data).read_to_end(&mut decompressed)?;
+    Ok(decompressed)
+}
+
+/// Decompresses a raw deflate frame without a zlib header, as pushed by OKX.
+pub fn inflate(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    DeflateDecoder::new(data).read_to_end(&mut decompressed)?;
+    Ok(decompressed)
+}
+
+/// Decompresses a gzip frame into text, invalid utf-8 is an error.
+pub fn gunzip_text(data: &[u8]) -> std::io::Result<String> {
+    String::from_utf8(gunzip(data)?)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+/// Decompresses a frame with `codec`.
+/// Frames that fail to decompress are returned unchanged,
+/// as exchanges send some messages like pongs uncompressed.
+pub fn decompress_frame(codec: Codec, frame: Vec<u8>) -> Vec<u8> {
+    let decompressed = match codec {
+        Codec::None => return frame,
+        Codec::Gzip => gunzip(&frame),
+        Codec::Deflate => inflate(&frame),
+    };
+    decompressed.unwrap_or_else(|e| {
+        tracing::debug!(
+            "Frame is not {:?} compressed, passing it as is: {}",
+            codec,
+            e
+        );
+        frame
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use flate2::write::{DeflateEncoder, GzEncoder};
+    use flate2::Compression;
+
+    use super::{decompress_frame, gunzip, gunzip_text, inflate, Codec};
+
+    const MESSAGE: &str = r#"{"ch":"market.btcusdt.depth.step0","tick":{"bids":[[100.5,1]]}}"#;
+
+    #[test]
+    fn gzip_round_trip() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(MESSAGE.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        assert_eq!(gunzip(&compressed).unwrap(), MESSAGE.as_bytes());
+        assert_eq!(gunzip_text(&compressed).unwrap(), MESSAGE);
+        assert!(gunzip(MESSAGE.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn deflate_round_trip() {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(MESSAGE.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        assert_eq!(inflate(&compressed).unwrap(), MESSAGE.as_bytes());
+    }
+
+    #[test]
+    fn frames_fall_back_when_not_compressed() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(MESSAGE.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        assert_eq!(
+            decompress_frame(Codec::Gzip, compressed.clone()),
+            MESSAGE.as_bytes()
+        );
+        assert_eq!(
+            decompress_frame(Codec::None, compressed.clone()),
+            compressed
+        );
+        assert_eq!(decompress_frame(Codec::Gzip, b"pong".to_vec()), b"pong");
+    }
+}
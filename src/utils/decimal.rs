@@ -46,6 +46,11 @@ impl Decimal {
     pub fn from_str(s: &str) -> Result<Self, rust_decimal::Error> {
         Ok(Decimal(rust_decimal::Decimal::from_str(s)?))
     }
+
+    /// The nearest f64, 0 if it does not fit, for drawing charts.
+    pub fn to_f64_lossy(self) -> f64 {
+        self.0.to_f64().unwrap_or_default()
+    }
 }
 
 impl One for Decimal {
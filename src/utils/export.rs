@@ -0,0 +1,181 @@
+//! Exports of market data for offline analysis.
+//! On desktop the files are written to the given path,
+//! on wasm the browser downloads them under the file name of the path.
+
+use std::time::Duration;
+
+use crate::currency::Currency;
+use crate::exchange::{candle, Exchange, Orderbook, Ticker};
+
+/// Candles as csv with a header row, oldest first.
+/// Decimals are written in full precision, never in scientific notation.
+pub fn candles_csv(tickers: &[Ticker]) -> String {
+    let mut csv = "timestamp,open,high,low,close\n".to_string();
+    for ticker in tickers {
+        csv.push_str(&format!(
+            "{},{},{},{},{}\n",
+            ticker.timestamp, ticker.open, ticker.high, ticker.low, ticker.close
+        ));
+    }
+    csv
+}
+
+/// The orderbook as pretty json, decimals are written as strings to keep their precision.
+pub fn orderbook_json(orderbook: &Orderbook) -> String {
+    serde_json::to_string_pretty(orderbook).unwrap_or_default()
+}
+
+/// Parses intervals like `1m`, `15m`, `1h` or `1d`.
+pub fn parse_interval(interval: &str) -> Option<Duration> {
+    let unit = interval.chars().last()?;
+    let count = &interval[..interval.len() - unit.len_utf8()];
+    let count: u64 = count.parse().ok().filter(|count| *count > 0)?;
+    let unit = match unit {
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        _ => return None,
+    };
+    Some(Duration::from_secs(count * unit))
+}
+
+/// Writes the candle history of `pair`, merged into candles of `interval`.
+/// Returns the number of candles written.
+pub async fn export_candles<E>(
+    exchange: &E,
+    pair: (Currency, Currency),
+    interval: Duration,
+    path: &str,
+) -> Result<usize, String>
+where
+    E: Exchange,
+{
+    let history = exchange
+        .candlesticks(pair, None)
+        .await
+        .map_err(|e| e.to_string())?;
+    let tickers = candle::resample(&history.tickers, interval).ok_or_else(|| {
+        format!(
+            "{} serves candles coarser than {:?}, use a longer interval",
+            E::NAME,
+            interval
+        )
+    })?;
+
+    save(path, &candles_csv(&tickers), "text/csv")?;
+    Ok(tickers.len())
+}
+
+/// Writes the current orderbook of `pair`.
+pub async fn export_orderbook<E>(
+    exchange: &E,
+    pair: (Currency, Currency),
+    path: &str,
+) -> Result<(), String>
+where
+    E: Exchange,
+{
+    let orderbook = exchange
+        .orderbook(pair, None)
+        .await
+        .map_err(|e| e.to_string())?
+        .normalize();
+
+    save(path, &orderbook_json(&orderbook), "application/json")
+}
+
+/// Writes `contents` to `path`, the browser downloads it as a file instead.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save(path: &str, contents: &str, _mime: &str) -> Result<(), String> {
+    std::fs::write(path, contents).map_err(|e| format!("Failed to write {}: {}", path, e))
+}
+
+#[cfg(any(target_arch = "wasm32"))]
+pub fn save(path: &str, contents: &str, mime: &str) -> Result<(), String> {
+    use wasm_bindgen::{JsCast, JsValue};
+
+    let js_error = |e: JsValue| format!("Failed to download {}: {:?}", path, e);
+    let name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+
+    let parts = js_sys::Array::of1(&JsValue::from_str(contents));
+    let mut options = web_sys::BlobPropertyBag::new();
+    options.type_(mime);
+    let blob =
+        web_sys::Blob::new_with_str_sequence_and_options(&parts, &options).map_err(js_error)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob).map_err(js_error)?;
+
+    let anchor = web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or_else(|| format!("Failed to download {}: no document", path))?
+        .create_element("a")
+        .map_err(js_error)?
+        .dyn_into::<web_sys::HtmlAnchorElement>()
+        .map_err(|_| format!("Failed to download {}: not an anchor", path))?;
+    anchor.set_href(&url);
+    anchor.set_download(name);
+    anchor.click();
+
+    let _ = web_sys::Url::revoke_object_url(&url);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    use super::{candles_csv, orderbook_json, parse_interval};
+    use crate::currency::Currency;
+    use crate::exchange::{Orderbook, Ticker, Unit};
+    use crate::utils::Decimal;
+
+    fn dec(value: &str) -> Decimal {
+        Decimal::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn candles_in_full_precision() {
+        let tickers = vec![Ticker {
+            timestamp: 1700000000000,
+            open: dec("0.00000001"),
+            close: dec("0.000000012345678"),
+            low: dec("0.00000001"),
+            high: dec("123456789012.123456789"),
+        }];
+
+        assert_eq!(
+            candles_csv(&tickers),
+            "timestamp,open,high,low,close\n\
+             1700000000000,0.00000001,123456789012.123456789,0.00000001,0.000000012345678\n"
+        );
+    }
+
+    #[test]
+    fn orderbook_decimals_are_strings() {
+        let orderbook = Orderbook {
+            pair: (Currency::BTC, Currency::KRW),
+            bids: vec![Unit {
+                price: dec("0.00000001"),
+                amount: dec("100000000000"),
+            }],
+            asks: Vec::new(),
+        };
+
+        let value: serde_json::Value = serde_json::from_str(&orderbook_json(&orderbook)).unwrap();
+        assert_eq!(value["bids"][0]["price"], "0.00000001");
+        assert_eq!(value["bids"][0]["amount"], "100000000000");
+    }
+
+    #[test]
+    fn intervals() {
+        assert_eq!(parse_interval("15m"), Some(Duration::from_secs(15 * 60)));
+        assert_eq!(parse_interval("1h"), Some(Duration::from_secs(60 * 60)));
+        assert_eq!(
+            parse_interval("1d"),
+            Some(Duration::from_secs(24 * 60 * 60))
+        );
+        assert_eq!(parse_interval("0m"), None);
+        assert_eq!(parse_interval("m"), None);
+        assert_eq!(parse_interval("15"), None);
+    }
+}
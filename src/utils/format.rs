@@ -0,0 +1,214 @@
+//! Formatting of prices and amounts for display.
+//! Separators come from the `[format]` config section.
+
+use rust_decimal::RoundingStrategy;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::utils::Decimal;
+
+/// Digits kept by [`format_amount`], the integer part is never cut.
+const SIGNIFICANT_DIGITS: u32 = 6;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct FormatConfig {
+    /// Inserted between groups of three integer digits, may be empty.
+    pub thousands_separator: String,
+    pub decimal_separator: String,
+}
+
+impl Default for FormatConfig {
+    fn default() -> Self {
+        Self {
+            thousands_separator: ",".to_string(),
+            decimal_separator: ".".to_string(),
+        }
+    }
+}
+
+/// Formats a price with grouped thousands.
+/// With a tick size the price is rounded to its precision and keeps its trailing zeros,
+/// so a column of prices lines up. Without one trailing zeros are trimmed.
+pub fn format_price(price: Decimal, tick_size: Option<Decimal>) -> String {
+    format_price_with(price, tick_size, &Config::get().format)
+}
+
+/// Formats an amount truncated to six significant digits, with grouped thousands.
+/// With `suffix`, large amounts are shortened to K, M or B.
+pub fn format_amount(amount: Decimal, suffix: bool) -> String {
+    format_amount_with(amount, suffix, &Config::get().format)
+}
+
+/// Formats a fraction as a percentage rounded to two decimals, e.g. 0.1234 as `12.34%`.
+pub fn format_percent(fraction: Decimal) -> String {
+    format!(
+        "{}%",
+        (fraction * Decimal(100.into())).round_dp(2).normalize()
+    )
+}
+
+/// The tick size that shows each of `prices` exactly, None if there are none.
+pub fn tick_size(prices: impl IntoIterator<Item = Decimal>) -> Option<Decimal> {
+    let scale = prices
+        .into_iter()
+        .map(|price| price.0.normalize().scale())
+        .max()?;
+    Some(Decimal(rust_decimal::Decimal::new(1, scale)))
+}
+
+fn format_price_with(price: Decimal, tick_size: Option<Decimal>, config: &FormatConfig) -> String {
+    let price = match tick_size {
+        Some(tick_size) => {
+            let scale = tick_size.0.normalize().scale();
+            let mut rounded = price.0.round_dp(scale);
+            rounded.rescale(scale);
+            rounded
+        }
+        None => price.0.normalize(),
+    };
+    group(price, config)
+}
+
+fn format_amount_with(amount: Decimal, suffix: bool, config: &FormatConfig) -> String {
+    let suffixes = [
+        (rust_decimal::Decimal::new(1_000_000_000, 0), "B"),
+        (rust_decimal::Decimal::new(1_000_000, 0), "M"),
+        (rust_decimal::Decimal::new(1_000, 0), "K"),
+    ];
+    let (amount, suffix) = suffixes
+        .into_iter()
+        .filter(|_| suffix)
+        .find(|(unit, _)| amount.0.abs() >= *unit)
+        .map_or((amount.0, ""), |(unit, suffix)| (amount.0 / unit, suffix));
+
+    format!("{}{}", group(significant(amount), config), suffix)
+}
+
+/// Truncates the fraction so at most [`SIGNIFICANT_DIGITS`] digits remain.
+fn significant(value: rust_decimal::Decimal) -> rust_decimal::Decimal {
+    let value = value.normalize();
+    if value.is_zero() {
+        return value;
+    }
+
+    // Position of the leading digit, 0 for the ones and negative in the fraction
+    let digits = value.mantissa().unsigned_abs().to_string().len() as i64;
+    let exponent = digits - 1 - value.scale() as i64;
+    let scale = (SIGNIFICANT_DIGITS as i64 - 1 - exponent).max(0) as u32;
+    value
+        .round_dp_with_strategy(scale, RoundingStrategy::ToZero)
+        .normalize()
+}
+
+fn group(value: rust_decimal::Decimal, config: &FormatConfig) -> String {
+    let text = value.abs().to_string();
+    let (integer, fraction) = match text.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (text.as_str(), None),
+    };
+
+    let mut grouped = String::new();
+    if value.is_sign_negative() && !value.is_zero() {
+        grouped.push('-');
+    }
+    for (i, digit) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            grouped.push_str(&config.thousands_separator);
+        }
+        grouped.push(digit);
+    }
+    if let Some(fraction) = fraction {
+        grouped.push_str(&config.decimal_separator);
+        grouped.push_str(fraction);
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format_amount_with, format_percent, format_price_with, tick_size, FormatConfig};
+    use crate::utils::Decimal;
+
+    fn dec(value: &str) -> Decimal {
+        Decimal::from_str(value).unwrap()
+    }
+
+    fn price(value: &str, tick_size: Option<&str>) -> String {
+        format_price_with(dec(value), tick_size.map(dec), &FormatConfig::default())
+    }
+
+    fn amount(value: &str, suffix: bool) -> String {
+        format_amount_with(dec(value), suffix, &FormatConfig::default())
+    }
+
+    #[test]
+    fn prices_across_magnitudes() {
+        assert_eq!(price("0.00000001", None), "0.00000001");
+        assert_eq!(price("0.000012340000", None), "0.00001234");
+        assert_eq!(price("0.5", None), "0.5");
+        assert_eq!(price("12.30", None), "12.3");
+        assert_eq!(price("999", None), "999");
+        assert_eq!(price("1000", None), "1,000");
+        assert_eq!(price("86123000", None), "86,123,000");
+        assert_eq!(price("1000000000", None), "1,000,000,000");
+        assert_eq!(price("-1234.5", None), "-1,234.5");
+    }
+
+    #[test]
+    fn prices_at_tick_precision() {
+        assert_eq!(price("86123000", Some("1000")), "86,123,000");
+        assert_eq!(price("100.5", Some("0.01")), "100.50");
+        assert_eq!(price("0.0000123456", Some("0.00000001")), "0.00001235");
+        assert_eq!(price("1234.56", Some("1")), "1,235");
+    }
+
+    #[test]
+    fn amounts_across_magnitudes() {
+        assert_eq!(amount("0", false), "0");
+        assert_eq!(amount("0.00000001", false), "0.00000001");
+        assert_eq!(amount("0.0000123456789", false), "0.0000123456");
+        assert_eq!(amount("1.23456789", false), "1.23456");
+        assert_eq!(amount("1234.56789", false), "1,234.56");
+        assert_eq!(amount("1234567.891", false), "1,234,567");
+        assert_eq!(amount("1000000000", false), "1,000,000,000");
+    }
+
+    #[test]
+    fn amounts_with_suffix() {
+        assert_eq!(amount("999.99", true), "999.99");
+        assert_eq!(amount("1500", true), "1.5K");
+        assert_eq!(amount("1234567.891", true), "1.23456M");
+        assert_eq!(amount("2500000000", true), "2.5B");
+        assert_eq!(amount("0.00000001", true), "0.00000001");
+    }
+
+    #[test]
+    fn configured_separators() {
+        let config = FormatConfig {
+            thousands_separator: ".".to_string(),
+            decimal_separator: ",".to_string(),
+        };
+        assert_eq!(
+            format_price_with(dec("1234567.5"), None, &config),
+            "1.234.567,5"
+        );
+        assert_eq!(format_amount_with(dec("1234.5"), false, &config), "1.234,5");
+    }
+
+    #[test]
+    fn tick_size_of_prices() {
+        assert_eq!(
+            tick_size([dec("100.5"), dec("100.25"), dec("101.000")]),
+            Some(dec("0.01"))
+        );
+        assert_eq!(tick_size([dec("86123000")]), Some(dec("1")));
+        assert_eq!(tick_size([]), None);
+    }
+
+    #[test]
+    fn percentages() {
+        assert_eq!(format_percent(dec("0.12346")), "12.35%");
+        assert_eq!(format_percent(dec("-0.5")), "-50%");
+    }
+}
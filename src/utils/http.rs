@@ -1,7 +1,307 @@
+use std::future::Future;
+use std::time::Duration;
+
 use once_cell::sync::Lazy;
 pub use reqwest::*;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::utils::async_helpers;
+
+/// Settings of the shared http client, read once when the client is first used.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ClientConfig {
+    /// Timeout of a whole request, so a hung endpoint fails instead of blocking forever.
+    pub timeout_ms: u64,
+    pub user_agent: String,
+    /// Proxy every request goes through, e.g. `http://127.0.0.1:8080`, for regions that need one.
+    pub proxy: Option<String>,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            timeout_ms: 10_000,
+            user_agent: concat!("Rsader/", env!("CARGO_PKG_VERSION")).to_string(),
+            proxy: None,
+        }
+    }
+}
 
+impl ClientConfig {
+    /// Fails if the proxy is not a valid url, checked with the rest of the config
+    /// so a typo is reported instead of connecting without the proxy.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn validate(&self) -> Result<()> {
+        if let Some(proxy) = &self.proxy {
+            Proxy::all(proxy.as_str())?;
+        }
+        Ok(())
+    }
+
+    /// The browser owns the proxy, so there is nothing to check.
+    #[cfg(any(target_arch = "wasm32"))]
+    pub fn validate(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Returns the client shared by all exchanges, configured by the `[http]` section.
 pub fn client() -> Client {
-    static CLIENT: Lazy<Client> = Lazy::new(|| Client::new());
+    static CLIENT: Lazy<Client> = Lazy::new(|| {
+        let config = &Config::get().http;
+        // The config was validated when loaded, so this only fails on a broken tls backend
+        client_with(
+            Duration::from_millis(config.timeout_ms),
+            &config.user_agent,
+            config.proxy.as_deref(),
+        )
+        .expect("failed to build the http client")
+    });
     CLIENT.clone()
 }
+
+/// Fails on an invalid proxy rather than silently connecting without it.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn client_with(timeout: Duration, user_agent: &str, proxy: Option<&str>) -> Result<Client> {
+    let mut builder = Client::builder().timeout(timeout).user_agent(user_agent);
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(Proxy::all(proxy)?);
+    }
+    builder.build()
+}
+
+/// The browser owns the timeouts, the user agent and the proxy of fetch requests, so all are ignored.
+#[cfg(any(target_arch = "wasm32"))]
+pub fn client_with(_timeout: Duration, _user_agent: &str, _proxy: Option<&str>) -> Result<Client> {
+    Ok(Client::new())
+}
+
+/// Where an exchange is reached, without trailing slashes.
+/// Each exchange has production defaults, fields set in `[base_urls.<exchange>]` replace them,
+/// e.g. to point the exchange at a mock server.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct BaseUrls {
+    pub rest: String,
+    /// Rest api of the futures market, empty if the exchange serves both from `rest`.
+    pub rest_futures: String,
+    /// Public websocket streaming the realtime data.
+    pub ws: String,
+}
+
+impl BaseUrls {
+    pub fn new(rest: &str, rest_futures: &str, ws: &str) -> Self {
+        Self {
+            rest: rest.to_string(),
+            rest_futures: rest_futures.to_string(),
+            ws: ws.to_string(),
+        }
+    }
+
+    /// The `defaults` with the overrides configured for `exchange`.
+    pub fn from_config(exchange: &str, defaults: Self) -> Self {
+        match Config::get().base_urls.get(exchange) {
+            Some(overrides) => defaults.overridden_by(overrides),
+            None => defaults,
+        }
+    }
+
+    fn overridden_by(self, overrides: &Self) -> Self {
+        let pick = |url: &String, default: String| match url.trim_end_matches('/') {
+            "" => default,
+            url => url.to_string(),
+        };
+        Self {
+            rest: pick(&overrides.rest, self.rest),
+            rest_futures: pick(&overrides.rest_futures, self.rest_futures),
+            ws: pick(&overrides.ws, self.ws),
+        }
+    }
+
+    /// Url of `path` on the rest api, `path` starts with a slash.
+    pub fn rest_url(&self, path: &str) -> String {
+        format!("{}{}", self.rest, path)
+    }
+
+    /// Url of `path` on the futures rest api, `path` starts with a slash.
+    pub fn futures_url(&self, path: &str) -> String {
+        format!("{}{}", self.rest_futures, path)
+    }
+}
+
+/// How transient http failures are retried by [`send_with_retry`].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one.
+    pub max_attempts: u32,
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay_ms: 200,
+            max_delay_ms: 5000,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Exponential backoff before the `attempt`th retry (starting from 1), capped by `max_delay_ms`.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self
+            .base_delay_ms
+            .saturating_mul(1 << (attempt - 1).min(16))
+            .min(self.max_delay_ms);
+
+        // Equal jitter, keeps at least half of the backoff so retries never hammer the server
+        let jitter = chrono::Utc::now().timestamp_subsec_nanos() as u64 % (exp / 2 + 1);
+        Duration::from_millis(exp / 2 + jitter)
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(header::RETRY_AFTER)?;
+    let secs = value.to_str().ok()?.trim().parse::<u64>().ok()?;
+    Some(Duration::from_secs(secs))
+}
+
+/// Sends the request, retrying on connection errors, 5xx and 429 responses.
+/// Other responses, including 4xx, are returned as is.
+///
+/// Requests with a streaming body cannot be cloned and are sent only once.
+/// Signed requests go through [`send_signed_with_retry`] instead, a copy would replay their nonce.
+pub async fn send_with_retry(request: RequestBuilder, policy: &RetryPolicy) -> Result<Response> {
+    if request.try_clone().is_none() {
+        return request.send().await;
+    }
+
+    send_signed_with_retry(
+        || std::future::ready(Ok(request.try_clone().expect("the body is not a stream"))),
+        policy,
+    )
+    .await
+}
+
+/// Like [`send_with_retry`], but sends the request `build` returns on each attempt,
+/// so every retry is signed again with a fresh nonce instead of being rejected as a replay.
+pub async fn send_signed_with_retry<F, Fut, E>(
+    mut build: F,
+    policy: &RetryPolicy,
+) -> std::result::Result<Response, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = std::result::Result<RequestBuilder, E>>,
+    E: From<Error>,
+{
+    let mut attempt = 1;
+    loop {
+        let last_attempt = attempt >= policy.max_attempts;
+        let wait = match build().await?.send().await {
+            Ok(response) if last_attempt || !is_retryable(response.status()) => {
+                return Ok(response)
+            }
+            Ok(response) => {
+                tracing::warn!(
+                    "http: {} responded {}, retrying ({}/{})",
+                    response.url(),
+                    response.status(),
+                    attempt,
+                    policy.max_attempts
+                );
+                retry_after(&response)
+                    .map(|wait| wait.min(Duration::from_millis(policy.max_delay_ms)))
+                    .unwrap_or_else(|| policy.backoff(attempt))
+            }
+            Err(e) if last_attempt || !(e.is_connect() || e.is_timeout() || e.is_request()) => {
+                return Err(e.into())
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "http: request failed {}, retrying ({}/{})",
+                    e,
+                    attempt,
+                    policy.max_attempts
+                );
+                policy.backoff(attempt)
+            }
+        };
+
+        async_helpers::sleep(wait).await;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{client_with, BaseUrls, ClientConfig, RetryPolicy};
+
+    #[test]
+    fn backoff_is_capped() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base_delay_ms: 100,
+            max_delay_ms: 1000,
+        };
+
+        for attempt in 1..10 {
+            let exp = (100u64 << (attempt - 1)).min(1000);
+            let wait = policy.backoff(attempt);
+            assert!(wait >= Duration::from_millis(exp / 2));
+            assert!(wait <= Duration::from_millis(exp));
+        }
+    }
+
+    #[test]
+    fn base_url_overrides() {
+        let defaults = BaseUrls::new("https://api.example.com", "https://fapi.example.com", "");
+        let overrides = BaseUrls {
+            rest: "http://127.0.0.1:8080/".to_string(),
+            ..Default::default()
+        };
+
+        let urls = defaults.overridden_by(&overrides);
+        assert_eq!(
+            urls.rest_url("/v1/orders"),
+            "http://127.0.0.1:8080/v1/orders"
+        );
+        assert_eq!(
+            urls.futures_url("/v1/depth"),
+            "https://fapi.example.com/v1/depth"
+        );
+    }
+
+    #[test]
+    fn invalid_proxies_fail() {
+        let config = |proxy: &str| ClientConfig {
+            proxy: Some(proxy.to_string()),
+            ..Default::default()
+        };
+        assert!(config("http://127.0.0.1:8080").validate().is_ok());
+        assert!(config("http://[::1").validate().is_err());
+        assert!(client_with(Duration::from_secs(1), "Rsader/test", Some("http://[::1")).is_err());
+    }
+
+    #[ignore]
+    #[tokio::test]
+    async fn timeout_on_blackhole() {
+        // Packets to this non-routable address are dropped, so the connection never completes
+        let client = client_with(Duration::from_millis(500), "Rsader/test", None).unwrap();
+        let started = std::time::Instant::now();
+        let error = client.get("http://10.255.255.1/").send().await.unwrap_err();
+
+        assert!(error.is_timeout(), "{}", error);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,549 @@
+//! Append-only ledger of the order lifecycle, one json object per line:
+//! orders placed, their fills, cancellations and withdrawals.
+//! On desktop the entries go to one file per day next to the configured path,
+//! on wasm they live in the local storage.
+
+use std::collections::{HashSet, VecDeque};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::currency::Currency;
+use crate::exchange::{OrderState, OrderToken, Side};
+use crate::utils::async_helpers::{self, TaskClass};
+use crate::utils::Decimal;
+
+/// Entries kept in the local storage, the oldest are dropped, wasm only.
+#[cfg(any(target_arch = "wasm32"))]
+const STORAGE_LIMIT: usize = 1000;
+
+#[cfg(any(target_arch = "wasm32"))]
+const STORAGE_KEY: &str = "ledger";
+
+/// Settled orders remembered, the oldest are forgotten past it.
+/// Orders are not polled that long after they closed.
+const SETTLED_LIMIT: usize = 10_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct LedgerConfig {
+    /// Base path of the files, `ledger.jsonl` is written as `ledger-<date>.jsonl`. Desktop only.
+    pub path: String,
+}
+
+impl Default for LedgerConfig {
+    fn default() -> Self {
+        Self {
+            path: "ledger.jsonl".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderKind {
+    Limit,
+    Market,
+    StopLimit,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum LedgerEvent {
+    /// An order was sent, or would have been in a dry run.
+    Order {
+        pair: (Currency, Currency),
+        side: Side,
+        #[serde(rename = "type")]
+        kind: OrderKind,
+        /// Limit price, None for market orders.
+        price: Option<Decimal>,
+        /// In the base currency, except for market bids which spend it in the quote currency.
+        amount: Decimal,
+        /// None if the order was not placed.
+        order_token: Option<OrderToken>,
+        /// `placed`, `dry run`, or the error the order failed with.
+        result: String,
+    },
+    /// An order was seen closed with some volume executed.
+    Fill {
+        order_token: OrderToken,
+        executed_volume: Decimal,
+        avg_price: Option<Decimal>,
+    },
+    Cancel {
+        order_token: OrderToken,
+        /// Volume executed before the cancellation, None if it failed.
+        executed_volume: Option<Decimal>,
+        result: String,
+    },
+    Withdraw {
+        currency: Currency,
+        amount: Decimal,
+        address: String,
+        network: Option<String>,
+        /// The withdrawal id, or the error it failed with.
+        result: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LedgerEntry {
+    /// Time of the event, in milliseconds.
+    pub timestamp: i64,
+    pub exchange: String,
+    #[serde(flatten)]
+    pub event: LedgerEvent,
+}
+
+impl LedgerEntry {
+    pub fn now(exchange: &str, event: LedgerEvent) -> Self {
+        Self {
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            exchange: exchange.to_string(),
+            event,
+        }
+    }
+}
+
+/// Lines waiting to be written, so recording never delays an order. The writes block, so they
+/// run with the actions and leave the market data runtime alone.
+static WRITER: Lazy<async_channel::Sender<(i64, String)>> = Lazy::new(|| {
+    let (sender, receiver) = async_channel::unbounded::<(i64, String)>();
+    async_helpers::spawn_in(TaskClass::Action, async move {
+        while let Ok((timestamp, line)) = receiver.recv().await {
+            append(timestamp, &line);
+        }
+    });
+    sender
+});
+
+/// Orders already recorded as filled or cancelled, so polling a closed order records it once
+/// and a cancelled one is not recorded as filled.
+static SETTLED: Lazy<Mutex<Settled>> = Lazy::new(|| Mutex::new(Settled::default()));
+
+#[derive(Default)]
+struct Settled {
+    orders: HashSet<OrderToken>,
+    /// Oldest first, to forget them past [`SETTLED_LIMIT`].
+    order: VecDeque<OrderToken>,
+}
+
+impl Settled {
+    /// Returns false if the order was already settled.
+    fn insert(&mut self, order_token: &OrderToken) -> bool {
+        if !self.orders.insert(order_token.clone()) {
+            return false;
+        }
+        self.order.push_back(order_token.clone());
+        if self.order.len() > SETTLED_LIMIT {
+            if let Some(oldest) = self.order.pop_front() {
+                self.orders.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+/// Appends the entry to the ledger in the background.
+pub fn record(entry: &LedgerEntry) {
+    match serde_json::to_string(entry) {
+        Ok(line) => {
+            let _ = WRITER.try_send((entry.timestamp, line));
+        }
+        Err(e) => tracing::warn!("Failed to serialize a ledger entry: {}", e),
+    }
+}
+
+/// Records the fill of a closed order, once per order.
+/// Exchanges report cancelled orders as closed too, so nothing is recorded
+/// for an order closed without executing or already recorded as cancelled.
+pub fn record_fill(
+    exchange: &str,
+    order_token: &OrderToken,
+    state: &OrderState,
+    executed_volume: Decimal,
+    avg_price: Option<Decimal>,
+) {
+    if *state != OrderState::Closed
+        || executed_volume.0.is_zero()
+        || !SETTLED.lock().insert(order_token)
+    {
+        return;
+    }
+
+    record(&LedgerEntry::now(
+        exchange,
+        LedgerEvent::Fill {
+            order_token: order_token.clone(),
+            executed_volume,
+            avg_price,
+        },
+    ));
+}
+
+/// Records the cancellation of an order, `executed_volume` is None if it failed.
+/// A cancelled order is not recorded as filled afterwards, its executed volume is in here.
+pub fn record_cancel(
+    exchange: &str,
+    order_token: &OrderToken,
+    executed_volume: Option<Decimal>,
+    result: String,
+) {
+    if executed_volume.is_some() && !SETTLED.lock().insert(order_token) {
+        return;
+    }
+
+    record(&LedgerEntry::now(
+        exchange,
+        LedgerEvent::Cancel {
+            order_token: order_token.clone(),
+            executed_volume,
+            result,
+        },
+    ));
+}
+
+/// The latest `count` entries, oldest first.
+pub fn tail(count: usize) -> Vec<LedgerEntry> {
+    let lines = read_lines(count);
+    let skip = lines.len().saturating_sub(count);
+    lines
+        .iter()
+        .skip(skip)
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+fn csv_field(value: impl ToString) -> String {
+    let value = value.to_string();
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+fn optional<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map(ToString::to_string).unwrap_or_default()
+}
+
+/// Entries as csv, with a header row. Order tokens are written as json.
+pub fn to_csv(entries: &[LedgerEntry]) -> String {
+    let mut csv =
+        "timestamp,exchange,event,pair,side,type,price,amount,order_token,result\n".to_string();
+    for entry in entries {
+        let token = |token: Option<&OrderToken>| {
+            token
+                .and_then(|token| serde_json::to_string(token).ok())
+                .unwrap_or_default()
+        };
+        let fields = match &entry.event {
+            LedgerEvent::Order {
+                pair,
+                side,
+                kind,
+                price,
+                amount,
+                order_token,
+                result,
+            } => [
+                "order".to_string(),
+                format!("{}-{}", pair.0, pair.1),
+                format!("{:?}", side),
+                format!("{:?}", kind),
+                optional(price),
+                amount.to_string(),
+                token(order_token.as_ref()),
+                result.clone(),
+            ],
+            LedgerEvent::Fill {
+                order_token,
+                executed_volume,
+                avg_price,
+            } => [
+                "fill".to_string(),
+                String::new(),
+                String::new(),
+                String::new(),
+                optional(avg_price),
+                executed_volume.to_string(),
+                token(Some(order_token)),
+                String::new(),
+            ],
+            LedgerEvent::Cancel {
+                order_token,
+                executed_volume,
+                result,
+            } => [
+                "cancel".to_string(),
+                String::new(),
+                String::new(),
+                String::new(),
+                String::new(),
+                optional(executed_volume),
+                token(Some(order_token)),
+                result.clone(),
+            ],
+            LedgerEvent::Withdraw {
+                currency,
+                amount,
+                address,
+                network,
+                result,
+            } => [
+                "withdraw".to_string(),
+                currency.to_string(),
+                String::new(),
+                optional(network),
+                String::new(),
+                amount.to_string(),
+                address.clone(),
+                result.clone(),
+            ],
+        };
+
+        let mut row = vec![entry.timestamp.to_string(), csv_field(&entry.exchange)];
+        row.extend(fields.iter().map(csv_field));
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Path of the file for the day of `timestamp`, `ledger.jsonl` becomes `ledger-2024-01-31.jsonl`.
+#[cfg(not(target_arch = "wasm32"))]
+fn day_path(path: &str, timestamp: i64) -> std::path::PathBuf {
+    let path = std::path::Path::new(path);
+    let date = chrono::DateTime::from_timestamp_millis(timestamp)
+        .unwrap_or_default()
+        .format("%Y-%m-%d");
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}-{}.{}", stem, date, extension.to_string_lossy()),
+        None => format!("{}-{}", stem, date),
+    };
+    path.with_file_name(name)
+}
+
+/// True for the names [`day_path`] gives to the files of `path`,
+/// so other files sharing the prefix, like `ledger-backup.jsonl`, are not read.
+#[cfg(not(target_arch = "wasm32"))]
+fn is_day_file(path: &std::path::Path, name: &str) -> bool {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    name.strip_prefix(stem.as_ref())
+        .and_then(|name| name.strip_prefix('-'))
+        .and_then(|name| name.strip_suffix(extension.as_str()))
+        .is_some_and(|date| {
+            date.len() == "2024-01-31".len()
+                && chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok()
+        })
+}
+
+/// Files of the ledger, oldest first, including the undated file of older versions.
+#[cfg(not(target_arch = "wasm32"))]
+fn files(path: &str) -> Vec<std::path::PathBuf> {
+    let path = std::path::Path::new(path);
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => std::path::Path::new("."),
+    };
+
+    // The dates sort by name
+    let mut files = std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|file| {
+                    file.file_name()
+                        .is_some_and(|name| is_day_file(path, &name.to_string_lossy()))
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    files.sort();
+
+    // Written before the files were split by day
+    if path.is_file() {
+        files.insert(0, path.to_path_buf());
+    }
+    files
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn append(timestamp: i64, line: &str) {
+    use std::io::Write;
+
+    let path = day_path(&crate::config::Config::get().ledger.path, timestamp);
+    let result = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| writeln!(file, "{}", line));
+    if let Err(e) = result {
+        tracing::warn!("Failed to append to the ledger {}: {}", path.display(), e);
+    }
+}
+
+/// At least the latest `count` lines if there are that many, reading the newest days first.
+#[cfg(not(target_arch = "wasm32"))]
+fn read_lines(count: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for file in files(&crate::config::Config::get().ledger.path)
+        .iter()
+        .rev()
+    {
+        let Ok(text) = std::fs::read_to_string(file) else {
+            continue;
+        };
+        let mut day = text.lines().map(str::to_string).collect::<Vec<_>>();
+        day.append(&mut lines);
+        lines = day;
+        if lines.len() >= count {
+            break;
+        }
+    }
+    lines
+}
+
+/// Writes every entry as csv to `path`, returns the number of entries.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn export_csv(path: &str) -> std::io::Result<usize> {
+    let entries = tail(usize::MAX);
+    std::fs::write(path, to_csv(&entries))?;
+    Ok(entries.len())
+}
+
+#[cfg(any(target_arch = "wasm32"))]
+fn append(_timestamp: i64, line: &str) {
+    let mut lines = read_lines(usize::MAX);
+    lines.push(line.to_string());
+    if lines.len() > STORAGE_LIMIT {
+        let excess = lines.len() - STORAGE_LIMIT;
+        lines.drain(..excess);
+    }
+    crate::utils::storage::write(STORAGE_KEY, &lines.join("\n"));
+}
+
+#[cfg(any(target_arch = "wasm32"))]
+fn read_lines(_count: usize) -> Vec<String> {
+    crate::utils::storage::read(STORAGE_KEY)
+        .map(|text| text.lines().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{to_csv, LedgerEntry, LedgerEvent, OrderKind, Settled, SETTLED, SETTLED_LIMIT};
+    use crate::currency::Currency;
+    use crate::exchange::{OrderState, OrderToken, Side};
+    use crate::utils::Decimal;
+
+    fn order() -> LedgerEntry {
+        LedgerEntry {
+            timestamp: 1700000000000,
+            exchange: "upbit".to_string(),
+            event: LedgerEvent::Order {
+                pair: (Currency::BTC, Currency::KRW),
+                side: Side::Bid,
+                kind: OrderKind::Limit,
+                price: Some(Decimal(50_000_000.into())),
+                amount: Decimal(1.into()),
+                order_token: Some(OrderToken::Upbit {
+                    uuid: "uuid".to_string(),
+                }),
+                result: "placed".to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn entry_format() {
+        let entry = order();
+
+        let line = serde_json::to_string(&entry).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["event"], "order");
+        assert_eq!(value["type"], "limit");
+        assert_eq!(value["order_token"]["exchange"], "upbit");
+        assert_eq!(serde_json::from_str::<LedgerEntry>(&line).unwrap(), entry);
+    }
+
+    #[test]
+    fn csv_rows() {
+        let cancel = LedgerEntry {
+            timestamp: 1700000001000,
+            exchange: "upbit".to_string(),
+            event: LedgerEvent::Cancel {
+                order_token: OrderToken::Upbit {
+                    uuid: "uuid".to_string(),
+                },
+                executed_volume: None,
+                result: "failed, retry".to_string(),
+            },
+        };
+
+        let csv = to_csv(&[order(), cancel]);
+        let rows = csv.lines().collect::<Vec<_>>();
+        assert_eq!(rows.len(), 3);
+        assert!(rows[1].starts_with("1700000000000,upbit,order,BTC-KRW,Bid,Limit,50000000,1,"));
+        // Json tokens and texts with commas are quoted
+        assert!(rows[1].contains(r#""{""exchange"":""upbit"",""uuid"":""uuid""}""#));
+        assert!(rows[2].ends_with(",\"failed, retry\""));
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn daily_files() {
+        let path = super::day_path("logs/ledger.jsonl", 1700000000000);
+        assert_eq!(path, std::path::Path::new("logs/ledger-2023-11-14.jsonl"));
+
+        let base = std::path::Path::new("logs/ledger.jsonl");
+        assert!(super::is_day_file(base, "ledger-2023-11-14.jsonl"));
+        assert!(!super::is_day_file(base, "ledger-backup.jsonl"));
+        assert!(!super::is_day_file(base, "ledger-2023-11-14.jsonl.bak"));
+        assert!(!super::is_day_file(base, "ledger-2023-1-4.jsonl"));
+        assert!(!super::is_day_file(base, "ledger.jsonl"));
+        assert!(super::is_day_file(
+            std::path::Path::new("ledger"),
+            "ledger-2023-11-14"
+        ));
+    }
+
+    #[test]
+    fn settled_orders_are_bounded() {
+        let order = |id: usize| OrderToken::Upbit {
+            uuid: id.to_string(),
+        };
+        let mut settled = Settled::default();
+        assert!(settled.insert(&order(0)));
+        assert!(!settled.insert(&order(0)));
+
+        for id in 1..=SETTLED_LIMIT {
+            assert!(settled.insert(&order(id)));
+        }
+        assert_eq!(settled.orders.len(), SETTLED_LIMIT);
+        // The oldest was forgotten
+        assert!(settled.insert(&order(0)));
+    }
+
+    #[test]
+    fn orders_closed_without_executing_are_not_filled() {
+        let order_token = OrderToken::Upbit {
+            uuid: "cancelled".to_string(),
+        };
+        super::record_fill(
+            "upbit",
+            &order_token,
+            &OrderState::Closed,
+            Decimal::ZERO,
+            None,
+        );
+        assert!(SETTLED.lock().insert(&order_token));
+    }
+}
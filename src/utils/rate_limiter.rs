@@ -0,0 +1,143 @@
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::utils::async_helpers;
+
+/// Token bucket limits of an exchange.
+///
+/// `capacity` is the burst size and `refill_per_sec` is the sustained rate.
+/// Each request consumes its weight from the bucket, which is 1 for most exchanges
+/// but follows the documented endpoint weight for binance.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub capacity: u32,
+    pub refill_per_sec: f64,
+}
+
+/// Token bucket rate limiter shared by all requests of an exchange.
+pub struct RateLimiter {
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            bucket: Mutex::new(Bucket::new(limit, now_millis())),
+        }
+    }
+
+    /// Creates a rate limiter for the exchange with the limit from the config.
+    /// Falls back to `default` if the exchange has no `[rate_limit.<name>]` section.
+    pub fn from_config(name: &str, default: RateLimit) -> Self {
+        let limit = Config::get()
+            .rate_limit
+            .get(name)
+            .copied()
+            .unwrap_or(default);
+
+        Self::new(limit)
+    }
+
+    /// Waits until `weight` tokens are available and consumes them.
+    pub async fn acquire(&self, weight: u32) {
+        loop {
+            let result = self.bucket.lock().try_take(now_millis(), weight);
+            match result {
+                Ok(()) => return,
+                Err(wait) => {
+                    tracing::debug!("RateLimiter: waiting {:?} for {} tokens", wait, weight);
+                    async_helpers::sleep(wait).await;
+                }
+            }
+        }
+    }
+
+    /// Returns the ratio of consumed tokens, from 0.0 (idle) to 1.0 (exhausted).
+    pub fn utilization(&self) -> f64 {
+        let mut bucket = self.bucket.lock();
+        bucket.refill(now_millis());
+        1.0 - bucket.tokens / bucket.limit.capacity as f64
+    }
+}
+
+fn now_millis() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+struct Bucket {
+    limit: RateLimit,
+    tokens: f64,
+    last_refill: i64,
+}
+
+impl Bucket {
+    fn new(limit: RateLimit, now: i64) -> Self {
+        Self {
+            limit,
+            tokens: limit.capacity as f64,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, now: i64) {
+        let elapsed = (now - self.last_refill).max(0) as f64 / 1000.0;
+        self.tokens =
+            (self.tokens + elapsed * self.limit.refill_per_sec).min(self.limit.capacity as f64);
+        self.last_refill = now;
+    }
+
+    /// Takes `weight` tokens from the bucket.
+    /// Returns how long to wait until enough tokens are refilled if there are not enough tokens.
+    fn try_take(&mut self, now: i64, weight: u32) -> Result<(), Duration> {
+        self.refill(now);
+
+        // A request heavier than the whole bucket would wait forever
+        let weight = weight.min(self.limit.capacity) as f64;
+        if self.tokens >= weight {
+            self.tokens -= weight;
+            return Ok(());
+        }
+
+        let missing = weight - self.tokens;
+        let wait_secs = missing / self.limit.refill_per_sec;
+        Err(Duration::from_millis((wait_secs * 1000.0).ceil() as u64))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{Bucket, RateLimit};
+
+    #[test]
+    fn bucket_refill() {
+        let limit = RateLimit {
+            capacity: 2,
+            refill_per_sec: 1.0,
+        };
+        let mut bucket = Bucket::new(limit, 0);
+
+        assert_eq!(bucket.try_take(0, 1), Ok(()));
+        assert_eq!(bucket.try_take(0, 1), Ok(()));
+        assert_eq!(bucket.try_take(0, 1), Err(Duration::from_millis(1000)));
+        assert_eq!(bucket.try_take(500, 1), Err(Duration::from_millis(500)));
+        assert_eq!(bucket.try_take(1000, 1), Ok(()));
+    }
+
+    #[test]
+    fn bucket_heavy_weight() {
+        let limit = RateLimit {
+            capacity: 10,
+            refill_per_sec: 10.0,
+        };
+        let mut bucket = Bucket::new(limit, 0);
+
+        // Weights above the capacity are clamped so they can eventually be served
+        assert_eq!(bucket.try_take(0, 20), Ok(()));
+        assert_eq!(bucket.try_take(0, 20), Err(Duration::from_millis(1000)));
+    }
+}
@@ -0,0 +1,43 @@
+use rust_decimal::RoundingStrategy;
+
+use super::Decimal;
+
+/// Rounds `value` toward zero to `dp` decimal places.
+/// Quantities sent to exchanges must never exceed what is available, so they are never rounded up.
+pub fn round_down_dp(value: Decimal, dp: u32) -> Decimal {
+    Decimal(value.0.round_dp_with_strategy(dp, RoundingStrategy::ToZero))
+}
+
+/// Rounds `value` toward zero to a multiple of `step`, e.g. the lot size of a market.
+/// Returns `value` unchanged if `step` is not positive.
+pub fn round_down_to_step(value: Decimal, step: Decimal) -> Decimal {
+    if step.0 <= rust_decimal::Decimal::ZERO {
+        return value;
+    }
+
+    Decimal((value.0 / step.0).trunc() * step.0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{round_down_dp, round_down_to_step};
+    use crate::dec;
+
+    #[test]
+    fn round_down_dp_never_rounds_up() {
+        assert_eq!(round_down_dp(dec!(1.23456789), 6), dec!(1.234567));
+        assert_eq!(round_down_dp(dec!(0.9999999), 6), dec!(0.999999));
+        assert_eq!(round_down_dp(dec!(63.66), 2), dec!(63.66));
+        assert_eq!(round_down_dp(dec!(63.66), 0), dec!(63));
+        assert_eq!(round_down_dp(dec!(-1.55), 1), dec!(-1.5));
+    }
+
+    #[test]
+    fn round_down_to_lot_size() {
+        assert_eq!(round_down_to_step(dec!(1.2345), dec!(0.01)), dec!(1.23));
+        assert_eq!(round_down_to_step(dec!(17), dec!(5)), dec!(15));
+        assert_eq!(round_down_to_step(dec!(0.3), dec!(0.1)), dec!(0.3));
+        assert_eq!(round_down_to_step(dec!(0.00009), dec!(0.0001)), dec!(0));
+        assert_eq!(round_down_to_step(dec!(1.5), dec!(0)), dec!(1.5));
+    }
+}
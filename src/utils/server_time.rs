@@ -0,0 +1,127 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+
+/// How long a measured offset is trusted before it is measured again.
+pub const SYNC_INTERVAL_MILLIS: i64 = 60 * 60 * 1000;
+
+/// Offsets beyond this are logged. Signed requests are stamped with the measured server time,
+/// so the offset only matters once it moves by the receive window of the exchange between syncs.
+pub const DRIFT_NOTICE_MILLIS: i64 = 1000;
+
+/// Current local time in unix milliseconds.
+pub fn local_millis() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+/// Parses a http `Date` header into unix milliseconds.
+/// The header only has seconds resolution, which is still enough to absorb local clock drift.
+pub fn parse_http_date(date: &str) -> Option<i64> {
+    chrono::DateTime::parse_from_rfc2822(date)
+        .ok()
+        .map(|date| date.timestamp_millis())
+}
+
+/// Offset between the local clock and the clock of an exchange server.
+///
+/// Signed requests must carry timestamps or nonces close to the server time,
+/// so they should be generated from [`ServerTime::now_millis`] instead of the local clock.
+pub struct ServerTime {
+    offset: AtomicI64,
+    synced_at: AtomicI64,
+    local: fn() -> i64,
+}
+
+impl ServerTime {
+    pub fn new() -> Self {
+        Self::with_local_clock(local_millis)
+    }
+
+    /// Reads the local time from `local` instead of the system clock, for tests.
+    pub fn with_local_clock(local: fn() -> i64) -> Self {
+        Self {
+            offset: AtomicI64::new(0),
+            synced_at: AtomicI64::new(i64::MIN),
+            local,
+        }
+    }
+
+    /// Current server time estimated from the local clock, in unix milliseconds.
+    pub fn now_millis(&self) -> i64 {
+        (self.local)() + self.offset_millis()
+    }
+
+    /// Server time minus local time, in milliseconds.
+    pub fn offset_millis(&self) -> i64 {
+        self.offset.load(Ordering::Relaxed)
+    }
+
+    /// The offset, None until it is measured.
+    pub fn drift_millis(&self) -> Option<i64> {
+        (self.synced_at.load(Ordering::Relaxed) != i64::MIN).then(|| self.offset_millis())
+    }
+
+    /// Returns true if the offset has never been measured or is too old.
+    pub fn needs_sync(&self) -> bool {
+        let synced_at = self.synced_at.load(Ordering::Relaxed);
+        (self.local)().saturating_sub(synced_at) > SYNC_INTERVAL_MILLIS
+    }
+
+    /// Records a server time that was read between the local times `sent` and `received`.
+    /// The server is assumed to have stamped the response halfway through the round trip.
+    pub fn update(&self, server: i64, sent: i64, received: i64) {
+        let offset = server - (sent + (received - sent) / 2);
+        self.offset.store(offset, Ordering::Relaxed);
+        self.synced_at.store(received, Ordering::Relaxed);
+
+        if offset.abs() >= DRIFT_NOTICE_MILLIS {
+            tracing::info!(
+                "ServerTime: the local clock is {}ms off the server, signed requests are stamped with the server time",
+                -offset
+            );
+        } else {
+            tracing::debug!("ServerTime: offset {}ms", offset);
+        }
+    }
+}
+
+impl Default for ServerTime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_http_date, ServerTime, SYNC_INTERVAL_MILLIS};
+
+    #[test]
+    fn offset_from_round_trip() {
+        let time = ServerTime::new();
+        assert!(time.needs_sync());
+        assert_eq!(time.drift_millis(), None);
+
+        time.update(10_000, 1_000, 1_200);
+        assert_eq!(time.offset_millis(), 8_900);
+        assert_eq!(time.drift_millis(), Some(8_900));
+    }
+
+    #[test]
+    fn resyncs_after_the_interval() {
+        let time = ServerTime::with_local_clock(|| 1_000 + SYNC_INTERVAL_MILLIS);
+        time.update(4_000, 1_000, 1_000);
+        assert!(!time.needs_sync());
+        assert_eq!(time.now_millis(), 4_000 + SYNC_INTERVAL_MILLIS);
+
+        let time = ServerTime::with_local_clock(|| 1_001 + SYNC_INTERVAL_MILLIS);
+        time.update(4_000, 1_000, 1_000);
+        assert!(time.needs_sync());
+    }
+
+    #[test]
+    fn http_date() {
+        assert_eq!(
+            parse_http_date("Thu, 01 Jan 1970 00:00:10 GMT"),
+            Some(10_000)
+        );
+        assert_eq!(parse_http_date("yesterday"), None);
+    }
+}
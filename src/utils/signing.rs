@@ -0,0 +1,344 @@
+//! Signing of the private api requests.
+//! A [`Signer`] turns a request into the headers authenticating it, the exchanges differ
+//! in what is signed and how the signature is encoded.
+
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use reqwest::RequestBuilder;
+use sha2::{Digest, Sha256, Sha512};
+
+/// Headers authenticating a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedHeaders {
+    pub headers: Vec<(&'static str, String)>,
+    /// The signature alone, Binance sends it as a query parameter instead of a header.
+    pub signature: String,
+}
+
+impl SignedHeaders {
+    /// Value of the header `name`, compared case-insensitively.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+pub trait Signer {
+    /// Signs a request to `endpoint` carrying the url encoded `query`, `nonce` is in milliseconds.
+    /// Arguments a scheme does not sign are ignored.
+    fn sign(&self, endpoint: &str, query: &str, nonce: i64) -> SignedHeaders;
+}
+
+/// Adds signed headers to a request.
+pub trait SignRequest {
+    fn signed(self, signed: &SignedHeaders) -> Self;
+}
+
+impl SignRequest for RequestBuilder {
+    fn signed(self, signed: &SignedHeaders) -> Self {
+        signed
+            .headers
+            .iter()
+            .fold(self, |request, (name, value)| request.header(*name, value))
+    }
+}
+
+fn hmac_sha256(secret_key: &str, message: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret_key.as_bytes()).unwrap();
+    mac.update(message.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hmac_sha512(secret_key: &str, message: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha512>::new_from_slice(secret_key.as_bytes()).unwrap();
+    mac.update(message.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Binance signs the query string with HMAC-SHA256, hex encoded.
+/// The timestamp is a query parameter, so the nonce is not used.
+pub struct BinanceSigner {
+    pub api_key: String,
+    pub secret_key: String,
+}
+
+impl Signer for BinanceSigner {
+    fn sign(&self, _endpoint: &str, query: &str, _nonce: i64) -> SignedHeaders {
+        SignedHeaders {
+            headers: vec![("X-MBX-APIKEY", self.api_key.clone())],
+            signature: hex::encode(hmac_sha256(&self.secret_key, query)),
+        }
+    }
+}
+
+/// Bithumb signs `endpoint \0 query \0 nonce` with HMAC-SHA512,
+/// and base64 encodes the hex digest rather than the digest itself.
+pub struct BithumbSigner {
+    pub connect_key: String,
+    pub secret_key: String,
+}
+
+impl Signer for BithumbSigner {
+    fn sign(&self, endpoint: &str, query: &str, nonce: i64) -> SignedHeaders {
+        let parameters = format!("{}\0{}\0{}", endpoint, query, nonce);
+        let signature = base64::engine::general_purpose::STANDARD
+            .encode(hex::encode(hmac_sha512(&self.secret_key, &parameters)));
+
+        SignedHeaders {
+            headers: vec![
+                ("api-client-type", "0".to_string()),
+                ("Api-Key", self.connect_key.clone()),
+                ("Api-Nonce", nonce.to_string()),
+                ("Api-Sign", signature.clone()),
+            ],
+            signature,
+        }
+    }
+}
+
+/// Upbit authenticates with a HS256 JWT carrying the SHA512 hash of the query.
+/// The endpoint is not signed.
+pub struct UpbitSigner {
+    pub access_key: String,
+    pub secret_key: String,
+}
+
+impl Signer for UpbitSigner {
+    fn sign(&self, _endpoint: &str, query: &str, nonce: i64) -> SignedHeaders {
+        use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+
+        let payload = serde_json::json!({
+            "access_key": self.access_key,
+            "nonce": nonce,
+            "query_hash": hex::encode(Sha512::digest(query.as_bytes())),
+            "query_hash_alg": "SHA512",
+        });
+
+        let header = Header::new(Algorithm::HS256);
+        let key = EncodingKey::from_secret(self.secret_key.as_ref());
+        let token = encode(&header, &payload, &key).unwrap();
+        SignedHeaders {
+            headers: vec![("Authorization", format!("Bearer {}", token))],
+            signature: token,
+        }
+    }
+}
+
+impl UpbitSigner {
+    /// Token of the private websocket. The handshake has no query, so the token has no hash,
+    /// and its nonce is a random uuid as Upbit asks for.
+    pub fn websocket_token(&self) -> String {
+        use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+
+        let payload = serde_json::json!({
+            "access_key": self.access_key,
+            "nonce": uuid::Uuid::new_v4().to_string(),
+        });
+
+        let header = Header::new(Algorithm::HS256);
+        let key = EncodingKey::from_secret(self.secret_key.as_ref());
+        encode(&header, &payload, &key).unwrap()
+    }
+}
+
+/// OKX signs `timestamp + method + request_path + body` with HMAC-SHA256, base64 encoded.
+/// The endpoint is the method followed by the request path, e.g. `GET/api/v5/account/balance`,
+/// and the query is the json body.
+pub struct OkxSigner {
+    pub api_key: String,
+    pub secret_key: String,
+    pub passphrase: String,
+}
+
+/// OKX expects an ISO 8601 timestamp with millisecond precision, e.g. `2020-12-08T09:08:57.715Z`
+fn iso_timestamp(millis: i64) -> String {
+    chrono::DateTime::from_timestamp_millis(millis)
+        .unwrap_or_default()
+        .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+        .to_string()
+}
+
+impl Signer for OkxSigner {
+    fn sign(&self, endpoint: &str, query: &str, nonce: i64) -> SignedHeaders {
+        let timestamp = iso_timestamp(nonce);
+        let message = format!("{}{}{}", timestamp, endpoint, query);
+        let signature = base64::engine::general_purpose::STANDARD
+            .encode(hmac_sha256(&self.secret_key, &message));
+
+        SignedHeaders {
+            headers: vec![
+                ("OK-ACCESS-KEY", self.api_key.clone()),
+                ("OK-ACCESS-SIGN", signature.clone()),
+                ("OK-ACCESS-TIMESTAMP", timestamp),
+                ("OK-ACCESS-PASSPHRASE", self.passphrase.clone()),
+            ],
+            signature,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BinanceSigner, BithumbSigner, OkxSigner, Signer, UpbitSigner};
+    use crate::utils::server_time::ServerTime;
+
+    #[test]
+    fn binance_documentation_example() {
+        // The example of the Binance api documentation, "SIGNED Endpoint Examples"
+        let signer = BinanceSigner {
+            api_key: "vmPUZE6mv9SD5VNHk4HlWFsOr6aKE2zvsw0MuIgwCIPy6utIco14y7Ju91duEh8A".to_string(),
+            secret_key: "NhqPtmdSJYdKjVHjA7PZj4Mge3R5YNiP1e3UZjInClVN65XAbvqqM6A7H5fATj0j"
+                .to_string(),
+        };
+        let query = "symbol=LTCBTC&side=BUY&type=LIMIT&timeInForce=GTC&quantity=1&price=0.1\
+                     &recvWindow=5000&timestamp=1499827319559";
+
+        let signed = signer.sign("", query, 0);
+        assert_eq!(
+            signed.signature,
+            "c8db56825ae71d6d79447849e617115f4a920fa2acdcab2b053c4b2838bd6b71"
+        );
+        assert_eq!(
+            signed.get("x-mbx-apikey"),
+            Some("vmPUZE6mv9SD5VNHk4HlWFsOr6aKE2zvsw0MuIgwCIPy6utIco14y7Ju91duEh8A")
+        );
+    }
+
+    #[test]
+    fn bithumb_signature() {
+        let signer = BithumbSigner {
+            connect_key: "bithumb-key".to_string(),
+            secret_key: "bithumb-secret".to_string(),
+        };
+
+        let signed = signer.sign(
+            "/info/balance",
+            "endpoint=%2Finfo%2Fbalance&currency=BTC",
+            1700000000000,
+        );
+        assert_eq!(
+            signed.get("Api-Sign"),
+            Some(
+                "ZTFiZTJmOWFlNjY5ODBjNTkxMjc1ZTQ1MjY4OGE4MDFiMDA1NTU0NWY4ZmNlYjNhYWJmOWY5ZWExOTc1\
+                 MWYyNWI4ZmFiYzI3ZTlkMGVhN2NhNDRkZGMxNjY5ZDk5M2MyNTliNzQ5YTZhYmFkYWNjN2UzNDc5NjRh\
+                 MGEyMmRmODE="
+            )
+        );
+        assert_eq!(signed.get("Api-Key"), Some("bithumb-key"));
+        assert_eq!(signed.get("Api-Nonce"), Some("1700000000000"));
+    }
+
+    #[test]
+    fn upbit_jwt() {
+        use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+
+        let signer = UpbitSigner {
+            access_key: "upbit-key".to_string(),
+            secret_key: "upbit-secret".to_string(),
+        };
+        let query = "market=KRW-BTC&side=bid&volume=0.01&price=100&ord_type=limit";
+
+        let signed = signer.sign("", query, 1700000000000);
+        let token = signed
+            .get("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap();
+        assert_eq!(token, signed.signature);
+
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.required_spec_claims.clear();
+        validation.validate_exp = false;
+        let claims = decode::<serde_json::Value>(
+            token,
+            &DecodingKey::from_secret(b"upbit-secret"),
+            &validation,
+        )
+        .unwrap()
+        .claims;
+
+        assert_eq!(claims["access_key"], "upbit-key");
+        assert_eq!(claims["nonce"], 1700000000000i64);
+        assert_eq!(claims["query_hash_alg"], "SHA512");
+        assert_eq!(
+            claims["query_hash"],
+            "da670bea980ba35ed6a354a1580ae42e2e44b7feb2524b1477e5087ecbd233cf\
+             41de9598218c7d5582488e5a6b78f8931f1df9db9ce2fc68cd90496d9c90fe74"
+        );
+    }
+
+    #[test]
+    fn upbit_websocket_token() {
+        use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+
+        let signer = UpbitSigner {
+            access_key: "upbit-key".to_string(),
+            secret_key: "upbit-secret".to_string(),
+        };
+
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.required_spec_claims.clear();
+        validation.validate_exp = false;
+        let claims = |token: &str| {
+            decode::<serde_json::Value>(
+                token,
+                &DecodingKey::from_secret(b"upbit-secret"),
+                &validation,
+            )
+            .unwrap()
+            .claims
+        };
+
+        let first = claims(&signer.websocket_token());
+        assert_eq!(first["access_key"], "upbit-key");
+        assert!(first.get("query_hash").is_none());
+        // Every connect has its own nonce
+        assert_ne!(first["nonce"], claims(&signer.websocket_token())["nonce"]);
+    }
+
+    #[test]
+    fn okx_signature() {
+        let signer = OkxSigner {
+            api_key: "okx-key".to_string(),
+            secret_key: "okx-secret".to_string(),
+            passphrase: "okx-passphrase".to_string(),
+        };
+
+        let signed = signer.sign("GET/api/v5/account/balance?ccy=BTC", "", 1607418537715);
+        assert_eq!(
+            signed.get("OK-ACCESS-TIMESTAMP"),
+            Some("2020-12-08T09:08:57.715Z")
+        );
+        assert_eq!(
+            signed.signature,
+            "bcaop0CD6XyPPEF8Hrl2ytRKjQL3KE6d4aQOfeEr+kw="
+        );
+        assert_eq!(signed.get("OK-ACCESS-PASSPHRASE"), Some("okx-passphrase"));
+    }
+
+    #[test]
+    fn signed_with_the_server_clock() {
+        // The local clock is a second behind the server
+        let clock = ServerTime::with_local_clock(|| 1607418536715);
+        clock.update(1607418537715, 1607418536715, 1607418536715);
+
+        let okx = OkxSigner {
+            api_key: "okx-key".to_string(),
+            secret_key: "okx-secret".to_string(),
+            passphrase: "okx-passphrase".to_string(),
+        };
+        let signed = okx.sign("GET/api/v5/account/balance?ccy=BTC", "", clock.now_millis());
+        assert_eq!(
+            signed.get("OK-ACCESS-TIMESTAMP"),
+            Some("2020-12-08T09:08:57.715Z")
+        );
+
+        let bithumb = BithumbSigner {
+            connect_key: "bithumb-key".to_string(),
+            secret_key: "bithumb-secret".to_string(),
+        };
+        let signed = bithumb.sign("/info/balance", "currency=BTC", clock.now_millis());
+        assert_eq!(signed.get("Api-Nonce"), Some("1607418537715"));
+    }
+}
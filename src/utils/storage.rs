@@ -0,0 +1,53 @@
+//! Small persistent storage of text values, used for the state that survives restarts.
+//! On wasm values live in the local storage, on desktop in json files next to `config.toml`.
+
+#[cfg(any(target_arch = "wasm32"))]
+mod imp {
+    fn local_storage() -> Option<web_sys::Storage> {
+        web_sys::window()?.local_storage().ok()?
+    }
+
+    pub fn read(key: &str) -> Option<String> {
+        local_storage()?.get_item(&format!("rsader.{}", key)).ok()?
+    }
+
+    pub fn write(key: &str, text: &str) {
+        let result =
+            local_storage().map(|storage| storage.set_item(&format!("rsader.{}", key), text));
+        if !matches!(result, Some(Ok(()))) {
+            tracing::warn!("Failed to save {} to local storage", key);
+        }
+    }
+
+    pub fn remove(key: &str) {
+        if let Some(storage) = local_storage() {
+            let _ = storage.remove_item(&format!("rsader.{}", key));
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod imp {
+    pub fn read(key: &str) -> Option<String> {
+        std::fs::read_to_string(format!("{}.json", key)).ok()
+    }
+
+    pub fn write(key: &str, text: &str) {
+        let path = format!("{}.json", key);
+        if let Err(e) = std::fs::write(&path, text) {
+            tracing::warn!("Failed to save {} to {}: {}", key, path, e);
+        }
+    }
+
+    pub fn remove(key: &str) {
+        let path = format!("{}.json", key);
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                tracing::warn!("Failed to remove {}: {}", path, e)
+            }
+            _ => {}
+        }
+    }
+}
+
+pub use imp::{read, remove, write};
@@ -0,0 +1,296 @@
+//! Queue limiting how many tasks run at once and how closely they start after each other.
+//! Tasks are admitted in the order they were queued.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future;
+use parking_lot::Mutex;
+
+use crate::utils::broadcaster::Broadcaster;
+use crate::utils::{async_helpers, server_time};
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum ThrottleError {
+    #[error("{0} tasks are already queued, try again later")]
+    Full(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThrottleLimits {
+    /// At least 1, nothing would ever run otherwise.
+    pub max_running: usize,
+    /// Queueing beyond this is rejected.
+    pub max_queued: usize,
+    /// Least time between two admissions.
+    pub min_interval: Duration,
+}
+
+/// Number of running and queued tasks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ThrottleStats {
+    pub running: usize,
+    pub queued: usize,
+}
+
+struct State {
+    limits: ThrottleLimits,
+    running: usize,
+    queue: VecDeque<u64>,
+    next_ticket: u64,
+    last_admitted: Option<i64>,
+}
+
+#[derive(Clone)]
+pub struct Throttle {
+    state: Arc<Mutex<State>>,
+    /// Wakes the queued tasks when a slot may have been freed.
+    changed: Broadcaster<()>,
+}
+
+impl Throttle {
+    pub fn new(limits: ThrottleLimits) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                limits,
+                running: 0,
+                queue: VecDeque::new(),
+                next_ticket: 0,
+                last_admitted: None,
+            })),
+            changed: Broadcaster::new(),
+        }
+    }
+
+    /// Applies new limits to the running and queued tasks, e.g. after the config changed.
+    pub fn set_limits(&self, limits: ThrottleLimits) {
+        let mut state = self.state.lock();
+        if state.limits != limits {
+            state.limits = limits;
+            drop(state);
+            self.changed.broadcast(());
+        }
+    }
+
+    /// Takes a place at the back of the queue.
+    /// Dropping the ticket before it is admitted gives up the place.
+    pub fn enqueue(&self) -> Result<Ticket, ThrottleError> {
+        let mut state = self.state.lock();
+        if state.queue.len() >= state.limits.max_queued {
+            return Err(ThrottleError::Full(state.queue.len()));
+        }
+
+        let id = state.next_ticket;
+        state.next_ticket += 1;
+        state.queue.push_back(id);
+        Ok(Ticket {
+            throttle: self.clone(),
+            id,
+            admitted: false,
+        })
+    }
+
+    /// Queues and waits until admitted, the task runs until the permit is dropped.
+    pub async fn acquire(&self) -> Result<Permit, ThrottleError> {
+        Ok(self.enqueue()?.admitted().await)
+    }
+
+    pub fn stats(&self) -> ThrottleStats {
+        let state = self.state.lock();
+        ThrottleStats {
+            running: state.running,
+            queued: state.queue.len(),
+        }
+    }
+
+    /// Admits the ticket `id` if it is at the front and a slot is free.
+    /// Otherwise returns how long to wait for the min interval,
+    /// or None to wait until the queue or the running tasks change.
+    fn try_admit(&self, id: u64, now: i64) -> Result<(), Option<Duration>> {
+        let mut state = self.state.lock();
+        if state.queue.front() != Some(&id) || state.running >= state.limits.max_running {
+            return Err(None);
+        }
+
+        let interval = state.limits.min_interval.as_millis() as i64;
+        if let Some(last) = state.last_admitted {
+            let remaining = last + interval - now;
+            if remaining > 0 {
+                return Err(Some(Duration::from_millis(remaining as u64)));
+            }
+        }
+
+        state.queue.pop_front();
+        state.running += 1;
+        state.last_admitted = Some(now);
+        drop(state);
+        // The next ticket is at the front now
+        self.changed.broadcast(());
+        Ok(())
+    }
+}
+
+/// A place in the queue of a [`Throttle`].
+pub struct Ticket {
+    throttle: Throttle,
+    id: u64,
+    admitted: bool,
+}
+
+impl Ticket {
+    pub async fn admitted(mut self) -> Permit {
+        loop {
+            // Subscribed before checking, so a slot freed in between is not missed
+            let changed = self.throttle.changed.subscribe();
+            match self
+                .throttle
+                .try_admit(self.id, server_time::local_millis())
+            {
+                Ok(()) => {
+                    self.admitted = true;
+                    return Permit {
+                        throttle: self.throttle.clone(),
+                    };
+                }
+                Err(None) => changed.recv().await,
+                Err(Some(wait)) => {
+                    let sleep = Box::pin(async_helpers::sleep(wait));
+                    future::select(sleep, Box::pin(changed.recv())).await;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        if !self.admitted {
+            self.throttle.state.lock().queue.retain(|id| *id != self.id);
+            self.throttle.changed.broadcast(());
+        }
+    }
+}
+
+/// A running task, frees its slot when dropped.
+pub struct Permit {
+    throttle: Throttle,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.throttle.state.lock().running -= 1;
+        self.throttle.changed.broadcast(());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use parking_lot::Mutex;
+
+    use super::{Throttle, ThrottleError, ThrottleLimits, ThrottleStats};
+    use crate::utils::async_helpers;
+
+    fn throttle(max_running: usize, max_queued: usize, min_interval_ms: u64) -> Throttle {
+        Throttle::new(ThrottleLimits {
+            max_running,
+            max_queued,
+            min_interval: Duration::from_millis(min_interval_ms),
+        })
+    }
+
+    #[tokio::test]
+    async fn runs_in_queue_order() {
+        let throttle = throttle(1, 10, 0);
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        let actions = (0..5)
+            .map(|i| {
+                let ticket = throttle.enqueue().unwrap();
+                let order = order.clone();
+                tokio::spawn(async move {
+                    let _permit = ticket.admitted().await;
+                    order.lock().push(i);
+                    async_helpers::sleep(Duration::from_millis(5)).await;
+                })
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            throttle.stats(),
+            ThrottleStats {
+                running: 0,
+                queued: 5
+            }
+        );
+
+        for action in actions {
+            action.await.unwrap();
+        }
+        assert_eq!(*order.lock(), vec![0, 1, 2, 3, 4]);
+        assert_eq!(throttle.stats(), ThrottleStats::default());
+    }
+
+    #[tokio::test]
+    async fn cancelled_ticket_never_runs() {
+        let throttle = throttle(1, 10, 0);
+        let running = throttle.acquire().await.unwrap();
+
+        let cancelled = throttle.enqueue().unwrap();
+        let next = throttle.enqueue().unwrap();
+        assert_eq!(throttle.stats().queued, 2);
+
+        drop(cancelled);
+        assert_eq!(throttle.stats().queued, 1);
+
+        drop(running);
+        let admitted = tokio::time::timeout(Duration::from_secs(1), next.admitted()).await;
+        assert!(admitted.is_ok(), "waited for a cancelled ticket");
+        assert_eq!(
+            throttle.stats(),
+            ThrottleStats {
+                running: 1,
+                queued: 0
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn spaced_by_min_interval() {
+        let throttle = throttle(10, 10, 30);
+
+        let started = Instant::now();
+        let _first = throttle.acquire().await.unwrap();
+        let _second = throttle.acquire().await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(30));
+    }
+
+    #[tokio::test]
+    async fn new_limits_apply_to_queued_tasks() {
+        let throttle = throttle(1, 10, 0);
+        let _running = throttle.acquire().await.unwrap();
+        let queued = throttle.enqueue().unwrap();
+
+        let admitted = tokio::time::timeout(Duration::from_millis(100), queued.admitted()).await;
+        assert!(admitted.is_err(), "admitted beyond max_running");
+
+        let queued = throttle.enqueue().unwrap();
+        throttle.set_limits(ThrottleLimits {
+            max_running: 2,
+            max_queued: 10,
+            min_interval: Duration::ZERO,
+        });
+        let admitted = tokio::time::timeout(Duration::from_secs(1), queued.admitted()).await;
+        assert!(admitted.is_ok(), "the new limit did not apply");
+    }
+
+    #[test]
+    fn rejects_beyond_the_queue_cap() {
+        let throttle = throttle(1, 2, 0);
+        let _first = throttle.enqueue().unwrap();
+        let _second = throttle.enqueue().unwrap();
+        assert_eq!(throttle.enqueue().err(), Some(ThrottleError::Full(2)));
+    }
+}
@@ -1,3 +1,18 @@
+pub mod backtest;
+pub mod completion;
+pub mod console;
+pub mod control;
 pub mod error;
 pub mod exchange;
+pub mod pretty;
 pub mod utils;
+
+/// Compiles `script` against the modules installed in `context`, for the tests of the modules.
+#[cfg(test)]
+fn test_vm(context: &rune::Context, script: &str) -> rune::Vm {
+    let unit = console::compile(context, "test", script.to_string()).unwrap();
+    rune::Vm::new(
+        std::sync::Arc::new(context.runtime().unwrap()),
+        std::sync::Arc::new(unit),
+    )
+}
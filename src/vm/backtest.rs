@@ -0,0 +1,314 @@
+//! Runs a strategy script against a [`Backtest`] of the past candles of an exchange,
+//! started by the `backtest` console command.
+//!
+//! The candles are the latest ones the exchange serves in one request, older history is not paged,
+//! so a range starting before them is rejected instead of running on partial data.
+
+use std::sync::Arc;
+
+use async_channel::Sender;
+use chrono::{NaiveDate, NaiveDateTime};
+use once_cell::sync::Lazy;
+use rune::{Context, Vm};
+
+use crate::config::Config;
+use crate::currency::{Currency, CurrencyPair};
+use crate::exchange::backtest::{Backtest, BacktestReport};
+use crate::exchange::binance::Binance;
+use crate::exchange::bithumb::Bithumb;
+use crate::exchange::okx::Okx;
+use crate::exchange::upbit::Upbit;
+use crate::exchange::{Exchange, Exchanges, Ticker};
+use crate::utils::async_helpers::{self, TaskClass};
+use crate::utils::broadcaster::{Broadcaster, Subscription};
+use crate::utils::export;
+
+use super::console::{compile, install_module_output};
+use super::control::Control;
+use super::error::install_module_error;
+use super::exchange::{install_exchange_as, install_module_exchange};
+use super::utils::install_module_utils_with_clock;
+
+/// Reports of the backtests as they finish, the ui opens a window for each.
+static FINISHED: Lazy<Broadcaster<BacktestReport>> = Lazy::new(Broadcaster::new);
+
+/// Reports of the backtests finishing from now on.
+pub fn finished() -> Subscription<BacktestReport> {
+    FINISHED.subscribe()
+}
+
+const USAGE: &str = "usage: backtest <script> <exchange> <pair> <start> <end> [csv <path>]";
+
+#[derive(Debug, PartialEq)]
+pub struct BacktestCommand {
+    pub script: String,
+    pub exchange: String,
+    pub pair: (Currency, Currency),
+    /// Milliseconds in UTC.
+    pub start: i64,
+    pub end: i64,
+    /// The fills are exported as csv to this path.
+    pub csv: Option<String>,
+}
+
+/// Parses `2024-01-31` or `2024-01-31T12:00` in UTC, into milliseconds.
+fn parse_time(time: &str) -> Option<i64> {
+    let time = NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M")
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(time, "%Y-%m-%d")
+                .ok()?
+                .and_hms_opt(0, 0, 0)
+        })?;
+    Some(time.and_utc().timestamp_millis())
+}
+
+/// Parses `backtest <script> <exchange> <pair> <start> <end> [csv <path>]`,
+/// None if the input is a script.
+pub fn parse_backtest_command(input: &str) -> Option<Result<BacktestCommand, String>> {
+    match input.split_whitespace().collect::<Vec<_>>().as_slice() {
+        ["backtest", words @ ..] => Some(parse_arguments(words)),
+        _ => None,
+    }
+}
+
+fn parse_arguments(words: &[&str]) -> Result<BacktestCommand, String> {
+    let (words, csv) = match words {
+        [words @ .., "csv", path] => (words, Some(path.to_string())),
+        _ => (words, None),
+    };
+    let [script, exchange, pair, start, end] = words else {
+        return Err(USAGE.to_string());
+    };
+
+    let pair = pair
+        .parse::<CurrencyPair>()
+        .map_err(|e| format!("{}, {}", e, USAGE))?;
+    let time = |time: &str| {
+        parse_time(time).ok_or_else(|| {
+            format!(
+                "Invalid time {}, expected 2024-01-31 or 2024-01-31T12:00",
+                time
+            )
+        })
+    };
+    let (start, end) = (time(*start)?, time(*end)?);
+    if start >= end {
+        return Err("The start must be before the end".to_string());
+    }
+
+    Ok(BacktestCommand {
+        script: script.to_string(),
+        exchange: exchange.to_lowercase(),
+        pair: pair.into(),
+        start,
+        end,
+        csv,
+    })
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_script(path: &str) -> Result<String, String> {
+    std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))
+}
+
+#[cfg(any(target_arch = "wasm32"))]
+fn read_script(path: &str) -> Result<String, String> {
+    Err(format!(
+        "Failed to read {}: scripts can't be read from files in the browser",
+        path
+    ))
+}
+
+/// The candles the exchange serves between `start` and `end`, with the name it is installed under.
+/// Only the latest candles are served, fails if `start` is before them.
+async fn candles(
+    exchanges: &Exchanges,
+    name: &str,
+    pair: (Currency, Currency),
+    start: i64,
+    end: i64,
+) -> Result<(&'static str, Vec<Ticker>), String> {
+    let (name, candles) = if name == Upbit::NAME {
+        (Upbit::NAME, exchanges.upbit.candlesticks(pair, None).await)
+    } else if name == Binance::NAME {
+        (
+            Binance::NAME,
+            exchanges.binance.candlesticks(pair, None).await,
+        )
+    } else if name == Bithumb::NAME {
+        (
+            Bithumb::NAME,
+            exchanges.bithumb.candlesticks(pair, None).await,
+        )
+    } else if name == Okx::NAME {
+        (Okx::NAME, exchanges.okx.candlesticks(pair, None).await)
+    } else {
+        return Err(format!("Unknown exchange {}", name));
+    };
+
+    let candles = candles.map_err(|e| e.to_string())?.tickers;
+    check_range(name, &candles, start)?;
+    let candles = candles
+        .into_iter()
+        .filter(|candle| (start..=end).contains(&(candle.timestamp as i64)))
+        .collect();
+    Ok((name, candles))
+}
+
+fn format_time(millis: i64) -> String {
+    chrono::DateTime::from_timestamp_millis(millis)
+        .map(|time| time.format("%Y-%m-%dT%H:%M").to_string())
+        .unwrap_or_else(|| millis.to_string())
+}
+
+/// Fails if the backtest starts before the oldest candle served,
+/// as the history before it can't be fetched.
+fn check_range(name: &str, candles: &[Ticker], start: i64) -> Result<(), String> {
+    let Some(oldest) = candles.iter().map(|candle| candle.timestamp as i64).min() else {
+        return Err(format!("{} served no candles", name));
+    };
+    if start < oldest {
+        return Err(format!(
+            "{} only serves its latest candles, the oldest at {} UTC, \
+             start the backtest then or later as older history is not supported",
+            name,
+            format_time(oldest)
+        ));
+    }
+    Ok(())
+}
+
+/// Runs the script of `command` until its `main` returns or the candles run out,
+/// printing to `output` like the console. Returns the summary of the report.
+/// Cancelling `control` ends the sleeps and order waits of the script like in the console.
+pub async fn run(
+    command: BacktestCommand,
+    exchanges: &Exchanges,
+    output: Sender<String>,
+    control: &Control,
+) -> Result<String, String> {
+    let source = read_script(&command.script)?;
+    let (name, candles) = candles(
+        exchanges,
+        &command.exchange,
+        command.pair,
+        command.start,
+        command.end,
+    )
+    .await?;
+    let backtest =
+        Backtest::new(command.pair, candles, Config::get().backtest).map_err(|e| e.to_string())?;
+
+    let (report, outcome) = run_script(
+        &command.script,
+        source,
+        name,
+        Arc::new(backtest),
+        output,
+        control,
+    )
+    .await?;
+    let mut lines = vec![report.to_string(), outcome];
+    let csv = command.csv.map(|path| (path, report.to_csv()));
+    let fills = report.fills.len();
+    // The report window opens even if the export fails
+    FINISHED.broadcast(report);
+    if let Some((path, csv)) = csv {
+        export::save(&path, &csv, "text/csv")?;
+        lines.push(format!("Exported {} fills to {}", fills, path));
+    }
+    Ok(lines.join("\n"))
+}
+
+/// Runs `source` with `backtest` installed as the exchange `name`,
+/// returns the report and how the script ended.
+async fn run_script(
+    path: &str,
+    source: String,
+    name: &'static str,
+    backtest: Arc<Backtest>,
+    output: Sender<String>,
+    control: &Control,
+) -> Result<(BacktestReport, String), String> {
+    let mut context = Context::with_config(false).map_err(|e| e.to_string())?;
+    install_module_output(&mut context, output);
+    install_module_utils_with_clock(&mut context, backtest.clock());
+    install_module_error(&mut context);
+    install_module_exchange(&mut context);
+    install_exchange_as(&mut context, name, backtest.clone());
+
+    let unit = compile(&context, path, source)?;
+    let runtime = Arc::new(context.runtime().map_err(|e| e.to_string())?);
+    let vm = Vm::new(runtime, Arc::new(unit));
+    let execution = vm.send_execute(["main"], ()).map_err(|e| e.to_string())?;
+
+    // Scripts usually loop until a sleep or order wait fails with the end of the data
+    let control = control.clone();
+    let outcome = async_helpers::spawn_in(TaskClass::Action, async move {
+        match control.run(execution.async_complete()).await.into_result() {
+            Ok(value) => format!("Script returned {:?}", value),
+            Err(e) => format!("Script failed: {}", e),
+        }
+    })
+    .await_handle()
+    .await;
+
+    Ok((backtest.report(), outcome))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check_range, parse_backtest_command, BacktestCommand};
+    use crate::currency::Currency;
+    use crate::exchange::Ticker;
+    use crate::utils::Decimal;
+
+    #[test]
+    fn backtest_command() {
+        assert_eq!(
+            parse_backtest_command("backtest grid.rn Upbit btc-krw 2024-01-01 2024-01-02T12:00"),
+            Some(Ok(BacktestCommand {
+                script: "grid.rn".to_string(),
+                exchange: "upbit".to_string(),
+                pair: (Currency::BTC, Currency::KRW),
+                start: 1_704_067_200_000,
+                end: 1_704_196_800_000,
+                csv: None,
+            }))
+        );
+
+        let command = parse_backtest_command(
+            "backtest grid.rn upbit BTC-KRW 2024-01-01 2024-01-02 csv fills.csv",
+        );
+        assert_eq!(command.unwrap().unwrap().csv.as_deref(), Some("fills.csv"));
+
+        assert!(matches!(
+            parse_backtest_command("backtest grid.rn upbit BTC-KRW 2024-01-02 2024-01-01"),
+            Some(Err(_))
+        ));
+        assert!(matches!(
+            parse_backtest_command("backtest grid.rn upbit BTC-KRW yesterday 2024-01-01"),
+            Some(Err(_))
+        ));
+        assert!(matches!(parse_backtest_command("backtest"), Some(Err(_))));
+        assert_eq!(parse_backtest_command("backtests()"), None);
+    }
+
+    #[test]
+    fn ranges_before_the_candles_are_rejected() {
+        let candle = |timestamp: u64| Ticker {
+            timestamp,
+            open: Decimal::ONE,
+            close: Decimal::ONE,
+            low: Decimal::ONE,
+            high: Decimal::ONE,
+        };
+        let candles = [candle(1_704_067_200_000), candle(1_704_067_260_000)];
+
+        assert!(check_range("upbit", &candles, 1_704_067_200_000).is_ok());
+        let error = check_range("upbit", &candles, 1_704_067_140_000).unwrap_err();
+        assert!(error.contains("2024-01-01T00:00"), "{}", error);
+        assert!(check_range("upbit", &[], 1_704_067_200_000).is_err());
+    }
+}
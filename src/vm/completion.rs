@@ -0,0 +1,244 @@
+use std::ops::Range;
+
+use crate::currency::Currency;
+use crate::exchange::binance::Binance;
+use crate::exchange::bithumb::Bithumb;
+use crate::exchange::okx::Okx;
+use crate::exchange::upbit::Upbit;
+use crate::exchange::Exchange;
+
+/// Functions and methods installed in the console, rune can't list the items of a context.
+const FUNCTIONS: &[&str] = &[
+    // output
+    "print",
+    "println",
+    "log",
+    // utils
+    "sleep",
+    "normalize",
+    "abs",
+    "round_dp",
+    "from_str",
+    // error
+    "display",
+    // exchange
+    "orderbook",
+    "price",
+    "balances",
+    "imbalance",
+    "depth",
+    "spread_ticks",
+    "convert",
+    "withdraw_fee",
+    "min_notional",
+    "markets",
+    "bid_limit",
+    "bid_market",
+    "ask_limit",
+    "ask_market",
+    "stop_limit",
+    "view_order",
+    "wait_order",
+    "wait_order_timeout",
+    "cancel_order",
+    "watch_order",
+    "set_dry_run",
+    "fx",
+];
+
+const TYPES: &[&str] = &[
+    "Balance",
+    "Currency",
+    "Decimal",
+    "Error",
+    "Market",
+    "Order",
+    "OrderState",
+    "Orderbook",
+    "Side",
+];
+
+/// Console commands that are not scripts.
+const COMMANDS: &[&str] = &[
+    "actions", "backtest", "cancel", "dequeue", "expand", "journal", "ledger", "panic", "stopall",
+];
+
+const EXCHANGES: &[&str] = &[Upbit::NAME, Binance::NAME, Bithumb::NAME, Okx::NAME];
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Byte range of the word around the `cursor`-th char.
+fn word_at(input: &str, cursor: usize) -> Range<usize> {
+    let cursor = input
+        .char_indices()
+        .nth(cursor)
+        .map_or(input.len(), |(index, _)| index);
+    let start = input[..cursor]
+        .rfind(|c| !is_word(c))
+        .map_or(0, |index| index + 1);
+    let end = input[cursor..]
+        .find(|c| !is_word(c))
+        .map_or(input.len(), |index| cursor + index);
+    start..end
+}
+
+/// Names bound with `let` in the input, each input is a script of its own
+/// so only its own variables are in scope.
+fn variables(input: &str) -> Vec<&str> {
+    let mut words = input.split(|c| !is_word(c)).filter(|word| !word.is_empty());
+    let mut variables = Vec::new();
+    while let Some(word) = words.next() {
+        if word != "let" {
+            continue;
+        }
+        match words.next() {
+            Some("mut") => variables.extend(words.next()),
+            name => variables.extend(name),
+        }
+    }
+    variables
+}
+
+/// Everything starting with `prefix`, ignoring the case, variables first.
+fn candidates(input: &str, prefix: &str) -> Vec<String> {
+    if prefix.is_empty() {
+        return Vec::new();
+    }
+
+    let prefix = prefix.to_lowercase();
+    let names = variables(input)
+        .into_iter()
+        .chain(FUNCTIONS.iter().copied())
+        .chain(COMMANDS.iter().copied())
+        .chain(EXCHANGES.iter().copied())
+        .chain(TYPES.iter().copied())
+        .chain(Currency::KNOWN.iter().map(Currency::as_str));
+
+    let mut candidates: Vec<String> = Vec::new();
+    for name in names {
+        if name.to_lowercase().starts_with(&prefix) && !candidates.iter().any(|c| c == name) {
+            candidates.push(name.to_string());
+        }
+    }
+    candidates
+}
+
+#[derive(Debug)]
+struct Cycle {
+    /// The input before and after the completed word.
+    before: String,
+    after: String,
+    candidates: Vec<String>,
+    index: usize,
+    /// The input as last completed, any other input starts over.
+    completed: String,
+}
+
+impl Cycle {
+    fn new(input: &str, cursor: usize) -> Option<Self> {
+        let word = word_at(input, cursor);
+        let typed = input.chars().take(cursor).count() - input[..word.start].chars().count();
+        let prefix = input[word.clone()].chars().take(typed).collect::<String>();
+
+        let candidates = candidates(input, &prefix);
+        if candidates.is_empty() {
+            return None;
+        }
+        Some(Self {
+            before: input[..word.start].to_string(),
+            after: input[word.end..].to_string(),
+            candidates,
+            index: 0,
+            completed: String::new(),
+        })
+    }
+}
+
+/// Completes the word under the cursor with builtins, variables, exchanges and currencies.
+/// Completing the same input again cycles through the candidates.
+#[derive(Debug, Default)]
+pub struct Completer {
+    cycle: Option<Cycle>,
+}
+
+impl Completer {
+    /// Returns the completed input and the cursor after the completed word.
+    /// The cursor counts chars, only the part of the word before it is matched.
+    pub fn complete(&mut self, input: &str, cursor: usize) -> Option<(String, usize)> {
+        match &mut self.cycle {
+            Some(cycle) if cycle.completed == input => {
+                cycle.index = (cycle.index + 1) % cycle.candidates.len();
+            }
+            cycle => *cycle = Cycle::new(input, cursor),
+        }
+
+        let cycle = self.cycle.as_mut()?;
+        let candidate = &cycle.candidates[cycle.index];
+        cycle.completed = format!("{}{}{}", cycle.before, candidate, cycle.after);
+        let cursor = cycle.before.chars().count() + candidate.chars().count();
+        Some((cycle.completed.clone(), cursor))
+    }
+
+    /// Candidates of the word being completed, with the index of the one in the input.
+    pub fn candidates(&self) -> Option<(&[String], usize)> {
+        self.cycle
+            .as_ref()
+            .map(|cycle| (cycle.candidates.as_slice(), cycle.index))
+    }
+
+    pub fn reset(&mut self) {
+        self.cycle = None;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{candidates, variables, Completer};
+
+    #[test]
+    fn matches_ignore_the_case() {
+        assert_eq!(candidates("", "BID_"), vec!["bid_limit", "bid_market"]);
+        assert_eq!(candidates("", "bt"), vec!["BTC"]);
+        assert_eq!(candidates("", "Upb"), vec!["upbit"]);
+        assert!(candidates("", "").is_empty());
+    }
+
+    #[test]
+    fn variables_of_the_input() {
+        let input = "let spread = 1; let mut sp_total = 2; sp";
+        assert_eq!(variables(input), vec!["spread", "sp_total"]);
+        assert_eq!(
+            candidates(input, "sp"),
+            vec!["spread", "sp_total", "spread_ticks"]
+        );
+    }
+
+    #[test]
+    fn cycles_on_repeated_completion() {
+        let mut completer = Completer::default();
+        let (input, cursor) = completer.complete("upbit.bid_", 10).unwrap();
+        assert_eq!((input.as_str(), cursor), ("upbit.bid_limit", 15));
+        let (input, cursor) = completer.complete(&input, cursor).unwrap();
+        assert_eq!((input.as_str(), cursor), ("upbit.bid_market", 16));
+        let (input, _) = completer.complete(&input, cursor).unwrap();
+        assert_eq!(input, "upbit.bid_limit");
+        assert_eq!(completer.candidates().unwrap().1, 0);
+
+        // An edited input starts over
+        assert_eq!(completer.complete("xyz", 3), None);
+        assert!(completer.candidates().is_none());
+    }
+
+    #[test]
+    fn completes_mid_word() {
+        let mut completer = Completer::default();
+        // The cursor is after `wait_o`, the rest of the word is replaced
+        let (input, cursor) = completer.complete("upbit.wait_oXX(token)", 12).unwrap();
+        assert_eq!(input, "upbit.wait_order(token)");
+        assert_eq!(cursor, 16);
+        let (input, _) = completer.complete(&input, cursor).unwrap();
+        assert_eq!(input, "upbit.wait_order_timeout(token)");
+    }
+}
@@ -0,0 +1,555 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use async_channel::{Receiver, Sender};
+use futures::future::{self, Either};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use rune::runtime::{RuntimeContext, Unit};
+use rune::termcolor::Buffer;
+use rune::{Context, Diagnostics, Module, Source, Sources, Vm};
+
+use crate::config::Config;
+use crate::exchange::binance::Binance;
+use crate::exchange::bithumb::Bithumb;
+use crate::exchange::okx::Okx;
+use crate::exchange::upbit::Upbit;
+use crate::exchange::{Exchange, Exchanges, Order, OrderState, OrderToken, Side};
+use crate::utils::async_helpers::{self, TaskClass};
+use crate::utils::ledger::{self, LedgerEntry, LedgerEvent, OrderKind};
+use crate::utils::throttle::{Permit, Throttle, ThrottleStats, Ticket};
+use crate::utils::Decimal;
+
+use super::backtest::{self, parse_backtest_command};
+use super::control::{self, Control};
+use super::error::install_module_error;
+use super::exchange::{install_exchange, install_module_exchange, install_module_fx, order_stats};
+use super::pretty::{self, Limits};
+use super::utils::install_module_utils;
+
+/// Limits the scripts running at once, shared by every console.
+static SCRIPTS: Lazy<Throttle> = Lazy::new(|| Throttle::new(Config::get().actions.scripts()));
+
+/// Replaces the stdout printing of `std::io` with a channel, so the console can show the output.
+/// Also adds `log`, which prints like `println` and records the message in the tracing log,
+/// values are formatted with `log(format!("{}", value))`.
+pub(super) fn install_module_output(context: &mut Context, output: Sender<String>) {
+    let mut module = Module::with_crate_item("std", ["io"]).unwrap();
+
+    let sender = output.clone();
+    module
+        .function("print", move |message: &str| {
+            let _ = sender.try_send(message.to_string());
+        })
+        .build()
+        .unwrap();
+    let sender = output.clone();
+    module
+        .function("println", move |message: &str| {
+            let _ = sender.try_send(message.to_string());
+        })
+        .build()
+        .unwrap();
+
+    context.install(module).unwrap();
+
+    let mut module = Module::new();
+    module
+        .function("log", move |message: &str| {
+            tracing::info!("script: {}", message);
+            let _ = output.try_send(message.to_string());
+        })
+        .build()
+        .unwrap();
+
+    context.install(module).unwrap();
+}
+
+const STOPPED_IN_QUEUE: &str = "Dropped from the queue before the script started";
+
+/// Cut results kept for `expand`, the oldest are dropped.
+const RECENT_RESULTS: usize = 20;
+
+/// Entries shown by `ledger` without a count.
+const LEDGER_DEFAULT_COUNT: usize = 20;
+
+#[derive(Debug, PartialEq)]
+enum LedgerCommand {
+    /// Shows the latest entries.
+    Tail(usize),
+    /// Exports every entry as csv, to the path on desktop.
+    Export(String),
+}
+
+/// Parses the `ledger [tail] [count]` and `ledger export <path>` commands,
+/// `journal` is the same command. None if the input is a script.
+fn parse_ledger_command(input: &str) -> Option<Result<LedgerCommand, String>> {
+    let words = input.split_whitespace().collect::<Vec<_>>();
+    let (command, arguments) = words.split_first()?;
+    if !matches!(*command, "ledger" | "journal") {
+        return None;
+    }
+
+    match arguments {
+        [] | ["tail"] => Some(Ok(LedgerCommand::Tail(LEDGER_DEFAULT_COUNT))),
+        ["export", path] => Some(Ok(LedgerCommand::Export(path.to_string()))),
+        ["tail", count] | [count] => Some(count.parse().map(LedgerCommand::Tail).map_err(|_| {
+            format!(
+                "Invalid count {}, usage: {} tail <count> or {} export <path>",
+                count, command, command
+            )
+        })),
+        _ => None,
+    }
+}
+
+/// Parses `expand <n>`, None if the input is a script.
+fn parse_expand_command(input: &str) -> Option<Result<u64, String>> {
+    match input.split_whitespace().collect::<Vec<_>>().as_slice() {
+        ["expand", number] => Some(
+            number
+                .parse()
+                .map_err(|_| format!("Invalid result {}, usage: expand <n>", number)),
+        ),
+        _ => None,
+    }
+}
+
+fn format_ledger_entry(entry: &LedgerEntry) -> String {
+    let time = chrono::DateTime::from_timestamp_millis(entry.timestamp)
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default();
+    let event = match &entry.event {
+        LedgerEvent::Order {
+            pair,
+            side,
+            kind,
+            price,
+            amount,
+            result,
+            ..
+        } => {
+            let price = price
+                .map(|price| price.normalize().to_string())
+                .unwrap_or_else(|| "market".to_string());
+            // Market bids are sized in the quote currency
+            let amount = match (kind, side) {
+                (OrderKind::Market, Side::Bid) => format!("{} {} of", amount.normalize(), pair.1),
+                _ => amount.normalize().to_string(),
+            };
+            format!(
+                "{:?} {:?} {} {}-{} at {}: {}",
+                kind, side, amount, pair.0, pair.1, price, result
+            )
+        }
+        LedgerEvent::Fill {
+            order_token,
+            executed_volume,
+            avg_price,
+        } => {
+            let price = avg_price
+                .map(|price| format!(" at {}", price.normalize()))
+                .unwrap_or_default();
+            format!(
+                "Filled {}{} of {:?}",
+                executed_volume.normalize(),
+                price,
+                order_token
+            )
+        }
+        LedgerEvent::Cancel {
+            order_token,
+            result,
+            ..
+        } => format!("Cancel {:?}: {}", order_token, result),
+        LedgerEvent::Withdraw {
+            currency,
+            amount,
+            address,
+            result,
+            ..
+        } => format!(
+            "Withdraw {} {} to {}: {}",
+            amount.normalize(),
+            currency,
+            address,
+            result
+        ),
+    };
+    format!("{} {} {}", time, entry.exchange, event)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn export_ledger(path: &str) -> Result<String, String> {
+    let count = ledger::export_csv(path).map_err(|e| e.to_string())?;
+    Ok(format!("Exported {} entries to {}", count, path))
+}
+
+/// The browser has no file system, the csv is printed to be copied instead.
+#[cfg(any(target_arch = "wasm32"))]
+fn export_ledger(_path: &str) -> Result<String, String> {
+    Ok(ledger::to_csv(&ledger::tail(usize::MAX)))
+}
+
+/// Compiles `source`, the errors are rendered with the diagnostics of the compiler.
+pub(super) fn compile(context: &Context, name: &str, source: String) -> Result<Unit, String> {
+    let mut sources = Sources::new();
+    sources
+        .insert(Source::new(name, source).map_err(|e| e.to_string())?)
+        .map_err(|e| e.to_string())?;
+
+    let mut diagnostics = Diagnostics::new();
+    let unit = rune::prepare(&mut sources)
+        .with_context(context)
+        .with_diagnostics(&mut diagnostics)
+        .build();
+
+    unit.map_err(|e| {
+        let mut buffer = Buffer::no_color();
+        if diagnostics.emit(&mut buffer, &sources).is_err() {
+            return e.to_string();
+        }
+        String::from_utf8_lossy(buffer.as_slice()).into_owned()
+    })
+}
+
+/// Running and queued scripts and orders, one line each.
+fn actions_summary() -> String {
+    let line = |name: &str, stats: ThrottleStats| {
+        format!(
+            "{}: {} running, {} queued",
+            name, stats.running, stats.queued
+        )
+    };
+
+    let mut lines = vec![line("scripts", SCRIPTS.stats())];
+    for name in [Upbit::NAME, Binance::NAME, Bithumb::NAME, Okx::NAME] {
+        lines.push(line(&format!("{} orders", name), order_stats(name)));
+    }
+    lines.join("\n")
+}
+
+/// How an order left open by a stopped script ended.
+enum Settled {
+    /// It closed before the console got to it.
+    Closed(Order),
+    /// Cancelled, with the volume executed before.
+    Cancelled(Decimal),
+}
+
+/// Cancels the order unless it already closed, a failed cancel of a filled order is not an error.
+async fn settle<E: Exchange>(exchange: &E, order_token: &OrderToken) -> Result<Settled, String> {
+    if let Ok(order) = exchange.view_order(order_token).await {
+        if order.state == OrderState::Closed {
+            return Ok(Settled::Closed(order));
+        }
+    }
+    exchange
+        .cancel_order(order_token)
+        .await
+        .map(Settled::Cancelled)
+        .map_err(|e| e.to_string())
+}
+
+async fn settle_open_order(
+    exchanges: &Exchanges,
+    exchange: &str,
+    order_token: &OrderToken,
+) -> Result<Settled, String> {
+    if exchange == Upbit::NAME {
+        settle(exchanges.upbit.as_ref(), order_token).await
+    } else if exchange == Binance::NAME {
+        settle(exchanges.binance.as_ref(), order_token).await
+    } else if exchange == Bithumb::NAME {
+        settle(exchanges.bithumb.as_ref(), order_token).await
+    } else if exchange == Okx::NAME {
+        settle(exchanges.okx.as_ref(), order_token).await
+    } else {
+        Err(format!("Unknown exchange {}", exchange))
+    }
+}
+
+/// Stops the running and queued scripts of `control`, then cancels the orders they left open.
+/// One line for each order.
+async fn stop_all(control: &Control, exchanges: &Exchanges) -> String {
+    control.stop();
+
+    let mut lines = vec!["Stopped the running and queued scripts".to_string()];
+    for (exchange, order_token) in control.take_open_orders() {
+        let (executed_volume, result) =
+            match settle_open_order(exchanges, exchange, &order_token).await {
+                Ok(Settled::Closed(order)) => {
+                    lines.push(format!("{} {:?}: already closed", exchange, order_token));
+                    ledger::record_fill(
+                        exchange,
+                        &order_token,
+                        &order.state,
+                        order.executed_volume,
+                        order.avg_price,
+                    );
+                    continue;
+                }
+                Ok(Settled::Cancelled(executed_volume)) => {
+                    (Some(executed_volume), "cancelled".to_string())
+                }
+                Err(e) => (None, e),
+            };
+        lines.push(format!("{} {:?}: {}", exchange, order_token, result));
+        ledger::record_cancel(exchange, &order_token, executed_volume, result);
+    }
+    lines.join("\n")
+}
+
+/// Queues a script, with the limits of the current config.
+fn enqueue_script() -> Result<Ticket, String> {
+    SCRIPTS.set_limits(Config::get().actions.scripts());
+    SCRIPTS.enqueue().map_err(|e| e.to_string())
+}
+
+/// Waits for a script slot, None if the console stops the script
+/// or drops the queued ones before it gets one.
+async fn admitted(ticket: Ticket) -> Option<Permit> {
+    let admitted = Box::pin(ticket.admitted());
+    match future::select(admitted, Box::pin(control::dequeued())).await {
+        Either::Left((permit, _)) => Some(permit),
+        // Dropping the ticket leaves the queue
+        Either::Right(_) => None,
+    }
+}
+
+/// Evaluates console input against the exchanges.
+///
+/// Each input is compiled as the body of an async `main`,
+/// so exchange calls can be awaited directly.
+/// `ledger [tail] [count]` and `ledger export <path>`, or `journal` alike, are not scripts,
+/// they show or export the latest recorded order events.
+/// `backtest <script> <exchange> <pair> <start> <end> [csv <path>]` runs the `main` of a script file
+/// against the candles of the exchange between the dates, on a virtual clock,
+/// and shows the pnl, win rate and drawdown of its simulated fills.
+/// `cancel` ends the sleeps of the scripts of this console still running,
+/// `stopall` (or `panic`) stops them and the queued ones from placing orders,
+/// and cancels the orders they placed that are still open.
+/// Scripts beyond the configured limit wait for a slot, `actions` shows how many are waiting
+/// and `dequeue` drops the ones of this console.
+/// Results too long or too deep to show are cut and numbered, `expand <n>` shows all of one.
+pub struct Console {
+    context: Context,
+    runtime: Arc<RuntimeContext>,
+    exchanges: Exchanges,
+    output: Sender<String>,
+    control: Control,
+    /// The last cut results by their number, with their debug representation.
+    results: Mutex<VecDeque<(u64, String)>>,
+    /// Results cut so far.
+    cut: AtomicU64,
+}
+
+impl Console {
+    /// Returns the console and the receiver of the printed output.
+    pub fn new(exchanges: &Exchanges) -> (Self, Receiver<String>) {
+        let (sender, receiver) = async_channel::unbounded();
+
+        let mut context = Context::with_config(false).unwrap();
+        install_module_output(&mut context, sender.clone());
+        install_module_utils(&mut context);
+        install_module_error(&mut context);
+        install_module_exchange(&mut context);
+        install_module_fx(&mut context, exchanges.clone());
+        install_exchange(&mut context, exchanges.upbit.clone());
+        install_exchange(&mut context, exchanges.binance.clone());
+        install_exchange(&mut context, exchanges.bithumb.clone());
+        install_exchange(&mut context, exchanges.okx.clone());
+
+        let runtime = Arc::new(context.runtime().unwrap());
+        let console = Self {
+            context,
+            runtime,
+            exchanges: exchanges.clone(),
+            output: sender,
+            control: Control::new(),
+            results: Mutex::new(VecDeque::new()),
+            cut: AtomicU64::new(0),
+        };
+        (console, receiver)
+    }
+
+    /// Pretty prints the result, a cut one is kept to be expanded.
+    fn show(&self, debug: String) -> String {
+        let shown = pretty::pretty(&debug, Limits::default());
+        if !shown.truncated {
+            return shown.text;
+        }
+
+        let number = self.cut.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut results = self.results.lock();
+        results.push_back((number, debug));
+        if results.len() > RECENT_RESULTS {
+            results.pop_front();
+        }
+        format!("{}\n(cut, expand {} shows all of it)", shown.text, number)
+    }
+
+    fn expand(&self, number: u64) -> Result<String, String> {
+        let results = self.results.lock();
+        let (_, debug) = results
+            .iter()
+            .find(|(kept, _)| *kept == number)
+            .ok_or_else(|| {
+                format!(
+                    "No result {}, the last {} cut results are kept",
+                    number, RECENT_RESULTS
+                )
+            })?;
+        Ok(pretty::pretty(debug, Limits::UNLIMITED).text)
+    }
+
+    /// Returns the pretty printed result, or the compile or runtime error.
+    pub async fn evaluate(&self, input: &str) -> Result<String, String> {
+        if input.trim() == "cancel" {
+            self.control.cancel();
+            return Ok("Cancelled running sleeps and order waits".to_string());
+        }
+
+        if matches!(input.trim(), "stopall" | "panic") {
+            return Ok(stop_all(&self.control, &self.exchanges).await);
+        }
+
+        if input.trim() == "dequeue" {
+            self.control.drop_queued();
+            return Ok("Dropped the queued scripts".to_string());
+        }
+
+        if input.trim() == "actions" {
+            return Ok(actions_summary());
+        }
+
+        if let Some(number) = parse_expand_command(input) {
+            return self.expand(number?);
+        }
+
+        if let Some(command) = parse_ledger_command(input) {
+            let count = match command? {
+                LedgerCommand::Tail(count) => count,
+                LedgerCommand::Export(path) => return export_ledger(&path),
+            };
+            let entries = ledger::tail(count);
+            if entries.is_empty() {
+                return Ok("No orders recorded".to_string());
+            }
+            return Ok(entries
+                .iter()
+                .map(format_ledger_entry)
+                .collect::<Vec<_>>()
+                .join("\n"));
+        }
+
+        if let Some(command) = parse_backtest_command(input) {
+            let command = command?;
+            let ticket = enqueue_script()?;
+            let Some(_permit) = self.control.run(admitted(ticket)).await else {
+                return Err(STOPPED_IN_QUEUE.to_string());
+            };
+            let output = self.output.clone();
+            return backtest::run(command, &self.exchanges, output, &self.control).await;
+        }
+
+        let source = format!("pub async fn main() {{\n{}\n}}", input);
+        let unit = compile(&self.context, "console", source)?;
+
+        // Scripts run on the action runtime, a busy script must not hold up the market data
+        let ticket = enqueue_script()?;
+        let vm = Vm::new(self.runtime.clone(), Arc::new(unit));
+        let execution = vm.send_execute(["main"], ()).map_err(|e| e.to_string())?;
+        let debug = async_helpers::spawn_in(
+            TaskClass::Action,
+            self.control.run(async move {
+                let Some(_permit) = admitted(ticket).await else {
+                    return Err(STOPPED_IN_QUEUE.to_string());
+                };
+                let value = execution
+                    .async_complete()
+                    .await
+                    .into_result()
+                    .map_err(|e| e.to_string())?;
+                Ok(format!("{:?}", value))
+            }),
+        )
+        .await_handle()
+        .await?;
+        Ok(self.show(debug))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rune::Context;
+
+    use super::{
+        install_module_output, parse_expand_command, parse_ledger_command, LedgerCommand,
+        LEDGER_DEFAULT_COUNT,
+    };
+    use crate::vm::error::install_module_error;
+    use crate::vm::test_vm;
+    use crate::vm::utils::install_module_utils;
+
+    #[test]
+    fn log_formats_values() {
+        let (sender, receiver) = async_channel::unbounded();
+        let mut context = Context::with_config(false).unwrap();
+        install_module_output(&mut context, sender);
+        install_module_utils(&mut context);
+        install_module_error(&mut context);
+
+        let script = r#"
+            pub fn main() {
+                let price = Decimal::from_str("1.50")?;
+                log(format!("price {}", price));
+                println("done");
+            }
+        "#;
+        let mut vm = test_vm(&context, script);
+        vm.call(["main"], ()).unwrap();
+
+        assert_eq!(receiver.try_recv().unwrap(), "price 1.50");
+        assert_eq!(receiver.try_recv().unwrap(), "done");
+    }
+
+    #[test]
+    fn ledger_command() {
+        assert_eq!(
+            parse_ledger_command(" ledger "),
+            Some(Ok(LedgerCommand::Tail(LEDGER_DEFAULT_COUNT)))
+        );
+        assert_eq!(
+            parse_ledger_command("ledger 5"),
+            Some(Ok(LedgerCommand::Tail(5)))
+        );
+        assert_eq!(
+            parse_ledger_command("ledger export orders.csv"),
+            Some(Ok(LedgerCommand::Export("orders.csv".to_string())))
+        );
+        assert_eq!(
+            parse_ledger_command("journal tail 5"),
+            Some(Ok(LedgerCommand::Tail(5)))
+        );
+        assert_eq!(
+            parse_ledger_command("journal export orders.csv"),
+            Some(Ok(LedgerCommand::Export("orders.csv".to_string())))
+        );
+        assert!(matches!(parse_ledger_command("ledger many"), Some(Err(_))));
+        assert!(matches!(
+            parse_ledger_command("journal tail many"),
+            Some(Err(_))
+        ));
+        assert_eq!(parse_ledger_command("ledger(5)"), None);
+    }
+
+    #[test]
+    fn expand_command() {
+        assert_eq!(parse_expand_command(" expand 3 "), Some(Ok(3)));
+        assert!(matches!(parse_expand_command("expand all"), Some(Err(_))));
+        assert_eq!(parse_expand_command("expand"), None);
+        assert_eq!(parse_expand_command("expand(3)"), None);
+    }
+}
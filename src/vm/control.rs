@@ -0,0 +1,339 @@
+//! Cancelling the waits of the scripts one console runs and stopping them,
+//! without touching the scripts of others.
+//! Builtins find the [`Control`] of the script calling them through [`Control::current`].
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use crate::exchange::OrderToken;
+use crate::utils::broadcaster::Broadcaster;
+
+use super::error;
+
+/// Open orders remembered per console, the oldest are forgotten past it.
+const OPEN_ORDER_LIMIT: usize = 1000;
+
+thread_local! {
+    /// The script being polled on this thread, set by [`Controlled`].
+    static CURRENT: RefCell<Option<Script>> = const { RefCell::new(None) };
+}
+
+/// Control of the scripts run outside of [`Control::run`], like the ones of tests.
+static DETACHED: Lazy<Control> = Lazy::new(Control::new);
+
+#[derive(thiserror::Error, Debug)]
+#[error("wait cancelled")]
+pub struct Cancelled;
+
+#[derive(thiserror::Error, Debug)]
+#[error("the script was stopped")]
+pub struct Stopped;
+
+/// Shared by the scripts of one console.
+#[derive(Clone)]
+pub struct Control(Arc<State>);
+
+struct State {
+    /// Bumped by [`Control::cancel`] and [`Control::stop`],
+    /// waits started before it end with [`Cancelled`].
+    generation: AtomicU64,
+    /// Bumped by [`Control::stop`], scripts started before it fail with [`Stopped`].
+    stops: AtomicU64,
+    /// Bumped by [`Control::drop_queued`], scripts started before it leave the queue.
+    dequeues: AtomicU64,
+    changed: Broadcaster<()>,
+    /// Orders placed by the scripts and not seen closed yet, by exchange name.
+    open_orders: Mutex<Vec<(&'static str, OrderToken)>>,
+    /// When set, the order builtins log the order and return a filled synthetic order
+    /// instead of sending it to the exchange.
+    dry_run: AtomicBool,
+}
+
+/// A script run under a control, with the stops and dequeues of the control when it was started.
+#[derive(Clone)]
+struct Script {
+    control: Control,
+    stops: u64,
+    dequeues: u64,
+}
+
+impl Script {
+    fn current() -> Self {
+        CURRENT
+            .with(|current| current.borrow().clone())
+            .unwrap_or_else(|| DETACHED.script())
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.control.0.stops.load(Ordering::Relaxed) != self.stops
+    }
+
+    fn is_dequeued(&self) -> bool {
+        self.control.0.dequeues.load(Ordering::Relaxed) != self.dequeues
+    }
+}
+
+impl Control {
+    pub fn new() -> Self {
+        Self(Arc::new(State {
+            generation: AtomicU64::new(0),
+            stops: AtomicU64::new(0),
+            dequeues: AtomicU64::new(0),
+            changed: Broadcaster::new(),
+            open_orders: Mutex::new(Vec::new()),
+            dry_run: AtomicBool::new(false),
+        }))
+    }
+
+    /// Control of the script being run on this thread.
+    pub fn current() -> Self {
+        Script::current().control
+    }
+
+    /// Runs the execution of a script, the builtins it calls see this control as current.
+    /// Stopping the control after this call stops the script, even if it did not start yet.
+    pub fn run<F: Future>(&self, future: F) -> Controlled<F> {
+        Controlled {
+            script: self.script(),
+            future,
+        }
+    }
+
+    fn script(&self) -> Script {
+        Script {
+            control: self.clone(),
+            stops: self.0.stops.load(Ordering::Relaxed),
+            dequeues: self.0.dequeues.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Ends every sleep and order wait running in the scripts of this control.
+    pub fn cancel(&self) {
+        self.0.generation.fetch_add(1, Ordering::Relaxed);
+        self.0.changed.broadcast(());
+    }
+
+    /// Stops the scripts started so far, queued ones included: their waits end
+    /// and the order builtins fail with [`Stopped`]. Scripts started afterwards run normally.
+    pub fn stop(&self) {
+        self.0.stops.fetch_add(1, Ordering::Relaxed);
+        self.cancel();
+    }
+
+    /// Drops the scripts started so far that still wait for a slot, running ones go on.
+    pub fn drop_queued(&self) {
+        self.0.dequeues.fetch_add(1, Ordering::Relaxed);
+        self.0.changed.broadcast(());
+    }
+
+    fn generation(&self) -> u64 {
+        self.0.generation.load(Ordering::Relaxed)
+    }
+
+    pub fn set_dry_run(&self, enabled: bool) {
+        self.0.dry_run.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.0.dry_run.load(Ordering::Relaxed)
+    }
+
+    /// Remembers an order placed by a script, until it is seen closed or cancelled.
+    pub fn track_order(&self, exchange: &'static str, order_token: OrderToken) {
+        let mut open_orders = self.0.open_orders.lock();
+        open_orders.push((exchange, order_token));
+        if open_orders.len() > OPEN_ORDER_LIMIT {
+            let (exchange, order_token) = open_orders.remove(0);
+            tracing::warn!(
+                "{}: no longer tracking {:?}, too many open orders",
+                exchange,
+                order_token
+            );
+        }
+    }
+
+    /// Returns false if the order was not tracked, or already taken by [`Control::take_open_orders`].
+    pub fn forget_order(&self, order_token: &OrderToken) -> bool {
+        let mut open_orders = self.0.open_orders.lock();
+        let tracked = open_orders.len();
+        open_orders.retain(|(_, open_order)| open_order != order_token);
+        open_orders.len() != tracked
+    }
+
+    /// Takes the orders placed by the scripts that were not seen closed or cancelled,
+    /// orders closed without a script looking at them are still in here.
+    pub fn take_open_orders(&self) -> Vec<(&'static str, OrderToken)> {
+        std::mem::take(&mut *self.0.open_orders.lock())
+    }
+}
+
+/// Fails if the console stopped the calling script.
+pub fn check_stopped() -> error::Result<()> {
+    if Script::current().is_stopped() {
+        return Err(error::Error::from_stderr(Stopped));
+    }
+    Ok(())
+}
+
+/// Resolves once the console drops the calling script from the queue, or stops it.
+pub async fn dequeued() {
+    let script = Script::current();
+    let changed = script.control.0.changed.subscribe();
+    while !script.is_stopped() && !script.is_dequeued() {
+        changed.recv().await;
+    }
+}
+
+impl Default for Control {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A future run under a [`Control`], see [`Control::run`].
+#[pin_project::pin_project]
+pub struct Controlled<F> {
+    script: Script,
+    #[pin]
+    future: F,
+}
+
+impl<F: Future> Future for Controlled<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let project = self.project();
+        let previous = CURRENT.with(|current| current.replace(Some(project.script.clone())));
+        // Restored even if the script panics, the thread goes on polling other tasks
+        let _restore = Restore(previous);
+        project.future.poll(cx)
+    }
+}
+
+struct Restore(Option<Script>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// Taken when a builtin starts waiting, tells if the control of the script was cancelled since.
+#[derive(Clone)]
+pub struct Cancellation {
+    control: Control,
+    generation: u64,
+}
+
+impl Cancellation {
+    pub fn start() -> Self {
+        let control = Control::current();
+        let generation = control.generation();
+        Self {
+            control,
+            generation,
+        }
+    }
+
+    pub fn check(&self) -> error::Result<()> {
+        if self.control.generation() != self.generation {
+            return Err(error::Error::from_stderr(Cancelled));
+        }
+        Ok(())
+    }
+
+    /// Resolves once cancelled, to end waits that can't be sliced.
+    pub async fn cancelled(&self) {
+        let changed = self.control.0.changed.subscribe();
+        while self.check().is_ok() {
+            changed.recv().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::FutureExt;
+
+    use super::{check_stopped, dequeued, Cancellation, Control};
+    use crate::exchange::OrderToken;
+    use crate::utils::async_helpers::block_on;
+
+    #[test]
+    fn cancellation_after_start() {
+        let control = Control::new();
+        let cancellation = block_on(control.run(async { Cancellation::start() }));
+        assert!(cancellation.check().is_ok());
+
+        // Other consoles are not cancelled
+        Control::new().cancel();
+        assert!(cancellation.check().is_ok());
+
+        control.cancel();
+        assert!(cancellation.check().is_err());
+        let restarted = block_on(control.run(async { Cancellation::start() }));
+        assert!(restarted.check().is_ok());
+    }
+
+    #[test]
+    fn stop_ends_the_scripts_started_before() {
+        let control = Control::new();
+        let queued = control.run(async {
+            dequeued().await;
+            check_stopped()
+        });
+        let cancellation = block_on(control.run(async { Cancellation::start() }));
+
+        control.stop();
+        assert!(block_on(queued).is_err());
+        assert!(cancellation.check().is_err());
+        assert!(block_on(control.run(async { check_stopped() })).is_ok());
+    }
+
+    #[test]
+    fn drop_queued_spares_later_scripts() {
+        let control = Control::new();
+        let queued = control.run(async {
+            dequeued().await;
+            check_stopped()
+        });
+
+        control.drop_queued();
+        // Dropped from the queue, not stopped
+        assert!(block_on(queued).is_ok());
+        assert!(control.run(dequeued()).now_or_never().is_none());
+    }
+
+    #[test]
+    fn open_orders() {
+        let control = Control::new();
+        let order = |uuid: &str| OrderToken::Upbit {
+            uuid: uuid.to_string(),
+        };
+        control.track_order("upbit", order("a"));
+        control.track_order("upbit", order("b"));
+
+        assert!(control.forget_order(&order("a")));
+        assert!(!control.forget_order(&order("a")));
+        assert_eq!(control.take_open_orders(), vec![("upbit", order("b"))]);
+        assert!(!control.forget_order(&order("b")));
+    }
+
+    #[test]
+    fn dry_run_per_control() {
+        let control = Control::new();
+        control.set_dry_run(true);
+        assert!(block_on(
+            control.run(async { Control::current().is_dry_run() })
+        ));
+        assert!(!Control::new().is_dry_run());
+    }
+}
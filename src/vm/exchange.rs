@@ -1,34 +1,119 @@
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 
-use crate::exchange::{Exchange, Market, OrderToken};
+use futures::future::{self, Either};
+use num_traits::Zero;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use crate::config::Config;
+use crate::exchange::order_watch::{OrderWatcher, PlacedOrder};
+use crate::exchange::{
+    fx, Balance, Exchange, Exchanges, Market, Order, OrderState, OrderToken, Side, Unit,
+};
+use crate::utils::clock::Clock;
+use crate::utils::ledger::{self, LedgerEntry, LedgerEvent, OrderKind};
 use crate::utils::maybe_trait::MaybeSend;
+use crate::utils::throttle::{Permit, Throttle, ThrottleStats};
 use crate::utils::Decimal;
 use crate::{currency::Currency, exchange::Orderbook};
 
+use super::control::{self, Cancellation, Cancelled, Control};
 use super::error::Error;
+use super::utils;
 
 use rune::runtime::Ref;
 
+/// Longest wait of `wait_order_timeout` between checks for a cancellation.
+const WAIT_SLICE: Duration = Duration::from_secs(1);
+
+/// Order throttles by exchange name, following the limits of the config.
+static ORDER_THROTTLES: Lazy<Mutex<HashMap<&'static str, Throttle>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
 pub fn install_module_exchange(context: &mut rune::Context) {
     let mut module = rune::Module::new();
 
     module.ty::<Currency>().unwrap();
     module.ty::<Orderbook>().unwrap();
+    // Levels of `Orderbook::bids` and `Orderbook::asks`
+    module.ty::<Unit>().unwrap();
+    module.ty::<Balance>().unwrap();
     module.ty::<Market>().unwrap();
+    module.ty::<Side>().unwrap();
+    module.ty::<Order>().unwrap();
+    module.ty::<OrderState>().unwrap();
     module.ty::<ExchangeOpaque>().unwrap();
+    module.ty::<OrderTokenOpaque>().unwrap();
 
     module.function_meta(orderbook).unwrap();
+    module.function_meta(price).unwrap();
+    module.function_meta(balances).unwrap();
+    module.function_meta(imbalance).unwrap();
+    module.function_meta(depth).unwrap();
+    module.function_meta(spread_ticks).unwrap();
+    module.function_meta(convert).unwrap();
+    module.function_meta(withdraw_fee).unwrap();
+    module.function_meta(min_notional).unwrap();
+    module.function_meta(markets).unwrap();
+    module.function_meta(bid_limit).unwrap();
+    module.function_meta(bid_market).unwrap();
+    module.function_meta(ask_limit).unwrap();
+    module.function_meta(ask_market).unwrap();
+    module.function_meta(stop_limit).unwrap();
+    module.function_meta(view_order).unwrap();
+    module.function_meta(wait_order).unwrap();
+    module.function_meta(wait_order_timeout).unwrap();
+    module.function_meta(cancel_order).unwrap();
+    module.function_meta(watch_order).unwrap();
+    module.function_meta(set_dry_run).unwrap();
 
     context.install(module).unwrap();
 }
 
 pub fn install_exchange<E>(context: &mut rune::Context, ex: Arc<E>)
+where
+    E: Exchange + MaybeSend + 'static,
+{
+    install_exchange_as(context, E::NAME, ex);
+}
+
+/// Installs `ex` under `name`, e.g. a backtest standing in for the exchange a script trades on.
+pub fn install_exchange_as<E>(context: &mut rune::Context, name: &'static str, ex: Arc<E>)
 where
     E: Exchange + MaybeSend + 'static,
 {
     let mut module = rune::Module::new();
     let ex = ExchangeOpaque(ex);
-    module.constant(E::NAME, ex).build().unwrap();
+    module.constant(name, ex).build().unwrap();
+
+    context.install(module).unwrap();
+}
+
+/// Installs `fx(amount, from, to)`, converting at the reference rates.
+/// Fails when the rates are stale, a script should not trade on rates that could not be refreshed.
+pub fn install_module_fx(context: &mut rune::Context, exchanges: Exchanges) {
+    let mut module = rune::Module::new();
+    module
+        .function(
+            "fx",
+            move |amount: Decimal, from: Currency, to: Currency| {
+                let exchanges = exchanges.clone();
+                async move {
+                    let conversion = fx::convert(&exchanges, amount, from, to)
+                        .await
+                        .map_err(Error::from_stderr)?;
+                    if conversion.stale {
+                        let ttl_secs = Config::get().fx.ttl_secs;
+                        return Err(Error::from_stderr(fx::FxError::Stale(ttl_secs)));
+                    }
+                    Ok::<_, Error>(conversion.amount)
+                }
+            },
+        )
+        .build()
+        .unwrap();
 
     context.install(module).unwrap();
 }
@@ -36,12 +121,41 @@ where
 #[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
 #[cfg_attr(any(target_arch = "wasm32"), async_trait::async_trait(?Send))]
 pub trait VmExchange {
+    fn name(&self) -> &'static str;
+
+    fn clock(&self) -> Clock;
+
     async fn orderbook(
         &self,
         pair: (Currency, Currency),
         market: Option<Market>,
     ) -> Result<Orderbook, Error>;
 
+    async fn ticker(
+        &self,
+        pair: (Currency, Currency),
+        market: Option<Market>,
+    ) -> Result<Decimal, Error>;
+
+    async fn balance(&self, currency: Currency, market: Option<Market>) -> Result<Balance, Error>;
+
+    async fn balances(&self, market: Option<Market>) -> Result<Vec<(Currency, Balance)>, Error>;
+
+    async fn convert(
+        &self,
+        from: Currency,
+        to: Currency,
+        amount: Decimal,
+    ) -> Result<Decimal, Error>;
+
+    async fn withdraw_fee(
+        &self,
+        currency: Currency,
+        network: Option<String>,
+    ) -> Result<Decimal, Error>;
+
+    fn min_notional(&self, pair: (Currency, Currency), market: Option<Market>) -> Option<Decimal>;
+
     async fn bid_limit(
         &self,
         pair: (Currency, Currency),
@@ -53,7 +167,7 @@ pub trait VmExchange {
     async fn bid_market(
         &self,
         pair: (Currency, Currency),
-        base_qty: Decimal,
+        quote_qty: Decimal,
         market: Option<Market>,
     ) -> Result<OrderTokenOpaque, Error>;
 
@@ -71,6 +185,36 @@ pub trait VmExchange {
         base_qty: Decimal,
         market: Option<Market>,
     ) -> Result<OrderTokenOpaque, Error>;
+
+    async fn stop_limit(
+        &self,
+        pair: (Currency, Currency),
+        stop_price: Decimal,
+        limit_price: Decimal,
+        amount: Decimal,
+        side: Side,
+        market: Option<Market>,
+    ) -> Result<OrderTokenOpaque, Error>;
+
+    async fn markets(&self) -> Result<Vec<(Currency, Currency, Market)>, Error>;
+
+    async fn view_order(&self, order_token: &OrderToken) -> Result<Order, Error>;
+    async fn wait_order(&self, order_token: &OrderToken) -> Result<Decimal, Error>;
+    async fn wait_order_timeout(
+        &self,
+        order_token: &OrderToken,
+        timeout: Duration,
+    ) -> Result<Option<Decimal>, Error>;
+    async fn cancel_order(&self, order_token: &OrderToken) -> Result<Decimal, Error>;
+
+    /// Watches the order until it closes, see [`OrderWatcher`].
+    /// Once closed, the order is no longer open for `control`.
+    fn watch_order(
+        self: Arc<Self>,
+        order_token: OrderToken,
+        placed: PlacedOrder,
+        control: Control,
+    ) -> bool;
 }
 
 #[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
@@ -79,6 +223,14 @@ impl<E> VmExchange for E
 where
     E: Exchange + MaybeSend + 'static,
 {
+    fn name(&self) -> &'static str {
+        E::NAME
+    }
+
+    fn clock(&self) -> Clock {
+        Exchange::clock(self)
+    }
+
     async fn orderbook(
         &self,
         pair: (Currency, Currency),
@@ -90,6 +242,58 @@ where
             .map_err(|e| Error::from_stderr(e))?)
     }
 
+    async fn ticker(
+        &self,
+        pair: (Currency, Currency),
+        market: Option<Market>,
+    ) -> Result<Decimal, Error> {
+        Ok(self
+            .ticker(pair, market)
+            .await
+            .map_err(|e| Error::from_stderr(e))?)
+    }
+
+    async fn balance(&self, currency: Currency, market: Option<Market>) -> Result<Balance, Error> {
+        self.balance(currency, market)
+            .await
+            .map_err(|e| Error::from_stderr(e))
+    }
+
+    async fn balances(&self, market: Option<Market>) -> Result<Vec<(Currency, Balance)>, Error> {
+        Ok(self
+            .balances(market)
+            .await
+            .map_err(|e| Error::from_stderr(e))?
+            .into_iter()
+            .collect())
+    }
+
+    async fn convert(
+        &self,
+        from: Currency,
+        to: Currency,
+        amount: Decimal,
+    ) -> Result<Decimal, Error> {
+        Ok(crate::exchange::convert::convert(self, from, to, amount)
+            .await
+            .map_err(|e| Error::from_stderr(e))?)
+    }
+
+    async fn withdraw_fee(
+        &self,
+        currency: Currency,
+        network: Option<String>,
+    ) -> Result<Decimal, Error> {
+        Ok(self
+            .withdraw_fee(currency, network.as_deref())
+            .await
+            .map_err(|e| Error::from_stderr(e))?)
+    }
+
+    fn min_notional(&self, pair: (Currency, Currency), market: Option<Market>) -> Option<Decimal> {
+        Exchange::min_notional(self, pair, market)
+    }
+
     async fn bid_limit(
         &self,
         pair: (Currency, Currency),
@@ -97,23 +301,33 @@ where
         amount: Decimal,
         market: Option<Market>,
     ) -> Result<OrderTokenOpaque, Error> {
-        Ok(OrderTokenOpaque(
+        Ok(OrderTokenOpaque::Placed(
             self.bid_limit(pair, price, amount, market)
                 .await
                 .map_err(|e| Error::from_stderr(e))?,
+            PlacedOrder {
+                pair,
+                side: Side::Bid,
+                price: Some(price),
+            },
         ))
     }
 
     async fn bid_market(
         &self,
         pair: (Currency, Currency),
-        base_qty: Decimal,
+        quote_qty: Decimal,
         market: Option<Market>,
     ) -> Result<OrderTokenOpaque, Error> {
-        Ok(OrderTokenOpaque(
-            self.bid_market(pair, base_qty, market)
+        Ok(OrderTokenOpaque::Placed(
+            self.bid_market(pair, quote_qty, market)
                 .await
                 .map_err(|e| Error::from_stderr(e))?,
+            PlacedOrder {
+                pair,
+                side: Side::Bid,
+                price: None,
+            },
         ))
     }
 
@@ -124,10 +338,15 @@ where
         amount: Decimal,
         market: Option<Market>,
     ) -> Result<OrderTokenOpaque, Error> {
-        Ok(OrderTokenOpaque(
+        Ok(OrderTokenOpaque::Placed(
             self.ask_limit(pair, price, amount, market)
                 .await
                 .map_err(|e| Error::from_stderr(e))?,
+            PlacedOrder {
+                pair,
+                side: Side::Ask,
+                price: Some(price),
+            },
         ))
     }
 
@@ -137,21 +356,370 @@ where
         base_qty: Decimal,
         market: Option<Market>,
     ) -> Result<OrderTokenOpaque, Error> {
-        Ok(OrderTokenOpaque(
+        Ok(OrderTokenOpaque::Placed(
             self.ask_market(pair, base_qty, market)
                 .await
                 .map_err(|e| Error::from_stderr(e))?,
+            PlacedOrder {
+                pair,
+                side: Side::Ask,
+                price: None,
+            },
+        ))
+    }
+
+    async fn stop_limit(
+        &self,
+        pair: (Currency, Currency),
+        stop_price: Decimal,
+        limit_price: Decimal,
+        amount: Decimal,
+        side: Side,
+        market: Option<Market>,
+    ) -> Result<OrderTokenOpaque, Error> {
+        Ok(OrderTokenOpaque::Placed(
+            self.stop_limit(pair, stop_price, limit_price, amount, side, market)
+                .await
+                .map_err(|e| Error::from_stderr(e))?,
+            PlacedOrder {
+                pair,
+                side,
+                price: Some(limit_price),
+            },
         ))
     }
+
+    async fn markets(&self) -> Result<Vec<(Currency, Currency, Market)>, Error> {
+        Ok(self.markets().await.map_err(|e| Error::from_stderr(e))?)
+    }
+
+    async fn view_order(&self, order_token: &OrderToken) -> Result<Order, Error> {
+        Ok(self
+            .view_order(order_token)
+            .await
+            .map_err(|e| Error::from_stderr(e))?)
+    }
+
+    async fn wait_order(&self, order_token: &OrderToken) -> Result<Decimal, Error> {
+        Ok(self
+            .wait_order(order_token)
+            .await
+            .map_err(|e| Error::from_stderr(e))?)
+    }
+
+    async fn wait_order_timeout(
+        &self,
+        order_token: &OrderToken,
+        timeout: Duration,
+    ) -> Result<Option<Decimal>, Error> {
+        Ok(self
+            .wait_order_timeout(order_token, timeout)
+            .await
+            .map_err(|e| Error::from_stderr(e))?)
+    }
+
+    async fn cancel_order(&self, order_token: &OrderToken) -> Result<Decimal, Error> {
+        Ok(self
+            .cancel_order(order_token)
+            .await
+            .map_err(|e| Error::from_stderr(e))?)
+    }
+
+    fn watch_order(
+        self: Arc<Self>,
+        order_token: OrderToken,
+        placed: PlacedOrder,
+        control: Control,
+    ) -> bool {
+        OrderWatcher::instance().watch(E::NAME, order_token, placed, move |order_token| {
+            let ex = self.clone();
+            let control = control.clone();
+            async move {
+                let order = Exchange::view_order(ex.as_ref(), &order_token)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                if order.state == OrderState::Closed {
+                    control.forget_order(&order_token);
+                }
+                ledger::record_fill(
+                    E::NAME,
+                    &order_token,
+                    &order.state,
+                    order.executed_volume,
+                    order.avg_price,
+                );
+                Ok(order)
+            }
+        })
+    }
 }
 
 #[allow(dead_code)]
 #[derive(rune::Any, Clone)]
 pub struct ExchangeOpaque(Arc<dyn VmExchange + 'static>);
 
-#[allow(dead_code)]
 #[derive(rune::Any, Clone)]
-pub struct OrderTokenOpaque(OrderToken);
+pub enum OrderTokenOpaque {
+    Placed(OrderToken, PlacedOrder),
+    /// Order of a dry run, never sent to the exchange.
+    DryRun(Order),
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("insufficient {currency} for the order: {needed} needed, {available} available")]
+pub struct InsufficientBalance {
+    pub currency: Currency,
+    pub needed: Decimal,
+    pub available: Decimal,
+}
+
+/// Fails with [`InsufficientBalance`] if less than `needed` of `currency` is available,
+/// instead of sending an order the exchange rejects with a less clear error.
+/// Futures are not checked, their orders are backed by margin.
+async fn check_balance(
+    ex: &ExchangeOpaque,
+    kind: OrderKind,
+    currency: Currency,
+    needed: Decimal,
+    market: Option<Market>,
+) -> Result<(), Error> {
+    let config = Config::get().actions;
+    let enabled = match kind {
+        OrderKind::Market => config.check_market_balance,
+        _ => config.check_limit_balance,
+    };
+    if !enabled || simulated(ex) || market.unwrap_or_default() == Market::Future {
+        return Ok(());
+    }
+
+    let available = ex.0.balance(currency, market).await?.available;
+    if available < needed {
+        let error = InsufficientBalance {
+            currency,
+            needed,
+            available,
+        };
+        tracing::warn!("{}: {}", ex.0.name(), error);
+        return Err(Error::from_stderr(error));
+    }
+    Ok(())
+}
+
+/// Currency and amount a limit order needs, the quote for bids and the base for asks.
+fn limit_requirement(
+    pair: (Currency, Currency),
+    side: Side,
+    price: Decimal,
+    amount: Decimal,
+) -> (Currency, Decimal) {
+    match side {
+        Side::Bid => (pair.1, price * amount),
+        Side::Ask => (pair.0, amount),
+    }
+}
+
+/// Orders of a backtest are simulated, they skip the dry run, the throttles and the ledger.
+fn simulated(ex: &ExchangeOpaque) -> bool {
+    ex.0.clock().is_virtual()
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("the orderbook of {0:?} is empty, the dry run can't be priced")]
+pub struct UnpricedDryRun((Currency, Currency));
+
+/// Returns the synthetic order to answer with if dry run is enabled for the console.
+/// Dry run orders are reported fully filled, limit orders at their limit price
+/// and market orders at the best price of the book, `amount` of a market bid being in the quote.
+async fn dry_run_order(
+    ex: &ExchangeOpaque,
+    description: String,
+    pair: (Currency, Currency),
+    side: Side,
+    amount: Decimal,
+    price: Option<Decimal>,
+    market: Option<Market>,
+) -> Option<Result<OrderTokenOpaque, Error>> {
+    if !Control::current().is_dry_run() || simulated(ex) {
+        return None;
+    }
+
+    tracing::info!("Dry run, not sent to {}: {}", ex.0.name(), description);
+    let (executed_volume, avg_price) = match price {
+        Some(price) => (amount, price),
+        None => {
+            let orderbook = match ex.0.orderbook(pair, market).await {
+                Ok(orderbook) => orderbook,
+                Err(e) => return Some(Err(e)),
+            };
+            let best = match side {
+                Side::Bid => orderbook.best_ask(),
+                Side::Ask => orderbook.best_bid(),
+            };
+            let Some(best) = best.filter(|best| !best.is_zero()) else {
+                return Some(Err(Error::from_stderr(UnpricedDryRun(pair))));
+            };
+            match side {
+                Side::Bid => (amount / best, best),
+                Side::Ask => (amount, best),
+            }
+        }
+    };
+    Some(Ok(OrderTokenOpaque::DryRun(Order {
+        state: OrderState::Closed,
+        executed_volume,
+        remaining: Decimal::zero(),
+        avg_price: Some(avg_price),
+    })))
+}
+
+/// Waits for a free order slot of the exchange, so a runaway script can't flood it with orders.
+/// Fails if too many orders are already waiting.
+async fn order_permit(ex: &ExchangeOpaque) -> Result<Option<Permit>, Error> {
+    if simulated(ex) {
+        return Ok(None);
+    }
+
+    let limits = Config::get().actions.orders();
+    let throttle = ORDER_THROTTLES
+        .lock()
+        .entry(ex.0.name())
+        .or_insert_with(|| Throttle::new(limits))
+        .clone();
+    throttle.set_limits(limits);
+    throttle
+        .acquire()
+        .await
+        .map(Some)
+        .map_err(Error::from_stderr)
+}
+
+/// Orders running and queued on the exchange `name`.
+pub fn order_stats(name: &str) -> ThrottleStats {
+    ORDER_THROTTLES
+        .lock()
+        .get(name)
+        .map(Throttle::stats)
+        .unwrap_or_default()
+}
+
+/// Records the order in the ledger, whether it was placed, dry run or failed.
+fn record(
+    ex: &ExchangeOpaque,
+    pair: (Currency, Currency),
+    side: Side,
+    kind: OrderKind,
+    price: Option<Decimal>,
+    amount: Decimal,
+    result: &Result<OrderTokenOpaque, Error>,
+) {
+    if simulated(ex) {
+        return;
+    }
+
+    if let Ok(OrderTokenOpaque::Placed(order_token, _)) = result {
+        Control::current().track_order(ex.0.name(), order_token.clone());
+    }
+    let (order_token, result) = order_result(result);
+    ledger::record(&LedgerEntry::now(
+        ex.0.name(),
+        LedgerEvent::Order {
+            pair,
+            side,
+            kind,
+            price,
+            amount,
+            order_token,
+            result,
+        },
+    ));
+}
+
+/// The order token and result of the ledger entry of an order,
+/// orders rejected before reaching the exchange are recorded with their error.
+fn order_result(result: &Result<OrderTokenOpaque, Error>) -> (Option<OrderToken>, String) {
+    match result {
+        Ok(OrderTokenOpaque::Placed(order_token, _)) => {
+            (Some(order_token.clone()), "placed".to_string())
+        }
+        Ok(OrderTokenOpaque::DryRun(order)) => {
+            let filled = match order.avg_price {
+                Some(avg_price) => format!(
+                    "dry run, filled {} at {}",
+                    order.executed_volume.normalize(),
+                    avg_price.normalize()
+                ),
+                None => "dry run".to_string(),
+            };
+            (None, filled)
+        }
+        Err(e) => (None, e.to_string()),
+    }
+}
+
+/// Records the latest state of a placed order in the ledger.
+fn record_fill(
+    ex: &ExchangeOpaque,
+    order_token: &OrderToken,
+    state: &OrderState,
+    executed_volume: Decimal,
+    avg_price: Option<Decimal>,
+) {
+    if simulated(ex) {
+        return;
+    }
+    if *state == OrderState::Closed {
+        Control::current().forget_order(order_token);
+    }
+    ledger::record_fill(ex.0.name(), order_token, state, executed_volume, avg_price);
+}
+
+/// Cancels a placed order and records the outcome in the ledger.
+async fn cancel_placed(ex: &ExchangeOpaque, order_token: &OrderToken) -> Result<Decimal, Error> {
+    let result = ex.0.cancel_order(order_token).await;
+    if simulated(ex) {
+        return result;
+    }
+
+    if result.is_ok() {
+        Control::current().forget_order(order_token);
+    }
+    ledger::record_cancel(
+        ex.0.name(),
+        order_token,
+        result.as_ref().ok().copied(),
+        match &result {
+            Ok(_) => "cancelled".to_string(),
+            Err(e) => e.to_string(),
+        },
+    );
+    result
+}
+
+/// Cancels an order placed while its script was being stopped,
+/// the console could not see it among the open orders yet.
+async fn cancel_if_stopped(
+    ex: &ExchangeOpaque,
+    result: Result<OrderTokenOpaque, Error>,
+) -> Result<OrderTokenOpaque, Error> {
+    let Ok(OrderTokenOpaque::Placed(order_token, _)) = &result else {
+        return result;
+    };
+    let Err(stopped) = control::check_stopped() else {
+        return result;
+    };
+
+    // Orders the console took are cancelled by the console
+    if simulated(ex) || Control::current().forget_order(order_token) {
+        let _ = cancel_placed(ex, order_token).await;
+    }
+    Err(stopped)
+}
+
+/// Enables or disables dry run for the scripts of the console.
+#[rune::function]
+pub fn set_dry_run(enabled: bool) {
+    Control::current().set_dry_run(enabled);
+}
 
 #[rune::function(instance)]
 pub async fn orderbook(
@@ -159,9 +727,111 @@ pub async fn orderbook(
     pair: (Currency, Currency),
     market: Option<Market>,
 ) -> Result<Orderbook, Error> {
+    control::check_stopped()?;
     ex.0.orderbook(pair, market).await
 }
 
+/// Last traded price of the pair.
+#[rune::function(instance)]
+pub async fn price(
+    ex: Ref<ExchangeOpaque>,
+    pair: (Currency, Currency),
+    market: Option<Market>,
+) -> Result<Decimal, Error> {
+    control::check_stopped()?;
+    ex.0.ticker(pair, market).await
+}
+
+/// Orderbook imbalance over the best `levels` levels, see [`Orderbook::imbalance`].
+#[rune::function(instance)]
+pub async fn imbalance(
+    ex: Ref<ExchangeOpaque>,
+    pair: (Currency, Currency),
+    levels: usize,
+    market: Option<Market>,
+) -> Result<Decimal, Error> {
+    control::check_stopped()?;
+    Ok(ex.0.orderbook(pair, market).await?.imbalance(levels))
+}
+
+/// Bid and ask amounts summed over the levels `from` up to `to`, 0 being the best,
+/// see [`Orderbook::bid_depth`].
+#[rune::function(instance)]
+pub async fn depth(
+    ex: Ref<ExchangeOpaque>,
+    pair: (Currency, Currency),
+    from: usize,
+    to: usize,
+    market: Option<Market>,
+) -> Result<(Decimal, Decimal), Error> {
+    control::check_stopped()?;
+    let orderbook = ex.0.orderbook(pair, market).await?;
+    Ok((orderbook.bid_depth(from..to), orderbook.ask_depth(from..to)))
+}
+
+/// The spread in price steps of the book, None if it is too shallow to tell,
+/// see [`Orderbook::spread_ticks`].
+#[rune::function(instance)]
+pub async fn spread_ticks(
+    ex: Ref<ExchangeOpaque>,
+    pair: (Currency, Currency),
+    market: Option<Market>,
+) -> Result<Option<Decimal>, Error> {
+    control::check_stopped()?;
+    Ok(ex.0.orderbook(pair, market).await?.spread_ticks())
+}
+
+/// Converts `amount` of `from` into `to` at the current orderbook depth.
+#[rune::function(instance)]
+pub async fn convert(
+    ex: Ref<ExchangeOpaque>,
+    from: Currency,
+    to: Currency,
+    amount: Decimal,
+) -> Result<Decimal, Error> {
+    control::check_stopped()?;
+    ex.0.convert(from, to, amount).await
+}
+
+/// Fee deducted from a withdrawal, on the default network if `network` is None.
+#[rune::function(instance)]
+pub async fn withdraw_fee(
+    ex: Ref<ExchangeOpaque>,
+    currency: Currency,
+    network: Option<String>,
+) -> Result<Decimal, Error> {
+    control::check_stopped()?;
+    ex.0.withdraw_fee(currency, network).await
+}
+
+/// Returns every non-zero balance as `(currency, balance)` pairs.
+#[rune::function(instance)]
+pub async fn balances(
+    ex: Ref<ExchangeOpaque>,
+    market: Option<Market>,
+) -> Result<Vec<(Currency, Balance)>, Error> {
+    control::check_stopped()?;
+    ex.0.balances(market).await
+}
+
+/// Smallest order value in the quote currency, None if the exchange does not have one.
+/// Orders below it fail without being sent, so scripts can size them up first.
+#[rune::function(instance)]
+pub fn min_notional(
+    ex: Ref<ExchangeOpaque>,
+    pair: (Currency, Currency),
+    market: Option<Market>,
+) -> Option<Decimal> {
+    ex.0.min_notional(pair, market)
+}
+
+/// Every pair the exchange lists as `(base, quote, market)`, refreshed once a day.
+#[rune::function(instance)]
+pub async fn markets(ex: Ref<ExchangeOpaque>) -> Result<Vec<(Currency, Currency, Market)>, Error> {
+    control::check_stopped()?;
+    ex.0.markets().await
+}
+
 #[rune::function(instance)]
 pub async fn bid_limit(
     ex: Ref<ExchangeOpaque>,
@@ -170,17 +840,73 @@ pub async fn bid_limit(
     amount: Decimal,
     market: Option<Market>,
 ) -> Result<OrderTokenOpaque, Error> {
-    ex.0.bid_limit(pair, price, amount, market).await
+    control::check_stopped()?;
+    let description = format!("bid {} {:?} at {}", amount, pair, price);
+    let dry_run = dry_run_order(
+        &ex,
+        description,
+        pair,
+        Side::Bid,
+        amount,
+        Some(price),
+        market,
+    );
+    let result = match dry_run.await {
+        Some(result) => result,
+        None => {
+            async {
+                let (currency, needed) = limit_requirement(pair, Side::Bid, price, amount);
+                check_balance(&ex, OrderKind::Limit, currency, needed, market).await?;
+                let _permit = order_permit(&ex).await?;
+                ex.0.bid_limit(pair, price, amount, market).await
+            }
+            .await
+        }
+    };
+    record(
+        &ex,
+        pair,
+        Side::Bid,
+        OrderKind::Limit,
+        Some(price),
+        amount,
+        &result,
+    );
+    cancel_if_stopped(&ex, result).await
 }
 
+/// Buys for `quote_qty` of the quote currency at market.
 #[rune::function(instance)]
 pub async fn bid_market(
     ex: Ref<ExchangeOpaque>,
     pair: (Currency, Currency),
-    base_qty: Decimal,
+    quote_qty: Decimal,
     market: Option<Market>,
 ) -> Result<OrderTokenOpaque, Error> {
-    ex.0.bid_market(pair, base_qty, market).await
+    control::check_stopped()?;
+    let description = format!("bid {} {} of {:?} at market", quote_qty, pair.1, pair);
+    let dry_run = dry_run_order(&ex, description, pair, Side::Bid, quote_qty, None, market);
+    let result = match dry_run.await {
+        Some(result) => result,
+        None => {
+            async {
+                check_balance(&ex, OrderKind::Market, pair.1, quote_qty, market).await?;
+                let _permit = order_permit(&ex).await?;
+                ex.0.bid_market(pair, quote_qty, market).await
+            }
+            .await
+        }
+    };
+    record(
+        &ex,
+        pair,
+        Side::Bid,
+        OrderKind::Market,
+        None,
+        quote_qty,
+        &result,
+    );
+    cancel_if_stopped(&ex, result).await
 }
 
 #[rune::function(instance)]
@@ -191,7 +917,39 @@ pub async fn ask_limit(
     amount: Decimal,
     market: Option<Market>,
 ) -> Result<OrderTokenOpaque, Error> {
-    ex.0.ask_limit(pair, price, amount, market).await
+    control::check_stopped()?;
+    let description = format!("ask {} {:?} at {}", amount, pair, price);
+    let dry_run = dry_run_order(
+        &ex,
+        description,
+        pair,
+        Side::Ask,
+        amount,
+        Some(price),
+        market,
+    );
+    let result = match dry_run.await {
+        Some(result) => result,
+        None => {
+            async {
+                let (currency, needed) = limit_requirement(pair, Side::Ask, price, amount);
+                check_balance(&ex, OrderKind::Limit, currency, needed, market).await?;
+                let _permit = order_permit(&ex).await?;
+                ex.0.ask_limit(pair, price, amount, market).await
+            }
+            .await
+        }
+    };
+    record(
+        &ex,
+        pair,
+        Side::Ask,
+        OrderKind::Limit,
+        Some(price),
+        amount,
+        &result,
+    );
+    cancel_if_stopped(&ex, result).await
 }
 
 #[rune::function(instance)]
@@ -201,5 +959,327 @@ pub async fn ask_market(
     base_qty: Decimal,
     market: Option<Market>,
 ) -> Result<OrderTokenOpaque, Error> {
-    ex.0.ask_market(pair, base_qty, market).await
+    control::check_stopped()?;
+    let description = format!("ask {} {:?} at market", base_qty, pair);
+    let dry_run = dry_run_order(&ex, description, pair, Side::Ask, base_qty, None, market);
+    let result = match dry_run.await {
+        Some(result) => result,
+        None => {
+            async {
+                check_balance(&ex, OrderKind::Market, pair.0, base_qty, market).await?;
+                let _permit = order_permit(&ex).await?;
+                ex.0.ask_market(pair, base_qty, market).await
+            }
+            .await
+        }
+    };
+    record(
+        &ex,
+        pair,
+        Side::Ask,
+        OrderKind::Market,
+        None,
+        base_qty,
+        &result,
+    );
+    cancel_if_stopped(&ex, result).await
+}
+
+/// Places a limit order that is submitted once the price reaches `stop_price`,
+/// fails on exchanges without native stop orders.
+#[rune::function(instance)]
+pub async fn stop_limit(
+    ex: Ref<ExchangeOpaque>,
+    pair: (Currency, Currency),
+    stop_price: Decimal,
+    limit_price: Decimal,
+    amount: Decimal,
+    side: Side,
+    market: Option<Market>,
+) -> Result<OrderTokenOpaque, Error> {
+    control::check_stopped()?;
+    let description = format!(
+        "{:?} {} {:?} at {} once the price reaches {}",
+        side, amount, pair, limit_price, stop_price
+    );
+    let dry_run = dry_run_order(
+        &ex,
+        description,
+        pair,
+        side,
+        amount,
+        Some(limit_price),
+        market,
+    );
+    let result = match dry_run.await {
+        Some(result) => result,
+        None => {
+            async {
+                let (currency, needed) = limit_requirement(pair, side, limit_price, amount);
+                check_balance(&ex, OrderKind::StopLimit, currency, needed, market).await?;
+                let _permit = order_permit(&ex).await?;
+                ex.0.stop_limit(pair, stop_price, limit_price, amount, side, market)
+                    .await
+            }
+            .await
+        }
+    };
+    record(
+        &ex,
+        pair,
+        side,
+        OrderKind::StopLimit,
+        Some(limit_price),
+        amount,
+        &result,
+    );
+    cancel_if_stopped(&ex, result).await
+}
+
+/// State of an order, dry run orders are always closed.
+#[rune::function(instance)]
+pub async fn view_order(
+    ex: Ref<ExchangeOpaque>,
+    order_token: Ref<OrderTokenOpaque>,
+) -> Result<Order, Error> {
+    control::check_stopped()?;
+    match &*order_token {
+        OrderTokenOpaque::Placed(order_token, _) => {
+            let order = ex.0.view_order(order_token).await?;
+            record_fill(
+                &ex,
+                order_token,
+                &order.state,
+                order.executed_volume,
+                order.avg_price,
+            );
+            Ok(order)
+        }
+        OrderTokenOpaque::DryRun(order) => Ok(order.clone()),
+    }
+}
+
+/// Waits until the order is closed, returns the executed volume.
+/// Ends with an error when the console cancels running waits.
+#[rune::function(instance)]
+pub async fn wait_order(
+    ex: Ref<ExchangeOpaque>,
+    order_token: Ref<OrderTokenOpaque>,
+) -> Result<Decimal, Error> {
+    control::check_stopped()?;
+    match &*order_token {
+        OrderTokenOpaque::Placed(order_token, _) => {
+            let cancellation = Cancellation::start();
+            let wait = Box::pin(ex.0.wait_order(order_token));
+            let executed_volume =
+                match future::select(wait, Box::pin(cancellation.cancelled())).await {
+                    Either::Left((executed_volume, _)) => executed_volume?,
+                    Either::Right(_) => return Err(Error::from_stderr(Cancelled)),
+                };
+            record_fill(&ex, order_token, &OrderState::Closed, executed_volume, None);
+            Ok(executed_volume)
+        }
+        OrderTokenOpaque::DryRun(order) => Ok(order.executed_volume),
+    }
+}
+
+/// Waits until the order is closed for at most `seconds`, returns the executed volume,
+/// or None if the order is still open so the script can cancel it.
+/// Ends with an error when the console cancels running waits.
+#[rune::function(instance)]
+pub async fn wait_order_timeout(
+    ex: Ref<ExchangeOpaque>,
+    order_token: Ref<OrderTokenOpaque>,
+    seconds: Decimal,
+) -> Result<Option<Decimal>, Error> {
+    control::check_stopped()?;
+    let order_token = match &*order_token {
+        OrderTokenOpaque::Placed(order_token, _) => order_token,
+        OrderTokenOpaque::DryRun(order) => return Ok(Some(order.executed_volume)),
+    };
+
+    // Waited in slices so a cancellation is noticed between them
+    let cancellation = Cancellation::start();
+    let clock = ex.0.clock();
+    let timeout = i64::try_from(utils::seconds(seconds)?.as_millis()).unwrap_or(i64::MAX);
+    let deadline = clock.now_millis().saturating_add(timeout);
+    loop {
+        let remaining = (deadline - clock.now_millis()).max(0) as u64;
+        let slice = WAIT_SLICE.min(Duration::from_millis(remaining));
+        if let Some(executed_volume) = ex.0.wait_order_timeout(order_token, slice).await? {
+            record_fill(&ex, order_token, &OrderState::Closed, executed_volume, None);
+            return Ok(Some(executed_volume));
+        }
+
+        if clock.now_millis() >= deadline {
+            return Ok(None);
+        }
+        cancellation.check()?;
+    }
+}
+
+/// Cancels the order, returns the volume executed before the cancellation.
+#[rune::function(instance)]
+pub async fn cancel_order(
+    ex: Ref<ExchangeOpaque>,
+    order_token: Ref<OrderTokenOpaque>,
+) -> Result<Decimal, Error> {
+    // Stopped scripts can still cancel, it only takes risk off
+    match &*order_token {
+        OrderTokenOpaque::Placed(order_token, _) => cancel_placed(&ex, order_token).await,
+        OrderTokenOpaque::DryRun(order) => Ok(order.executed_volume),
+    }
+}
+
+/// Reports the order to the console and the ui once it fills or is cancelled, without waiting for it.
+/// Returns false if the order is already watched, dry run and backtest orders are never watched.
+#[rune::function(instance)]
+pub fn watch_order(ex: Ref<ExchangeOpaque>, order_token: Ref<OrderTokenOpaque>) -> bool {
+    if simulated(&ex) {
+        return false;
+    }
+
+    match &*order_token {
+        OrderTokenOpaque::Placed(order_token, placed) => {
+            ex.0.clone()
+                .watch_order(order_token.clone(), placed.clone(), Control::current())
+        }
+        OrderTokenOpaque::DryRun(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rune::{Context, Vm};
+
+    use super::{
+        install_module_exchange, limit_requirement, order_result, InsufficientBalance,
+        OrderTokenOpaque,
+    };
+    use crate::currency::Currency;
+    use crate::exchange::{Balance, Order, OrderState, Orderbook, Side, Unit};
+    use crate::utils::Decimal;
+    use crate::vm::error::Error;
+    use crate::vm::test_vm;
+    use crate::vm::utils::install_module_utils;
+
+    fn vm(script: &str) -> Vm {
+        let mut context = Context::with_default_modules().unwrap();
+        install_module_utils(&mut context);
+        install_module_exchange(&mut context);
+        test_vm(&context, script)
+    }
+
+    fn orderbook() -> Orderbook {
+        let unit = |price: i64, amount: i64| Unit {
+            price: Decimal(price.into()),
+            amount: Decimal(amount.into()),
+        };
+        Orderbook {
+            pair: (Currency::BTC, Currency::KRW),
+            bids: vec![unit(99, 1), unit(98, 2)],
+            asks: vec![unit(101, 3)],
+        }
+    }
+
+    #[test]
+    fn orderbook_levels() {
+        let mut vm =
+            vm("pub fn main(book) { (book.bids.len(), book.bids[1].price, book.asks[0].amount) }");
+        let output = vm.call(["main"], (orderbook(),)).unwrap();
+        let (levels, price, amount): (usize, Decimal, Decimal) = rune::from_value(output).unwrap();
+
+        assert_eq!(levels, 2);
+        assert_eq!(price, Decimal(98.into()));
+        assert_eq!(amount, Decimal(3.into()));
+    }
+
+    #[test]
+    fn out_of_bounds_level_is_an_error() {
+        let mut vm = vm("pub fn main(book) { book.asks[5] }");
+        assert!(vm.call(["main"], (orderbook(),)).is_err());
+    }
+
+    #[test]
+    fn balance_fields() {
+        let balance = Balance {
+            available: Decimal(3.into()),
+            locked: Decimal(1.into()),
+        };
+
+        let mut vm = vm("pub fn main(balance) { (balance.available, balance.locked) }");
+        let output = vm.call(["main"], (balance.clone(),)).unwrap();
+        let (available, locked): (Decimal, Decimal) = rune::from_value(output).unwrap();
+        assert_eq!((available, locked), (balance.available, balance.locked));
+
+        // Unknown fields are a runtime error, not a silent unit
+        let mut missing = vm("pub fn main(balance) { balance.total }");
+        assert!(missing.call(["main"], (balance,)).is_err());
+    }
+
+    #[test]
+    fn match_order_state() {
+        let order = |state: OrderState, avg_price: Option<i64>| Order {
+            state,
+            executed_volume: Decimal(1.into()),
+            remaining: Decimal(0.into()),
+            avg_price: avg_price.map(|price| Decimal(price.into())),
+        };
+        let script = r#"
+            pub fn main(order) {
+                match order.state {
+                    OrderState::Closed => match order.avg_price {
+                        Some(price) => format!("filled at {}", price),
+                        None => "filled",
+                    },
+                    OrderState::Wait => "waiting",
+                }
+            }
+        "#;
+
+        let mut vm = vm(script);
+        let mut run = |order: Order| -> String {
+            rune::from_value(vm.call(["main"], (order,)).unwrap()).unwrap()
+        };
+        assert_eq!(run(order(OrderState::Closed, Some(100))), "filled at 100");
+        assert_eq!(run(order(OrderState::Closed, None)), "filled");
+        assert_eq!(run(order(OrderState::Wait, None)), "waiting");
+    }
+
+    #[test]
+    fn rejected_orders_are_recorded_with_their_error() {
+        let rejected = Err(Error::from_stderr(InsufficientBalance {
+            currency: Currency::KRW,
+            needed: Decimal(200.into()),
+            available: Decimal(100.into()),
+        }));
+        let (order_token, result) = order_result(&rejected);
+        assert_eq!(order_token, None);
+        assert!(result.contains("KRW"), "{}", result);
+
+        let dry_run = Ok(OrderTokenOpaque::DryRun(Order {
+            state: OrderState::Closed,
+            executed_volume: Decimal(2.into()),
+            remaining: Decimal(0.into()),
+            avg_price: Some(Decimal(100.into())),
+        }));
+        assert_eq!(
+            order_result(&dry_run),
+            (None, "dry run, filled 2 at 100".to_string())
+        );
+    }
+
+    #[test]
+    fn stop_limits_need_the_balance_of_their_side() {
+        let pair = (Currency::BTC, Currency::KRW);
+        let (price, amount) = (Decimal(100.into()), Decimal(2.into()));
+        assert_eq!(
+            limit_requirement(pair, Side::Bid, price, amount),
+            (Currency::KRW, Decimal(200.into()))
+        );
+        assert_eq!(
+            limit_requirement(pair, Side::Ask, price, amount),
+            (Currency::BTC, amount)
+        );
+    }
 }
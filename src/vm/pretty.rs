@@ -0,0 +1,266 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Values up to this many chars stay on one line.
+const WIDTH: usize = 80;
+const INDENT: &str = "    ";
+
+/// How much of a value is shown, the rest is summarised.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Nesting shown, deeper lists are shown as `[…]`.
+    pub depth: usize,
+    /// Items shown of each list, the rest as `… N more`.
+    pub length: usize,
+}
+
+impl Limits {
+    pub const UNLIMITED: Limits = Limits {
+        depth: usize::MAX,
+        length: usize::MAX,
+    };
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            depth: 4,
+            length: 10,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Node {
+    /// Anything but a list, e.g. a number, a string or the `key: ` of a field.
+    Text(String),
+    /// Comma separated items between brackets.
+    List {
+        open: char,
+        close: char,
+        items: Vec<Vec<Node>>,
+    },
+}
+
+fn closing(open: char) -> Option<char> {
+    match open {
+        '[' => Some(']'),
+        '(' => Some(')'),
+        '{' => Some('}'),
+        _ => None,
+    }
+}
+
+/// Reads the string or char literal opened by `quote`, escapes included.
+fn read_quoted(chars: &mut Peekable<Chars>, quote: char, text: &mut String) {
+    text.push(quote);
+    while let Some(c) = chars.next() {
+        text.push(c);
+        if c == '\\' {
+            text.extend(chars.next());
+        } else if c == quote {
+            return;
+        }
+    }
+}
+
+/// Ends the text of the item read so far.
+fn flush(text: &mut String, item: &mut Vec<Node>) {
+    if !text.trim().is_empty() {
+        item.push(Node::Text(std::mem::take(text)));
+    }
+    text.clear();
+}
+
+/// Items until `close`, or until the end of the text at the top.
+fn parse_items(chars: &mut Peekable<Chars>, close: Option<char>) -> Vec<Vec<Node>> {
+    let mut items = Vec::new();
+    let mut item = Vec::new();
+    let mut text = String::new();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' | '\'' => read_quoted(chars, c, &mut text),
+            ',' if close.is_some() => {
+                flush(&mut text, &mut item);
+                items.push(std::mem::take(&mut item));
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            }
+            c if Some(c) == close => break,
+            c => match closing(c) {
+                Some(inner) => {
+                    flush(&mut text, &mut item);
+                    let list = parse_items(chars, Some(inner));
+                    item.push(Node::List {
+                        open: c,
+                        close: inner,
+                        items: list,
+                    });
+                }
+                None => text.push(c),
+            },
+        }
+    }
+
+    flush(&mut text, &mut item);
+    // A trailing comma leaves no item behind it
+    if !item.is_empty() {
+        items.push(item);
+    }
+    items
+}
+
+struct Printer {
+    limits: Limits,
+    truncated: bool,
+}
+
+impl Printer {
+    fn item(&mut self, item: &[Node], depth: usize, indent: usize) -> String {
+        let mut line = String::new();
+        for node in item {
+            match node {
+                Node::Text(text) if line.is_empty() => line.push_str(text.trim_start()),
+                Node::Text(text) => line.push_str(text),
+                Node::List { open, close, items } => {
+                    let list = self.list(*open, *close, items, depth, indent);
+                    line.push_str(&list);
+                }
+            }
+        }
+        line.trim_end().to_string()
+    }
+
+    fn list(
+        &mut self,
+        open: char,
+        close: char,
+        items: &[Vec<Node>],
+        depth: usize,
+        indent: usize,
+    ) -> String {
+        if items.is_empty() {
+            return format!("{}{}", open, close);
+        }
+        if depth >= self.limits.depth {
+            self.truncated = true;
+            return format!("{}…{}", open, close);
+        }
+
+        let shown = items.len().min(self.limits.length);
+        let more = (items.len() > shown).then(|| format!("… {} more", items.len() - shown));
+        self.truncated |= more.is_some();
+
+        let lines = items[..shown]
+            .iter()
+            .map(|item| self.item(item, depth + 1, indent + 1))
+            .chain(more)
+            .collect::<Vec<_>>();
+
+        // Braces of structs are spaced like `Order { side: Bid }`
+        let padding = if open == '{' { " " } else { "" };
+        let inline = format!(
+            "{}{}{}{}{}",
+            open,
+            padding,
+            lines.join(", "),
+            padding,
+            close
+        );
+        let fits = indent * INDENT.len() + inline.chars().count() <= WIDTH;
+        if fits && !inline.contains('\n') {
+            return inline;
+        }
+
+        let mut text = open.to_string();
+        for line in lines {
+            text.push('\n');
+            text.push_str(&INDENT.repeat(indent + 1));
+            text.push_str(&line);
+            text.push(',');
+        }
+        text.push('\n');
+        text.push_str(&INDENT.repeat(indent));
+        text.push(close);
+        text
+    }
+}
+
+/// A value printed with indentation, `truncated` if the limits left some of it out.
+#[derive(Debug, PartialEq)]
+pub struct Pretty {
+    pub text: String,
+    pub truncated: bool,
+}
+
+/// Indents the debug representation of a value, each list on its own lines unless it is short.
+/// Lists nested deeper than the limits, or their items past the limits, are left out.
+pub fn pretty(debug: &str, limits: Limits) -> Pretty {
+    let items = parse_items(&mut debug.chars().peekable(), None);
+    let mut printer = Printer {
+        limits,
+        truncated: false,
+    };
+    let text = items
+        .iter()
+        .map(|item| printer.item(item, 0, 0))
+        .collect::<Vec<_>>()
+        .join(", ");
+    Pretty {
+        text,
+        truncated: printer.truncated,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{pretty, Limits};
+
+    fn show(debug: &str, limits: Limits) -> String {
+        pretty(debug, limits).text
+    }
+
+    #[test]
+    fn short_values_stay_on_one_line() {
+        let value = pretty("[1, 2, (3, \"a, b]\")]", Limits::default());
+        assert_eq!(value.text, "[1, 2, (3, \"a, b]\")]");
+        assert!(!value.truncated);
+        assert_eq!(show("Some(1)", Limits::default()), "Some(1)");
+        assert_eq!(show("[]", Limits::default()), "[]");
+    }
+
+    #[test]
+    fn long_lists_are_cut() {
+        let debug = format!("{:?}", (0..50).collect::<Vec<_>>());
+        let value = pretty(&debug, Limits::default());
+        assert_eq!(value.text, "[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, … 40 more]");
+        assert!(value.truncated);
+
+        // Too wide for one line in full, one item per line
+        let full = pretty(&debug, Limits::UNLIMITED);
+        assert!(!full.truncated);
+        assert_eq!(full.text.lines().count(), 52);
+        assert_eq!(full.text.lines().nth(50), Some("    49,"));
+    }
+
+    #[test]
+    fn wide_values_are_indented() {
+        let debug = r#"[Order { price: 100000000, amount: 0.5, side: Bid, state: Wait }, Order { price: 100000001, amount: 1.5, side: Ask, state: Closed }]"#;
+        let expected = r#"[
+    Order { price: 100000000, amount: 0.5, side: Bid, state: Wait },
+    Order { price: 100000001, amount: 1.5, side: Ask, state: Closed },
+]"#;
+        assert_eq!(show(debug, Limits::default()), expected);
+    }
+
+    #[test]
+    fn deep_values_are_cut() {
+        let limits = Limits {
+            depth: 2,
+            length: 10,
+        };
+        let value = pretty("[[[1]], [2]]", limits);
+        assert_eq!(value.text, "[[[…]], [2]]");
+        assert!(value.truncated);
+    }
+}
@@ -1,13 +1,23 @@
 use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Sub, SubAssign};
+use std::time::Duration;
 
+use num_traits::ToPrimitive;
+
+use crate::utils::clock::{self, Clock};
 use crate::utils::Decimal;
 
 use rune::alloc::fmt::TryWrite;
 use rune::runtime::{Formatter, Protocol, VmResult};
 
+use super::control::Cancellation;
 use super::error;
 
 pub fn install_module_utils(context: &mut rune::Context) {
+    install_module_utils_with_clock(context, Clock::Real);
+}
+
+/// Installs the utils with `sleep` passing on `clock`, virtual for backtests.
+pub fn install_module_utils_with_clock(context: &mut rune::Context, clock: Clock) {
     let mut module = rune::Module::new();
 
     module.ty::<Decimal>().unwrap();
@@ -16,6 +26,13 @@ pub fn install_module_utils(context: &mut rune::Context) {
     module.function_meta(Decimal::round_dp__meta).unwrap();
     module.function_meta(Decimal::decimal_from_str).unwrap();
     module.function_meta(Decimal::string_display).unwrap();
+    module
+        .function("sleep", move |seconds: Decimal| {
+            let clock = clock.clone();
+            async move { sleep(&clock, seconds).await }
+        })
+        .build()
+        .unwrap();
 
     module
         .associated_function(Protocol::ADD, Decimal::add)
@@ -57,3 +74,90 @@ impl Decimal {
         VmResult::Ok(())
     }
 }
+
+/// Sleeps in slices of this, so a cancellation ends them quickly.
+const SLEEP_SLICE: Duration = Duration::from_millis(100);
+
+#[derive(thiserror::Error, Debug)]
+#[error("{0} seconds is too long to wait")]
+pub struct TooLong(Decimal);
+
+/// Converts seconds given by a script, negative values are treated as zero.
+pub fn seconds(seconds: Decimal) -> error::Result<Duration> {
+    Duration::try_from_secs_f64(seconds.0.to_f64().unwrap_or_default().max(0.0))
+        .map_err(|_| error::Error::from_stderr(TooLong(seconds)))
+}
+
+/// Pauses the script for `seconds`, fails if cancelled before it ends.
+/// A virtual clock passes the whole sleep at once, and fails once its backtest has ended
+/// so a strategy looping forever still finishes.
+async fn sleep(clock: &Clock, seconds: Decimal) -> error::Result<()> {
+    let cancellation = Cancellation::start();
+    let mut remaining = self::seconds(seconds)?;
+
+    while !remaining.is_zero() {
+        cancellation.check()?;
+
+        let slice = if clock.is_virtual() {
+            remaining
+        } else {
+            remaining.min(SLEEP_SLICE)
+        };
+        clock.sleep(slice).await;
+        remaining -= slice;
+    }
+
+    if clock.ended() {
+        return Err(error::Error::from_stderr(clock::Ended));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use rune::Context;
+
+    use super::{install_module_utils, seconds};
+    use crate::utils::Decimal;
+    use crate::vm::control::Control;
+    use crate::vm::error::install_module_error;
+    use crate::vm::test_vm;
+
+    #[test]
+    fn script_seconds() {
+        let half = Decimal::from_str("0.5").unwrap();
+        assert_eq!(seconds(half).unwrap(), Duration::from_millis(500));
+        assert_eq!(
+            seconds(Decimal::from_str("-1").unwrap()).unwrap(),
+            Duration::ZERO
+        );
+        assert!(seconds(Decimal(u64::MAX.into()) * Decimal(10.into())).is_err());
+    }
+
+    #[tokio::test]
+    async fn cancelled_sleep_ends_early() {
+        let mut context = Context::with_default_modules().unwrap();
+        install_module_utils(&mut context);
+        install_module_error(&mut context);
+
+        let mut vm = test_vm(
+            &context,
+            "pub async fn main() { sleep(Decimal::from_str(\"60\")?).await }",
+        );
+
+        let control = Control::new();
+        let cancel = control.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            cancel.cancel();
+        });
+
+        let started = Instant::now();
+        let output = control.run(vm.async_call(["main"], ())).await.unwrap();
+        let result: Result<(), crate::vm::error::Error> = rune::from_value(output).unwrap();
+        assert!(result.is_err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
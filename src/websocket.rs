@@ -1,59 +1,528 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
 use async_channel::{Receiver as AsyncRx, Sender as AsyncTx};
+use futures::future::{self, Either};
+use futures::{Future, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
 
+use crate::config::Config;
 use crate::utils::async_helpers;
+use crate::utils::broadcaster::{Broadcaster, Subscription};
+use crate::utils::compression::Codec;
+use crate::utils::maybe_trait::MaybeSend;
+
+/// Keepalive of the websocket connections.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct KeepaliveConfig {
+    /// How often a ping is sent, desktop only as browsers can not send pings.
+    pub ping_interval_secs: u64,
+    /// A connection without any inbound message for this long is considered dead and reconnected.
+    pub timeout_secs: u64,
+    /// A connection still answering pings but without any data for this long is reconnected too.
+    /// Off by default, as quiet markets can go without data for a long time.
+    pub data_timeout_secs: Option<u64>,
+}
+
+impl Default for KeepaliveConfig {
+    fn default() -> Self {
+        Self {
+            ping_interval_secs: 15,
+            timeout_secs: 45,
+            data_timeout_secs: None,
+        }
+    }
+}
+
+impl KeepaliveConfig {
+    fn ping_interval(&self) -> Duration {
+        Duration::from_secs(self.ping_interval_secs.max(1))
+    }
+
+    /// At least a second, a zero timeout would reconnect in a loop.
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs.max(1))
+    }
+
+    /// None for quiet websockets, which are only reconnected on silence.
+    fn data_timeout(&self, options: &WebsocketOptions) -> Option<Duration> {
+        if options.quiet {
+            return None;
+        }
+        self.data_timeout_secs
+            .map(|secs| Duration::from_secs(secs.max(1)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    /// Connecting for the first time, or again after the connection was lost.
+    Reconnecting,
+    /// Receiving messages, `last_message` is the time of the latest one in milliseconds.
+    Connected { last_message: i64 },
+    /// Nothing was received within the timeout, the connection is about to be reconnected.
+    Stale { last_message: i64 },
+}
+
+/// Transition of a websocket connection, see [`Websocket::state_rx`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnState {
+    Connecting,
+    /// Connected, subscriptions have to be sent again after a reconnect.
+    Connected,
+    /// The connection failed or was dropped, with the reason. A reconnect follows.
+    Disconnected(String),
+}
+
+/// Counters of the data received by a websocket, kept across reconnects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MessageMetrics {
+    /// Text messages received, control frames are not counted.
+    pub messages: u64,
+    /// Time of the latest text message in milliseconds, None before the first one.
+    pub last_recv_at: Option<i64>,
+    /// Connections dropped by the watchdog because nothing or no data arrived in time.
+    pub stale_reconnects: u64,
+}
+
+/// Observable status of a websocket connection, shared by all clones of the `Websocket`.
+#[derive(Clone)]
+pub struct StatusHandle {
+    shared: Arc<Mutex<(ConnectionStatus, MessageMetrics)>>,
+    events: Broadcaster<ConnState>,
+}
+
+impl StatusHandle {
+    fn new() -> Self {
+        Self {
+            shared: Arc::new(Mutex::new((
+                ConnectionStatus::Reconnecting,
+                MessageMetrics::default(),
+            ))),
+            events: Broadcaster::new(),
+        }
+    }
+
+    pub fn get(&self) -> ConnectionStatus {
+        self.shared.lock().unwrap().0
+    }
+
+    pub fn metrics(&self) -> MessageMetrics {
+        self.shared.lock().unwrap().1
+    }
+
+    fn set(&self, status: ConnectionStatus) {
+        self.shared.lock().unwrap().0 = status;
+    }
+
+    /// Transitions of the connection from now on.
+    pub fn events(&self) -> Subscription<ConnState> {
+        self.events.subscribe()
+    }
+
+    fn connecting(&self) {
+        self.set(ConnectionStatus::Reconnecting);
+        self.events.broadcast(ConnState::Connecting);
+    }
+
+    fn connected(&self) {
+        self.received();
+        self.events.broadcast(ConnState::Connected);
+    }
+
+    fn disconnected(&self, reason: String) {
+        self.events.broadcast(ConnState::Disconnected(reason));
+    }
+
+    fn received(&self) {
+        self.set(ConnectionStatus::Connected {
+            last_message: chrono::Utc::now().timestamp_millis(),
+        });
+    }
+
+    fn received_data(&self) {
+        let now = chrono::Utc::now().timestamp_millis();
+        let mut shared = self.shared.lock().unwrap();
+        shared.0 = ConnectionStatus::Connected { last_message: now };
+        shared.1.messages += 1;
+        shared.1.last_recv_at = Some(now);
+    }
+
+    fn stale(&self) {
+        let mut shared = self.shared.lock().unwrap();
+        if let ConnectionStatus::Connected { last_message } = shared.0 {
+            shared.0 = ConnectionStatus::Stale { last_message };
+            shared.1.stale_reconnects += 1;
+        }
+    }
+}
+
+/// Payload of the inbound messages of a [`Websocket`].
+pub trait Payload: Send + 'static {
+    fn from_text(text: String) -> Self;
+    fn from_binary(binary: Vec<u8>) -> Self;
+}
+
+/// Binary frames are converted lossily.
+impl Payload for String {
+    fn from_text(text: String) -> Self {
+        text
+    }
+
+    fn from_binary(binary: Vec<u8>) -> Self {
+        String::from_utf8_lossy(&binary).into_owned()
+    }
+}
+
+/// Frames are kept as sent, text frames as their utf-8 bytes.
+impl Payload for Vec<u8> {
+    fn from_text(text: String) -> Self {
+        text.into_bytes()
+    }
+
+    fn from_binary(binary: Vec<u8>) -> Self {
+        binary
+    }
+}
+
+/// First delay before retrying a failed connect, doubled on every failure.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Exponential backoff between connect attempts.
+struct Backoff(Duration);
+
+impl Backoff {
+    fn new() -> Self {
+        Self(INITIAL_BACKOFF)
+    }
+
+    fn reset(&mut self) {
+        self.0 = INITIAL_BACKOFF;
+    }
+
+    async fn wait(&mut self) {
+        async_helpers::sleep(self.0).await;
+        self.0 = (self.0 * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// Runs `task` until every sender of `stop` is dropped.
+async fn until_stopped<F>(stop: AsyncRx<()>, task: F)
+where
+    F: Future<Output = ()>,
+{
+    let stopped = Box::pin(async move {
+        let _ = stop.recv().await;
+    });
+    future::select(stopped, Box::pin(task)).await;
+}
+
+/// Forwards inbound messages to `rx_sender` until the stream ends,
+/// or until nothing arrives within `timeout` which marks the connection stale.
+/// `None` items are control frames like pongs, they only count as activity,
+/// so with a `data_timeout` the connection also goes stale without data for that long.
+/// Returns why forwarding stopped.
+async fn forward_inbound<S, T>(
+    mut inbound: S,
+    rx_sender: &AsyncTx<T>,
+    status: &StatusHandle,
+    timeout: Duration,
+    data_timeout: Option<Duration>,
+) -> String
+where
+    S: Stream<Item = Option<T>> + Unpin,
+{
+    let now = || chrono::Utc::now().timestamp_millis();
+    let data_deadline = |from: i64| data_timeout.map(|t| from + t.as_millis() as i64);
+
+    let mut deadline = data_deadline(now());
+    loop {
+        let data_remaining = deadline.map(|deadline| deadline - now());
+        if data_remaining.is_some_and(|remaining| remaining <= 0) {
+            let data_timeout = data_timeout.unwrap_or_default();
+            tracing::warn!("No websocket data for {:?}, reconnecting", data_timeout);
+            status.stale();
+            return format!("no data for {:?}", data_timeout);
+        }
+
+        let wait = data_remaining.map_or(timeout, |remaining| {
+            timeout.min(Duration::from_millis(remaining as u64))
+        });
+        let timer = Box::pin(async_helpers::sleep(wait));
+        match future::select(inbound.next(), timer).await {
+            Either::Left((Some(Some(message)), _)) => {
+                status.received_data();
+                deadline = data_deadline(now());
+                if rx_sender.send(message).await.is_err() {
+                    return "no longer received".to_string();
+                }
+            }
+            Either::Left((Some(None), _)) => status.received(),
+            Either::Left((None, _)) => return "closed".to_string(),
+            // The data deadline is checked at the top of the loop
+            Either::Right(_) if wait < timeout => {}
+            Either::Right(_) => {
+                tracing::warn!("No websocket message for {:?}, reconnecting", timeout);
+                status.stale();
+                return format!("no message for {:?}", timeout);
+            }
+        }
+    }
+}
+
+/// How a [`Websocket`] connects and treats the frames it receives.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WebsocketOptions {
+    /// Binary frames are decompressed with this codec before delivery,
+    /// frames that are not compressed are delivered as they are.
+    pub codec: Codec,
+    /// Builds the `Authorization` header of the handshake, called again on every reconnect
+    /// so signed tokens are always fresh. Browsers can not set it, it is ignored on wasm.
+    pub authorization: Option<fn() -> Option<String>>,
+    /// The websocket can go without data for long, like private user streams,
+    /// so `data_timeout_secs` does not apply to it.
+    pub quiet: bool,
+}
 
 /// Clonable websocket client implementation with auto-reconnect feature.
 ///
-/// Note that the `Websocket` client only will recieve text messages.
-/// If the binary messages are received, they will be converted to text messages.
+/// A `Websocket` created by `new` receives text messages,
+/// binary messages are converted to text lossily.
+/// One created by `new_with_options` inflates compressed binary messages first,
+/// and one created by `new_binary` receives every message as raw bytes.
+#[derive(Clone)]
+pub struct Websocket<T = String> {
+    sender: AsyncTx<String>,
+    recver: AsyncRx<T>,
+    status: StatusHandle,
+    /// Never sent to, the connection task stops once the last clone drops it.
+    _stop: AsyncTx<()>,
+    /// Closed along with `_stop`, ends the tasks spawned for the socket.
+    stopped: AsyncRx<()>,
+}
+
+/// Sends to a [`Websocket`] without keeping it open, for its callbacks and the tasks
+/// of its owner. Messages sent after the socket was dropped are discarded.
 #[derive(Clone)]
-pub struct Websocket {
+pub struct WebsocketSender {
     sender: AsyncTx<String>,
-    recver: AsyncRx<String>,
+}
+
+impl WebsocketSender {
+    /// Queues a message, never blocks as the outbound channel is unbounded.
+    pub fn send(&self, msg: &str) {
+        let _ = self.sender.try_send(msg.to_string());
+    }
+
+    /// Same as `send`, for callers that already await.
+    pub async fn send_async(&self, msg: &str) {
+        let _ = self.sender.send(msg.to_string()).await;
+    }
+}
+
+/// Receives from a [`Websocket`] without keeping it open, for the tasks of its owner.
+#[derive(Clone)]
+pub struct WebsocketReceiver<T = String> {
+    recver: AsyncRx<T>,
+}
+
+impl<T> WebsocketReceiver<T> {
+    /// None once the socket was dropped and its connection stopped.
+    pub async fn recv(&self) -> Option<T> {
+        self.recver.recv().await.ok()
+    }
 }
 
 impl Websocket {
     pub fn new(url: &str) -> Self {
+        Self::connect(url, WebsocketOptions::default())
+    }
+
+    /// A websocket receiving text, binary messages are decompressed as `options` say.
+    pub fn new_with_options(url: &str, options: WebsocketOptions) -> Self {
+        Self::connect(url, options)
+    }
+}
+
+impl Websocket<Vec<u8>> {
+    /// A websocket receiving the raw bytes of each message, see [`crate::utils::compression`].
+    pub fn new_binary(url: &str) -> Self {
+        Self::connect(url, WebsocketOptions::default())
+    }
+}
+
+impl<T: Payload> Websocket<T> {
+    fn connect(url: &str, options: WebsocketOptions) -> Self {
+        let keepalive = Config::get().websocket;
+        let status = StatusHandle::new();
+        let (stop, stopped) = async_channel::bounded(1);
+
         #[cfg(not(target_arch = "wasm32"))]
-        let (sender, recver) = websocket_tokio::spawn_and_handle(url);
+        let (sender, recver) = websocket_tokio::spawn_and_handle(
+            url,
+            keepalive,
+            options,
+            status.clone(),
+            stopped.clone(),
+        );
         #[cfg(target_arch = "wasm32")]
-        let (sender, recver) = websocket_wasm::spawn_and_handle(url);
+        let (sender, recver) = websocket_wasm::spawn_and_handle(
+            url,
+            keepalive,
+            options,
+            status.clone(),
+            stopped.clone(),
+        );
 
-        Self { sender, recver }
+        Self {
+            sender,
+            recver,
+            status,
+            _stop: stop,
+            stopped,
+        }
     }
 
-    pub async fn recv(&self) -> Option<String> {
+    pub async fn recv(&self) -> Option<T> {
         self.recver.recv().await.ok()
     }
 
+    /// Queues a message, never blocks as the outbound channel is unbounded.
+    /// Safe to call from async code, e.g. the subscribe of a broadcaster called by the ui.
     pub fn send(&self, msg: &str) {
-        async_helpers::block_on(self.sender.send(msg.to_string())).unwrap()
+        // The connection task lives as long as `self`, so the channel is open
+        let _ = self.sender.try_send(msg.to_string());
+    }
+
+    /// Same as `send`, for callers that already await.
+    pub async fn send_async(&self, msg: &str) {
+        // The connection task lives as long as `self`, so the channel is open
+        let _ = self.sender.send(msg.to_string()).await;
+    }
+
+    pub fn status(&self) -> StatusHandle {
+        self.status.clone()
+    }
+
+    /// Connection transitions from now on, for connection indicators and re-subscribing.
+    /// Receiving them is optional, unreceived events are dropped once the buffer is full.
+    pub fn state_rx(&self) -> Subscription<ConnState> {
+        self.status.events()
+    }
+
+    /// A handle sending to this socket, which does not keep it open.
+    pub fn sender(&self) -> WebsocketSender {
+        WebsocketSender {
+            sender: self.sender.clone(),
+        }
+    }
+
+    /// A handle receiving from this socket, which does not keep it open.
+    pub fn receiver(&self) -> WebsocketReceiver<T> {
+        WebsocketReceiver {
+            recver: self.recver.clone(),
+        }
+    }
+
+    /// Calls `subscribe` on every connect, reconnects by the watchdog included,
+    /// as the subscriptions of a connection are lost with it.
+    /// `subscribe` sends through the given handle, a `Websocket` it holds would never be dropped.
+    pub fn on_connected<F>(&self, subscribe: F)
+    where
+        F: Fn(&WebsocketSender) + MaybeSend + 'static,
+    {
+        // Subscribed before the task is spawned, so the first connect is not missed
+        let states = self.state_rx();
+        let sender = self.sender();
+        async_helpers::spawn(until_stopped(self.stopped.clone(), async move {
+            while let Some(state) = states.recv_or_closed().await {
+                if state == ConnState::Connected {
+                    subscribe(&sender);
+                }
+            }
+        }));
+    }
+
+    /// Time of the latest data message in milliseconds, None if nothing was received yet.
+    pub fn last_recv_at(&self) -> Option<i64> {
+        self.status.metrics().last_recv_at
     }
 }
 
 #[cfg(any(target_arch = "wasm32"))]
 mod websocket_wasm {
+    use std::time::Duration;
+
     use async_channel::{Receiver as AsyncRx, Sender as AsyncTx};
-    use futures::{SinkExt, StreamExt};
+    use futures::future;
     use wasm_sockets::EventClient as WasmWebSocket;
 
+    use super::{
+        forward_inbound, until_stopped, Backoff, KeepaliveConfig, Payload, StatusHandle,
+        WebsocketOptions,
+    };
     use crate::utils::async_helpers;
+    use crate::utils::compression::decompress_frame;
 
-    pub(super) fn spawn_and_handle(url: &str) -> (AsyncTx<String>, AsyncRx<String>) {
+    pub(super) fn spawn_and_handle<T: Payload>(
+        url: &str,
+        keepalive: KeepaliveConfig,
+        options: WebsocketOptions,
+        status: StatusHandle,
+        stop: AsyncRx<()>,
+    ) -> (AsyncTx<String>, AsyncRx<T>) {
         let (tx_sender, tx_recver) = async_channel::unbounded();
         let (rx_sender, rx_recver) = async_channel::unbounded();
 
-        // TODO: Abort the future when the websocket is dropped.
-        async_helpers::spawn(handler(url.to_string(), tx_recver, rx_sender));
+        async_helpers::spawn(until_stopped(
+            stop,
+            handler(
+                url.to_string(),
+                tx_recver,
+                rx_sender,
+                keepalive,
+                options,
+                status,
+            ),
+        ));
         (tx_sender, rx_recver)
     }
 
-    async fn handler(url: String, tx_recver: AsyncRx<String>, rx_sender: AsyncTx<String>) {
+    async fn handler<T: Payload>(
+        url: String,
+        tx_recver: AsyncRx<String>,
+        rx_sender: AsyncTx<T>,
+        keepalive: KeepaliveConfig,
+        options: WebsocketOptions,
+        status: StatusHandle,
+    ) {
+        if options.authorization.is_some() {
+            tracing::warn!(
+                "Connecting to {} without authorization, not supported on wasm",
+                url
+            );
+        }
+
+        let timeout = keepalive.timeout();
+        let data_timeout = keepalive.data_timeout(&options);
+        let mut backoff = Backoff::new();
         let mut last_message: Option<String> = None;
         loop {
-            let mut ws = WasmWebSocket::new(&url).unwrap();
+            status.connecting();
+
+            let mut ws = match WasmWebSocket::new(&url) {
+                Ok(ws) => ws,
+                Err(e) => {
+                    tracing::warn!("Failed to connect to {}, retrying: {:?}", url, e);
+                    status.disconnected(format!("{:?}", e));
+                    backoff.wait().await;
+                    continue;
+                }
+            };
             let tx_recver = tx_recver.clone();
-            let rx_sender = rx_sender.clone();
+            let (inbound_sender, inbound_recver) = async_channel::unbounded();
 
             let (connected_tx, connected_rx) = async_channel::bounded(1);
             ws.set_on_error(None);
@@ -62,86 +531,375 @@ mod websocket_wasm {
             })));
             ws.set_on_message(Some(Box::new(
                 move |client: &wasm_sockets::EventClient, message: wasm_sockets::Message| {
-                    match message {
-                        wasm_sockets::Message::Text(text) => {
-                            let _ = rx_sender.try_send(text);
-                        }
+                    let message = match message {
+                        wasm_sockets::Message::Text(text) => T::from_text(text),
                         wasm_sockets::Message::Binary(binary) => {
-                            let _ =
-                                rx_sender.try_send(String::from_utf8_lossy(&binary).to_string());
+                            T::from_binary(decompress_frame(options.codec, binary))
                         }
-                    }
+                    };
+                    let _ = inbound_sender.try_send(Some(message));
                 },
             )));
 
-            connected_rx.recv().await.unwrap();
+            let connected = Box::pin(connected_rx.recv());
+            let timer = Box::pin(async_helpers::sleep(timeout));
+            if !matches!(
+                future::select(connected, timer).await,
+                future::Either::Left((Ok(()), _))
+            ) {
+                tracing::warn!("Timed out connecting to {}, retrying", url);
+                status.disconnected("timed out connecting".to_string());
+                let _ = ws.close();
+                backoff.wait().await;
+                continue;
+            }
+            backoff.reset();
+            status.connected();
             if let Some(msg) = last_message.take() {
                 ws.send_string(&msg).unwrap();
             }
 
-            while let Ok(msg) = tx_recver.recv().await {
-                if ws.send_string(&msg).is_err() {
-                    last_message = Some(msg);
-                    break;
+            let outbound = Box::pin(async {
+                while let Ok(msg) = tx_recver.recv().await {
+                    if ws.send_string(&msg).is_err() {
+                        return Some(msg);
+                    }
                 }
-            }
+                None
+            });
+            let inbound = Box::pin(forward_inbound(
+                Box::pin(inbound_recver),
+                &rx_sender,
+                &status,
+                timeout,
+                data_timeout,
+            ));
+
+            // Browsers answer pings themselves, so a dead connection only shows as silence
+            let reason = match future::select(outbound, inbound).await {
+                future::Either::Left((unsent, _)) => {
+                    last_message = unsent;
+                    "failed to send".to_string()
+                }
+                future::Either::Right((reason, _)) => reason,
+            };
+            let _ = ws.close();
+            status.disconnected(reason);
         }
     }
 }
 
 #[cfg(not(target_arch = "wasm32"))]
 mod websocket_tokio {
+    use std::time::Duration;
+
     use async_channel::{Receiver as AsyncRx, Sender as AsyncTx};
-    use futures::{SinkExt, StreamExt};
+    use futures::{future, SinkExt, StreamExt};
     use tokio::select;
+    use tokio_tungstenite::tungstenite::{
+        self, client::IntoClientRequest, handshake::client::Request,
+    };
 
+    use super::{
+        forward_inbound, until_stopped, Backoff, KeepaliveConfig, Payload, StatusHandle,
+        WebsocketOptions,
+    };
     use crate::utils::async_helpers;
+    use crate::utils::compression::decompress_frame;
 
-    pub(super) fn spawn_and_handle(url: &str) -> (AsyncTx<String>, AsyncRx<String>) {
+    pub(super) fn spawn_and_handle<T: Payload>(
+        url: &str,
+        keepalive: KeepaliveConfig,
+        options: WebsocketOptions,
+        status: StatusHandle,
+        stop: AsyncRx<()>,
+    ) -> (AsyncTx<String>, AsyncRx<T>) {
         let (tx_sender, tx_recver) = async_channel::unbounded();
         let (rx_sender, rx_recver) = async_channel::unbounded();
 
-        // TODO: Abort the future when the websocket is dropped.
-        async_helpers::spawn(handler(url.to_string(), tx_recver, rx_sender));
+        async_helpers::spawn(until_stopped(
+            stop,
+            handler(
+                url.to_string(),
+                tx_recver,
+                rx_sender,
+                keepalive,
+                options,
+                status,
+            ),
+        ));
         (tx_sender, rx_recver)
     }
 
-    async fn handler(url: String, tx_recver: AsyncRx<String>, rx_sender: AsyncTx<String>) {
+    /// The handshake request, with the authorization header if the options have one.
+    fn request(url: &str, options: &WebsocketOptions) -> Result<Request, tungstenite::Error> {
+        let mut request = url.into_client_request()?;
+        if let Some(authorization) = options.authorization.and_then(|authorize| authorize()) {
+            let value = authorization
+                .parse()
+                .map_err(|e| tungstenite::Error::HttpFormat(tungstenite::http::Error::from(e)))?;
+            request.headers_mut().insert("Authorization", value);
+        }
+        Ok(request)
+    }
+
+    async fn handler<T: Payload>(
+        url: String,
+        tx_recver: AsyncRx<String>,
+        rx_sender: AsyncTx<T>,
+        keepalive: KeepaliveConfig,
+        options: WebsocketOptions,
+        status: StatusHandle,
+    ) {
         use tokio_tungstenite::tungstenite::protocol::Message;
 
-        // TODO: Add stop token to stop the loop.
+        let ping_interval = keepalive.ping_interval();
+        let timeout = keepalive.timeout();
+        let data_timeout = keepalive.data_timeout(&options);
+
+        let mut backoff = Backoff::new();
         loop {
-            let (ws_stream, _) = tokio_tungstenite::connect_async(url.clone()).await.unwrap();
-            let (mut ws_sender, mut ws_recver) = ws_stream.split();
-
-            let handle1 = {
-                let tx_recver = tx_recver.clone();
-                tokio::spawn(async move {
-                    while let Ok(msg) = tx_recver.recv().await {
-                        ws_sender.send(Message::Text(msg)).await.unwrap();
+            status.connecting();
+
+            let connected = match request(&url, &options) {
+                Ok(request) => tokio_tungstenite::connect_async(request).await,
+                Err(e) => Err(e),
+            };
+            let ws_stream = match connected {
+                Ok((ws_stream, _)) => ws_stream,
+                Err(e) => {
+                    tracing::warn!("Failed to connect to {}, retrying: {}", url, e);
+                    status.disconnected(e.to_string());
+                    backoff.wait().await;
+                    continue;
+                }
+            };
+            backoff.reset();
+            let (mut ws_sender, ws_recver) = ws_stream.split();
+            status.connected();
+
+            let outbound = async {
+                let mut ping = tokio::time::interval(ping_interval);
+                loop {
+                    let message = select! {
+                        msg = tx_recver.recv() => match msg {
+                            Ok(msg) => Message::Text(msg),
+                            Err(_) => break,
+                        },
+                        _ = ping.tick() => Message::Ping(Vec::new()),
+                    };
+                    if ws_sender.send(message).await.is_err() {
+                        break;
                     }
-                })
+                }
             };
 
-            let handle2 = {
-                let rx_sender = rx_sender.clone();
-                tokio::spawn(async move {
-                    while let Some(msg) = ws_recver.next().await {
-                        let msg = msg.unwrap();
-                        if let Message::Text(text) = msg {
-                            rx_sender.send(text).await.unwrap();
+            // Pongs and other control frames count as activity, but are not forwarded
+            let inbound =
+                ws_recver
+                    .take_while(|msg| future::ready(msg.is_ok()))
+                    .map(|msg| match msg {
+                        Ok(Message::Text(text)) => Some(T::from_text(text)),
+                        Ok(Message::Binary(binary)) => {
+                            Some(T::from_binary(decompress_frame(options.codec, binary)))
                         }
-                    }
-                })
+                        _ => None,
+                    });
+
+            // If either direction is done, reconnect.
+            // Both run in this task, so stopping it also drops the connection.
+            let reason = select! {
+                _ = outbound => "failed to send".to_string(),
+                reason = forward_inbound(inbound, &rx_sender, &status, timeout, data_timeout) => reason,
             };
+            status.disconnected(reason);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
 
-            // If any of the two handles are done, abort the other one.
-            // And then, reconnect.
-            let (ah1, ah2) = (handle1.abort_handle(), handle2.abort_handle());
-            select! {
-                _ = handle1 => { ah2.abort(); }
-                _ = handle2 => { ah1.abort(); }
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::{
+        forward_inbound, until_stopped, ConnState, ConnectionStatus, KeepaliveConfig, Payload,
+        StatusHandle, Websocket, WebsocketOptions,
+    };
+    use crate::utils::async_helpers;
+
+    #[tokio::test]
+    async fn silent_connection_goes_stale() {
+        let (inbound_sender, inbound) = async_channel::unbounded();
+        let (rx_sender, rx_recver) = async_channel::unbounded();
+        let status = StatusHandle::new();
+
+        inbound_sender.send(Some("book".to_string())).await.unwrap();
+        // A pong is activity, but not data
+        inbound_sender.send(None).await.unwrap();
+
+        // The sender is kept alive, so only the timer can end the forwarding
+        let reason = forward_inbound(
+            Box::pin(inbound),
+            &rx_sender,
+            &status,
+            Duration::from_millis(50),
+            None,
+        )
+        .await;
+
+        assert_eq!(rx_recver.try_recv().unwrap(), "book");
+        assert!(rx_recver.is_empty());
+        assert!(matches!(status.get(), ConnectionStatus::Stale { .. }));
+        assert_eq!(reason, "no message for 50ms");
+        drop(inbound_sender);
+    }
+
+    #[tokio::test]
+    async fn pongs_without_data_go_stale() {
+        let (inbound_sender, inbound) = async_channel::unbounded();
+        let (rx_sender, _rx_recver) = async_channel::unbounded();
+        let status = StatusHandle::new();
+
+        inbound_sender.send(Some("book".to_string())).await.unwrap();
+        let pongs = tokio::spawn(async move {
+            loop {
+                if inbound_sender.send(None).await.is_err() {
+                    return;
+                }
+                async_helpers::sleep(Duration::from_millis(10)).await;
             }
-        }
+        });
+
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            forward_inbound(
+                Box::pin(inbound),
+                &rx_sender,
+                &status,
+                Duration::from_secs(60),
+                Some(Duration::from_millis(100)),
+            ),
+        )
+        .await
+        .expect("the data watchdog did not fire");
+        pongs.abort();
+
+        assert!(matches!(status.get(), ConnectionStatus::Stale { .. }));
+        let metrics = status.metrics();
+        assert_eq!(metrics.messages, 1);
+        assert!(metrics.last_recv_at.is_some());
+        assert_eq!(metrics.stale_reconnects, 1);
+    }
+
+    #[test]
+    fn keepalive_timeouts_are_clamped() {
+        let keepalive = KeepaliveConfig {
+            ping_interval_secs: 0,
+            timeout_secs: 0,
+            data_timeout_secs: Some(0),
+        };
+        let options = WebsocketOptions::default();
+        assert_eq!(keepalive.ping_interval(), Duration::from_secs(1));
+        assert_eq!(keepalive.timeout(), Duration::from_secs(1));
+        assert_eq!(
+            keepalive.data_timeout(&options),
+            Some(Duration::from_secs(1))
+        );
+
+        let quiet = WebsocketOptions {
+            quiet: true,
+            ..options
+        };
+        assert_eq!(keepalive.data_timeout(&quiet), None);
+    }
+
+    #[test]
+    fn binary_payloads() {
+        assert_eq!(String::from_binary(b"{}".to_vec()), "{}");
+        assert_eq!(String::from_binary(vec![b'a', 0xff]), "a\u{fffd}");
+        assert_eq!(Vec::<u8>::from_text("{}".to_string()), b"{}");
+        assert_eq!(Vec::<u8>::from_binary(vec![0x1f, 0x8b]), vec![0x1f, 0x8b]);
+    }
+
+    #[tokio::test]
+    async fn connection_transitions() {
+        let status = StatusHandle::new();
+        let events = status.events();
+
+        status.connecting();
+        status.disconnected("refused".to_string());
+        status.connecting();
+        status.connected();
+
+        assert_eq!(events.recv().await, ConnState::Connecting);
+        assert_eq!(
+            events.recv().await,
+            ConnState::Disconnected("refused".to_string())
+        );
+        assert_eq!(events.recv().await, ConnState::Connecting);
+        assert_eq!(events.recv().await, ConnState::Connected);
+        assert!(matches!(status.get(), ConnectionStatus::Connected { .. }));
+    }
+
+    #[tokio::test]
+    async fn closed_stream_is_not_stale() {
+        let (inbound_sender, inbound) = async_channel::unbounded::<Option<String>>();
+        let (rx_sender, _rx_recver) = async_channel::unbounded();
+        let status = StatusHandle::new();
+
+        drop(inbound_sender);
+        forward_inbound(
+            Box::pin(inbound),
+            &rx_sender,
+            &status,
+            Duration::from_secs(60),
+            None,
+        )
+        .await;
+
+        assert_eq!(status.get(), ConnectionStatus::Reconnecting);
+    }
+
+    #[tokio::test]
+    async fn task_ends_when_stop_is_dropped() {
+        let (stop, stopped) = async_channel::bounded::<()>(1);
+        let counter = Arc::new(AtomicUsize::new(0));
+
+        let ticks = counter.clone();
+        let task = tokio::spawn(until_stopped(stopped, async move {
+            loop {
+                ticks.fetch_add(1, Ordering::Relaxed);
+                async_helpers::sleep(Duration::from_millis(5)).await;
+            }
+        }));
+
+        async_helpers::sleep(Duration::from_millis(30)).await;
+        drop(stop);
+        tokio::time::timeout(Duration::from_secs(1), task)
+            .await
+            .expect("the task did not stop")
+            .unwrap();
+
+        let count = counter.load(Ordering::Relaxed);
+        assert!(count > 0);
+        async_helpers::sleep(Duration::from_millis(30)).await;
+        assert_eq!(counter.load(Ordering::Relaxed), count);
+    }
+
+    #[tokio::test]
+    async fn handles_do_not_keep_the_websocket() {
+        // Nothing listens there, the socket retries until it is dropped
+        let ws = Websocket::new("ws://127.0.0.1:9");
+        let receiver = ws.receiver();
+        ws.on_connected(|sender| sender.send("subscribe"));
+
+        drop(ws);
+        let received = tokio::time::timeout(Duration::from_secs(1), receiver.recv())
+            .await
+            .expect("the connection did not stop");
+        assert!(received.is_none());
     }
 }